@@ -0,0 +1,408 @@
+use crate::tools::ToolError;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Entries returned by `list_archive` at most.
+const MAX_LISTED: usize = 500;
+/// Entries extracted at most, and their total uncompressed size, so a
+/// crafted archive can't fill the disk.
+const MAX_EXTRACTED: usize = 5_000;
+const MAX_EXTRACTED_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Directory archives are extracted into (`~/.ronge/extracted`), one
+/// subdirectory per extraction.
+pub fn extracted_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("extracted")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    TarGz,
+    Tar,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::TarGz => "tar.gz",
+            Self::Tar => "tar",
+        }
+    }
+
+    /// By extension, else by the file's magic bytes.
+    fn detect(path: &Path) -> Result<Self, String> {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if name.ends_with(".zip") {
+            return Ok(Self::Zip);
+        }
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            return Ok(Self::TarGz);
+        }
+        if name.ends_with(".tar") {
+            return Ok(Self::Tar);
+        }
+        let mut magic = [0u8; 4];
+        let read = std::fs::File::open(path)
+            .and_then(|mut f| f.read(&mut magic))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        match &magic[..read] {
+            [b'P', b'K', 3, 4] => Ok(Self::Zip),
+            [0x1f, 0x8b, ..] => Ok(Self::TarGz),
+            _ => Err(format!("{} is not a zip, tar.gz or tar archive", path.display())),
+        }
+    }
+}
+
+/// Archive path from the model: `~` expanded, must be an existing file.
+fn source(path: &str) -> Result<PathBuf, ToolError> {
+    let path = crate::watcher::expand_home(path.trim());
+    if !path.is_file() {
+        return Err(ToolError::CommandFailed(format!("No archive at {}", path.display())));
+    }
+    Ok(path)
+}
+
+/// An entry's path inside the destination, or `None` when it is absolute or
+/// climbs out with `..` (a "zip slip").
+fn contained(name: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+fn list_entries(path: &Path, format: Format) -> Result<(Vec<ArchiveEntry>, usize), String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    let mut total = 0;
+    let mut push = |entry: ArchiveEntry| {
+        total += 1;
+        if entries.len() < MAX_LISTED {
+            entries.push(entry);
+        }
+    };
+    match format {
+        Format::Zip => {
+            let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+            for i in 0..archive.len() {
+                let entry = archive.by_index_raw(i).map_err(|e| e.to_string())?;
+                push(ArchiveEntry { path: entry.name().to_string(), size: entry.size(), is_dir: entry.is_dir() });
+            }
+        }
+        Format::TarGz | Format::Tar => {
+            let reader: Box<dyn Read> = match format {
+                Format::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+                _ => Box::new(file),
+            };
+            let mut archive = tar::Archive::new(reader);
+            for entry in archive.entries().map_err(|e| e.to_string())? {
+                let entry = entry.map_err(|e| e.to_string())?;
+                let name = entry.path().map_err(|e| e.to_string())?.display().to_string();
+                let is_dir = entry.header().entry_type().is_dir();
+                push(ArchiveEntry { path: name, size: entry.size(), is_dir });
+            }
+        }
+    }
+    Ok((entries, total))
+}
+
+/// What was written and what was left out of one extraction.
+#[derive(Default)]
+struct Extraction {
+    files: Vec<ArchiveEntry>,
+    skipped: Vec<String>,
+    bytes: u64,
+}
+
+impl Extraction {
+    /// Whether `name` is wanted: every entry when `members` is empty, else
+    /// the named entries and anything under a named directory.
+    fn wanted(members: &[String], name: &Path) -> bool {
+        members.is_empty()
+            || members.iter().any(|m| {
+                let m = Path::new(m.trim_end_matches('/'));
+                name == m || name.starts_with(m)
+            })
+    }
+
+    /// Copy one regular file to `dest/relative`, counting against the size cap.
+    fn write(&mut self, dest: &Path, relative: &Path, reader: &mut dyn Read) -> Result<(), String> {
+        if self.files.len() >= MAX_EXTRACTED {
+            return Err(format!("The archive has more than {} files", MAX_EXTRACTED));
+        }
+        let target = dest.join(relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut out = std::fs::File::create(&target).map_err(|e| e.to_string())?;
+        // Count what is actually decompressed, not what the header claims.
+        let remaining = MAX_EXTRACTED_BYTES - self.bytes;
+        let written = std::io::copy(&mut reader.take(remaining + 1), &mut out).map_err(|e| e.to_string())?;
+        if written > remaining {
+            drop(out);
+            let _ = std::fs::remove_file(&target);
+            return Err(format!("The archive expands to more than {} MB", MAX_EXTRACTED_BYTES / (1024 * 1024)));
+        }
+        out.flush().map_err(|e| e.to_string())?;
+        self.bytes += written;
+        self.files.push(ArchiveEntry { path: relative.display().to_string(), size: written, is_dir: false });
+        Ok(())
+    }
+}
+
+/// Extract regular files and directories only; links, devices and entries
+/// that would land outside `dest` are skipped.
+fn extract_entries(path: &Path, format: Format, dest: &Path, members: &[String]) -> Result<Extraction, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut done = Extraction::default();
+    match format {
+        Format::Zip => {
+            let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+            for i in 0..archive.len() {
+                let name = archive.name_for_index(i).unwrap_or("?").to_string();
+                let mut entry = match archive.by_index(i) {
+                    Ok(entry) => entry,
+                    // Encrypted or unsupported compression.
+                    Err(e) => {
+                        done.skipped.push(format!("{} ({})", name, e));
+                        continue;
+                    }
+                };
+                let Some(relative) = contained(Path::new(&name)) else {
+                    done.skipped.push(format!("{} (outside the archive root)", name));
+                    continue;
+                };
+                if !Extraction::wanted(members, &relative) {
+                    continue;
+                }
+                if entry.is_dir() {
+                    std::fs::create_dir_all(dest.join(&relative)).map_err(|e| e.to_string())?;
+                } else if entry.is_symlink() {
+                    done.skipped.push(format!("{} (symbolic link)", name));
+                } else {
+                    done.write(dest, &relative, &mut entry)?;
+                }
+            }
+        }
+        Format::TarGz | Format::Tar => {
+            let reader: Box<dyn Read> = match format {
+                Format::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+                _ => Box::new(file),
+            };
+            let mut archive = tar::Archive::new(reader);
+            for entry in archive.entries().map_err(|e| e.to_string())? {
+                let mut entry = entry.map_err(|e| e.to_string())?;
+                let name = entry.path().map_err(|e| e.to_string())?.into_owned();
+                let Some(relative) = contained(&name) else {
+                    done.skipped.push(format!("{} (outside the archive root)", name.display()));
+                    continue;
+                };
+                if !Extraction::wanted(members, &relative) {
+                    continue;
+                }
+                let kind = entry.header().entry_type();
+                if kind.is_dir() {
+                    std::fs::create_dir_all(dest.join(&relative)).map_err(|e| e.to_string())?;
+                } else if kind.is_file() {
+                    done.write(dest, &relative, &mut entry)?;
+                } else if !kind.is_pax_global_extensions() && !kind.is_pax_local_extensions() {
+                    done.skipped.push(format!("{} (not a regular file)", name.display()));
+                }
+            }
+        }
+    }
+    Ok(done)
+}
+
+// ── ListArchive ──
+
+/// `list_archive`: the entries of a zip, tar.gz or tar file without
+/// extracting anything.
+#[derive(Deserialize, Serialize)]
+pub struct ListArchive;
+
+#[derive(Deserialize, Serialize)]
+pub struct ListArchiveArgs {
+    path: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ListArchiveOutput {
+    pub path: String,
+    pub format: String,
+    pub total_entries: usize,
+    pub entries: Vec<ArchiveEntry>,
+}
+
+impl Tool for ListArchive {
+    const NAME: &'static str = "list_archive";
+    type Args = ListArchiveArgs;
+    type Output = ListArchiveOutput;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "List the files inside a .zip, .tar.gz/.tgz or .tar archive (paths and uncompressed \
+                sizes) without extracting it, e.g. a Gmail attachment that was just saved."
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Path of the archive; ~ is the home directory" }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let path = source(&args.path)?;
+        let listed = path.clone();
+        let (format, (entries, total_entries)) = tokio::task::spawn_blocking(move || {
+            let format = Format::detect(&listed)?;
+            list_entries(&listed, format).map(|entries| (format, entries))
+        })
+        .await
+        .map_err(|e| ToolError::CommandFailed(e.to_string()))?
+        .map_err(ToolError::CommandFailed)?;
+        Ok(ListArchiveOutput {
+            path: path.display().to_string(),
+            format: format.name().to_string(),
+            total_entries,
+            entries,
+        })
+    }
+}
+
+// ── ExtractArchive ──
+
+/// `extract_archive`: unpack an archive (or some of its entries) into a new
+/// directory under `~/.ronge/extracted/`, where the file tools can read it.
+#[derive(Deserialize, Serialize)]
+pub struct ExtractArchive;
+
+#[derive(Deserialize, Serialize)]
+pub struct ExtractArchiveArgs {
+    path: String,
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExtractArchiveOutput {
+    pub directory: String,
+    pub files: Vec<ArchiveEntry>,
+    pub total_bytes: u64,
+    pub skipped: Vec<String>,
+}
+
+impl Tool for ExtractArchive {
+    const NAME: &'static str = "extract_archive";
+    type Args = ExtractArchiveArgs;
+    type Output = ExtractArchiveOutput;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Extract a .zip, .tar.gz/.tgz or .tar archive into a new folder under ~/.ronge/extracted \
+                and return the extracted files' paths, which can then be read with the file tools. Pass `members` \
+                to extract only some entries (e.g. the README). Links and entries pointing outside the archive \
+                are skipped."
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Path of the archive; ~ is the home directory" },
+                    "members": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Entry paths (as listed by list_archive) or directories to extract; all by default"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let path = source(&args.path)?;
+        let stem: String = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+            .trim_end_matches(".gz")
+            .trim_end_matches(".tgz")
+            .trim_end_matches(".tar")
+            .trim_end_matches(".zip")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+            .collect();
+        let dest = extracted_dir().join(format!("{}-{}", stem, chrono::Local::now().format("%Y%m%d-%H%M%S-%3f")));
+        tokio::fs::create_dir_all(&dest).await?;
+
+        let (archive, target, members) = (path.clone(), dest.clone(), args.members);
+        let result = tokio::task::spawn_blocking(move || {
+            let format = Format::detect(&archive)?;
+            extract_entries(&archive, format, &target, &members)
+        })
+        .await
+        .map_err(|e| ToolError::CommandFailed(e.to_string()))?;
+        let done = match result {
+            Ok(done) => done,
+            Err(e) => {
+                let _ = tokio::fs::remove_dir_all(&dest).await;
+                return Err(ToolError::CommandFailed(e));
+            }
+        };
+        println!("📦 Extracted {} file(s) from {} to {}", done.files.len(), path.display(), dest.display());
+        Ok(ExtractArchiveOutput {
+            directory: dest.display().to_string(),
+            files: done.files,
+            total_bytes: done.bytes,
+            skipped: done.skipped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contained_keeps_relative_entries() {
+        assert_eq!(contained(Path::new("docs/readme.md")), Some(PathBuf::from("docs/readme.md")));
+        assert_eq!(contained(Path::new("./docs/./readme.md")), Some(PathBuf::from("docs/readme.md")));
+    }
+
+    #[test]
+    fn contained_rejects_entries_that_escape() {
+        assert_eq!(contained(Path::new("../outside.txt")), None);
+        assert_eq!(contained(Path::new("docs/../../outside.txt")), None);
+        assert_eq!(contained(Path::new("/etc/passwd")), None);
+        assert_eq!(contained(Path::new("./")), None);
+        assert_eq!(contained(Path::new("")), None);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::state::SharedState;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Default lifetime of an artifact before it is garbage-collected.
+const DEFAULT_TTL_SECS: i64 = 24 * 60 * 60;
+const GC_INTERVAL_SECS: u64 = 10 * 60;
+
+/// Our own tools whose output reports a generated file as `path`: the exact
+/// tool name, whether it must be a built-in (not MCP-served) tool, and the
+/// directory its files are written to.
+type Producer = (&'static str, bool, fn() -> PathBuf);
+const ARTIFACT_TOOLS: &[Producer] = &[
+    ("render_chart", true, crate::chart::charts_dir),
+    // Served by the in-process calendar server through the MCP proxy.
+    (crate::calendar::EXPORT_ICS, false, crate::ics::exports_dir),
+];
+
+/// A file produced during a session (chart, export, downloaded attachment).
+#[derive(Debug, Clone, Serialize)]
+pub struct Artifact {
+    pub id: String,
+    #[serde(skip)]
+    pub session_id: String,
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+    pub created_at: i64,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// Per-session registry of generated files. Artifacts live on disk until
+/// their TTL (`RONGE_ARTIFACT_TTL_SECS`, default 24h) elapses.
+pub struct ArtifactStore {
+    items: HashMap<String, Artifact>,
+    ttl_secs: i64,
+}
+
+impl ArtifactStore {
+    pub fn from_env() -> Self {
+        let ttl_secs = std::env::var("RONGE_ARTIFACT_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_TTL_SECS);
+        Self {
+            items: HashMap::new(),
+            ttl_secs,
+        }
+    }
+
+    /// Register an existing file for `session_id` and return its metadata.
+    pub fn register(&mut self, session_id: &str, path: PathBuf) -> Option<Artifact> {
+        let size = std::fs::metadata(&path).ok()?.len();
+        let name = path.file_name()?.to_string_lossy().to_string();
+        let artifact = Artifact {
+            id: crate::session::new_session_id(),
+            session_id: session_id.to_string(),
+            mime: mime_for(&name).to_string(),
+            name,
+            size,
+            created_at: chrono::Utc::now().timestamp(),
+            path,
+        };
+        self.items.insert(artifact.id.clone(), artifact.clone());
+        Some(artifact)
+    }
+
+    pub fn list(&self, session_id: &str) -> Vec<Artifact> {
+        let mut artifacts: Vec<Artifact> = self
+            .items
+            .values()
+            .filter(|a| a.session_id == session_id)
+            .cloned()
+            .collect();
+        artifacts.sort_by_key(|a| a.created_at);
+        artifacts
+    }
+
+    /// Artifacts are only visible to the session that produced them.
+    pub fn get(&self, session_id: &str, id: &str) -> Option<Artifact> {
+        self.items
+            .get(id)
+            .filter(|a| a.session_id == session_id)
+            .cloned()
+    }
+
+    /// Remove expired entries and return them so their files can be deleted
+    /// outside the state lock.
+    fn take_expired(&mut self, now: i64) -> Vec<Artifact> {
+        let expired: Vec<String> = self
+            .items
+            .values()
+            .filter(|a| now - a.created_at >= self.ttl_secs)
+            .map(|a| a.id.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|id| self.items.remove(id))
+            .collect()
+    }
+}
+
+/// Register every file produced by an artifact tool during a turn. Paths
+/// outside the producing tool's own output directory are ignored, so no tool
+/// result can expose an arbitrary local file for download.
+pub async fn register_from_events(
+    state: &SharedState,
+    session_id: &str,
+    events: &[Value],
+) -> Vec<Artifact> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for (name, builtin, dir) in ARTIFACT_TOOLS {
+        let dir = dir();
+        let results: Vec<Value> = if *builtin {
+            crate::widgets::builtin_results(events, name).collect()
+        } else {
+            events
+                .iter()
+                .filter(|e| e["type"] == "tool_result" && e["content"]["toolName"] == *name)
+                .filter_map(|e| e["content"]["result"].as_str())
+                .filter_map(|r| serde_json::from_str(r).ok())
+                .collect()
+        };
+        // MCP tools report the path inside their result's text content.
+        for result in results {
+            crate::widgets::walk(&result, 0, &mut |obj| {
+                if let Some(path) = obj.get("path").and_then(|p| p.as_str()) {
+                    match crate::widgets::path_within(path, &dir) {
+                        Some(path) => paths.push(path),
+                        None => println!("⚠️ Ignored an artifact outside {}: {}", dir.display(), path),
+                    }
+                }
+            });
+        }
+    }
+    if paths.is_empty() {
+        return Vec::new();
+    }
+    let mut s = state.lock().await;
+    paths
+        .into_iter()
+        .filter_map(|p| s.artifacts.register(session_id, p))
+        .collect()
+}
+
+/// Spawn the background task that deletes expired artifacts.
+pub fn spawn_gc(state: SharedState) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(GC_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            let expired = state
+                .lock()
+                .await
+                .artifacts
+                .take_expired(chrono::Utc::now().timestamp());
+            for artifact in expired {
+                if let Err(e) = tokio::fs::remove_file(&artifact.path).await
+                    && e.kind() != std::io::ErrorKind::NotFound
+                {
+                    println!("⚠️ Could not delete artifact {}: {}", artifact.path.display(), e);
+                }
+            }
+        }
+    });
+}
+
+fn mime_for(name: &str) -> &'static str {
+    match name.rsplit('.').next().map(|e| e.to_ascii_lowercase()).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("pdf") => "application/pdf",
+        Some("csv") => "text/csv",
+        Some("json") => "application/json",
+        Some("txt") | Some("md") => "text/plain",
+        Some("ics") => "text/calendar",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,877 @@
+use crate::state::{McpConnection, McpToolSet};
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDateTime, TimeZone};
+use rmcp::{
+    ServerHandler,
+    model::{CallToolRequestParam, CallToolResult, Content, ErrorData, ListToolsResult, PaginatedRequestParam},
+    service::{Peer, RequestContext, RoleClient, RoleServer},
+};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Extra boolean argument added to calendar create-event tools while the
+/// conflict check is on. Stripped before the call is forwarded.
+pub const ALLOW_CONFLICTS: &str = "allow_conflicts";
+
+pub const QUICK_ADD: &str = "calendar_quick_add_event";
+pub const GET_EVENT: &str = "calendar_get_event";
+pub const AGGREGATE_AGENDA: &str = "aggregate_agenda";
+pub const EXPORT_ICS: &str = "calendar_export_ics";
+pub const PARSE_ICS: &str = "calendar_parse_ics";
+const MESSAGE_ID_KEYS: &[&str] = &["message_id", "messageId"];
+const ATTACHMENT_ID_KEYS: &[&str] = &["attachment_id", "attachmentId"];
+const QUICK_ADD_TEXT_KEYS: &[&str] = &["text", "query", "event_text"];
+const EVENT_ID_KEYS: &[&str] = &["event_id", "eventId"];
+const CALENDAR_ID_KEYS: &[&str] = &["calendar_id", "calendarId"];
+
+const START_KEYS: &[&str] = &["start_datetime", "start_time", "startTime", "start"];
+const END_KEYS: &[&str] = &["end_datetime", "end_time", "endTime", "end"];
+const TIME_MIN_KEYS: &[&str] = &["timeMin", "time_min", "timemin"];
+const TIME_MAX_KEYS: &[&str] = &["timeMax", "time_max", "timemax"];
+/// Events without an end or duration are assumed to last this long.
+const DEFAULT_DURATION_MINUTES: i64 = 60;
+
+/// Whether `tool_name` creates a Calendar event from explicit times
+/// (Composio's `GOOGLECALENDAR_CREATE_EVENT`, `events_insert`, …).
+pub fn is_create_event(tool_name: &str) -> bool {
+    let lower = tool_name.to_ascii_lowercase();
+    lower.contains("calendar")
+        && ["create_event", "insert_event", "events_insert"].iter().any(|f| lower.contains(f))
+}
+
+fn is_list_events(tool_name: &str) -> bool {
+    let lower = tool_name.to_ascii_lowercase();
+    lower.contains("calendar")
+        && ["events_list", "list_events", "find_event"].iter().any(|f| lower.contains(f))
+}
+
+/// Offer `allow_conflicts` on every create-event tool of a server.
+pub fn with_conflict_flag(tools: &[rmcp::model::Tool]) -> Vec<rmcp::model::Tool> {
+    tools
+        .iter()
+        .cloned()
+        .map(|mut tool| {
+            if is_create_event(&tool.name) {
+                let mut schema = (*tool.input_schema).clone();
+                if let Some(properties) = schema.get_mut("properties").and_then(|p| p.as_object_mut()) {
+                    properties.insert(
+                        ALLOW_CONFLICTS.to_string(),
+                        json!({
+                            "type": "boolean",
+                            "description": "Create the event even if it overlaps others. Only set this after the user confirmed the double-booking."
+                        }),
+                    );
+                }
+                tool.input_schema = Arc::new(schema);
+            }
+            tool
+        })
+        .collect()
+}
+
+/// Parse an RFC 3339 time, or a local time without offset.
+fn parse_time(value: &Value) -> Option<DateTime<FixedOffset>> {
+    let text = value.as_str().or_else(|| value["dateTime"].as_str())?;
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time);
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(|time| time.fixed_offset())
+}
+
+fn first_time(args: &serde_json::Map<String, Value>, keys: &[&str]) -> Option<DateTime<FixedOffset>> {
+    keys.iter().find_map(|k| args.get(*k).and_then(parse_time))
+}
+
+/// The window a create-event call would occupy. `None` for all-day or
+/// unparseable events, which are not checked.
+pub fn event_window(args: &serde_json::Map<String, Value>) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let start = first_time(args, START_KEYS)?;
+    let end = first_time(args, END_KEYS).unwrap_or_else(|| {
+        let hours = args.get("event_duration_hour").and_then(|v| v.as_i64()).unwrap_or(0);
+        let minutes = args.get("event_duration_minutes").and_then(|v| v.as_i64()).unwrap_or(0);
+        let total = hours * 60 + minutes;
+        start + Duration::minutes(if total > 0 { total } else { DEFAULT_DURATION_MINUTES })
+    });
+    (end > start).then_some((start, end))
+}
+
+fn schema_key(tool: &rmcp::model::Tool, candidates: &[&'static str]) -> Option<&'static str> {
+    let properties = tool.input_schema.get("properties")?.as_object()?;
+    candidates.iter().copied().find(|k| properties.contains_key(*k))
+}
+
+/// Events overlapping `window`, listed with the same server's list-events
+/// tool. Errors when the server has no such tool or the lookup fails.
+pub async fn find_conflicts(
+    peer: &Peer<RoleClient>,
+    tools: &[rmcp::model::Tool],
+    name_map: &HashMap<String, String>,
+    window: (DateTime<FixedOffset>, DateTime<FixedOffset>),
+) -> Result<Vec<Value>, String> {
+    let (list, min_key, max_key) = tools
+        .iter()
+        .filter(|t| is_list_events(&t.name))
+        .find_map(|t| Some((t, schema_key(t, TIME_MIN_KEYS)?, schema_key(t, TIME_MAX_KEYS)?)))
+        .ok_or("no list-events tool with a time range")?;
+    let mut arguments = serde_json::Map::new();
+    arguments.insert(min_key.to_string(), json!(window.0.to_rfc3339()));
+    arguments.insert(max_key.to_string(), json!(window.1.to_rfc3339()));
+    if schema_key(list, &["singleEvents", "single_events"]).is_some() {
+        arguments.insert("singleEvents".to_string(), json!(true));
+    }
+    let safe = list.name.to_string();
+    let result = peer
+        .call_tool(CallToolRequestParam {
+            name: Cow::Owned(name_map.get(&safe).cloned().unwrap_or(safe)),
+            arguments: Some(arguments),
+            task: None,
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    if result.is_error == Some(true) {
+        return Err(format!("{} failed", list.name));
+    }
+
+    let value = serde_json::to_value(&result).unwrap_or(Value::Null);
+    let mut conflicts: Vec<Value> = Vec::new();
+    crate::widgets::walk(&value, 0, &mut |obj| {
+        let Some(event) = crate::widgets::calendar_event(obj) else { return };
+        // All-day events (birthdays, OOO markers) do not block a time slot.
+        if event["all_day"] == true || conflicts.iter().any(|c| c["title"] == event["title"] && c["start"] == event["start"]) {
+            return;
+        }
+        let (Some(start), Some(end)) = (parse_time(&event["start"]), parse_time(&event["end"])) else {
+            return;
+        };
+        if start < window.1 && end > window.0 {
+            conflicts.push(event);
+        }
+    });
+    Ok(conflicts)
+}
+
+/// The tool result returned instead of creating an overlapping event.
+pub fn conflict_warning(conflicts: &[Value]) -> String {
+    json!({
+        "created": false,
+        "conflict": true,
+        "conflicting_events": conflicts,
+        "message": format!(
+            "Not created: the event overlaps {} existing event(s). Ask the user whether to book it anyway; \
+             if they agree, call the tool again with the same arguments and {}: true.",
+            conflicts.len(),
+            ALLOW_CONFLICTS
+        ),
+    })
+    .to_string()
+}
+
+const ATTENDEE_KEYS: &[&str] = &["attendees", "attendee_emails", "guests"];
+const CONTACT_QUERY_KEYS: &[&str] = &["query", "q", "search_query", "name"];
+
+fn is_contact_search(tool_name: &str) -> bool {
+    let lower = tool_name.to_ascii_lowercase();
+    ["search_people", "contacts", "people"].iter().any(|f| lower.contains(f))
+}
+
+/// `(display name, email)` pairs in a contacts search result: People API
+/// persons (`names[].displayName`, `emailAddresses[].value`) or flat
+/// `{name, email}` objects.
+fn contact_candidates(value: &Value) -> Vec<(String, String)> {
+    let mut candidates: Vec<(String, String)> = Vec::new();
+    crate::widgets::walk(value, 0, &mut |obj| {
+        let name = obj
+            .get("names")
+            .and_then(|n| n.as_array())
+            .and_then(|n| n.first())
+            .and_then(|n| n["displayName"].as_str())
+            .or_else(|| obj.get("displayName").and_then(|n| n.as_str()))
+            .or_else(|| obj.get("name").and_then(|n| n.as_str()))
+            .unwrap_or("");
+        let emails: Vec<&str> = match obj.get("emailAddresses").and_then(|e| e.as_array()) {
+            Some(list) => list.iter().filter_map(|e| e["value"].as_str()).collect(),
+            None => obj.get("email").and_then(|e| e.as_str()).into_iter().collect(),
+        };
+        for email in emails {
+            if email.contains('@') && !candidates.iter().any(|(_, e)| e.eq_ignore_ascii_case(email)) {
+                candidates.push((name.to_string(), email.to_string()));
+            }
+        }
+    });
+    candidates
+}
+
+/// Replace attendees given by name (no `@`) with their email address from
+/// the same server's contacts search. Returns the `(name, email)` pairs that
+/// were resolved; `Err` carries the tool result to return instead of
+/// creating an event with bogus addresses, listing the candidates when a
+/// name matched several people.
+pub async fn resolve_attendees(
+    peer: &Peer<RoleClient>,
+    tools: &[rmcp::model::Tool],
+    name_map: &HashMap<String, String>,
+    args: &mut serde_json::Map<String, Value>,
+) -> Result<Vec<(String, String)>, String> {
+    let Some(key) = ATTENDEE_KEYS.iter().copied().find(|k| args.get(*k).is_some_and(|v| v.is_array())) else {
+        return Ok(Vec::new());
+    };
+    let entry_name = |entry: &Value| -> Option<String> {
+        let text = entry.as_str().or_else(|| entry["email"].as_str()).unwrap_or("").trim();
+        (!text.is_empty() && !text.contains('@')).then(|| text.to_string())
+    };
+    let names: Vec<String> = args[key].as_array().into_iter().flatten().filter_map(entry_name).collect();
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let search = tools
+        .iter()
+        .filter(|t| is_contact_search(&t.name))
+        .find_map(|t| Some((t, schema_key(t, CONTACT_QUERY_KEYS)?)));
+    let mut resolved: Vec<(String, String)> = Vec::new();
+    let mut ambiguous = serde_json::Map::new();
+    let mut not_found = Vec::new();
+    for name in &names {
+        let candidates = match search {
+            Some((tool, query_key)) => {
+                let safe = tool.name.to_string();
+                let mut arguments = serde_json::Map::new();
+                arguments.insert(query_key.to_string(), json!(name));
+                match peer
+                    .call_tool(CallToolRequestParam {
+                        name: Cow::Owned(name_map.get(&safe).cloned().unwrap_or(safe)),
+                        arguments: Some(arguments),
+                        task: None,
+                    })
+                    .await
+                {
+                    Ok(result) if result.is_error != Some(true) => {
+                        contact_candidates(&serde_json::to_value(&result).unwrap_or(Value::Null))
+                    }
+                    Ok(_) | Err(_) => Vec::new(),
+                }
+            }
+            None => Vec::new(),
+        };
+        match candidates.as_slice() {
+            [] => not_found.push(name.clone()),
+            [(_, email)] => resolved.push((name.clone(), email.clone())),
+            many => {
+                let options: Vec<Value> = many.iter().map(|(n, e)| json!({"name": n, "email": e})).collect();
+                ambiguous.insert(name.clone(), json!(options));
+            }
+        }
+    }
+
+    if !ambiguous.is_empty() || !not_found.is_empty() {
+        let message = if search.is_none() {
+            "Not created: attendees must be email addresses and no contacts tool is connected to look the names up. Ask the user for their email addresses.".to_string()
+        } else {
+            "Not created: some attendees could not be matched to a single contact. Ask the user which person they mean (or for the email address), then call the tool again with email addresses.".to_string()
+        };
+        return Err(json!({
+            "created": false,
+            "attendees_unresolved": true,
+            "ambiguous": ambiguous,
+            "not_found": not_found,
+            "message": message,
+        })
+        .to_string());
+    }
+
+    if let Some(entries) = args.get_mut(key).and_then(|v| v.as_array_mut()) {
+        for entry in entries.iter_mut() {
+            let Some(name) = entry_name(entry) else { continue };
+            let Some((_, email)) = resolved.iter().find(|(n, _)| *n == name) else { continue };
+            match entry {
+                Value::Object(obj) => {
+                    obj.insert("email".to_string(), json!(email));
+                }
+                _ => *entry = json!(email),
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// A connected Calendar tool taking one main argument (the phrase, the
+/// event ID) and optionally a calendar ID.
+struct UpstreamTool {
+    set: McpToolSet,
+    name: String,
+    key: &'static str,
+    calendar_key: Option<&'static str>,
+}
+
+impl UpstreamTool {
+    fn find(tool_sets: &[McpToolSet], fragments: &[&str], keys: &[&'static str]) -> Option<Self> {
+        tool_sets.iter().find_map(|set| {
+            set.tools.iter().find_map(|tool| {
+                let lower = tool.name.to_ascii_lowercase();
+                if !(lower.contains("calendar") && fragments.iter().any(|f| lower.contains(f))) {
+                    return None;
+                }
+                let safe = tool.name.to_string();
+                Some(Self {
+                    set: set.clone(),
+                    name: set.name_map.get(&safe).cloned().unwrap_or(safe),
+                    key: schema_key(tool, keys)?,
+                    calendar_key: schema_key(tool, CALENDAR_ID_KEYS),
+                })
+            })
+        })
+    }
+
+    async fn call(&self, value: &str, args: &serde_json::Map<String, Value>) -> Result<Value, String> {
+        let mut arguments = serde_json::Map::new();
+        arguments.insert(self.key.to_string(), json!(value));
+        if let Some(key) = self.calendar_key {
+            let calendar = args.get("calendar_id").and_then(|v| v.as_str()).unwrap_or("primary");
+            arguments.insert(key.to_string(), json!(calendar));
+        }
+        invoke(&self.set, &self.name, arguments).await
+    }
+}
+
+/// Call a connected tool by its original name; error results become `Err`.
+async fn invoke(set: &McpToolSet, name: &str, arguments: serde_json::Map<String, Value>) -> Result<Value, String> {
+    let result = set
+        .peer
+        .call_tool(CallToolRequestParam {
+            name: Cow::Owned(name.to_string()),
+            arguments: Some(arguments),
+            task: None,
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    let value = serde_json::to_value(&result).unwrap_or(Value::Null);
+    if result.is_error == Some(true) {
+        return Err(format!("{} failed: {}", name, value));
+    }
+    Ok(value)
+}
+
+/// Gmail's get-attachment tool, for `.ics` invites attached to emails.
+struct AttachmentTool {
+    set: McpToolSet,
+    name: String,
+    message_key: &'static str,
+    attachment_key: &'static str,
+}
+
+impl AttachmentTool {
+    fn find(tool_sets: &[McpToolSet]) -> Option<Self> {
+        tool_sets.iter().find_map(|set| {
+            set.tools.iter().find_map(|tool| {
+                let lower = tool.name.to_ascii_lowercase();
+                if !(lower.contains("gmail") && lower.contains("attachment")) {
+                    return None;
+                }
+                let safe = tool.name.to_string();
+                Some(Self {
+                    set: set.clone(),
+                    name: set.name_map.get(&safe).cloned().unwrap_or(safe),
+                    message_key: schema_key(tool, MESSAGE_ID_KEYS)?,
+                    attachment_key: schema_key(tool, ATTACHMENT_ID_KEYS)?,
+                })
+            })
+        })
+    }
+
+    /// The attachment's text: base64url `data` in the result, or a file the
+    /// tool downloaded and reports by path.
+    async fn fetch_text(&self, message_id: &str, attachment_id: &str, file_name: &str) -> Result<String, String> {
+        let mut arguments = serde_json::Map::new();
+        arguments.insert(self.message_key.to_string(), json!(message_id));
+        arguments.insert(self.attachment_key.to_string(), json!(attachment_id));
+        arguments.insert("file_name".to_string(), json!(if file_name.is_empty() { "invite.ics" } else { file_name }));
+        let value = invoke(&self.set, &self.name, arguments).await?;
+        let mut data = None;
+        let mut path = None;
+        crate::widgets::walk(&value, 0, &mut |obj| {
+            if data.is_none() {
+                data = obj.get("data").and_then(|d| d.as_str()).and_then(crate::gmail::decode_base64url);
+            }
+            if path.is_none() {
+                path = ["file_path", "local_path", "path", "file"]
+                    .iter()
+                    .find_map(|k| obj.get(*k).and_then(|p| p.as_str()))
+                    .map(str::to_string);
+            }
+        });
+        if let Some(bytes) = data {
+            return Ok(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        match path {
+            Some(path) => tokio::fs::read_to_string(&path).await.map_err(|e| format!("Could not read {}: {}", path, e)),
+            None => Err("The attachment tool returned no data".to_string()),
+        }
+    }
+}
+
+/// The connected list-calendars and list-events tools behind `aggregate_agenda`.
+struct AgendaTools {
+    set: McpToolSet,
+    list_calendars: String,
+    list_events: String,
+    calendar_key: &'static str,
+    min_key: &'static str,
+    max_key: &'static str,
+    single_events: bool,
+}
+
+impl AgendaTools {
+    fn find(tool_sets: &[McpToolSet]) -> Option<Self> {
+        tool_sets.iter().find_map(|set| {
+            let original = |tool: &rmcp::model::Tool| {
+                let safe = tool.name.to_string();
+                set.name_map.get(&safe).cloned().unwrap_or(safe)
+            };
+            let calendars = set.tools.iter().find(|t| {
+                let lower = t.name.to_ascii_lowercase();
+                lower.contains("calendar") && (lower.contains("list_calendars") || lower.contains("calendar_list"))
+            })?;
+            let (events, calendar_key, min_key, max_key) = set.tools.iter().filter(|t| is_list_events(&t.name)).find_map(|t| {
+                Some((t, schema_key(t, CALENDAR_ID_KEYS)?, schema_key(t, TIME_MIN_KEYS)?, schema_key(t, TIME_MAX_KEYS)?))
+            })?;
+            Some(Self {
+                set: set.clone(),
+                list_calendars: original(calendars),
+                list_events: original(events),
+                calendar_key,
+                min_key,
+                max_key,
+                single_events: schema_key(events, &["singleEvents", "single_events"]).is_some(),
+            })
+        })
+    }
+
+    /// `(id, name)` of every calendar in the user's list, hidden ones excluded.
+    async fn calendars(&self) -> Result<Vec<(String, String)>, String> {
+        let value = invoke(&self.set, &self.list_calendars, serde_json::Map::new()).await?;
+        let mut calendars: Vec<(String, String)> = Vec::new();
+        crate::widgets::walk(&value, 0, &mut |obj| {
+            let Some(id) = obj.get("id").and_then(|i| i.as_str()) else { return };
+            if !(obj.contains_key("accessRole") || obj.contains_key("timeZone"))
+                || obj.get("hidden").and_then(|h| h.as_bool()) == Some(true)
+                || calendars.iter().any(|(c, _)| c == id)
+            {
+                return;
+            }
+            let name = obj
+                .get("summaryOverride")
+                .or_else(|| obj.get("summary"))
+                .and_then(|n| n.as_str())
+                .unwrap_or(id);
+            calendars.push((id.to_string(), name.to_string()));
+        });
+        Ok(calendars)
+    }
+
+    /// Events of every calendar in `window`, merged and sorted by start.
+    /// Events that several calendars share (invites) are listed once with
+    /// all their calendars. Calendars that fail are reported, not fatal.
+    async fn agenda(&self, window: (DateTime<FixedOffset>, DateTime<FixedOffset>), only: &[String]) -> Result<Value, String> {
+        let mut calendars = self.calendars().await?;
+        if !only.is_empty() {
+            calendars.retain(|(id, name)| only.iter().any(|o| o == id || o.eq_ignore_ascii_case(name)));
+        }
+        if calendars.is_empty() {
+            return Err("No matching calendars found".to_string());
+        }
+
+        let mut events: Vec<Value> = Vec::new();
+        let mut errors = Vec::new();
+        for (id, name) in &calendars {
+            let mut arguments = serde_json::Map::new();
+            arguments.insert(self.calendar_key.to_string(), json!(id));
+            arguments.insert(self.min_key.to_string(), json!(window.0.to_rfc3339()));
+            arguments.insert(self.max_key.to_string(), json!(window.1.to_rfc3339()));
+            if self.single_events {
+                arguments.insert("singleEvents".to_string(), json!(true));
+            }
+            let value = match invoke(&self.set, &self.list_events, arguments).await {
+                Ok(value) => value,
+                Err(e) => {
+                    errors.push(json!({"calendar": name, "error": e}));
+                    continue;
+                }
+            };
+            crate::widgets::walk(&value, 0, &mut |obj| {
+                let Some(mut event) = crate::widgets::calendar_event(obj) else { return };
+                if let Some(existing) = events
+                    .iter_mut()
+                    .find(|e| e["title"] == event["title"] && e["start"] == event["start"])
+                {
+                    if let Some(list) = existing["calendars"].as_array_mut()
+                        && !list.iter().any(|c| c == name.as_str())
+                    {
+                        list.push(json!(name));
+                    }
+                    return;
+                }
+                event["calendars"] = json!([name]);
+                events.push(event);
+            });
+        }
+
+        // All-day events (a bare date) sort before the timed events of that day.
+        let sort_key = |event: &Value| -> i64 {
+            let start = event["start"].as_str().unwrap_or("");
+            parse_time(&event["start"])
+                .map(|t| t.timestamp())
+                .or_else(|| {
+                    chrono::NaiveDate::parse_from_str(start, "%Y-%m-%d")
+                        .ok()
+                        .and_then(|d| d.and_hms_opt(0, 0, 0))
+                        .and_then(|d| Local.from_local_datetime(&d).earliest())
+                        .map(|t| t.timestamp() - 1)
+                })
+                .unwrap_or(i64::MAX)
+        };
+        events.sort_by_key(sort_key);
+        Ok(json!({
+            "window": {"start": window.0.to_rfc3339(), "end": window.1.to_rfc3339()},
+            "calendars": calendars.iter().map(|(_, name)| name).collect::<Vec<_>>(),
+            "event_count": events.len(),
+            "events": events,
+            "errors": errors,
+        }))
+    }
+}
+
+/// The first Calendar event object anywhere in a tool result.
+fn first_event(value: &Value) -> Option<serde_json::Map<String, Value>> {
+    let mut event = None;
+    crate::widgets::walk(value, 0, &mut |obj| {
+        if event.is_none() && crate::widgets::calendar_event(obj).is_some() {
+            event = Some(obj.clone());
+        }
+    });
+    event
+}
+
+/// Everything about one event, untruncated: attendees with their responses
+/// and the conferencing entry points.
+fn full_event(obj: &serde_json::Map<String, Value>) -> Value {
+    let mut event = crate::widgets::calendar_event(obj).unwrap_or_default();
+    let attendees: Vec<Value> = obj
+        .get("attendees")
+        .and_then(|a| a.as_array())
+        .into_iter()
+        .flatten()
+        .map(|a| {
+            json!({
+                "email": a["email"],
+                "name": a["displayName"],
+                "response_status": a["responseStatus"],
+                "organizer": a["organizer"].as_bool().unwrap_or(false),
+                "optional": a["optional"].as_bool().unwrap_or(false),
+            })
+        })
+        .collect();
+    let entry_points: Vec<Value> = obj
+        .get("conferenceData")
+        .and_then(|c| c["entryPoints"].as_array())
+        .into_iter()
+        .flatten()
+        .map(|e| json!({"type": e["entryPointType"], "uri": e["uri"], "label": e["label"], "pin": e["pin"]}))
+        .collect();
+    event["id"] = obj.get("id").cloned().unwrap_or(Value::Null);
+    event["description"] = obj.get("description").cloned().unwrap_or(Value::Null);
+    event["attendees"] = json!(attendees);
+    event["organizer"] = obj.get("organizer").map(|o| o["email"].clone()).unwrap_or(Value::Null);
+    event["conference"] = json!({
+        "link": crate::widgets::conference_link(obj),
+        "entry_points": entry_points,
+    });
+    event["recurrence"] = obj.get("recurrence").cloned().unwrap_or(Value::Null);
+    event["status"] = obj.get("status").cloned().unwrap_or(Value::Null);
+    event
+}
+
+fn start_of_today() -> DateTime<FixedOffset> {
+    let midnight = Local::now().date_naive().and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(Local::now)
+        .fixed_offset()
+}
+
+/// A window bound: RFC 3339, a local date-time, or a date (its midnight).
+fn window_bound(text: &str) -> Result<Option<DateTime<FixedOffset>>, String> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    if let Some(time) = parse_time(&json!(text)) {
+        return Ok(Some(time));
+    }
+    chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|d| Local.from_local_datetime(&d).earliest())
+        .map(|t| Some(t.fixed_offset()))
+        .ok_or_else(|| format!("'{}' is not a date or time", text))
+}
+
+/// Calendar tools served per turn over the connected Calendar server:
+/// `calendar_quick_add_event` (Google parses the phrase with `quickAdd`, in
+/// the calendar's own time zone, instead of the model writing RFC 3339) and
+/// `calendar_get_event` (one event in full), `aggregate_agenda` (every
+/// calendar's events in one list) and `.ics` export/import.
+struct CalendarServer {
+    quick_add: Option<UpstreamTool>,
+    get_event: Option<UpstreamTool>,
+    agenda: Option<AgendaTools>,
+    attachment: Option<AttachmentTool>,
+    /// `.ics` export/import, offered whenever a Calendar server is connected.
+    ics: bool,
+}
+
+fn tool(name: &'static str, description: &'static str, schema: Value) -> rmcp::model::Tool {
+    rmcp::model::Tool::new(name, description, Arc::new(schema.as_object().cloned().unwrap_or_default()))
+}
+
+impl CalendarServer {
+    fn definitions(&self) -> Vec<rmcp::model::Tool> {
+        let mut tools = Vec::new();
+        if self.quick_add.is_some() {
+            tools.push(tool(
+                QUICK_ADD,
+                "Create a Google Calendar event from a short phrase with a time, parsed by Google \
+                 (\"Dentist Tuesday 3pm\", \"Standup every weekday 9:30\"). Prefer it over exact \
+                 start/end times for simple events. Returns the event as created; tell the user its \
+                 date and time.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "text": {"type": "string", "description": "The event in plain words, e.g. \"Lunch with Sam Friday noon at Cafe Rio\""},
+                        "calendar_id": {"type": "string", "description": "Calendar to add to; default \"primary\""}
+                    },
+                    "required": ["text"]
+                }),
+            ));
+        }
+        if self.get_event.is_some() {
+            tools.push(tool(
+                GET_EVENT,
+                "Get one Google Calendar event in full: the whole description, attendees with \
+                 their responses, organizer, video-call links and dial-in numbers, recurrence.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "event_id": {"type": "string"},
+                        "calendar_id": {"type": "string", "description": "Default \"primary\""}
+                    },
+                    "required": ["event_id"]
+                }),
+            ));
+        }
+        if self.agenda.is_some() {
+            tools.push(tool(
+                AGGREGATE_AGENDA,
+                "List the events of ALL the user's calendars (primary, secondary and shared) in a \
+                 time window, merged and sorted by start, each tagged with its calendars. Use it for \
+                 \"what does my day/week look like?\". Defaults to today.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "time_min": {"type": "string", "description": "Window start, RFC 3339 or YYYY-MM-DD; default today 00:00"},
+                        "time_max": {"type": "string", "description": "Window end, RFC 3339 or YYYY-MM-DD (exclusive); default the end of time_min's day"},
+                        "calendars": {"type": "array", "items": {"type": "string"}, "description": "Only these calendars (names or IDs); default all"}
+                    }
+                }),
+            ));
+        }
+        if self.ics {
+            tools.push(tool(
+                EXPORT_ICS,
+                "Export calendar events as an .ics file the user can download or share. Pass \
+                 event_ids (fetched in full) and/or events already at hand. Returns the file path.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "event_ids": {"type": "array", "items": {"type": "string"}},
+                        "calendar_id": {"type": "string", "description": "Calendar of event_ids; default \"primary\""},
+                        "events": {"type": "array", "items": {"type": "object"}, "description": "Events as {title, start, end, all_day, location, description, attendees}"},
+                        "file_name": {"type": "string", "description": "Default \"events.ics\""}
+                    }
+                }),
+            ));
+            tools.push(tool(
+                PARSE_ICS,
+                "Read a calendar invite (.ics) into proposed events: from an email attachment \
+                 (message_id + attachment_id), a file path or raw text. Show the user the proposal \
+                 and, once they agree, create it with the calendar create-event tool.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "message_id": {"type": "string"},
+                        "attachment_id": {"type": "string"},
+                        "file_name": {"type": "string", "description": "Attachment file name"},
+                        "path": {"type": "string", "description": "Local .ics file"},
+                        "ics": {"type": "string", "description": "Raw iCalendar text"}
+                    }
+                }),
+            ));
+        }
+        tools
+    }
+
+    async fn export_ics(&self, args: &serde_json::Map<String, Value>) -> Result<String, String> {
+        let mut events: Vec<Value> = args.get("events").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+        let ids: Vec<&str> = args
+            .get("event_ids")
+            .and_then(|e| e.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|id| id.as_str())
+            .collect();
+        if !ids.is_empty() {
+            let get_event = self.get_event.as_ref().ok_or("No get-event tool is connected; pass the events instead")?;
+            for id in ids {
+                let value = get_event.call(id, args).await?;
+                let event = first_event(&value).ok_or_else(|| format!("Event {} not found", id))?;
+                let mut event = full_event(&event);
+                event["id"] = json!(id);
+                events.push(event);
+            }
+        }
+        if events.is_empty() {
+            return Err("Pass event_ids or events to export".to_string());
+        }
+
+        let file_name = args
+            .get("file_name")
+            .and_then(|f| f.as_str())
+            .map(|f| f.trim().replace(['/', '\\'], "_"))
+            .filter(|f| !f.is_empty())
+            .unwrap_or_else(|| "events.ics".to_string());
+        let file_name = if file_name.to_ascii_lowercase().ends_with(".ics") { file_name } else { format!("{}.ics", file_name) };
+        let dir = crate::ics::exports_dir();
+        tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+        let path = dir.join(format!("{}-{}", chrono::Local::now().format("%Y%m%d%H%M%S"), file_name));
+        tokio::fs::write(&path, crate::ics::to_ics(&events)).await.map_err(|e| e.to_string())?;
+        println!("📤 Exported {} event(s) to {}", events.len(), path.display());
+        Ok(json!({"path": path, "events": events.len()}).to_string())
+    }
+
+    async fn parse_ics(&self, args: &serde_json::Map<String, Value>) -> Result<String, String> {
+        let arg = |key: &str| args.get(key).and_then(|v| v.as_str()).unwrap_or("").trim().to_string();
+        let text = if !arg("ics").is_empty() {
+            arg("ics")
+        } else if !arg("path").is_empty() {
+            let path = crate::watcher::expand_home(&arg("path"));
+            tokio::fs::read_to_string(&path).await.map_err(|e| format!("Could not read {}: {}", path.display(), e))?
+        } else if !arg("message_id").is_empty() && !arg("attachment_id").is_empty() {
+            let attachment = self.attachment.as_ref().ok_or("No Gmail attachment tool is connected")?;
+            attachment.fetch_text(&arg("message_id"), &arg("attachment_id"), &arg("file_name")).await?
+        } else {
+            return Err("Pass message_id + attachment_id, path or ics".to_string());
+        };
+        let mut parsed = crate::ics::parse_ics(&text)?;
+        parsed["note"] = json!(
+            "Proposed only; nothing was added. Confirm with the user, then create it (keep time_zone when given)."
+        );
+        Ok(parsed.to_string())
+    }
+
+    async fn call(&self, name: &str, args: &serde_json::Map<String, Value>) -> Result<String, String> {
+        let arg = |key: &str| args.get(key).and_then(|v| v.as_str()).unwrap_or("").trim().to_string();
+        match name {
+            EXPORT_ICS if self.ics => return self.export_ics(args).await,
+            PARSE_ICS if self.ics => return self.parse_ics(args).await,
+            _ => {}
+        }
+        if let (AGGREGATE_AGENDA, Some(agenda)) = (name, &self.agenda) {
+            let start = window_bound(&arg("time_min"))?.unwrap_or_else(start_of_today);
+            let end = window_bound(&arg("time_max"))?.unwrap_or(start + Duration::days(1));
+            if end <= start {
+                return Err("`time_max` must be after `time_min`".to_string());
+            }
+            let only: Vec<String> = args
+                .get("calendars")
+                .and_then(|c| c.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|c| c.as_str().map(str::to_string))
+                .collect();
+            return agenda.agenda((start, end), &only).await.map(|v| v.to_string());
+        }
+        match (name, &self.quick_add, &self.get_event) {
+            (QUICK_ADD, Some(quick_add), _) => {
+                let text = arg("text");
+                if text.is_empty() {
+                    return Err("`text` is required".to_string());
+                }
+                let value = quick_add.call(&text, args).await?;
+                // Echo the parsed event so a misread phrase is obvious.
+                Ok(match first_event(&value).and_then(|e| crate::widgets::calendar_event(&e)) {
+                    Some(event) => json!({"created": true, "event": event}).to_string(),
+                    None => value.to_string(),
+                })
+            }
+            (GET_EVENT, _, Some(get_event)) => {
+                let id = arg("event_id");
+                if id.is_empty() {
+                    return Err("`event_id` is required".to_string());
+                }
+                let value = get_event.call(&id, args).await?;
+                Ok(match first_event(&value) {
+                    Some(event) => full_event(&event).to_string(),
+                    None => value.to_string(),
+                })
+            }
+            _ => Err(format!("Unknown tool {}", name)),
+        }
+    }
+}
+
+impl ServerHandler for CalendarServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(self.definitions()))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        Ok(match self.call(&request.name, &request.arguments.unwrap_or_default()).await {
+            Ok(text) => CallToolResult::success(vec![Content::text(text)]),
+            Err(e) => CallToolResult::error(vec![Content::text(e)]),
+        })
+    }
+}
+
+/// Serve the Calendar tools the connected Calendar server can back, for this
+/// turn. The returned connection must stay alive for the turn.
+pub async fn connect(tool_sets: &[McpToolSet]) -> Option<McpConnection> {
+    let server = CalendarServer {
+        quick_add: UpstreamTool::find(tool_sets, &["quick_add"], QUICK_ADD_TEXT_KEYS),
+        get_event: UpstreamTool::find(tool_sets, &["events_get", "get_event"], EVENT_ID_KEYS),
+        agenda: AgendaTools::find(tool_sets),
+        attachment: AttachmentTool::find(tool_sets),
+        ics: tool_sets.iter().any(|set| set.tools.iter().any(|t| is_create_event(&t.name))),
+    };
+    if server.quick_add.is_none() && server.get_event.is_none() && server.agenda.is_none() && !server.ics {
+        return None;
+    }
+    match crate::mcp_proxy::connect_in_process(server).await {
+        Ok(conn) => Some(conn),
+        Err(e) => {
+            println!("⚠️ Calendar tools unavailable this turn: {}", e);
+            None
+        }
+    }
+}
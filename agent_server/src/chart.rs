@@ -0,0 +1,103 @@
+use plotters::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const WIDTH: u32 = 960;
+const HEIGHT: u32 = 560;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ChartSeries {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+/// Directory rendered charts are written to (`~/.ronge/charts`).
+pub fn charts_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("charts")
+}
+
+/// Render a bar or line chart to a PNG at `path`. One x position per label;
+/// each series contributes one bar (grouped) or one line.
+pub fn draw_chart(
+    path: &Path,
+    title: &str,
+    kind: &str,
+    labels: &[String],
+    series: &[ChartSeries],
+    y_label: Option<&str>,
+) -> Result<(), String> {
+    let n = labels
+        .len()
+        .max(series.iter().map(|s| s.values.len()).max().unwrap_or(0));
+    if n == 0 {
+        return Err("Chart has no data points.".to_string());
+    }
+    let values = series.iter().flat_map(|s| s.values.iter().copied());
+    let y_max = values.clone().fold(0f64, f64::max);
+    let y_min = values.fold(0f64, f64::min);
+    let pad = ((y_max - y_min) * 0.1).max(1.0);
+    let y_low = if y_min < 0.0 { y_min - pad } else { 0.0 };
+
+    let root = BitMapBackend::new(path, (WIDTH, HEIGHT)).into_drawing_area();
+    root.fill(&WHITE).map_err(|e| e.to_string())?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(70)
+        .build_cartesian_2d(-0.5f64..(n as f64 - 0.5), y_low..(y_max + pad))
+        .map_err(|e| e.to_string())?;
+
+    let x_label = |x: &f64| {
+        let i = x.round();
+        if (x - i).abs() < 1e-6 && i >= 0.0 {
+            labels.get(i as usize).cloned().unwrap_or_default()
+        } else {
+            String::new()
+        }
+    };
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(n)
+        .x_label_formatter(&x_label)
+        .y_desc(y_label.unwrap_or(""))
+        .draw()
+        .map_err(|e| e.to_string())?;
+
+    let group_width = 0.8 / series.len().max(1) as f64;
+    for (k, s) in series.iter().enumerate() {
+        let color = Palette99::pick(k).mix(1.0);
+        let drawn = if kind == "line" {
+            chart.draw_series(LineSeries::new(
+                s.values.iter().enumerate().map(|(i, v)| (i as f64, *v)),
+                color.stroke_width(3),
+            ))
+        } else {
+            let x_offset = -0.4 + k as f64 * group_width;
+            chart.draw_series(s.values.iter().enumerate().map(|(i, v)| {
+                let x0 = i as f64 + x_offset;
+                Rectangle::new([(x0, 0.0), (x0 + group_width, *v)], color.filled())
+            }))
+        };
+        drawn
+            .map_err(|e| e.to_string())?
+            .label(s.name.clone())
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 12, y + 5)], color.filled()));
+    }
+
+    if series.len() > 1 {
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.85))
+            .border_style(BLACK)
+            .draw()
+            .map_err(|e| e.to_string())?;
+    }
+
+    root.present().map_err(|e| e.to_string())
+}
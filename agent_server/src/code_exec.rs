@@ -0,0 +1,340 @@
+use crate::tools::{ToolError, ToolEventSender};
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Default and maximum run time of a snippet (`RONGE_CODE_EXEC_TIMEOUT_SECS`
+/// sets the default).
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+const MAX_TIMEOUT_SECS: u64 = 60;
+/// Longest snippet accepted.
+const MAX_CODE_BYTES: usize = 64 * 1024;
+/// stdout/stderr handed back to the model, each.
+const MAX_OUTPUT_BYTES: usize = 16 * 1024;
+/// Largest file a snippet may write, and Python's address-space cap.
+const MAX_FILE_BYTES: u64 = 16 * 1024 * 1024;
+const PYTHON_MEMORY_BYTES: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    Python,
+    JavaScript,
+}
+
+impl Language {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "python" | "python3" | "py" => Some(Self::Python),
+            "javascript" | "js" | "node" => Some(Self::JavaScript),
+            _ => None,
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::Python => "main.py",
+            Self::JavaScript => "main.js",
+        }
+    }
+
+    /// Interpreter and flags; Python runs isolated from user site-packages
+    /// and `PYTHON*` variables.
+    fn command(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            Self::Python => ("python3", &["-I"]),
+            Self::JavaScript => ("node", &[]),
+        }
+    }
+}
+
+/// Network access for snippets, off unless `RONGE_CODE_EXEC_NETWORK=1`.
+fn network_allowed() -> bool {
+    std::env::var("RONGE_CODE_EXEC_NETWORK").is_ok_and(|v| v == "1" || v == "true")
+}
+
+fn default_timeout() -> u64 {
+    std::env::var("RONGE_CODE_EXEC_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(DEFAULT_TIMEOUT_SECS)
+        .min(MAX_TIMEOUT_SECS)
+}
+
+/// What a run can be held to here: (file access confined to its directory
+/// and system paths, network cut off).
+#[cfg(target_os = "macos")]
+fn isolation(network: bool) -> (bool, bool) {
+    (true, !network)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn isolation(network: bool) -> (bool, bool) {
+    (false, !network && unshare().is_some())
+}
+
+/// `unshare`, on Linux when it is installed.
+#[cfg(not(target_os = "macos"))]
+fn unshare() -> Option<String> {
+    let unshare = crate::logic::resolve_command("unshare", &crate::logic::build_expanded_path());
+    (cfg!(target_os = "linux") && Path::new(&unshare).is_absolute()).then_some(unshare)
+}
+
+/// Trees a macOS snippet may read besides its own directory: the system,
+/// developer tools and package managers. Home directories, other volumes
+/// and other apps' temp files stay out of reach.
+#[cfg(target_os = "macos")]
+const READABLE_PATHS: &[&str] = &[
+    "/System",
+    "/Library",
+    "/Applications",
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/opt",
+    "/dev",
+    "/private/etc",
+    "/private/var/db/timezone",
+    "/private/var/select",
+];
+
+/// The install tree of an interpreter outside the system paths (nvm, pyenv,
+/// conda): the parent of its `bin` directory, unless that would open the
+/// whole home directory.
+#[cfg(target_os = "macos")]
+fn interpreter_root(program: &str) -> Option<PathBuf> {
+    let root = Path::new(program).canonicalize().ok()?.parent()?.parent()?.to_path_buf();
+    let home = dirs::home_dir()?;
+    (!home.starts_with(&root)).then_some(root)
+}
+
+/// On macOS, run under `sandbox-exec`: reads only from the snippet's temp
+/// directory, the interpreter and `READABLE_PATHS`, writes only to the temp
+/// directory and, unless allowed, no network at all.
+#[cfg(target_os = "macos")]
+fn sandboxed(dir: &Path, program: &str, args: &[String], network: bool) -> tokio::process::Command {
+    let quoted = |path: &Path| format!("(subpath \"{}\")", path.display().to_string().replace('"', ""));
+    let mut readable: Vec<String> = READABLE_PATHS.iter().map(|p| quoted(Path::new(p))).collect();
+    readable.push(quoted(dir));
+    readable.extend(interpreter_root(program).map(|root| quoted(&root)));
+    let mut profile = format!(
+        "(version 1)(allow default)\
+         (deny file-read-data file-read-xattr)\
+         (allow file-read-data file-read-xattr (literal \"/\") {readable})\
+         (deny file-write*)\
+         (allow file-write* {dir} (literal \"/dev/null\"))",
+        readable = readable.join(" "),
+        dir = quoted(dir),
+    );
+    if !network {
+        profile.push_str("(deny network*)");
+    }
+    let mut cmd = tokio::process::Command::new("sandbox-exec");
+    cmd.arg("-p").arg(profile).arg(program).args(args);
+    cmd
+}
+
+/// On Linux, an unprivileged network namespace (`unshare -rn`) keeps the
+/// snippet offline when `unshare` is installed. File writes are not confined
+/// here, so every run needs the user's approval.
+#[cfg(not(target_os = "macos"))]
+fn sandboxed(_dir: &Path, program: &str, args: &[String], network: bool) -> tokio::process::Command {
+    if !network && let Some(unshare) = unshare() {
+        let mut cmd = tokio::process::Command::new(unshare);
+        cmd.arg("-rn").arg(program).args(args);
+        return cmd;
+    }
+    let mut cmd = tokio::process::Command::new(program);
+    cmd.args(args);
+    cmd
+}
+
+/// An output stream, cut to `MAX_OUTPUT_BYTES`.
+fn capped(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let kept = crate::text::truncate_bytes(&text, MAX_OUTPUT_BYTES);
+    if kept.len() < text.len() {
+        format!("{}\n… [truncated, {} bytes in all]", kept, text.len())
+    } else {
+        kept.to_string()
+    }
+}
+
+/// A fresh working directory for one run, removed afterwards.
+struct RunDir(PathBuf);
+
+impl RunDir {
+    async fn create() -> std::io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("ronge-exec-{}", crate::session::new_session_id()));
+        tokio::fs::create_dir_all(&dir).await?;
+        // Resolve /tmp → /private/tmp and the like, for the sandbox profile.
+        Ok(Self(dir.canonicalize()?))
+    }
+}
+
+impl Drop for RunDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// `execute_code`: run a short Python or JavaScript snippet in a temporary
+/// directory with a timeout and no network, and return what it printed.
+pub struct ExecuteCode {
+    pub tx: ToolEventSender,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExecuteCodeArgs {
+    language: String,
+    code: String,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExecuteCodeOutput {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub timed_out: bool,
+    pub duration_ms: u64,
+}
+
+impl Tool for ExecuteCode {
+    const NAME: &'static str = "execute_code";
+    type Args = ExecuteCodeArgs;
+    type Output = ExecuteCodeOutput;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        // Say only what this machine actually enforces.
+        let (confined, offline) = isolation(network_allowed());
+        let network = if offline { "no network access" } else { "network access" };
+        let approval = if confined {
+            " It can't read or change the user's files."
+        } else {
+            " Each run needs the user's approval first."
+        };
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: format!(
+                "Run a short Python or JavaScript (Node.js) snippet and get its stdout and stderr. \
+                Use it for data wrangling the calculator can't do: parsing CSV or JSON, statistics, date \
+                arithmetic, text processing. Print the results you need. The snippet runs in an empty \
+                temporary directory with {} and only the standard library; put any input data in the \
+                code itself.{}",
+                network, approval
+            ),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "language": { "type": "string", "enum": ["python", "javascript"] },
+                    "code": { "type": "string", "description": "The complete program" },
+                    "timeout_secs": { "type": "integer", "description": "Defaults to 10, at most 60" }
+                },
+                "required": ["language", "code"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let language = Language::parse(&args.language).ok_or_else(|| {
+            ToolError::CommandFailed(format!("Unsupported language '{}' (use python or javascript)", args.language))
+        })?;
+        if args.code.len() > MAX_CODE_BYTES {
+            return Err(ToolError::CommandFailed(format!("The snippet is over {} KB", MAX_CODE_BYTES / 1024)));
+        }
+        let timeout = Duration::from_secs(args.timeout_secs.unwrap_or_else(default_timeout).clamp(1, MAX_TIMEOUT_SECS));
+
+        let (program, flags) = language.command();
+        let path = crate::logic::build_expanded_path();
+        let interpreter = crate::logic::resolve_command(program, &path);
+        if !Path::new(&interpreter).is_absolute() {
+            return Err(ToolError::CommandFailed(format!(
+                "{} is not installed, so {} snippets can't run",
+                program, args.language
+            )));
+        }
+
+        let dir = RunDir::create().await?;
+        let script = dir.0.join(language.file_name());
+        tokio::fs::write(&script, &args.code).await?;
+        let mut argv: Vec<String> = flags.iter().map(|f| f.to_string()).collect();
+        argv.push(script.display().to_string());
+        let network = network_allowed();
+        let mut cmd = sandboxed(&dir.0, &interpreter, &argv, network);
+
+        // Without a sandbox the snippet could read and write anything the user
+        // can, so it runs only once someone approves it; never unattended.
+        let (confined, offline) = isolation(network);
+        if !confined {
+            let request = json!({"language": args.language, "code": args.code, "network": !offline});
+            if !self.tx.confirm_always(Self::NAME, &request).await {
+                return Err(ToolError::CommandFailed(
+                    "The code can't be sandboxed here and the user didn't approve running it.".to_string(),
+                ));
+            }
+        }
+
+        cmd.current_dir(&dir.0).kill_on_drop(true).stdin(std::process::Stdio::null());
+        // Nothing from our environment (API keys, tokens) reaches the snippet.
+        cmd.env_clear();
+        cmd.env("PATH", &path);
+        cmd.env("HOME", &dir.0);
+        cmd.env("TMPDIR", &dir.0);
+        cmd.env("LANG", std::env::var("LANG").unwrap_or_else(|_| "en_US.UTF-8".to_string()));
+        #[cfg(unix)]
+        {
+            let cpu_secs = timeout.as_secs() + 1;
+            let memory = (language == Language::Python).then_some(PYTHON_MEMORY_BYTES);
+            // Runs in the child between fork and exec: only async-signal-safe calls.
+            unsafe {
+                cmd.pre_exec(move || {
+                    let set = |resource, value: u64| {
+                        let limit = libc::rlimit { rlim_cur: value as libc::rlim_t, rlim_max: value as libc::rlim_t };
+                        if libc::setrlimit(resource, &limit) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                        Ok(())
+                    };
+                    set(libc::RLIMIT_CPU, cpu_secs)?;
+                    set(libc::RLIMIT_FSIZE, MAX_FILE_BYTES)?;
+                    if let Some(bytes) = memory {
+                        set(libc::RLIMIT_AS, bytes)?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        let started = Instant::now();
+        let child = cmd.stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped()).spawn()?;
+        let (output, timed_out) = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(output) => (Some(output?), false),
+            // Dropping the future kills the child (`kill_on_drop`).
+            Err(_) => (None, true),
+        };
+        let duration_ms = started.elapsed().as_millis() as u64;
+        println!("🧪 Ran a {} snippet in {} ms{}", args.language, duration_ms, if timed_out { " (timed out)" } else { "" });
+        Ok(match output {
+            Some(output) => ExecuteCodeOutput {
+                exit_code: output.status.code(),
+                stdout: capped(&output.stdout),
+                stderr: capped(&output.stderr),
+                timed_out,
+                duration_ms,
+            },
+            None => ExecuteCodeOutput {
+                exit_code: None,
+                stdout: String::new(),
+                stderr: format!("Stopped after {} s", timeout.as_secs()),
+                timed_out,
+                duration_ms,
+            },
+        })
+    }
+}
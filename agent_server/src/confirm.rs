@@ -0,0 +1,189 @@
+use crate::tools::ToolEventSender;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// A paused tool call is rejected if the user has not answered by then.
+const DECISION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Verbs in a tool name that mark it as changing something or reaching
+/// other people: `GMAIL_SEND_EMAIL`, `GOOGLECALENDAR_DELETE_EVENT`,
+/// `GOOGLESHEETS_BATCH_UPDATE`, `sheets_append_record`, `events_insert`,
+/// `calendar_quick_add_event`, `write_file`, `createEvent`, …
+const WRITE_WORDS: &[&str] = &[
+    // Removing or overwriting.
+    "delete", "remove", "trash", "destroy", "purge", "clear", "overwrite", "replace", "reset",
+    // Creating and changing.
+    "create", "add", "insert", "append", "update", "patch", "put", "set", "edit", "modify", "write",
+    "move", "rename", "copy", "duplicate", "upload", "import", "format", "mark", "label", "archive",
+    "execute",
+    // Reaching other people.
+    "send", "reply", "forward", "post", "share", "invite", "publish", "submit", "respond",
+    // Version control.
+    "commit", "push", "merge", "checkout",
+];
+
+/// `batch` calls write unless they only read (`BATCH_GET`).
+const READ_WORDS: &[&str] = &["get", "read", "list", "search", "fetch", "find"];
+
+/// Outward-facing tools whose names the word list does not catch.
+const DESTRUCTIVE_TOOLS: &[&str] = &["github_assign_issue", "GOOGLESHEETS_SHEET_FROM_JSON"];
+
+/// The lowercase words of a tool name, split at separators and camelCase
+/// humps (`spreadsheets.values.batchUpdate` → spreadsheets, values, batch, update).
+fn name_words(tool_name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut previous_lower = false;
+    for c in tool_name.chars() {
+        let boundary = !c.is_ascii_alphanumeric() || (c.is_ascii_uppercase() && previous_lower);
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if c.is_ascii_alphanumeric() {
+            word.push(c.to_ascii_lowercase());
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Whether a tool call writes or reaches out, so it needs confirmation and
+/// is simulated in dry-run mode.
+pub fn is_destructive(tool_name: &str) -> bool {
+    if DESTRUCTIVE_TOOLS.contains(&tool_name) {
+        return true;
+    }
+    let words = name_words(tool_name);
+    let has = |list: &[&str]| words.iter().any(|w| list.contains(&w.as_str()));
+    has(WRITE_WORDS) || (words.iter().any(|w| w == "batch") && !has(READ_WORDS))
+}
+
+/// Whether a tool only reads (`GMAIL_FETCH_EMAILS`, `sheets_get_values`):
+/// a read verb in its name and nothing that writes, so repeating the call
+/// can't act twice.
+pub fn is_read_only(tool_name: &str) -> bool {
+    let words = name_words(tool_name);
+    words.iter().any(|w| READ_WORDS.contains(&w.as_str())) && !is_destructive(tool_name)
+}
+
+/// Tool calls waiting for a `user_decision`, keyed by confirmation ID.
+///
+/// Shared by every connection so a client that reconnects mid-turn can still
+/// answer a confirmation it received from the buffered frames.
+#[derive(Clone, Default)]
+pub struct Confirmations(Arc<Mutex<HashMap<String, oneshot::Sender<bool>>>>);
+
+impl Confirmations {
+    /// Send a `confirmation` frame for this tool call and wait for the user.
+    /// Returns `false` on rejection, timeout or if the turn's client is gone.
+    pub async fn request(&self, tx: &ToolEventSender, tool_name: &str, args: &Value) -> bool {
+        let id = crate::session::new_session_id();
+        let event = json!({
+            "type": "confirmation",
+            "content": {
+                "id": &id,
+                "toolName": tool_name,
+                "toolArgs": args,
+                "widget": {
+                    "type": "confirmation",
+                    "label": format!("Allow {}?", tool_name),
+                    "subtitle": summarize_args(args),
+                    "action": {"confirm_action": "approve", "cancel_action": "reject"},
+                },
+            }
+        });
+        self.ask(tx, &id, event, tool_name).await
+    }
+
+    /// Send a `plan` frame for a drafted plan (see `planner.rs`) and wait for
+    /// the user to approve or reject it, like a tool confirmation.
+    pub async fn request_plan(&self, tx: &ToolEventSender, plan_id: &str, plan: &Value) -> bool {
+        let event = json!({
+            "type": "plan",
+            "content": {
+                "id": plan_id,
+                "plan": plan,
+                "widget": {
+                    "type": "plan",
+                    "label": "Run this plan?",
+                    "goal": plan["goal"],
+                    "steps": plan["steps"],
+                    "risks": plan["risks"],
+                    "action": {"confirm_action": "approve", "cancel_action": "reject"},
+                },
+            }
+        });
+        self.ask(tx, plan_id, event, "plan").await
+    }
+
+    /// Register `id`, send `event` and wait for the matching `user_decision`.
+    async fn ask(&self, tx: &ToolEventSender, id: &str, event: Value, what: &str) -> bool {
+        let (decision_tx, decision_rx) = oneshot::channel();
+        self.lock().insert(id.to_string(), decision_tx);
+        if tx.send(event).await.is_err() {
+            self.lock().remove(id);
+            return false;
+        }
+
+        println!("⏸️ Waiting for user decision on {} ({})", what, id);
+        let approved = matches!(
+            tokio::time::timeout(DECISION_TIMEOUT, decision_rx).await,
+            Ok(Ok(true))
+        );
+        self.lock().remove(id);
+        approved
+    }
+
+    /// Resume the tool call waiting on `id`. `false` if nothing is waiting.
+    pub fn resolve(&self, id: &str, approved: bool) -> bool {
+        match self.lock().remove(id) {
+            Some(waiter) => waiter.send(approved).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Handle a raw client frame if it is a `user_decision`; returns whether
+    /// it was one. Called by the socket reader so decisions are delivered
+    /// while a turn is still running.
+    pub fn handle_frame(&self, text: &str) -> bool {
+        let Ok(data) = serde_json::from_str::<Value>(text) else {
+            return false;
+        };
+        if data["data_type"] != "user_decision" {
+            return false;
+        }
+        let id = data["id"].as_str().unwrap_or("");
+        let approved = data["approved"].as_bool().unwrap_or(false);
+        if !self.resolve(id, approved) {
+            println!("⚠️ No pending confirmation with id {}", id);
+        }
+        true
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, oneshot::Sender<bool>>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// One-line `key: value` preview of the arguments for the widget subtitle.
+pub fn summarize_args(args: &Value) -> String {
+    let Some(map) = args.as_object() else {
+        return String::new();
+    };
+    map.iter()
+        .map(|(k, v)| {
+            let v = match v {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            format!("{}: {}", k, crate::text::truncate_chars(&v, 60))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,100 @@
+use rig::message::{AssistantContent, Message as RigMessage, UserContent};
+use serde_json::{json, Value};
+
+/// Share of the context window at which `context_usage` sets `warning`.
+const WARNING_RATIO: f64 = 0.8;
+
+/// Context windows by model-name fragment, most specific first.
+const MODEL_LIMITS: &[(&str, u64)] = &[
+    ("gemini-1.5-pro", 2_097_152),
+    ("gemini", 1_048_576),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-5", 400_000),
+    ("gpt-4o", 128_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("claude", 200_000),
+    ("llama3", 128_000),
+    ("qwen", 32_768),
+    ("mistral", 32_768),
+];
+
+/// Context window of `model`, or a conservative default for its provider.
+/// `RONGE_CONTEXT_LIMIT` overrides both (e.g. for an Ollama `num_ctx`).
+pub fn context_limit(provider: &str, model: &str) -> u64 {
+    if let Some(limit) = std::env::var("RONGE_CONTEXT_LIMIT").ok().and_then(|v| v.parse().ok()) {
+        return limit;
+    }
+    let model = model.to_ascii_lowercase();
+    if let Some((_, limit)) = MODEL_LIMITS.iter().find(|(fragment, _)| model.contains(fragment)) {
+        return *limit;
+    }
+    match provider {
+        "gemini" => 1_048_576,
+        "anthropic" => 200_000,
+        "ollama" => 8_192,
+        _ => 128_000,
+    }
+}
+
+/// Rough token count of `text`: tiktoken-style tokenizers average about four
+/// ASCII characters per token (a bit less for Claude's), while CJK and other
+/// non-ASCII characters are close to one token each.
+pub fn estimate_tokens(provider: &str, text: &str) -> u64 {
+    let chars_per_token = if provider == "anthropic" { 3.5 } else { 4.0 };
+    let (ascii, other) = text.chars().fold((0u64, 0u64), |(a, o), c| {
+        if c.is_ascii() { (a + 1, o) } else { (a, o + 1) }
+    });
+    (ascii as f64 / chars_per_token).ceil() as u64 + other
+}
+
+/// Estimated tokens of one history message.
+pub fn message_tokens(provider: &str, message: &RigMessage) -> u64 {
+    // Per-message overhead for role markers.
+    let mut tokens = 4;
+    if let RigMessage::User { content } = message {
+        for c in content.iter() {
+            if let UserContent::Text(t) = c {
+                tokens += estimate_tokens(provider, &t.text);
+            }
+        }
+    } else if let RigMessage::Assistant { content, .. } = message {
+        for c in content.iter() {
+            if let AssistantContent::Text(t) = c {
+                tokens += estimate_tokens(provider, &t.text);
+            }
+        }
+    }
+    tokens
+}
+
+/// Estimated tokens the history adds to every request.
+pub fn history_tokens(provider: &str, history: &[RigMessage]) -> u64 {
+    history.iter().map(|message| message_tokens(provider, message)).sum()
+}
+
+/// Tokens the history may take before its oldest turns are summarized:
+/// `RONGE_HISTORY_TOKEN_BUDGET`, else half the context window, leaving the
+/// rest for the system prompt, tool definitions, tool results and the answer.
+pub fn history_budget(provider: &str, model: &str) -> u64 {
+    if let Some(budget) = std::env::var("RONGE_HISTORY_TOKEN_BUDGET").ok().and_then(|v| v.trim().parse().ok()) {
+        return budget;
+    }
+    context_limit(provider, model) / 2
+}
+
+/// The `context_usage` frame sent after each turn.
+pub fn usage_event(provider: &str, model: &str, history: &[RigMessage]) -> Value {
+    let tokens = history_tokens(provider, history);
+    let limit = context_limit(provider, model);
+    let ratio = tokens as f64 / limit as f64;
+    json!({
+        "type": "context_usage",
+        "content": {
+            "tokens": tokens,
+            "limit": limit,
+            "ratio": (ratio * 1000.0).round() / 1000.0,
+            "warning": ratio >= WARNING_RATIO,
+        }
+    })
+}
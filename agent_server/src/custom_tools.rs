@@ -0,0 +1,303 @@
+use crate::state::SharedState;
+use rmcp::{
+    ServerHandler,
+    model::{CallToolRequestParam, CallToolResult, Content, ErrorData, JsonObject, ListToolsResult, PaginatedRequestParam},
+    service::{RequestContext, RoleServer},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// `~/.ronge/tools.toml`:
+///
+/// ```toml
+/// [[tool]]
+/// name = "add_todo"
+/// description = "Add an item to my todo list"
+/// command = "~/bin/todo add {text}"
+/// [tool.parameters]
+/// type = "object"
+/// properties = { text = { type = "string", description = "The todo item" } }
+/// required = ["text"]
+///
+/// [[tool]]
+/// name = "weather"
+/// description = "Current weather for a city"
+/// parameters = { type = "object", properties = { city = { type = "string" } } }
+/// [tool.http]
+/// url = "https://wttr.in/{city}?format=3"
+/// ```
+#[derive(Debug, Deserialize, Default)]
+struct ToolsFile {
+    #[serde(default)]
+    tool: Vec<CustomTool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomTool {
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the arguments. Defaults to an object with no properties.
+    #[serde(default)]
+    pub parameters: Option<Value>,
+    /// Shell command template; `{arg}` placeholders are filled from the call.
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub http: Option<HttpTemplate>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpTemplate {
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// `{arg}` placeholders are URL-encoded.
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Body template. Without one, non-GET requests send the arguments as JSON.
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+pub fn default_tools_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("tools.toml")
+}
+
+/// Parse the tools file, skipping (and logging) invalid entries.
+pub fn load_tools() -> Vec<CustomTool> {
+    let path = default_tools_path();
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+    let file: ToolsFile = match toml::from_str(&text) {
+        Ok(f) => f,
+        Err(e) => {
+            println!("❌ Failed to parse {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    file.tool
+        .into_iter()
+        .filter(|t| match (&t.command, &t.http) {
+            (Some(_), None) | (None, Some(_)) => true,
+            _ => {
+                println!("⚠️ Custom tool '{}' needs exactly one of `command` or `http`; skipped", t.name);
+                false
+            }
+        })
+        .filter(|t| {
+            let ok = t.parameters.as_ref().is_none_or(Value::is_object);
+            if !ok {
+                println!("⚠️ Custom tool '{}' has a non-object `parameters` schema; skipped", t.name);
+            }
+            ok
+        })
+        .collect()
+}
+
+/// Load `~/.ronge/tools.toml` and expose its tools to the agent through an
+/// in-process MCP server, so they get the same events, confirmation and
+/// sanitization as any other MCP tool.
+pub async fn start(state: SharedState) {
+    let tools = load_tools();
+    if tools.is_empty() {
+        return;
+    }
+    let handler = CustomToolServer {
+        tools: Arc::new(tools.into_iter().map(|t| (t.name.clone(), t)).collect()),
+    };
+    match crate::mcp_proxy::connect_in_process(handler).await {
+        Ok(conn) => {
+            println!("✅ Loaded {} custom tool(s) from {}", conn.tools.len(), default_tools_path().display());
+            state.lock().await.custom_tools = Some(conn);
+        }
+        Err(e) => println!("❌ Failed to start custom tools: {}", e),
+    }
+}
+
+struct CustomToolServer {
+    tools: Arc<HashMap<String, CustomTool>>,
+}
+
+impl ServerHandler for CustomToolServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        let tools = self
+            .tools
+            .values()
+            .map(|t| {
+                let schema: JsonObject = t
+                    .parameters
+                    .as_ref()
+                    .and_then(|p| p.as_object().cloned())
+                    .unwrap_or_else(|| {
+                        serde_json::json!({"type": "object", "properties": {}})
+                            .as_object()
+                            .cloned()
+                            .unwrap_or_default()
+                    });
+                rmcp::model::Tool::new(t.name.clone(), t.description.clone(), Arc::new(schema))
+            })
+            .collect();
+        Ok(ListToolsResult::with_all_items(tools))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let Some(tool) = self.tools.get(request.name.as_ref()) else {
+            return Err(ErrorData::invalid_params(format!("Unknown tool {}", request.name), None));
+        };
+        let args = request.arguments.unwrap_or_default();
+        let timeout = Duration::from_secs(tool.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+
+        let run = async {
+            match (&tool.command, &tool.http) {
+                (Some(command), _) => run_command(command, &args).await,
+                (None, Some(http)) => run_http(http, &args).await,
+                (None, None) => Err("Tool has no command or http template".to_string()),
+            }
+        };
+        let outcome = match tokio::time::timeout(timeout, run).await {
+            Ok(outcome) => outcome,
+            Err(_) => Err(format!("Timed out after {} s", timeout.as_secs())),
+        };
+        Ok(match outcome {
+            Ok(output) => CallToolResult::success(vec![Content::text(output)]),
+            Err(e) => {
+                println!("❌ Custom tool '{}' failed: {}", tool.name, e);
+                CallToolResult::error(vec![Content::text(e)])
+            }
+        })
+    }
+}
+
+/// Plain-text form of an argument value for substitution.
+pub(crate) fn arg_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Replace every `{name}` in `template` with `fill(name)` for each argument.
+fn fill_template(template: &str, args: &JsonObject, fill: impl Fn(&str, &Value) -> String) -> String {
+    args.iter().fold(template.to_string(), |out, (name, value)| {
+        out.replace(&format!("{{{}}}", name), &fill(name, value))
+    })
+}
+
+fn env_name(arg: &str) -> String {
+    let name: String = arg
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    format!("RONGE_ARG_{}", name)
+}
+
+/// Run the command through the platform shell. Placeholders become
+/// environment variable references rather than being spliced into the
+/// command line, so argument values can never inject shell syntax.
+async fn run_command(template: &str, args: &JsonObject) -> Result<String, String> {
+    let expanded = match template.strip_prefix("~/") {
+        Some(rest) => format!("{}/{}", dirs::home_dir().unwrap_or_default().display(), rest),
+        None => template.to_string(),
+    };
+
+    #[cfg(windows)]
+    let mut cmd = {
+        let line = fill_template(&expanded, args, |name, _| format!("$env:{}", env_name(name)));
+        let mut cmd = tokio::process::Command::new("powershell");
+        cmd.args(["-NoProfile", "-Command"]).arg(line);
+        cmd
+    };
+    #[cfg(not(windows))]
+    let mut cmd = {
+        let line = fill_template(&expanded, args, |name, _| format!("\"${}\"", env_name(name)));
+        let mut cmd = tokio::process::Command::new("sh");
+        cmd.arg("-c").arg(line);
+        cmd
+    };
+
+    for (name, value) in args {
+        cmd.env(env_name(name), arg_text(value));
+    }
+    cmd.kill_on_drop(true);
+    let output = cmd.output().await.map_err(|e| e.to_string())?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() {
+        Ok(if stdout.is_empty() { "Done.".to_string() } else { stdout })
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        Err(format!("Command exited with {}: {}", output.status, if stderr.is_empty() { stdout } else { stderr }))
+    }
+}
+
+async fn run_http(http: &HttpTemplate, args: &JsonObject) -> Result<String, String> {
+    let url = fill_template(&http.url, args, |_, v| urlencoding::encode(&arg_text(v)).into_owned());
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(format!("Refusing to request non-http(s) URL {}", url));
+    }
+    let method = reqwest::Method::from_bytes(http.method.to_ascii_uppercase().as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    let mut req = reqwest::Client::new().request(method.clone(), &url);
+    for (key, value) in &http.headers {
+        req = req.header(key, fill_template(value, args, |_, v| arg_text(v)));
+    }
+    req = match &http.body {
+        Some(body) => req.body(fill_template(body, args, |_, v| arg_text(v))),
+        None if method != reqwest::Method::GET => req.json(args),
+        None => req,
+    };
+
+    let resp = req.send().await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let text = resp.text().await.map_err(|e| e.to_string())?;
+    if status.is_success() {
+        Ok(text)
+    } else {
+        Err(format!("HTTP {}: {}", status, text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fill_template_replaces_each_placeholder() {
+        let args = json!({"city": "Oslo", "days": 3}).as_object().cloned().unwrap();
+        let filled = fill_template("forecast {city} --days {days} {other}", &args, |_, value| arg_text(value));
+        assert_eq!(filled, "forecast Oslo --days 3 {other}");
+    }
+
+    #[test]
+    fn fill_template_passes_names_not_values_to_the_shell() {
+        let args = json!({"city": "Oslo\"; rm -rf ~; echo \""}).as_object().cloned().unwrap();
+        let filled = fill_template("forecast {city}", &args, |name, _| format!("\"${}\"", env_name(name)));
+        assert_eq!(filled, "forecast \"$RONGE_ARG_CITY\"");
+    }
+}
@@ -0,0 +1,317 @@
+use crate::tools::ToolError;
+use chrono::{Datelike, Duration, Local, NaiveDate, Weekday};
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Public-holiday API (Nager.Date), overridable with `RONGE_HOLIDAYS_BASE_URL`.
+const DEFAULT_HOLIDAYS_BASE: &str = "https://date.nager.at/api/v3";
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+/// Business days moved at most in one call, so a typo can't walk for centuries.
+const MAX_BUSINESS_DAYS: i64 = 2_000;
+/// Calendar span `until` may cover.
+const MAX_SPAN_DAYS: i64 = 3_660;
+
+/// Countries whose weekend is Friday and Saturday; everywhere else it is
+/// Saturday and Sunday.
+const FRIDAY_SATURDAY_WEEKEND: &[&str] = &["BH", "DZ", "EG", "IL", "JO", "KW", "OM", "QA", "SA", "SD", "YE"];
+
+fn holidays_base_url() -> String {
+    std::env::var("RONGE_HOLIDAYS_BASE_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_HOLIDAYS_BASE.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn default_cache_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("holidays")
+}
+
+/// One public holiday as the API lists it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Holiday {
+    date: NaiveDate,
+    local_name: String,
+    name: String,
+    /// Nationwide; regional holidays list their subdivisions in `counties`.
+    #[serde(default)]
+    global: bool,
+    #[serde(default)]
+    counties: Option<Vec<String>>,
+    /// `Public`, `Bank`, `School`, `Optional`, `Observance`, ...
+    #[serde(default)]
+    types: Vec<String>,
+}
+
+impl Holiday {
+    /// Whether the country as a whole takes the day off.
+    fn is_day_off(&self) -> bool {
+        self.global && (self.types.is_empty() || self.types.iter().any(|t| t == "Public"))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "date": self.date.to_string(),
+            "weekday": self.date.format("%A").to_string(),
+            "name": self.name,
+            "local_name": self.local_name,
+            "day_off": self.is_day_off(),
+            "regions": self.counties,
+        })
+    }
+}
+
+/// A year's holidays for `country`: from `~/.ronge/holidays/<CC>-<year>.json`
+/// when fetched before (the dates don't change), else from the API.
+async fn holidays(country: &str, year: i32) -> Result<Vec<Holiday>, String> {
+    let path = default_cache_dir().join(format!("{}-{}.json", country, year));
+    if let Ok(text) = tokio::fs::read_to_string(&path).await
+        && let Ok(cached) = serde_json::from_str(&text)
+    {
+        return Ok(cached);
+    }
+    let resp = reqwest::Client::new()
+        .get(format!("{}/PublicHolidays/{}/{}", holidays_base_url(), year, country))
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match resp.status().as_u16() {
+        200 => {}
+        204 | 404 => return Err(format!("No holiday data for country '{}'", country)),
+        status => return Err(format!("Holiday service returned {}", status)),
+    }
+    let list: Vec<Holiday> = resp.json().await.map_err(|e| e.to_string())?;
+    if let Some(parent) = path.parent()
+        && tokio::fs::create_dir_all(parent).await.is_ok()
+    {
+        let _ = tokio::fs::write(&path, serde_json::to_string(&list).unwrap_or_default()).await;
+    }
+    println!("📅 Loaded {} holidays for {} {}", list.len(), country, year);
+    Ok(list)
+}
+
+/// The country's calendar, holidays loaded a year at a time as dates need them.
+struct Calendar {
+    country: Option<String>,
+    years: HashMap<i32, Vec<Holiday>>,
+    /// Why holidays are missing, when they are; weekends still count.
+    warning: Option<String>,
+}
+
+impl Calendar {
+    fn new(country: Option<String>) -> Self {
+        let warning = country
+            .is_none()
+            .then(|| "No country set (set_country or the `country` argument); only weekends are skipped".to_string());
+        Self { country, years: HashMap::new(), warning }
+    }
+
+    async fn holidays_on(&mut self, date: NaiveDate) -> Vec<Holiday> {
+        let Some(country) = self.country.clone() else {
+            return Vec::new();
+        };
+        if !self.years.contains_key(&date.year()) {
+            let list = match holidays(&country, date.year()).await {
+                Ok(list) => list,
+                Err(e) => {
+                    println!("⚠️ Holidays for {} {}: {}", country, date.year(), e);
+                    self.warning.get_or_insert_with(|| format!("{}; only weekends are skipped", e));
+                    Vec::new()
+                }
+            };
+            self.years.insert(date.year(), list);
+        }
+        self.years[&date.year()].iter().filter(|h| h.date == date).cloned().collect()
+    }
+
+    fn is_weekend(&self, date: NaiveDate) -> bool {
+        let friday_saturday = self.country.as_deref().is_some_and(|c| FRIDAY_SATURDAY_WEEKEND.contains(&c));
+        match date.weekday() {
+            Weekday::Fri => friday_saturday,
+            Weekday::Sat => true,
+            Weekday::Sun => !friday_saturday,
+            _ => false,
+        }
+    }
+
+    async fn is_business_day(&mut self, date: NaiveDate) -> bool {
+        !self.is_weekend(date) && !self.holidays_on(date).await.iter().any(Holiday::is_day_off)
+    }
+
+    /// `date` moved by `count` business days; 0 rolls a non-working day
+    /// forward to the next working one.
+    async fn add_business_days(&mut self, date: NaiveDate, count: i64) -> NaiveDate {
+        let step = if count < 0 { -1 } else { 1 };
+        let mut current = date;
+        if count == 0 {
+            while !self.is_business_day(current).await {
+                current += Duration::days(1);
+            }
+            return current;
+        }
+        let mut left = count.abs();
+        while left > 0 {
+            current += Duration::days(step);
+            if self.is_business_day(current).await {
+                left -= 1;
+            }
+        }
+        current
+    }
+
+    /// Business days after `from` up to and including `to`, negative when
+    /// `to` comes first.
+    async fn business_days_between(&mut self, from: NaiveDate, to: NaiveDate) -> i64 {
+        let (start, end, sign) = if to >= from { (from, to, 1) } else { (to, from, -1) };
+        let mut count = 0;
+        let mut current = start;
+        while current < end {
+            current += Duration::days(1);
+            if self.is_business_day(current).await {
+                count += 1;
+            }
+        }
+        count * sign
+    }
+
+    async fn describe(&mut self, date: NaiveDate) -> Value {
+        let holidays = self.holidays_on(date).await;
+        let week = date.iso_week();
+        json!({
+            "date": date.to_string(),
+            "weekday": date.format("%A").to_string(),
+            "iso_week": week.week(),
+            "iso_week_year": week.year(),
+            "day_of_year": date.ordinal(),
+            "quarter": (date.month0() / 3) + 1,
+            "weekend": self.is_weekend(date),
+            "holidays": holidays.iter().map(Holiday::to_json).collect::<Vec<_>>(),
+            "business_day": self.is_business_day(date).await,
+        })
+    }
+}
+
+/// `US` or `us` as an ISO 3166 code; `None` for an empty string.
+pub fn normalize_country(code: &str) -> Result<Option<String>, String> {
+    let code = code.trim();
+    if code.is_empty() {
+        return Ok(None);
+    }
+    if code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("\"{}\" is not a two-letter country code (e.g. \"US\", \"KR\")", code));
+    }
+    Ok(Some(code.to_ascii_uppercase()))
+}
+
+/// `date_info`: weekday, ISO week, holidays and business-day arithmetic for
+/// the user's country, so the model doesn't have to guess calendar facts.
+#[derive(Deserialize, Serialize)]
+pub struct DateInfo;
+
+#[derive(Deserialize, Serialize)]
+pub struct DateInfoArgs {
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default)]
+    add_business_days: Option<i64>,
+    #[serde(default)]
+    add_days: Option<i64>,
+    #[serde(default)]
+    until: Option<String>,
+    #[serde(default)]
+    list_holidays: Option<i32>,
+    #[serde(default)]
+    country: Option<String>,
+}
+
+impl Tool for DateInfo {
+    const NAME: &'static str = "date_info";
+    type Args = DateInfoArgs;
+    type Output = Value;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Calendar facts for a date in the user's country: weekday, ISO week number, public holidays \
+                and whether it is a business day. Can also add calendar or business days (skipping weekends and \
+                public holidays), count business days until another date, or list a year's public holidays. Use \
+                it instead of guessing for questions like \"first working day after Thanksgiving\" (list the \
+                holidays, then add 1 business day to that date)."
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "date": { "type": "string", "description": "YYYY-MM-DD, today, tomorrow, tuesday or next tuesday. Default: today" },
+                    "add_days": { "type": "integer", "description": "Calendar days to add (negative to go back)" },
+                    "add_business_days": { "type": "integer", "description": "Business days to add (negative to go back); 0 gives the date itself or the next business day" },
+                    "until": { "type": "string", "description": "Another date: count calendar and business days from `date` to it" },
+                    "list_holidays": { "type": "integer", "description": "A year whose public holidays to list" },
+                    "country": { "type": "string", "description": "ISO 3166 country code; default: the user's configured country" }
+                }
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let failed = ToolError::CommandFailed;
+        let country = match args.country.as_deref() {
+            Some(code) => normalize_country(code).map_err(failed)?,
+            None => crate::profile::Profile::load().country(),
+        };
+        let today = Local::now().date_naive();
+        let read_date = |text: &str| {
+            crate::world_clock::parse_date(text, today).ok_or_else(|| failed(format!("Can't read the date '{}'", text)))
+        };
+        let date = read_date(args.date.as_deref().unwrap_or("today"))?;
+        let mut calendar = Calendar::new(country.clone());
+        let mut result = json!({"country": country, "date": calendar.describe(date).await});
+
+        if let Some(days) = args.add_days {
+            if days.abs() > MAX_SPAN_DAYS {
+                return Err(failed(format!("add_days is limited to ±{}", MAX_SPAN_DAYS)));
+            }
+            let moved = date + Duration::days(days);
+            result["after_add_days"] = calendar.describe(moved).await;
+        }
+        if let Some(count) = args.add_business_days {
+            if count.abs() > MAX_BUSINESS_DAYS {
+                return Err(failed(format!("add_business_days is limited to ±{}", MAX_BUSINESS_DAYS)));
+            }
+            let moved = calendar.add_business_days(date, count).await;
+            result["after_add_business_days"] = calendar.describe(moved).await;
+        }
+        if let Some(text) = args.until.as_deref() {
+            let end = read_date(text)?;
+            let days = (end - date).num_days();
+            if days.abs() > MAX_SPAN_DAYS {
+                return Err(failed(format!("until may be at most {} days away", MAX_SPAN_DAYS)));
+            }
+            result["until"] = json!({
+                "date": end.to_string(),
+                "calendar_days": days,
+                "business_days": calendar.business_days_between(date, end).await,
+                "weeks": days / 7,
+            });
+        }
+        if let Some(year) = args.list_holidays {
+            let Some(code) = country.as_deref() else {
+                return Err(failed("No country set; pass `country` to list holidays".to_string()));
+            };
+            let list = holidays(code, year).await.map_err(failed)?;
+            result["holidays"] = json!(list.iter().map(Holiday::to_json).collect::<Vec<_>>());
+        }
+        result["note"] = json!(calendar.warning);
+        Ok(result)
+    }
+}
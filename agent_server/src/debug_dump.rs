@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Per-turn debug output directory (`~/.ronge/debug/<timestamp>/`).
+///
+/// Enabled with the `set_debug` protocol message. Each turn gets its own
+/// directory containing the rendered system prompt, the history sent to the
+/// provider, the tool definitions, the tool-event stream and the final answer,
+/// plus every raw provider round trip under `http/` (`provider_http.rs`).
+#[derive(Clone, Debug)]
+pub struct DebugDump {
+    dir: PathBuf,
+    exchanges: Arc<AtomicUsize>,
+}
+
+impl DebugDump {
+    pub fn new() -> Self {
+        let stamp = chrono::Local::now().format("%Y-%m-%dT%H-%M-%S%.3f").to_string();
+        Self {
+            dir: default_debug_dir().join(stamp),
+            exchanges: Default::default(),
+        }
+    }
+
+    pub async fn write(&self, name: &str, contents: &str) {
+        let path = self.dir.join(name);
+        let dir = path.parent().unwrap_or(&self.dir);
+        if let Err(e) = tokio::fs::create_dir_all(dir).await {
+            println!("⚠️ Could not create debug dir {}: {}", dir.display(), e);
+            return;
+        }
+        if let Err(e) = tokio::fs::write(&path, contents).await {
+            println!("⚠️ Could not write debug file {}: {}", path.display(), e);
+        }
+    }
+
+    pub async fn write_json<T: serde::Serialize>(&self, name: &str, value: &T) {
+        let body = serde_json::to_string_pretty(value).unwrap_or_else(|e| e.to_string());
+        self.write(name, &body).await;
+    }
+
+    pub fn dir(&self) -> &std::path::Path {
+        &self.dir
+    }
+
+    /// Number the next provider round trip and write its request body to
+    /// `http/NNN-request.json`. Headers are left out: they carry the API key.
+    pub async fn http_request(&self, method: &str, path: &str, body: &str) -> usize {
+        let n = self.exchanges.fetch_add(1, Ordering::Relaxed) + 1;
+        let request = serde_json::json!({"method": method, "path": path, "body": raw_body(body)});
+        self.write_json(&format!("http/{:03}-request.json", n), &request).await;
+        n
+    }
+
+    /// The response to round trip `n`, streamed ones as the whole SSE body.
+    pub async fn http_response(&self, n: usize, status: u16, body: &str) {
+        let response = serde_json::json!({"status": status, "body": raw_body(body)});
+        self.write_json(&format!("http/{:03}-response.json", n), &response).await;
+    }
+}
+
+/// JSON bodies stay JSON so the files read naturally; anything else is kept
+/// as text.
+fn raw_body(body: &str) -> serde_json::Value {
+    serde_json::from_str(body).unwrap_or_else(|_| serde_json::Value::String(body.to_string()))
+}
+
+tokio::task_local! {
+    static DUMP: DebugDump;
+}
+
+/// Run `turn` with `dump` (if any) visible to the provider clients it builds.
+pub async fn scoped<F: Future>(dump: Option<DebugDump>, turn: F) -> F::Output {
+    match dump {
+        Some(dump) => DUMP.scope(dump, turn).await,
+        None => turn.await,
+    }
+}
+
+/// The debug dump of the turn running on this task.
+pub fn current() -> Option<DebugDump> {
+    DUMP.try_with(Clone::clone).ok()
+}
+
+pub fn default_debug_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("debug")
+}
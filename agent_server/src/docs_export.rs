@@ -0,0 +1,103 @@
+use crate::state::McpToolSet;
+use rmcp::model::CallToolRequestParam;
+use serde_json::{json, Value};
+use std::borrow::Cow;
+
+/// Name fragments of a Google Docs "create document" MCP tool (Composio's
+/// `GOOGLEDOCS_CREATE_DOCUMENT_MARKDOWN`, `GOOGLEDOCS_CREATE_DOCUMENT`, …).
+const DOCS_FRAGMENTS: &[&str] = &["googledocs", "google_docs", "gdocs"];
+const TITLE_KEYS: &[&str] = &["title", "name"];
+const BODY_KEYS: &[&str] = &["markdown_text", "markdown", "text", "content", "body"];
+
+const SUMMARY_PREAMBLE: &str = "You write reports from chat conversations. Turn the conversation \
+you are given into a clear Markdown document: a one-paragraph summary, then the key findings, \
+decisions and action items under headings. Reply with the document only.";
+
+/// A connected tool that can create a Google Doc, with the argument names
+/// its schema uses.
+pub struct DocsTarget {
+    set: McpToolSet,
+    name: String,
+    title_key: &'static str,
+    body_key: &'static str,
+}
+
+fn schema_key(tool: &rmcp::model::Tool, candidates: &[&'static str]) -> Option<&'static str> {
+    let properties = tool.input_schema.get("properties")?.as_object()?;
+    candidates.iter().copied().find(|k| properties.contains_key(*k))
+}
+
+/// Find a Google Docs create-document tool among the connected MCP servers,
+/// preferring one that accepts Markdown.
+pub fn find_create_tool(tool_sets: &[McpToolSet]) -> Option<DocsTarget> {
+    let mut candidates: Vec<DocsTarget> = tool_sets
+        .iter()
+        .flat_map(|set| set.tools.iter().map(move |tool| (set, tool)))
+        .filter(|(_, tool)| {
+            let lower = tool.name.to_ascii_lowercase();
+            DOCS_FRAGMENTS.iter().any(|f| lower.contains(f)) && lower.contains("create_document")
+        })
+        .filter_map(|(set, tool)| {
+            let safe = tool.name.to_string();
+            Some(DocsTarget {
+                set: set.clone(),
+                name: set.name_map.get(&safe).cloned().unwrap_or(safe),
+                title_key: schema_key(tool, TITLE_KEYS)?,
+                body_key: schema_key(tool, BODY_KEYS)?,
+            })
+        })
+        .collect();
+    candidates.sort_by_key(|t| !t.name.to_ascii_lowercase().contains("markdown"));
+    candidates.into_iter().next()
+}
+
+/// Create a Doc titled `title` from `markdown` and return its URL.
+pub async fn create_doc(target: &DocsTarget, title: &str, markdown: &str) -> Result<String, String> {
+    let mut arguments = serde_json::Map::new();
+    arguments.insert(target.title_key.to_string(), json!(title));
+    arguments.insert(target.body_key.to_string(), json!(markdown));
+    let result = target
+        .set
+        .peer
+        .call_tool(CallToolRequestParam {
+            name: Cow::Owned(target.name.clone()),
+            arguments: Some(arguments),
+            task: None,
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let value = serde_json::to_value(&result).unwrap_or(Value::Null);
+    if result.is_error == Some(true) {
+        return Err(format!("{} failed: {}", target.name, value));
+    }
+    document_url(&value).ok_or_else(|| "The document was created but no link was returned.".to_string())
+}
+
+/// The document's URL, or one built from its ID, anywhere in the result.
+fn document_url(value: &Value) -> Option<String> {
+    let mut url = None;
+    let mut id = None;
+    crate::widgets::walk(value, 0, &mut |map| {
+        for (key, v) in map {
+            let Some(s) = v.as_str() else { continue };
+            if url.is_none() && s.starts_with("https://docs.google.com/document/") {
+                url = Some(s.to_string());
+            }
+            if id.is_none() && matches!(key.as_str(), "documentId" | "document_id") {
+                id = Some(s.to_string());
+            }
+        }
+    });
+    url.or_else(|| id.map(|id| format!("https://docs.google.com/document/d/{}/edit", id)))
+}
+
+/// Write a Markdown report of the conversation with the current provider.
+pub async fn summarize_conversation(
+    provider: &str,
+    api_key: &str,
+    model: &str,
+    transcript: &str,
+) -> Result<String, String> {
+    crate::llm::complete(provider, api_key, model, SUMMARY_PREAMBLE, transcript).await
+}
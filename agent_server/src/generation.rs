@@ -0,0 +1,102 @@
+use serde_json::{json, Value};
+
+/// Model round trips (tool calls included) per turn when nothing is set.
+pub const DEFAULT_MAX_TURNS: usize = 15;
+/// Upper bound on `max_turns`, so a typo can't loop for hours.
+const MAX_TURNS_LIMIT: usize = 100;
+
+/// Sampling and turn-length settings from `set_generation_params` or a
+/// single chat message. Unset fields leave the defaults in place.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GenerationParams {
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u64>,
+    pub max_turns: Option<usize>,
+}
+
+impl GenerationParams {
+    /// `temperature` (0–2), `max_tokens` and `max_turns` (1–100) from a
+    /// `set_generation_params` or chat frame.
+    pub fn from_frame(data: &Value) -> Result<Self, String> {
+        let temperature = match &data["temperature"] {
+            Value::Null => None,
+            value => Some(
+                value
+                    .as_f64()
+                    .filter(|t| (0.0..=2.0).contains(t))
+                    .ok_or_else(|| "temperature must be a number from 0 to 2".to_string())?,
+            ),
+        };
+        let max_tokens = match &data["max_tokens"] {
+            Value::Null => None,
+            value => Some(
+                value
+                    .as_u64()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| "max_tokens must be a positive number of tokens".to_string())?,
+            ),
+        };
+        let max_turns = match &data["max_turns"] {
+            Value::Null => None,
+            value => Some(
+                value
+                    .as_u64()
+                    .map(|n| n as usize)
+                    .filter(|n| (1..=MAX_TURNS_LIMIT).contains(n))
+                    .ok_or_else(|| format!("max_turns must be from 1 to {}", MAX_TURNS_LIMIT))?,
+            ),
+        };
+        Ok(Self { temperature, max_tokens, max_turns })
+    }
+
+    /// Fields set on a message override the server's, one by one.
+    pub fn or(self, server: Self) -> Self {
+        Self {
+            temperature: self.temperature.or(server.temperature),
+            max_tokens: self.max_tokens.or(server.max_tokens),
+            max_turns: self.max_turns.or(server.max_turns),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn max_turns(&self) -> usize {
+        self.max_turns.unwrap_or(DEFAULT_MAX_TURNS)
+    }
+
+    /// The temperature to send, if any. Models that only sample at their
+    /// default reject the parameter: OpenAI reasoning models, and Anthropic
+    /// with extended thinking on.
+    pub fn temperature(&self, provider: &str, model: &str, reasoning: &crate::reasoning::Reasoning) -> Option<f64> {
+        let temperature = self.temperature?;
+        let fixed = match provider {
+            "openai" => crate::reasoning::is_openai_reasoning_model(model),
+            "anthropic" => reasoning.max_tokens(provider).is_some(),
+            _ => false,
+        };
+        if fixed {
+            println!("🌡️ {} takes no temperature here; ignoring {}", model, temperature);
+            return None;
+        }
+        Some(temperature)
+    }
+
+    /// `max_tokens` to send: the requested one, raised when a thinking
+    /// budget needs more room (`Reasoning::max_tokens`).
+    pub fn max_tokens(&self, provider: &str, reasoning: &crate::reasoning::Reasoning) -> Option<u64> {
+        match (self.max_tokens, reasoning.max_tokens(provider)) {
+            (Some(requested), Some(needed)) => Some(requested.max(needed)),
+            (requested, needed) => requested.or(needed),
+        }
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({
+            "temperature": self.temperature,
+            "max_tokens": self.max_tokens,
+            "max_turns": self.max_turns,
+        })
+    }
+}
@@ -0,0 +1,404 @@
+use rmcp::{
+    ServerHandler,
+    model::{CallToolRequestParam, CallToolResult, Content, ErrorData, JsonObject, ListToolsResult, PaginatedRequestParam},
+    service::{RequestContext, RoleServer},
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const API: &str = "https://api.github.com";
+/// PR diffs longer than this are truncated.
+const MAX_DIFF_BYTES: usize = 48 * 1024;
+
+/// GitHub REST tools authenticated with the user's personal access token,
+/// served in-process so results go through the MCP proxy (events,
+/// confirmation of `github_create_issue`/`github_assign_issue`, sanitization).
+pub struct GithubServer {
+    token: String,
+    client: reqwest::Client,
+}
+
+/// Check the token and start the tool server. Returns the connection and
+/// the token owner's login.
+pub async fn connect(token: &str) -> Result<(crate::state::McpConnection, String), String> {
+    let server = GithubServer {
+        token: token.to_string(),
+        client: reqwest::Client::new(),
+    };
+    let user = server.get(&format!("{API}/user")).await?;
+    let login = user["login"].as_str().unwrap_or("").to_string();
+    let conn = crate::mcp_proxy::connect_in_process(server).await?;
+    Ok((conn, login))
+}
+
+/// Replace the running GitHub tools with ones for `token` (none when it is
+/// empty). Returns the `github_status` or `github_error` frame to send.
+pub async fn apply_token(state: &crate::state::SharedState, token: &str) -> Value {
+    if let Some(conn) = state.lock().await.github.take() {
+        let _ = conn._service.cancel().await;
+    }
+    if token.is_empty() {
+        println!("🛑 GitHub tools disconnected");
+        return json!({"type": "github_status", "content": {"connected": false, "login": null}});
+    }
+    match connect(token).await {
+        Ok((conn, login)) => {
+            println!("🐙 GitHub tools connected as {}", login);
+            state.lock().await.github = Some(conn);
+            json!({"type": "github_status", "content": {"connected": true, "login": login}})
+        }
+        Err(e) => {
+            println!("❌ GitHub connection failed: {}", e);
+            json!({"type": "github_error", "content": e})
+        }
+    }
+}
+
+/// Reconnect with the token kept in the secret store, if any.
+pub async fn restore(state: crate::state::SharedState) {
+    if let Some(token) = crate::secrets::get(crate::secrets::GITHUB) {
+        apply_token(&state, &token).await;
+    }
+}
+
+fn schema(value: Value) -> Arc<JsonObject> {
+    Arc::new(value.as_object().cloned().unwrap_or_default())
+}
+
+fn repo_prop() -> Value {
+    json!({"type": "string", "description": "owner/name, e.g. \"rust-lang/rust\""})
+}
+
+fn tool_definitions() -> Vec<rmcp::model::Tool> {
+    vec![
+        rmcp::model::Tool::new(
+            "github_list_issues",
+            "List issues of a GitHub repository (pull requests excluded).",
+            schema(json!({
+                "type": "object",
+                "properties": {
+                    "repo": repo_prop(),
+                    "state": {"type": "string", "enum": ["open", "closed", "all"], "description": "Default open"},
+                    "assignee": {"type": "string", "description": "Login, \"none\" or \"*\""},
+                    "labels": {"type": "string", "description": "Comma-separated label names"}
+                },
+                "required": ["repo"]
+            })),
+        ),
+        rmcp::model::Tool::new(
+            "github_create_issue",
+            "Create an issue in a GitHub repository.",
+            schema(json!({
+                "type": "object",
+                "properties": {
+                    "repo": repo_prop(),
+                    "title": {"type": "string"},
+                    "body": {"type": "string", "description": "Markdown"},
+                    "labels": {"type": "array", "items": {"type": "string"}}
+                },
+                "required": ["repo", "title"]
+            })),
+        ),
+        rmcp::model::Tool::new(
+            "github_assign_issue",
+            "Add assignees to a GitHub issue or pull request.",
+            schema(json!({
+                "type": "object",
+                "properties": {
+                    "repo": repo_prop(),
+                    "number": {"type": "integer"},
+                    "assignees": {"type": "array", "items": {"type": "string"}, "description": "Logins"}
+                },
+                "required": ["repo", "number", "assignees"]
+            })),
+        ),
+        rmcp::model::Tool::new(
+            "github_get_pull_request",
+            "Get a pull request's description, status and diff.",
+            schema(json!({
+                "type": "object",
+                "properties": {"repo": repo_prop(), "number": {"type": "integer"}},
+                "required": ["repo", "number"]
+            })),
+        ),
+        rmcp::model::Tool::new(
+            "github_pull_request_comments",
+            "Get a pull request's review comments (on code lines) and conversation comments.",
+            schema(json!({
+                "type": "object",
+                "properties": {"repo": repo_prop(), "number": {"type": "integer"}},
+                "required": ["repo", "number"]
+            })),
+        ),
+        rmcp::model::Tool::new(
+            "github_ci_status",
+            "Get the CI status (commit statuses and check runs) of a branch, tag, commit or pull request.",
+            schema(json!({
+                "type": "object",
+                "properties": {
+                    "repo": repo_prop(),
+                    "ref": {"type": "string", "description": "Branch, tag or SHA"},
+                    "number": {"type": "integer", "description": "Pull request number, instead of ref"}
+                },
+                "required": ["repo"]
+            })),
+        ),
+    ]
+}
+
+/// `owner/name`, rejecting anything that would change the request path.
+fn repo_arg(args: &JsonObject) -> Result<String, String> {
+    let repo = args.get("repo").and_then(|v| v.as_str()).unwrap_or("").trim();
+    let valid = repo.split('/').count() == 2
+        && repo
+            .split('/')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        && !repo.contains("..");
+    if valid {
+        Ok(repo.to_string())
+    } else {
+        Err(format!("'{}' is not an owner/name repository", repo))
+    }
+}
+
+fn number_arg(args: &JsonObject) -> Result<u64, String> {
+    args.get("number")
+        .and_then(|v| v.as_u64().or_else(|| v.as_str().and_then(|s| s.trim_start_matches('#').parse().ok())))
+        .ok_or_else(|| "`number` is required".to_string())
+}
+
+fn str_arg<'a>(args: &'a JsonObject, key: &str) -> Option<&'a str> {
+    args.get(key).and_then(|v| v.as_str()).map(str::trim).filter(|s| !s.is_empty())
+}
+
+fn login(value: &Value) -> Value {
+    value["login"].clone()
+}
+
+fn issue_summary(issue: &Value) -> Value {
+    json!({
+        "number": issue["number"],
+        "title": issue["title"],
+        "state": issue["state"],
+        "author": login(&issue["user"]),
+        "assignees": issue["assignees"].as_array().map(|a| a.iter().map(login).collect::<Vec<_>>()),
+        "labels": issue["labels"].as_array().map(|l| l.iter().map(|l| l["name"].clone()).collect::<Vec<_>>()),
+        "comments": issue["comments"],
+        "updated_at": issue["updated_at"],
+        "url": issue["html_url"],
+    })
+}
+
+fn comment_summary(comment: &Value) -> Value {
+    json!({
+        "author": login(&comment["user"]),
+        "path": comment.get("path"),
+        "line": comment.get("line"),
+        "body": comment["body"],
+        "created_at": comment["created_at"],
+    })
+}
+
+impl GithubServer {
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, url)
+            .bearer_auth(&self.token)
+            .header("User-Agent", "rong-e")
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+    }
+
+    async fn send(&self, req: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+        let body: Value = resp.json().await.unwrap_or(Value::Null);
+        let message = body["message"].as_str().unwrap_or("request failed");
+        Err(match status.as_u16() {
+            401 => "GitHub rejected the token. Check it in Settings.".to_string(),
+            403 | 404 => format!("GitHub {}: {} (is the token allowed to access this repository?)", status, message),
+            _ => format!("GitHub {}: {}", status, message),
+        })
+    }
+
+    async fn get(&self, url: &str) -> Result<Value, String> {
+        let resp = self.send(self.request(reqwest::Method::GET, url)).await?;
+        resp.json().await.map_err(|e| e.to_string())
+    }
+
+    async fn list_issues(&self, args: &JsonObject) -> Result<Value, String> {
+        let repo = repo_arg(args)?;
+        let mut query = vec![("per_page", "50"), ("state", str_arg(args, "state").unwrap_or("open"))];
+        if let Some(assignee) = str_arg(args, "assignee") {
+            query.push(("assignee", assignee));
+        }
+        if let Some(labels) = str_arg(args, "labels") {
+            query.push(("labels", labels));
+        }
+        let resp = self
+            .send(self.request(reqwest::Method::GET, &format!("{API}/repos/{repo}/issues")).query(&query))
+            .await?;
+        let issues: Vec<Value> = resp.json().await.map_err(|e| e.to_string())?;
+        Ok(Value::Array(
+            issues
+                .iter()
+                .filter(|i| i.get("pull_request").is_none())
+                .map(issue_summary)
+                .collect(),
+        ))
+    }
+
+    async fn create_issue(&self, args: &JsonObject) -> Result<Value, String> {
+        let repo = repo_arg(args)?;
+        let title = str_arg(args, "title").ok_or("`title` is required")?;
+        let mut body = json!({"title": title, "body": str_arg(args, "body").unwrap_or("")});
+        if let Some(labels) = args.get("labels").filter(|l| l.is_array()) {
+            body["labels"] = labels.clone();
+        }
+        let resp = self
+            .send(self.request(reqwest::Method::POST, &format!("{API}/repos/{repo}/issues")).json(&body))
+            .await?;
+        let issue: Value = resp.json().await.map_err(|e| e.to_string())?;
+        Ok(issue_summary(&issue))
+    }
+
+    async fn assign_issue(&self, args: &JsonObject) -> Result<Value, String> {
+        let repo = repo_arg(args)?;
+        let number = number_arg(args)?;
+        let assignees = args
+            .get("assignees")
+            .filter(|a| a.as_array().is_some_and(|a| !a.is_empty()))
+            .ok_or("`assignees` is required")?;
+        let resp = self
+            .send(
+                self.request(reqwest::Method::POST, &format!("{API}/repos/{repo}/issues/{number}/assignees"))
+                    .json(&json!({"assignees": assignees})),
+            )
+            .await?;
+        let issue: Value = resp.json().await.map_err(|e| e.to_string())?;
+        Ok(issue_summary(&issue))
+    }
+
+    async fn get_pull_request(&self, args: &JsonObject) -> Result<Value, String> {
+        let repo = repo_arg(args)?;
+        let number = number_arg(args)?;
+        let url = format!("{API}/repos/{repo}/pulls/{number}");
+        let pr = self.get(&url).await?;
+        let diff = self
+            .send(self.request(reqwest::Method::GET, &url).header("Accept", "application/vnd.github.diff"))
+            .await?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+        let diff = if diff.len() > MAX_DIFF_BYTES {
+            format!(
+                "{}\n… [diff truncated — {} bytes total]",
+                crate::text::truncate_bytes(&diff, MAX_DIFF_BYTES),
+                diff.len()
+            )
+        } else {
+            diff
+        };
+        Ok(json!({
+            "number": pr["number"],
+            "title": pr["title"],
+            "state": pr["state"],
+            "draft": pr["draft"],
+            "merged": pr["merged"],
+            "mergeable_state": pr["mergeable_state"],
+            "author": login(&pr["user"]),
+            "base": pr["base"]["ref"],
+            "head": pr["head"]["ref"],
+            "head_sha": pr["head"]["sha"],
+            "additions": pr["additions"],
+            "deletions": pr["deletions"],
+            "changed_files": pr["changed_files"],
+            "body": pr["body"],
+            "url": pr["html_url"],
+            "diff": diff,
+        }))
+    }
+
+    async fn pull_request_comments(&self, args: &JsonObject) -> Result<Value, String> {
+        let repo = repo_arg(args)?;
+        let number = number_arg(args)?;
+        let review = self.get(&format!("{API}/repos/{repo}/pulls/{number}/comments?per_page=100")).await?;
+        let conversation = self.get(&format!("{API}/repos/{repo}/issues/{number}/comments?per_page=100")).await?;
+        let summarize = |v: &Value| v.as_array().map(|a| a.iter().map(comment_summary).collect::<Vec<_>>());
+        Ok(json!({
+            "review_comments": summarize(&review),
+            "comments": summarize(&conversation),
+        }))
+    }
+
+    async fn ci_status(&self, args: &JsonObject) -> Result<Value, String> {
+        let repo = repo_arg(args)?;
+        let git_ref = match (str_arg(args, "ref"), number_arg(args)) {
+            (Some(r), _) => r.to_string(),
+            (None, Ok(number)) => {
+                let pr = self.get(&format!("{API}/repos/{repo}/pulls/{number}")).await?;
+                pr["head"]["sha"].as_str().unwrap_or_default().to_string()
+            }
+            (None, Err(_)) => return Err("Give a `ref` or a pull request `number`".to_string()),
+        };
+        let git_ref = urlencoding::encode(&git_ref).into_owned();
+        let status = self.get(&format!("{API}/repos/{repo}/commits/{git_ref}/status")).await?;
+        let checks = self.get(&format!("{API}/repos/{repo}/commits/{git_ref}/check-runs?per_page=100")).await?;
+        let check_runs: Vec<Value> = checks["check_runs"]
+            .as_array()
+            .map(|runs| {
+                runs.iter()
+                    .map(|r| json!({"name": r["name"], "status": r["status"], "conclusion": r["conclusion"], "url": r["html_url"]}))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let statuses: Vec<Value> = status["statuses"]
+            .as_array()
+            .map(|s| {
+                s.iter()
+                    .map(|s| json!({"context": s["context"], "state": s["state"], "description": s["description"]}))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(json!({
+            "sha": status["sha"],
+            "combined_state": status["state"],
+            "statuses": statuses,
+            "check_runs": check_runs,
+        }))
+    }
+}
+
+impl ServerHandler for GithubServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(tool_definitions()))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let args = request.arguments.unwrap_or_default();
+        let outcome = match request.name.as_ref() {
+            "github_list_issues" => self.list_issues(&args).await,
+            "github_create_issue" => self.create_issue(&args).await,
+            "github_assign_issue" => self.assign_issue(&args).await,
+            "github_get_pull_request" => self.get_pull_request(&args).await,
+            "github_pull_request_comments" => self.pull_request_comments(&args).await,
+            "github_ci_status" => self.ci_status(&args).await,
+            other => return Err(ErrorData::invalid_params(format!("Unknown tool {}", other), None)),
+        };
+        Ok(match outcome {
+            Ok(value) => CallToolResult::success(vec![Content::text(value.to_string())]),
+            Err(e) => CallToolResult::error(vec![Content::text(e)]),
+        })
+    }
+}
@@ -0,0 +1,315 @@
+use base64::{
+    Engine as _, alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+};
+use rmcp::model::{CallToolResult, Content, RawContent};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Extra boolean argument added to Gmail search tools. Stripped before the
+/// call is forwarded; when set, the result is collapsed per thread.
+pub const GROUP_BY_THREAD: &str = "group_by_thread";
+
+/// Gmail encodes part bodies as base64url, with or without padding.
+const BASE64URL: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Whether `tool_name` searches or lists Gmail messages (Composio's
+/// `GMAIL_FETCH_EMAILS`, `search_messages`, …).
+pub fn is_search(tool_name: &str) -> bool {
+    let lower = tool_name.to_ascii_lowercase();
+    lower.contains("gmail")
+        && ["fetch_emails", "search", "list_messages"].iter().any(|f| lower.contains(f))
+}
+
+/// Offer `group_by_thread` on every Gmail search tool of a server.
+pub fn with_thread_option(tools: &[rmcp::model::Tool]) -> Vec<rmcp::model::Tool> {
+    tools
+        .iter()
+        .cloned()
+        .map(|mut tool| {
+            if is_search(&tool.name) {
+                let mut schema = (*tool.input_schema).clone();
+                if let Some(properties) = schema.get_mut("properties").and_then(|p| p.as_object_mut()) {
+                    properties.insert(
+                        GROUP_BY_THREAD.to_string(),
+                        json!({
+                            "type": "boolean",
+                            "description": "Collapse the results into one entry per conversation thread (message count, participants, latest date first). Use it for broad searches."
+                        }),
+                    );
+                }
+                tool.input_schema = Arc::new(schema);
+            }
+            tool
+        })
+        .collect()
+}
+
+/// Milliseconds since the epoch of a message: Gmail's `internalDate`, or an
+/// RFC 3339 / RFC 2822 timestamp field.
+fn message_time(obj: &serde_json::Map<String, Value>) -> Option<i64> {
+    if let Some(ms) = obj.get("internalDate").and_then(|d| d.as_str().and_then(|s| s.parse().ok()).or_else(|| d.as_i64())) {
+        return Some(ms);
+    }
+    ["messageTimestamp", "date", "Date"].iter().find_map(|key| {
+        let text = obj.get(*key)?.as_str()?;
+        chrono::DateTime::parse_from_rfc3339(text)
+            .or_else(|_| chrono::DateTime::parse_from_rfc2822(text))
+            .ok()
+            .map(|t| t.timestamp_millis())
+    })
+}
+
+fn text_field<'a>(obj: &'a serde_json::Map<String, Value>, keys: &[&str]) -> Option<&'a str> {
+    keys.iter().find_map(|k| obj.get(*k).and_then(|v| v.as_str())).filter(|s| !s.is_empty())
+}
+
+#[derive(Default)]
+struct Thread {
+    id: String,
+    subject: Option<String>,
+    participants: Vec<String>,
+    message_ids: Vec<String>,
+    latest: Option<i64>,
+    latest_snippet: Option<String>,
+}
+
+/// Replace a Gmail search result with one entry per thread, latest first:
+/// `{"threads": [{"thread_id", "subject", "message_count", "participants",
+/// "latest_date", "latest_snippet", "message_ids"}], "thread_count", "message_count"}`.
+/// Left unchanged when no messages with thread IDs are found.
+pub fn group_by_thread(result: &mut CallToolResult) {
+    let value = serde_json::to_value(&*result).unwrap_or(Value::Null);
+    let mut threads: Vec<Thread> = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    crate::widgets::walk(&value, 0, &mut |obj| {
+        let (Some(thread_id), Some(message_id)) = (
+            text_field(obj, &["threadId", "thread_id"]),
+            text_field(obj, &["messageId", "message_id", "id"]),
+        ) else {
+            return;
+        };
+        if !seen.insert(message_id.to_string()) {
+            return;
+        }
+        let index = match threads.iter().position(|t| t.id == thread_id) {
+            Some(i) => i,
+            None => {
+                threads.push(Thread { id: thread_id.to_string(), ..Default::default() });
+                threads.len() - 1
+            }
+        };
+        let thread = &mut threads[index];
+        thread.message_ids.push(message_id.to_string());
+        if thread.subject.is_none() {
+            thread.subject = text_field(obj, &["subject", "Subject"]).map(str::to_string);
+        }
+        if let Some(sender) = text_field(obj, &["sender", "from", "From"])
+            && !thread.participants.iter().any(|p| p == sender)
+        {
+            thread.participants.push(sender.to_string());
+        }
+        let time = message_time(obj);
+        if thread.latest.is_none() || time > thread.latest {
+            thread.latest = time.or(thread.latest);
+            thread.latest_snippet = text_field(obj, &["snippet", "preview"]).map(|s| s.chars().take(200).collect());
+        }
+    });
+    if threads.is_empty() {
+        return;
+    }
+
+    threads.sort_by_key(|t| std::cmp::Reverse(t.latest));
+    let message_count: usize = threads.iter().map(|t| t.message_ids.len()).sum();
+    let grouped: Vec<Value> = threads
+        .into_iter()
+        .map(|t| {
+            json!({
+                "thread_id": t.id,
+                "subject": t.subject,
+                "message_count": t.message_ids.len(),
+                "participants": t.participants,
+                "latest_date": t.latest.and_then(chrono::DateTime::from_timestamp_millis).map(|d| d.to_rfc3339()),
+                "latest_snippet": t.latest_snippet,
+                "message_ids": t.message_ids,
+            })
+        })
+        .collect();
+    let summary = json!({
+        "thread_count": grouped.len(),
+        "message_count": message_count,
+        "threads": grouped,
+    });
+    result.content = vec![Content::text(summary.to_string())];
+    result.structured_content = None;
+}
+
+/// Whether `tool_name` reads whole Gmail messages or threads (Composio's
+/// `GMAIL_FETCH_MESSAGE_BY_MESSAGE_ID`, `GMAIL_FETCH_MESSAGE_BY_THREAD_ID`, …).
+pub fn reads_messages(tool_name: &str) -> bool {
+    let lower = tool_name.to_ascii_lowercase();
+    lower.contains("gmail")
+        && ["fetch_message", "get_message", "fetch_emails", "get_thread"].iter().any(|f| lower.contains(f))
+}
+
+/// Append an attachment index to a Gmail read result, so the model knows an
+/// invoice PDF is there even though the payload only carries its part:
+/// `{"attachments": [{"message_id", "filename", "mime_type", "size", "attachment_id"}]}`.
+/// Attachments are found in MIME parts (`filename` + `body.attachmentId`) and
+/// in Composio's flattened `attachmentList`.
+pub fn list_attachments(result: &mut CallToolResult) {
+    let value = serde_json::to_value(&*result).unwrap_or(Value::Null);
+    let mut attachments: Vec<Value> = Vec::new();
+    let mut current_message: Option<String> = None;
+    crate::widgets::walk(&value, 0, &mut |obj| {
+        // Parents are visited before their parts, so this is the enclosing message.
+        if let Some(id) = text_field(obj, &["messageId", "message_id"])
+            .or_else(|| obj.contains_key("threadId").then(|| text_field(obj, &["id"])).flatten())
+        {
+            current_message = Some(id.to_string());
+        }
+        let Some(filename) = text_field(obj, &["filename", "fileName", "name"]) else {
+            return;
+        };
+        let body = obj.get("body").and_then(|b| b.as_object());
+        let Some(attachment_id) = text_field(obj, &["attachmentId", "attachment_id"])
+            .or_else(|| body.and_then(|b| text_field(b, &["attachmentId"])))
+        else {
+            return;
+        };
+        if attachments.iter().any(|a| a["attachment_id"] == attachment_id) {
+            return;
+        }
+        let size = obj
+            .get("size")
+            .or_else(|| body.and_then(|b| b.get("size")))
+            .and_then(|s| s.as_u64().or_else(|| s.as_str().and_then(|s| s.parse().ok())));
+        attachments.push(json!({
+            "message_id": current_message,
+            "filename": filename,
+            "mime_type": text_field(obj, &["mimeType", "mime_type", "contentType"]),
+            "size": size,
+            "attachment_id": attachment_id,
+        }));
+    });
+    if attachments.is_empty() {
+        return;
+    }
+    println!("📎 Listed {} Gmail attachment(s)", attachments.len());
+    let index = json!({
+        "attachments": attachments,
+        "note": "Files attached to the message(s) above. To save one, call the Gmail get-attachment tool with its message_id, attachment_id and filename.",
+    });
+    result.content.push(Content::text(index.to_string()));
+}
+
+/// Decode the base64url `body.data` of text MIME parts in a Gmail read result
+/// in place, honouring the part's charset (ISO-8859-1, Shift_JIS, EUC-KR, …)
+/// so international mail reaches the model as readable text. Decoded parts are
+/// marked `"decoded": true`; parts that fail to decode are left as they are.
+pub fn decode_bodies(result: &mut CallToolResult) {
+    let mut decoded = 0;
+    for content in result.content.iter_mut() {
+        let RawContent::Text(text) = &mut content.raw else {
+            continue;
+        };
+        let Ok(mut value) = serde_json::from_str::<Value>(&text.text) else {
+            continue;
+        };
+        let before = decoded;
+        decode_parts(&mut value, 0, &mut decoded);
+        if decoded > before {
+            text.text = value.to_string();
+        }
+    }
+    if decoded > 0 {
+        // The structured copy still holds the encoded bodies.
+        result.structured_content = None;
+        println!("✉️ Decoded {} Gmail body part(s)", decoded);
+    }
+}
+
+fn decode_parts(value: &mut Value, depth: usize, decoded: &mut usize) {
+    if depth > 8 {
+        return;
+    }
+    match value {
+        Value::Object(map) => {
+            if let Some(text) = decode_part(map)
+                && let Some(body) = map.get_mut("body").and_then(|b| b.as_object_mut())
+            {
+                body.insert("data".to_string(), Value::String(text));
+                body.insert("decoded".to_string(), Value::Bool(true));
+                *decoded += 1;
+            }
+            for v in map.values_mut() {
+                decode_parts(v, depth + 1, decoded);
+            }
+        }
+        Value::Array(items) => {
+            for v in items {
+                decode_parts(v, depth + 1, decoded);
+            }
+        }
+        Value::String(s) if s.starts_with('{') || s.starts_with('[') => {
+            if let Ok(mut inner) = serde_json::from_str::<Value>(s) {
+                let before = *decoded;
+                decode_parts(&mut inner, depth + 1, decoded);
+                if *decoded > before {
+                    *s = inner.to_string();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The decoded text of one `text/*` MIME part, if it carries encoded data.
+fn decode_part(part: &serde_json::Map<String, Value>) -> Option<String> {
+    let mime_type = text_field(part, &["mimeType"])?;
+    if !mime_type.to_ascii_lowercase().starts_with("text/") {
+        return None;
+    }
+    let body = part.get("body")?.as_object()?;
+    if body.get("decoded").is_some() {
+        return None;
+    }
+    let bytes = decode_base64url(body.get("data")?.as_str()?)?;
+
+    let content_type = part
+        .get("headers")
+        .and_then(|h| h.as_array())
+        .and_then(|headers| {
+            headers.iter().find_map(|h| {
+                h["name"]
+                    .as_str()
+                    .filter(|n| n.eq_ignore_ascii_case("content-type"))
+                    .and(h["value"].as_str())
+            })
+        })
+        .unwrap_or(mime_type);
+    let encoding = charset(content_type)
+        .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    let (text, _, _) = encoding.decode(&bytes);
+    Some(text.into_owned())
+}
+
+/// Decode Gmail base64url data (attachment or part bodies), padded or not.
+pub fn decode_base64url(data: &str) -> Option<Vec<u8>> {
+    let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+    BASE64URL.decode(data).ok()
+}
+
+/// `charset` parameter of a Content-Type value, unquoted.
+fn charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
@@ -0,0 +1,146 @@
+use rmcp::model::{CallToolResult, Content};
+use serde::{Deserialize, Serialize};
+
+/// Phrases Google and Composio use when the stored grant no longer works
+/// (revoked consent, changed password, expired connected account).
+const EXPIRED_MARKERS: &[&str] = &[
+    "invalid_grant",
+    "token has been expired or revoked",
+    "invalid credentials",
+    "invalid_credentials",
+    "unauthenticated",
+    "request had invalid authentication credentials",
+    "connected account is not active",
+    "connection expired",
+    "reauthenticate",
+    "re-authenticate",
+    "reauthorize",
+    "re-authorize",
+];
+
+/// Whether the text of a failed Google tool call says the authorization is gone.
+/// A bare `401` only counts next to "unauthorized" or "status", so message IDs
+/// or amounts containing "401" are not mistaken for one.
+pub fn is_expired(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    EXPIRED_MARKERS.iter().any(|m| lower.contains(m))
+        || (lower.contains("401") && (lower.contains("unauthorized") || lower.contains("status")))
+}
+
+/// Whether a Google tool result failed for lack of authorization. Composio
+/// reports failures as `{"successful": false, "error": ...}` inside a result
+/// that is not flagged as an error, so both shapes are checked.
+pub fn result_expired(result: &CallToolResult) -> bool {
+    let text = serde_json::to_string(result).unwrap_or_default();
+    // Ignore escaping and pretty-printing of the JSON nested in `content[].text`.
+    let compact: String = text.chars().filter(|c| !c.is_whitespace() && *c != '\\').collect();
+    let failed = result.is_error == Some(true) || compact.contains("\"successful\":false");
+    failed && is_expired(&text)
+}
+
+/// The `google_auth_expired` frame pushed to the client as soon as a call fails this way.
+pub fn expired_event(tool_name: &str, api: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "google_auth_expired",
+        "content": {
+            "toolName": tool_name,
+            "api": api,
+            "message": "Google access has expired or was revoked. Reconnect your Google account to keep using Gmail, Calendar and Sheets.",
+        }
+    })
+}
+
+/// What the model sees instead of the raw 401, so it stops and tells the user.
+pub fn expired_result(api: &str) -> CallToolResult {
+    CallToolResult::error(vec![Content::text(format!(
+        "Google authorization for {} has expired or was revoked. Do not retry any Google tool in \
+         this turn; tell the user to reconnect their Google account, then try again.",
+        api
+    ))])
+}
+
+/// Name fragments of tools that reach the owner's Google account.
+const GOOGLE_TOOL_FRAGMENTS: &[&str] = &[
+    "gmail", "calendar", "sheets", "spreadsheet", "googledocs", "google_docs", "gdocs", "googledrive",
+    "google_drive", "gdrive", "googlecontacts", "google_contacts",
+];
+
+/// How much of the owner's Google account a session may use, so a guest or
+/// kiosk window on the same server cannot read the owner's email.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GoogleAccess {
+    #[default]
+    Full,
+    CalendarOnly,
+    None,
+}
+
+impl GoogleAccess {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "full" => Some(Self::Full),
+            "calendar" | "calendar_only" => Some(Self::CalendarOnly),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    /// What guest sessions (`session::is_guest`) get until the owner grants
+    /// more: `RONGE_GUEST_GOOGLE_ACCESS`, default `none`.
+    pub fn guest_default() -> Self {
+        std::env::var("RONGE_GUEST_GOOGLE_ACCESS")
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or(Self::None)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::CalendarOnly => "calendar_only",
+            Self::None => "none",
+        }
+    }
+
+    /// Whether this access level includes everything `other` allows.
+    pub fn covers(self, other: Self) -> bool {
+        let rank = |a: Self| match a {
+            Self::None => 0,
+            Self::CalendarOnly => 1,
+            Self::Full => 2,
+        };
+        rank(self) >= rank(other)
+    }
+
+    pub fn allows(self, tool_name: &str) -> bool {
+        let lower = tool_name.to_ascii_lowercase();
+        if !GOOGLE_TOOL_FRAGMENTS.iter().any(|f| lower.contains(f)) {
+            return true;
+        }
+        match self {
+            Self::Full => true,
+            Self::CalendarOnly => lower.contains("calendar"),
+            Self::None => false,
+        }
+    }
+
+    /// Drop the Google tools this level does not allow. Applied before the
+    /// per-turn Google servers are built, so those follow along.
+    pub fn filter(self, tool_sets: Vec<crate::state::McpToolSet>) -> Vec<crate::state::McpToolSet> {
+        if self == Self::Full {
+            return tool_sets;
+        }
+        tool_sets
+            .into_iter()
+            .filter_map(|set| {
+                let tools: Vec<rmcp::model::Tool> =
+                    set.tools.iter().filter(|t| self.allows(&t.name)).cloned().collect();
+                (!tools.is_empty()).then(|| crate::state::McpToolSet {
+                    tools: std::sync::Arc::new(tools),
+                    ..set
+                })
+            })
+            .collect()
+    }
+}
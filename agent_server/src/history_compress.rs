@@ -0,0 +1,179 @@
+use crate::state::SharedState;
+use rig::OneOrMany;
+use rig::message::{AssistantContent, Message as RigMessage};
+
+/// Prefix of the history entries holding a tool's output.
+const TOOL_OUTPUT_MARKER: &str = "[Tool output: ";
+/// Marks an entry whose raw output was replaced by a summary.
+const SUMMARIZED_MARKER: &str = " (summarized";
+/// Turns whose tool outputs stay verbatim (`RONGE_HISTORY_RAW_TURNS`).
+const DEFAULT_RAW_TURNS: usize = 2;
+/// Outputs shorter than this are kept as they are.
+const MIN_SUMMARIZE_CHARS: usize = 1_500;
+/// Characters of one output kept in the history.
+const MAX_RAW_CHARS: usize = 12_000;
+
+const SUMMARY_PREAMBLE: &str = "You compress a tool's output for an assistant's conversation \
+history. Keep what a follow-up question could need: names, IDs, dates, amounts, counts, \
+statuses and any errors. Drop formatting and boilerplate. At most ten short lines; do not \
+invent details.";
+
+fn raw_turns() -> usize {
+    std::env::var("RONGE_HISTORY_RAW_TURNS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RAW_TURNS)
+}
+
+/// `~/.ronge/tool-outputs`, where summarized outputs are kept in full.
+fn outputs_dir() -> std::path::PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("tool-outputs")
+}
+
+/// Append the turn's successful tool outputs to its answer (the last history
+/// message), so follow-up questions can refer to what the tools returned.
+/// `outputs` are what the model was given (`ToolEventSender::record_output`):
+/// budgeted, sanitized and redacted, never the raw result the client got.
+pub fn record_tool_outputs(history: &mut [RigMessage], outputs: &[(String, String)]) {
+    let Some(RigMessage::Assistant { content, .. }) = history.last_mut() else {
+        return;
+    };
+    for (name, result) in outputs {
+        if result.trim().is_empty() {
+            continue;
+        }
+        let mut output: String = result.chars().take(MAX_RAW_CHARS).collect();
+        if output.len() < result.len() {
+            output.push_str("\n… [truncated]");
+        }
+        content.push(AssistantContent::text(format!("{}{}]\n{}", TOOL_OUTPUT_MARKER, name, output)));
+    }
+}
+
+/// Delete every saved output (`purge_sessions`).
+pub async fn delete_outputs() -> std::io::Result<()> {
+    match tokio::fs::remove_dir_all(outputs_dir()).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// One raw tool output in the history waiting to be summarized.
+struct Pending {
+    message: usize,
+    part: usize,
+    name: String,
+    output: String,
+}
+
+/// Replace the raw tool outputs recorded more than `RONGE_HISTORY_RAW_TURNS`
+/// turns ago with short summaries from a small model. Each original is saved
+/// under `~/.ronge/tool-outputs/` and registered as an artifact of the
+/// session, and the summary names the artifact. Returns how many outputs
+/// were compressed.
+pub async fn compress(
+    state: &SharedState,
+    session_id: &str,
+    history: &mut [RigMessage],
+    provider: &str,
+    model: &str,
+    redact_pii: bool,
+) -> usize {
+    // Everything before the user message that opens the N-th most recent turn.
+    let Some(boundary) = history
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, m)| matches!(m, RigMessage::User { .. }))
+        .nth(raw_turns().saturating_sub(1))
+        .map(|(i, _)| i)
+    else {
+        return 0;
+    };
+
+    let mut pending = Vec::new();
+    for (message, entry) in history[..boundary].iter().enumerate() {
+        let RigMessage::Assistant { content, .. } = entry else {
+            continue;
+        };
+        for (part, item) in content.iter().enumerate() {
+            let AssistantContent::Text(text) = item else {
+                continue;
+            };
+            let Some((header, output)) = text.text.strip_prefix(TOOL_OUTPUT_MARKER).and_then(|t| t.split_once("]\n"))
+            else {
+                continue;
+            };
+            if header.contains(SUMMARIZED_MARKER) || output.chars().count() < MIN_SUMMARIZE_CHARS {
+                continue;
+            }
+            pending.push(Pending { message, part, name: header.to_string(), output: output.to_string() });
+        }
+    }
+    if pending.is_empty() {
+        return 0;
+    }
+
+    let api_key = state.lock().await.api_keys.get(provider).cloned().unwrap_or_default();
+    let summary_model = crate::email_summary::summary_model(provider, model);
+    let summaries = futures::future::join_all(pending.iter().map(|p| {
+        let prompt = if redact_pii { crate::pii::redact(&p.output) } else { p.output.clone() };
+        let (api_key, summary_model) = (&api_key, &summary_model);
+        async move {
+            let prompt = format!("Output of the tool {}:\n\n{}", p.name, prompt);
+            crate::llm::complete(provider, api_key, summary_model, SUMMARY_PREAMBLE, &prompt).await
+        }
+    }))
+    .await;
+
+    let mut compressed = 0;
+    for (p, summary) in pending.into_iter().zip(summaries) {
+        let summary = match summary {
+            Ok(summary) if !summary.trim().is_empty() => summary.trim().to_string(),
+            Ok(_) | Err(_) => {
+                println!("⚠️ Could not summarize {} output; keeping it", p.name);
+                continue;
+            }
+        };
+        let path = outputs_dir().join(format!("{}-{}.txt", crate::session::new_session_id(), p.name));
+        let saved = match tokio::fs::create_dir_all(outputs_dir()).await {
+            Ok(()) => tokio::fs::write(&path, &p.output).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            println!("⚠️ Could not save {} output: {}; keeping it", p.name, e);
+            continue;
+        }
+        let Some(artifact) = state.lock().await.artifacts.register(session_id, path) else {
+            continue;
+        };
+        let RigMessage::Assistant { id, content } = &history[p.message] else {
+            continue;
+        };
+        let parts: Vec<AssistantContent> = content
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, c)| {
+                if i != p.part {
+                    return c;
+                }
+                AssistantContent::text(format!(
+                    "{}{}{}; full output: artifact {})]\n{}",
+                    TOOL_OUTPUT_MARKER, p.name, SUMMARIZED_MARKER, artifact.id, summary
+                ))
+            })
+            .collect();
+        if let Ok(content) = OneOrMany::many(parts) {
+            history[p.message] = RigMessage::Assistant { id: id.clone(), content };
+            compressed += 1;
+        }
+    }
+    if compressed > 0 {
+        println!("🗜️ Summarized {} older tool output(s) in session {}", compressed, session_id);
+    }
+    compressed
+}
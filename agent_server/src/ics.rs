@@ -0,0 +1,251 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+/// Octets per iCalendar content line before it is folded (RFC 5545 §3.1).
+const FOLD_AT: usize = 75;
+
+/// Directory exported `.ics` files are written to (`~/.ronge/exports`).
+pub fn exports_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("exports")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Fold a content line at 75 octets without splitting a UTF-8 character.
+fn fold(line: &str) -> String {
+    let mut out = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > FOLD_AT {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out
+}
+
+/// `DTSTART:20250314T150000Z`, or `DTSTART;VALUE=DATE:20250314` for all-day events.
+fn date_property(name: &str, value: &str, all_day: bool) -> Option<String> {
+    if all_day {
+        let date = NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()?;
+        return Some(format!("{};VALUE=DATE:{}", name, date.format("%Y%m%d")));
+    }
+    let time = DateTime::parse_from_rfc3339(value).ok()?;
+    Some(format!("{}:{}", name, time.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ")))
+}
+
+/// An iCalendar document for events in the normalized shape of
+/// `widgets::calendar_event` (`title`, `start`, `end`, `all_day`, `location`,
+/// `description`, `attendees`, `link`, plus an optional `id`).
+pub fn to_ics(events: &[Value]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Rong-E//Calendar Export//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for (i, event) in events.iter().enumerate() {
+        let all_day = event["all_day"].as_bool().unwrap_or(false);
+        let Some(start) = event["start"].as_str().and_then(|s| date_property("DTSTART", s, all_day)) else {
+            continue;
+        };
+        lines.push("BEGIN:VEVENT".to_string());
+        let uid = event["id"].as_str().map(str::to_string).unwrap_or_else(|| format!("{}-{}", stamp, i));
+        lines.push(format!("UID:{}@rong-e", uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(start);
+        if let Some(end) = event["end"].as_str().and_then(|e| date_property("DTEND", e, all_day)) {
+            lines.push(end);
+        }
+        lines.push(format!("SUMMARY:{}", escape(event["title"].as_str().unwrap_or("(No title)"))));
+        for (property, key) in [("LOCATION", "location"), ("DESCRIPTION", "description"), ("URL", "link")] {
+            if let Some(text) = event[key].as_str().filter(|t| !t.is_empty()) {
+                let value = if property == "URL" { text.to_string() } else { escape(text) };
+                lines.push(format!("{}:{}", property, value));
+            }
+        }
+        for attendee in event["attendees"].as_array().into_iter().flatten() {
+            if let Some(email) = attendee.as_str().or_else(|| attendee["email"].as_str()) {
+                lines.push(format!("ATTENDEE;CN={}:mailto:{}", escape(email), email));
+            }
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|l| fold(l)).collect::<Vec<_>>().join("\r\n") + "\r\n"
+}
+
+/// One content line: name, parameters, value.
+struct Property<'a> {
+    name: String,
+    params: Vec<(String, &'a str)>,
+    value: &'a str,
+}
+
+impl Property<'_> {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(n, _)| n == name).map(|(_, v)| v.trim_matches('"'))
+    }
+}
+
+fn parse_line(line: &str) -> Option<Property<'_>> {
+    // The value starts after the first `:` that is not inside a quoted parameter.
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        (c == ':' && !quoted).then_some(i)
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = head.split(';');
+    let name = parts.next()?.to_ascii_uppercase();
+    let params = parts
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.to_ascii_uppercase(), v))
+        .collect();
+    Some(Property { name, params, value })
+}
+
+/// A DTSTART/DTEND value as `(text, all_day, time_zone)`: RFC 3339 for UTC
+/// times, a floating `YYYY-MM-DDTHH:MM:SS` plus its `TZID` otherwise.
+fn parse_date(property: &Property) -> Option<(String, bool, Option<String>)> {
+    let value = property.value.trim();
+    if property.param("VALUE") == Some("DATE") || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some((date.format("%Y-%m-%d").to_string(), true, None));
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((time.and_utc().to_rfc3339(), false, None));
+    }
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some((
+        time.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        false,
+        property.param("TZID").map(str::to_string),
+    ))
+}
+
+fn mailto(value: &str) -> String {
+    let value = value.trim();
+    value
+        .strip_prefix("mailto:")
+        .or_else(|| value.strip_prefix("MAILTO:"))
+        .unwrap_or(value)
+        .to_string()
+}
+
+/// The events of an iCalendar document (an invite attachment) as proposed
+/// events: `title`, `start`, `end`, `all_day`, `time_zone`, `location`,
+/// `description`, `organizer`, `attendees`, `recurrence`, `uid`, plus the
+/// calendar's `method` (`REQUEST` for an invite, `CANCEL` for a cancellation).
+pub fn parse_ics(text: &str) -> Result<Value, String> {
+    // Unfold continuation lines (starting with a space or tab) first.
+    let mut lines: Vec<String> = Vec::new();
+    for raw in text.lines() {
+        let raw = raw.trim_end_matches('\r');
+        match (raw.strip_prefix(' ').or_else(|| raw.strip_prefix('\t')), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(raw.to_string()),
+        }
+    }
+
+    let mut method = None;
+    let mut events = Vec::new();
+    let mut current: Option<serde_json::Map<String, Value>> = None;
+    let mut in_alarm = false;
+    for line in &lines {
+        let Some(property) = parse_line(line) else { continue };
+        match (property.name.as_str(), property.value.trim()) {
+            ("BEGIN", "VEVENT") => current = Some(serde_json::Map::new()),
+            ("BEGIN", "VALARM") => in_alarm = true,
+            ("END", "VALARM") => in_alarm = false,
+            ("END", "VEVENT") => {
+                if let Some(event) = current.take() {
+                    events.push(Value::Object(event));
+                }
+            }
+            ("METHOD", value) if current.is_none() => method = Some(value.to_string()),
+            _ => {}
+        }
+        let Some(event) = current.as_mut().filter(|_| !in_alarm) else { continue };
+        match property.name.as_str() {
+            "SUMMARY" => {
+                event.insert("title".into(), json!(unescape(property.value)));
+            }
+            "LOCATION" => {
+                event.insert("location".into(), json!(unescape(property.value)));
+            }
+            "DESCRIPTION" => {
+                event.insert("description".into(), json!(unescape(property.value)));
+            }
+            "UID" => {
+                event.insert("uid".into(), json!(property.value.trim()));
+            }
+            "RRULE" => {
+                event.insert("recurrence".into(), json!(format!("RRULE:{}", property.value.trim())));
+            }
+            "STATUS" => {
+                event.insert("status".into(), json!(property.value.trim()));
+            }
+            "ORGANIZER" => {
+                event.insert("organizer".into(), json!(mailto(property.value)));
+            }
+            "ATTENDEE" => {
+                let attendees = event.entry("attendees").or_insert_with(|| json!([]));
+                if let Some(list) = attendees.as_array_mut() {
+                    list.push(json!(mailto(property.value)));
+                }
+            }
+            name @ ("DTSTART" | "DTEND") => {
+                if let Some((value, all_day, time_zone)) = parse_date(&property) {
+                    let key = if name == "DTSTART" { "start" } else { "end" };
+                    event.insert(key.into(), json!(value));
+                    if name == "DTSTART" {
+                        event.insert("all_day".into(), json!(all_day));
+                        if let Some(tz) = time_zone {
+                            event.insert("time_zone".into(), json!(tz));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if events.is_empty() {
+        return Err("No events found in the .ics data".to_string());
+    }
+    Ok(json!({"method": method, "events": events}))
+}
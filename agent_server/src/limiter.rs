@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Default number of `call_llm` executions allowed at once.
+const DEFAULT_LIMIT: usize = 2;
+
+/// Global cap on simultaneous LLM turns across all sessions and background
+/// jobs. Configurable with `RONGE_MAX_CONCURRENT_LLM` or the
+/// `set_llm_concurrency` message.
+pub struct LlmLimiter {
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+    limit: usize,
+}
+
+impl LlmLimiter {
+    pub fn from_env() -> Self {
+        let limit = std::env::var("RONGE_MAX_CONCURRENT_LLM")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_LIMIT);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Swap in a fresh semaphore. Turns already holding a permit on the old
+    /// one finish normally; new turns queue against the new limit.
+    pub fn set_limit(&mut self, limit: usize) {
+        let limit = limit.max(1);
+        self.semaphore = Arc::new(Semaphore::new(limit));
+        self.limit = limit;
+    }
+
+    /// Cheap handle that can be awaited without holding the state lock.
+    pub fn handle(&self) -> LimiterHandle {
+        LimiterHandle {
+            semaphore: self.semaphore.clone(),
+            waiting: self.waiting.clone(),
+        }
+    }
+}
+
+pub struct LimiterHandle {
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+}
+
+impl LimiterHandle {
+    /// Take a permit immediately if one is free, otherwise `Err(position)`
+    /// with this caller's 1-based place in the queue. Follow up with
+    /// [`LimiterHandle::wait`].
+    pub fn try_acquire(&self) -> Result<OwnedSemaphorePermit, usize> {
+        self.semaphore
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| self.waiting.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    /// Wait for a permit after `try_acquire` reported a queue position.
+    pub async fn wait(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = self.semaphore.clone().acquire_owned().await.ok();
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        permit
+    }
+
+    /// Acquire without reporting queue position (background jobs).
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        match self.try_acquire() {
+            Ok(permit) => Some(permit),
+            Err(_) => self.wait().await,
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// At most this many links in one answer get a preview card.
+const MAX_PREVIEWS: usize = 3;
+/// Only the head of a page is needed for its title and OpenGraph tags.
+const MAX_HTML_BYTES: usize = 256 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(4);
+const MAX_REDIRECTS: usize = 5;
+
+/// Fetch title / description / `og:image` for the URLs in `text` and return
+/// one `link_preview` widget per page that could be read. Only public hosts
+/// are fetched: a link in an answer (possibly planted in an email or page)
+/// must not make us probe localhost or the LAN.
+///
+/// ```json
+/// {"type": "link_preview", "label": "<title>", "subtitle": "<description>",
+///  "action": {"url": "https://...", "image_url": "https://..."}}
+/// ```
+pub async fn link_preview_widgets(text: &str) -> Vec<Value> {
+    let urls = extract_urls(text);
+    if urls.is_empty() {
+        return Vec::new();
+    }
+    let redirects = reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS || !is_public_url(attempt.url()) {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    });
+    let Ok(client) = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent("Mozilla/5.0 (Macintosh; Rong-E link preview)")
+        .redirect(redirects)
+        .dns_resolver(Arc::new(PublicResolver))
+        .no_proxy()
+        .build()
+    else {
+        return Vec::new();
+    };
+    let previews =
+        futures::future::join_all(urls.iter().map(|url| fetch_preview(&client, url))).await;
+    previews.into_iter().flatten().collect()
+}
+
+/// `http(s)://` links in order of appearance, without duplicates or the
+/// punctuation / markdown brackets that usually surround them.
+fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("http") {
+        let candidate = &rest[start..];
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || matches!(c, ')' | '>' | ']' | '"' | '\'' | '<'))
+            .unwrap_or(candidate.len());
+        let url = candidate[..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        if (url.starts_with("https://") || url.starts_with("http://"))
+            && url.len() > "https://".len()
+            && !urls.iter().any(|u| u == url)
+        {
+            urls.push(url.to_string());
+            if urls.len() == MAX_PREVIEWS {
+                break;
+            }
+        }
+        rest = &candidate[end.max(4)..];
+    }
+    urls
+}
+
+/// Resolves names like the system does, minus every address that isn't
+/// public; a name with nothing left fails to resolve.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.filter(|a| is_public(a.ip())).collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// Not loopback, link-local, private (RFC 1918, CGNAT, IPv6 unique-local)
+/// or unspecified.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let shared = v4.octets()[0] == 100 && (v4.octets()[1] & 0xc0) == 64;
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || shared)
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public(IpAddr::V4(v4)),
+            None => !(v6.is_loopback() || v6.is_unspecified() || v6.is_unique_local() || v6.is_unicast_link_local()),
+        },
+    }
+}
+
+/// Literal IP hosts skip the resolver, so they are checked here.
+fn is_public_url(url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_public(ip),
+        Err(_) => host != "localhost" && !host.ends_with(".localhost"),
+    }
+}
+
+async fn fetch_preview(client: &reqwest::Client, url: &str) -> Option<Value> {
+    if !is_public_url(&reqwest::Url::parse(url).ok()?) {
+        return None;
+    }
+    let mut resp = client.get(url).send().await.ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    let is_html = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"));
+    if !is_html {
+        return None;
+    }
+    let final_url = resp.url().clone();
+
+    let mut body: Vec<u8> = Vec::new();
+    while let Ok(Some(chunk)) = resp.chunk().await {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_HTML_BYTES {
+            break;
+        }
+    }
+    let html = String::from_utf8_lossy(&body);
+
+    let title = meta_content(&html, "og:title").or_else(|| title_tag(&html))?;
+    let description =
+        meta_content(&html, "og:description").or_else(|| meta_content(&html, "description"));
+    let image = meta_content(&html, "og:image")
+        .and_then(|src| final_url.join(&src).ok())
+        .map(|u| u.to_string());
+
+    Some(json!({
+        "type": "link_preview",
+        "label": title,
+        "subtitle": description,
+        "action": {"url": url, "image_url": image},
+    }))
+}
+
+/// `content` of the first `<meta property|name="key">` tag.
+fn meta_content(html: &str, key: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets aligned with `html`.
+    let lower = html.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find("<meta") {
+        let start = from + pos;
+        let end = start + lower[start..].find('>').unwrap_or(lower.len() - start);
+        let tag_lower = &lower[start..end];
+        let names_key = ["property", "name"]
+            .iter()
+            .any(|attr| attr_value(tag_lower, attr).as_deref() == Some(key));
+        if names_key {
+            let content = attr_value(&html[start..end], "content")?;
+            let content = decode_entities(content.trim());
+            return (!content.is_empty()).then_some(content);
+        }
+        from = end;
+    }
+    None
+}
+
+fn title_tag(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = decode_entities(html[start..end].trim());
+    (!title.is_empty()).then_some(title)
+}
+
+/// Value of `attr="..."` or `attr='...'` inside a single tag.
+fn attr_value(tag: &str, attr: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(attr) {
+        let idx = from + pos;
+        from = idx + attr.len();
+        // Must be a whole attribute name (e.g. not `data-content`).
+        if idx > 0 && !lower.as_bytes()[idx - 1].is_ascii_whitespace() {
+            continue;
+        }
+        let after = tag[from..].trim_start();
+        let Some(after) = after.strip_prefix('=') else {
+            continue;
+        };
+        let after = after.trim_start();
+        let quote = after.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            continue;
+        }
+        let value = &after[1..];
+        return value.find(quote).map(|end| value[..end].to_string());
+    }
+    None
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn private_and_local_addresses_are_not_public() {
+        for addr in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:192.168.0.1",
+        ] {
+            assert!(!is_public(ip(addr)), "{} should not be public", addr);
+        }
+    }
+
+    #[test]
+    fn internet_addresses_are_public() {
+        for addr in ["93.184.216.34", "100.128.0.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public(ip(addr)), "{} should be public", addr);
+        }
+    }
+
+    #[test]
+    fn urls_with_local_hosts_are_refused() {
+        let public = |url: &str| is_public_url(&reqwest::Url::parse(url).unwrap());
+        assert!(public("https://example.com/page"));
+        assert!(public("http://93.184.216.34/"));
+        assert!(!public("http://localhost:8080/"));
+        assert!(!public("http://admin.localhost/"));
+        assert!(!public("http://127.0.0.1/"));
+        assert!(!public("http://[::1]:3000/"));
+        assert!(!public("http://[::ffff:10.0.0.1]/"));
+        assert!(!public("file:///etc/passwd"));
+    }
+}
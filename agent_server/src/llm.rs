@@ -1,15 +1,20 @@
+use crate::provider_http::TapClient;
 use crate::tools::{
-    AppendToMemory, Calculator, NotifyingTool, OpenApplication, OpenChromeTab,
-    ReadMemory, SaveToMemory, ToolEventSender,
+    AppendToMemory, Calculator, ExportToGoogleDoc, NotifyingTool, OpenApplication, OpenChromeTab,
+    ReadMemory, ReadScratchpad, RenderChart, SaveToMemory, ToolEventSender, WriteScratchpad,
 };
 use rig::{
-    completion::Chat,
-    message::{DocumentSourceKind, Image, ImageMediaType, Message as RigMessage, UserContent},
-    providers::{anthropic, gemini, ollama, openai},
+    completion::{Chat, Prompt},
+    message::{
+        Document, DocumentMediaType, DocumentSourceKind, Image, ImageMediaType, Message as RigMessage, UserContent,
+    },
+    providers::{anthropic, azure, gemini, mistral, ollama, openai},
     OneOrMany,
 };
 use rig::client::CompletionClient;
-use rig::client::ProviderClient;
+use rig::tool::Tool;
+use std::sync::Arc;
+use std::time::Duration;
 
 const SYSTEM_PROMPT_TEMPLATE: &str = include_str!("../prompts/system_prompt.txt");
 
@@ -19,41 +24,98 @@ pub async fn call_llm(
     api_key: String,
     model: String,
     query: String,
-    chat_history: Vec<RigMessage>,
-    mcp_tool_sets: Vec<(Vec<rmcp::model::Tool>, rmcp::service::ServerSink)>,
+    chat_history: &mut Vec<RigMessage>,
+    mcp_tool_sets: Vec<crate::state::McpToolSet>,
     system_prompt: Option<String>,
-    base64_image: Option<String>,
+    images: Vec<ChatImage>,
     tool_tx: ToolEventSender,
     user_name: Option<String>,
-) -> Result<String, String> {
+    debug: Option<crate::debug_dump::DebugDump>,
+    mode: &'static crate::modes::AgentMode,
+    reasoning: crate::reasoning::Reasoning,
+    generation: crate::generation::GenerationParams,
+) -> Result<String, LlmError> {
     let memory_path = crate::tools::default_memory_path();
+    // Gmail and Sheets results can fill a small model's context on their own.
+    let tool_tx = tool_tx.with_output_budget(crate::output_budget::OutputBudget::for_model(&provider, &model));
 
-    let user_name = user_name
-        .filter(|n| !n.is_empty())
-        .unwrap_or_else(|| std::env::var("USER").unwrap_or_else(|_| "User".to_string()));
+    let final_prompt = compose_preamble(user_name, mode, system_prompt.as_deref()).text();
 
-    let now = chrono::Local::now();
-    let current_datetime = now.format("%A, %B %-d, %Y %H:00").to_string();
+    // A replayed turn is offered the recorded tool lists as they were, so
+    // the wrappers, sub-agents and pruning below are not rebuilt for it.
+    let replays = tool_tx.replays();
+
+    // Column-mapped Sheets appends, Calendar quick-add/get-event and email
+    // summarization over the connected Google tools, for the main agent and
+    // the sub-agents alike.
+    let (sheet_records, calendar_tools, email_summary) = if replays {
+        (None, None, None)
+    } else {
+        (
+            crate::sheets::connect(&mcp_tool_sets).await,
+            crate::calendar::connect(&mcp_tool_sets).await,
+            crate::email_summary::connect(&mcp_tool_sets, &provider, &api_key, &model, tool_tx.redacts_pii())
+                .await,
+        )
+    };
+    let mut mcp_tool_sets = mcp_tool_sets;
+    for conn in sheet_records.iter().chain(calendar_tools.iter()).chain(email_summary.iter()) {
+        mcp_tool_sets.push(conn.tool_set());
+    }
 
-    let base_prompt = SYSTEM_PROMPT_TEMPLATE
-        .replace("{user_name}", &user_name)
-        .replace("{current_datetime}", &current_datetime);
+    // Sub-agents use the turn's provider and every connected MCP tool; the
+    // connection serving them must outlive the turn.
+    let subagents = if replays {
+        None
+    } else {
+        crate::subagent::connect(crate::subagent::TurnContext {
+            provider: provider.clone(),
+            api_key: api_key.clone(),
+            model: model.clone(),
+            mcp_tool_sets: mcp_tool_sets.clone(),
+            tx: tool_tx.clone(),
+        })
+        .await
+    };
+    let mut mcp_tool_sets: Vec<crate::state::McpToolSet> =
+        mcp_tool_sets.into_iter().filter(|set| !set.subagent_only).collect();
+    if let Some(conn) = &subagents {
+        mcp_tool_sets.push(conn.tool_set());
+    }
+    let docs_target = crate::docs_export::find_create_tool(&mcp_tool_sets).map(Arc::new);
+    let mcp_tool_sets = mode.filter_mcp(mcp_tool_sets);
+    // Small local models get only the tools that match the query.
+    let mcp_tool_sets = if replays {
+        mcp_tool_sets
+    } else {
+        crate::tool_pruning::prune(&provider, &model, &query, mcp_tool_sets, &tool_tx).await
+    };
 
-    let final_prompt = if let Some(ref mode_prompt) = system_prompt {
-        format!("{}\n\n{}", base_prompt, mode_prompt)
+    // Mask PII in everything that leaves the machine for a cloud provider.
+    let mut redacted_history;
+    let (final_prompt, query, chat_history) = if tool_tx.redacts_pii() {
+        redacted_history = crate::pii::redact_history(chat_history);
+        (crate::pii::redact(&final_prompt), crate::pii::redact(&query), &mut redacted_history)
     } else {
-        base_prompt
+        (final_prompt, query, chat_history)
     };
 
-    println!("🧠 Final system prompt:\n{}", final_prompt);
+    if let Some(ref dump) = debug {
+        dump_turn_inputs(dump, &final_prompt, &query, chat_history, &mcp_tool_sets, &memory_path, mode)
+            .await;
+        println!("🐞 Debug dump: {}", dump.dir().display());
+    }
 
     // Wrap each MCP connection with a notification proxy so tool_call/tool_result
     // events are emitted for MCP tools.
     let mut _proxy_guards: Vec<crate::mcp_proxy::McpProxyGuard> = Vec::new();
     let mut proxied_mcp_tool_sets: Vec<(Vec<rmcp::model::Tool>, rmcp::service::ServerSink)> =
         Vec::new();
-    for (tools, peer) in mcp_tool_sets {
-        match crate::mcp_proxy::create_notifying_proxy(tools, peer, tool_tx.clone()).await {
+    for tool_set in mcp_tool_sets {
+        if let Some(tape) = tool_tx.tape().filter(|t| !t.replays()) {
+            tape.push_tool_set(&tool_set.tools);
+        }
+        match crate::mcp_proxy::create_notifying_proxy(tool_set, tool_tx.clone()).await {
             Ok((sanitized_tools, proxy_peer, guard)) => {
                 proxied_mcp_tool_sets.push((sanitized_tools, proxy_peer));
                 _proxy_guards.push(guard);
@@ -64,79 +126,284 @@ pub async fn call_llm(
         }
     }
 
+    // An approved plan gets room for all of its steps.
+    let max_turns = tool_tx.plan().map_or(generation.max_turns(), |plan| plan.max_turns());
+
+    // Reasoning effort / thinking budget, plus Ollama's keep_alive.
+    let mut additional_params = reasoning.params(&provider, &model);
+    if provider == "ollama" {
+        let params = additional_params.get_or_insert_with(|| serde_json::json!({}));
+        params["keep_alive"] = serde_json::json!(crate::ollama::keep_alive());
+    }
+    let max_tokens = generation.max_tokens(&provider, &reasoning);
+    let temperature = generation.temperature(&provider, &model, &reasoning);
+
     macro_rules! build_agent {
         ($builder_expr:expr) => {{
             let tx = &tool_tx;
-            let mut builder = $builder_expr
+            let mut agent = $builder_expr;
+            if let Some(params) = &additional_params {
+                agent = agent.additional_params(params.clone());
+            }
+            if let Some(max_tokens) = max_tokens {
+                agent = agent.max_tokens(max_tokens);
+            }
+            if let Some(temperature) = temperature {
+                agent = agent.temperature(temperature);
+            }
+            // The calculator is always attached; the mode decides the rest.
+            let mut builder = agent
                 .tool(NotifyingTool { inner: Calculator, tx: tx.clone() })
-                .tool(NotifyingTool { inner: OpenApplication, tx: tx.clone() })
-                .tool(NotifyingTool { inner: OpenChromeTab, tx: tx.clone() })
-                .tool(NotifyingTool { inner: ReadMemory::new(memory_path.clone()), tx: tx.clone() })
-                .tool(NotifyingTool { inner: SaveToMemory::new(memory_path.clone()), tx: tx.clone() })
-                .tool(NotifyingTool { inner: AppendToMemory::new(memory_path.clone()), tx: tx.clone() })
                 .preamble(&final_prompt);
+            if mode.allows_builtin(OpenApplication::NAME) {
+                builder = builder.tool(NotifyingTool { inner: OpenApplication, tx: tx.clone() });
+            }
+            if mode.allows_builtin(OpenChromeTab::NAME) {
+                builder = builder.tool(NotifyingTool { inner: OpenChromeTab, tx: tx.clone() });
+            }
+            if mode.allows_builtin(ReadMemory::NAME) {
+                builder = builder.tool(NotifyingTool { inner: ReadMemory::new(memory_path.clone()).with_pii_redaction(tx.redacts_pii()), tx: tx.clone() });
+            }
+            if mode.allows_builtin(SaveToMemory::NAME) {
+                builder = builder.tool(NotifyingTool { inner: SaveToMemory::new(memory_path.clone()).with_dry_run(tx.is_dry_run()), tx: tx.clone() });
+            }
+            if mode.allows_builtin(AppendToMemory::NAME) {
+                builder = builder.tool(NotifyingTool { inner: AppendToMemory::new(memory_path.clone()).with_dry_run(tx.is_dry_run()), tx: tx.clone() });
+            }
+            if mode.allows_builtin(WriteScratchpad::NAME) {
+                builder = builder.tool(NotifyingTool { inner: WriteScratchpad { pad: tx.scratchpad() }, tx: tx.clone() });
+            }
+            if mode.allows_builtin(ReadScratchpad::NAME) {
+                builder = builder.tool(NotifyingTool { inner: ReadScratchpad { pad: tx.scratchpad() }, tx: tx.clone() });
+            }
+            if mode.allows_builtin(RenderChart::NAME) {
+                builder = builder.tool(NotifyingTool { inner: RenderChart, tx: tx.clone() });
+            }
+            if mode.allows_builtin(crate::code_exec::ExecuteCode::NAME) {
+                builder = builder.tool(NotifyingTool { inner: crate::code_exec::ExecuteCode { tx: tx.clone() }, tx: tx.clone() });
+            }
+            if mode.allows_builtin(crate::netcheck::NetworkCheck::NAME) {
+                builder = builder.tool(NotifyingTool { inner: crate::netcheck::NetworkCheck, tx: tx.clone() });
+            }
+            if mode.allows_builtin(crate::world_clock::ConvertTime::NAME) {
+                builder = builder.tool(NotifyingTool { inner: crate::world_clock::ConvertTime, tx: tx.clone() });
+            }
+            if mode.allows_builtin(crate::date_info::DateInfo::NAME) {
+                builder = builder.tool(NotifyingTool { inner: crate::date_info::DateInfo, tx: tx.clone() });
+            }
+            if tx.secret_refs().is_enabled() && mode.allows_builtin(crate::secret_refs::GetSecret::NAME) {
+                builder = builder.tool(NotifyingTool { inner: crate::secret_refs::GetSecret { tx: tx.clone() }, tx: tx.clone() });
+            }
+            if mode.allows_builtin(crate::archive::ListArchive::NAME) {
+                builder = builder.tool(NotifyingTool { inner: crate::archive::ListArchive, tx: tx.clone() });
+            }
+            if mode.allows_builtin(crate::archive::ExtractArchive::NAME) {
+                builder = builder.tool(NotifyingTool { inner: crate::archive::ExtractArchive, tx: tx.clone() });
+            }
+            if let Some(target) = &docs_target
+                && mode.allows_builtin(ExportToGoogleDoc::NAME)
+            {
+                builder = builder.tool(NotifyingTool { inner: ExportToGoogleDoc { target: target.clone() }, tx: tx.clone() });
+            }
+            if let Some(plan) = tx.plan() {
+                builder = builder.tool(NotifyingTool { inner: crate::planner::PlanCheckpoint { plan: plan.clone(), tx: tx.clone() }, tx: tx.clone() });
+            }
             for (tools, peer) in proxied_mcp_tool_sets {
                 builder = builder.rmcp_tools(tools, peer);
             }
-            builder.default_max_turns(15).build()
+            builder.default_max_turns(max_turns).build()
+        }};
+    }
+
+    // Streamed turns send the answer as it is generated; the result is the same.
+    // Rate limits and overloads are retried with backoff (`retry.rs`).
+    // Each attempt borrows the history and leaves it as it was, except that
+    // rig's streaming API only takes it by value, so a streamed attempt gets
+    // a copy.
+    let chat_history = tokio::sync::Mutex::new(chat_history);
+    macro_rules! run_agent {
+        ($agent:expr) => {{
+            let (agent, query, history, images, tx) = (&$agent, &query, &chat_history, &images, &tool_tx);
+            crate::retry::with_retries(&provider, &model, &tool_tx, move || async move {
+                let mut history = history.lock().await;
+                if tx.streams() {
+                    stream_with_agent(agent, query, history.to_vec(), images, max_turns, tx).await
+                } else {
+                    chat_with_agent(agent, query, &mut history, images).await
+                }
+            })
+            .await
         }};
     }
 
-    match provider.as_str() {
+    let result = match provider.as_str() {
         "gemini" => {
-            let client = gemini::Client::new(&api_key).map_err(|e| e.to_string())?;
-            let agent = build_agent!(client.agent(&model));
-            chat_with_agent(&agent, &query, chat_history, base64_image.as_deref()).await
+            let client = gemini_client(&api_key)?;
+            let agent = build_agent!(gemini_agent(client, &model));
+            run_agent!(agent)
         }
         "openai" => {
-            let client: openai::Client =
-                openai::Client::new(&api_key).map_err(|e| e.to_string())?;
+            let client = openai_client(&api_key)?;
             let agent = build_agent!(client.agent(&model));
-            chat_with_agent(&agent, &query, chat_history, base64_image.as_deref()).await
+            run_agent!(agent)
         }
         "anthropic" => {
-            let client: anthropic::Client =
-                anthropic::Client::new(&api_key).map_err(|e| e.to_string())?;
+            let client = anthropic_client(&api_key)?;
             let agent = build_agent!(client.agent(&model));
-            chat_with_agent(&agent, &query, chat_history, base64_image.as_deref()).await
+            run_agent!(agent)
         }
         "ollama" => {
-            let client = ollama::Client::from_env();
+            // A model evicted since the session's warm-up loads here; tell
+            // the client instead of leaving the first reply silently slow.
+            if !replays && !crate::ollama::is_loaded(&model).await {
+                let started = std::time::Instant::now();
+                let _ = tool_tx.send(crate::ollama::loading_event(&model, "loading", None, None)).await;
+                let event = match crate::ollama::load(&model).await {
+                    Ok(()) => crate::ollama::loading_event(&model, "ready", Some(started.elapsed()), None),
+                    Err(e) => crate::ollama::loading_event(&model, "failed", Some(started.elapsed()), Some(&e)),
+                };
+                let _ = tool_tx.send(event).await;
+            }
+            let client = ollama_client()?;
             let agent = build_agent!(client.agent(&model));
-            chat_with_agent(&agent, &query, chat_history, base64_image.as_deref()).await
+            run_agent!(agent)
         }
         "openrouter" => {
-            let client: openai::Client<reqwest::Client> = openai::Client::builder()
-                .api_key(api_key.clone())
-                .base_url("https://openrouter.ai/api/v1")
-                .build()
-                .map_err(|e| e.to_string())?;
+            let client = openrouter_client(&api_key)?;
             let agent = build_agent!(client.agent(&model));
-            chat_with_agent(&agent, &query, chat_history, base64_image.as_deref()).await
+            run_agent!(agent)
+        }
+        "azure_openai" => {
+            let client = azure_client(&api_key)?;
+            let agent = build_agent!(client.agent(&model));
+            run_agent!(agent)
+        }
+        "mistral" => {
+            let client = mistral_client(&api_key)?;
+            let agent = build_agent!(client.agent(&model));
+            run_agent!(agent)
+        }
+        "openai_compatible" => {
+            let client = openai_compatible_client(&api_key)?;
+            let agent = build_agent!(client.agent(&model));
+            run_agent!(agent)
+        }
+        "mock" => {
+            crate::mock_provider::run(&model, &query, proxied_mcp_tool_sets, &tool_tx, &memory_path)
+                .await
         }
         _ => Err(format!("Unsupported provider: {}", provider)),
+    };
+    result.map_err(|e| LlmError::classify(&provider, e))
+}
+
+/// The sections of a turn's preamble, in order: the system prompt template
+/// (with user name and current time), the profile's language instruction,
+/// the mode's preamble and the client's per-turn system prompt (spreadsheet
+/// context and the like).
+pub struct Preamble {
+    pub sections: Vec<(&'static str, String)>,
+}
+
+impl Preamble {
+    pub fn text(&self) -> String {
+        self.sections.iter().map(|(_, text)| text.as_str()).collect::<Vec<_>>().join("\n\n")
     }
 }
 
-/// Makes a minimal test call to verify the provider/model/key combination is valid.
+pub fn compose_preamble(
+    user_name: Option<String>,
+    mode: &crate::modes::AgentMode,
+    system_prompt: Option<&str>,
+) -> Preamble {
+    let user_name = user_name
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| std::env::var("USER").unwrap_or_else(|_| "User".to_string()));
+
+    let profile = crate::profile::Profile::load();
+    let current_datetime = profile.format_datetime(chrono::Local::now(), true);
+
+    let mut sections = vec![(
+        "base",
+        SYSTEM_PROMPT_TEMPLATE
+            .replace("{user_name}", &user_name)
+            .replace("{current_datetime}", &current_datetime),
+    )];
+    if let Some(instruction) = profile.language_instruction() {
+        sections.push(("language", instruction));
+    }
+    if !mode.preamble.is_empty() {
+        sections.push(("mode", mode.preamble.to_string()));
+    }
+    if let Some(mode_prompt) = system_prompt {
+        sections.push(("client", mode_prompt.to_string()));
+    }
+    Preamble { sections }
+}
+
+/// Write the rendered prompt, history and tool definitions for a debug turn.
+async fn dump_turn_inputs(
+    dump: &crate::debug_dump::DebugDump,
+    final_prompt: &str,
+    query: &str,
+    chat_history: &[RigMessage],
+    mcp_tool_sets: &[crate::state::McpToolSet],
+    memory_path: &std::path::Path,
+    mode: &crate::modes::AgentMode,
+) {
+    dump.write("system_prompt.txt", final_prompt).await;
+    dump.write("query.txt", query).await;
+    dump.write_json("history.json", &chat_history).await;
+
+    let builtin = vec![
+        Calculator.definition(String::new()).await,
+        OpenApplication.definition(String::new()).await,
+        OpenChromeTab.definition(String::new()).await,
+        ReadMemory::new(memory_path.to_path_buf()).definition(String::new()).await,
+        SaveToMemory::new(memory_path.to_path_buf()).definition(String::new()).await,
+        AppendToMemory::new(memory_path.to_path_buf()).definition(String::new()).await,
+        WriteScratchpad::default().definition(String::new()).await,
+        ReadScratchpad::default().definition(String::new()).await,
+        RenderChart.definition(String::new()).await,
+        crate::code_exec::ExecuteCode { tx: crate::tools::tool_event_channel(1).0 }.definition(String::new()).await,
+        crate::archive::ListArchive.definition(String::new()).await,
+        crate::archive::ExtractArchive.definition(String::new()).await,
+        crate::netcheck::NetworkCheck.definition(String::new()).await,
+        crate::world_clock::ConvertTime.definition(String::new()).await,
+        crate::date_info::DateInfo.definition(String::new()).await,
+    ];
+    let builtin: Vec<_> = builtin
+        .into_iter()
+        .filter(|d| d.name == Calculator::NAME || mode.allows_builtin(&d.name))
+        .collect();
+    let mcp: Vec<&rmcp::model::Tool> = mcp_tool_sets.iter().flat_map(|set| set.tools.iter()).collect();
+    dump.write_json("tools.json", &serde_json::json!({"builtin": builtin, "mcp": mcp}))
+        .await;
+}
+
+/// Verify the provider/model/key combination: through the provider's free
+/// metadata endpoint (`verify.rs`) or, for Ollama, that the model is pulled;
+/// a minimal test call only when neither gives an answer.
 pub async fn verify_llm(provider: &str, api_key: &str, model: &str) -> Result<(), String> {
+    if let Some(result) = crate::verify::check_metadata(provider, api_key, model).await {
+        return result;
+    }
     let ping = RigMessage::User {
         content: OneOrMany::one(UserContent::text("Hi")),
     };
     match provider {
         "gemini" => {
-            let client = gemini::Client::new(api_key).map_err(|e| e.to_string())?;
-            let agent = client.agent(model).build();
+            let client = gemini_client(api_key)?;
+            let agent = gemini_agent(client, model).build();
             agent.chat(ping, vec![]).await.map(|_| ()).map_err(|e| e.to_string())
         }
         "openai" => {
-            let client: openai::Client = openai::Client::new(api_key).map_err(|e| e.to_string())?;
+            let client = openai_client(api_key)?;
             let agent = client.agent(model).build();
             agent.chat(ping, vec![]).await.map(|_| ()).map_err(|e| e.to_string())
         }
         "anthropic" => {
-            let client: anthropic::Client =
-                anthropic::Client::new(api_key).map_err(|e| e.to_string())?;
+            let client = anthropic_client(api_key)?;
             let agent = client.agent(model).build();
             agent.chat(ping, vec![]).await.map(|_| ()).map_err(|e| e.to_string())
         }
@@ -149,11 +416,20 @@ pub async fn verify_llm(provider: &str, api_key: &str, model: &str) -> Result<()
             )
             .await;
             match reachable {
-                Ok(Ok(_)) => {
-                    let client = ollama::Client::from_env();
-                    let agent = client.agent(model).build();
-                    agent.chat(ping, vec![]).await.map(|_| ()).map_err(|e| e.to_string())
-                }
+                Ok(Ok(_)) => match crate::ollama::has_model(model).await {
+                    Ok(true) => Ok(()),
+                    Ok(false) => Err(format!("Model '{}' isn't pulled yet. Run `ollama pull {}` first.", model, model)),
+                    // Older servers without `/api/show`: try the model itself.
+                    Err(e) => {
+                        println!("⚠️ Ollama model check failed: {}; sending a test message", e);
+                        let client = ollama_client()?;
+                        let agent = client
+                            .agent(model)
+                            .additional_params(serde_json::json!({"keep_alive": crate::ollama::keep_alive()}))
+                            .build();
+                        agent.chat(ping, vec![]).await.map(|_| ()).map_err(|e| e.to_string())
+                    }
+                },
                 _ => Err(
                     "Ollama doesn't appear to be running. Please start it with `ollama serve`."
                         .to_string(),
@@ -161,58 +437,664 @@ pub async fn verify_llm(provider: &str, api_key: &str, model: &str) -> Result<()
             }
         }
         "openrouter" => {
-            let client: openai::Client<reqwest::Client> = openai::Client::builder()
-                .api_key(api_key)
-                .base_url("https://openrouter.ai/api/v1")
-                .build()
-                .map_err(|e| e.to_string())?;
+            let client = openrouter_client(api_key)?;
             let agent = client.agent(model).build();
             agent.chat(ping, vec![]).await.map(|_| ()).map_err(|e| e.to_string())
         }
+        // Azure has no key-only metadata endpoint for a deployment.
+        "azure_openai" => {
+            let client = azure_client(api_key)?;
+            let agent = client.agent(model).build();
+            agent.chat(ping, vec![]).await.map(|_| ()).map_err(|e| e.to_string())
+        }
+        "mistral" => {
+            let client = mistral_client(api_key)?;
+            let agent = client.agent(model).build();
+            agent.chat(ping, vec![]).await.map(|_| ()).map_err(|e| e.to_string())
+        }
+        "openai_compatible" => {
+            let client = openai_compatible_client(api_key)?;
+            let agent = client.agent(model).build();
+            agent.chat(ping, vec![]).await.map(|_| ()).map_err(|e| e.to_string())
+        }
+        "mock" => crate::mock_provider::load_scenario(model).await.map(|_| ()),
         _ => Err(format!("Unsupported provider: {}", provider)),
     }
 }
 
-async fn chat_with_agent(
-    agent: &impl Chat,
-    query: &str,
-    history: Vec<RigMessage>,
-    base64_image: Option<&str>,
+/// One-shot, tool-less completion for small background tasks (titles,
+/// summaries). Uses the same provider/model/key as chat.
+pub async fn complete(
+    provider: &str,
+    api_key: &str,
+    model: &str,
+    preamble: &str,
+    prompt: &str,
 ) -> Result<String, String> {
-    let new_message = if let Some(img_data) = base64_image {
-        if !img_data.is_empty() {
-            let image = Image {
-                data: DocumentSourceKind::base64(img_data),
-                media_type: Some(ImageMediaType::PNG),
-                ..Default::default()
-            };
-            let content = OneOrMany::many(vec![
-                UserContent::text(query),
-                UserContent::Image(image),
-            ])
-            .map_err(|e| e.to_string())?;
-            RigMessage::User { content }
-        } else {
-            RigMessage::User {
-                content: OneOrMany::one(UserContent::text(query)),
+    let message = RigMessage::User {
+        content: OneOrMany::one(UserContent::text(prompt)),
+    };
+    match provider {
+        "gemini" => {
+            let client = gemini_client(api_key)?;
+            let agent = gemini_agent(client, model).preamble(preamble).build();
+            agent.chat(message, vec![]).await.map_err(|e| e.to_string())
+        }
+        "openai" => {
+            let client = openai_client(api_key)?;
+            let agent = client.agent(model).preamble(preamble).build();
+            agent.chat(message, vec![]).await.map_err(|e| e.to_string())
+        }
+        "anthropic" => {
+            let client = anthropic_client(api_key)?;
+            let agent = client.agent(model).preamble(preamble).build();
+            agent.chat(message, vec![]).await.map_err(|e| e.to_string())
+        }
+        "ollama" => {
+            let client = ollama_client()?;
+            let agent = client.agent(model).preamble(preamble).build();
+            agent.chat(message, vec![]).await.map_err(|e| e.to_string())
+        }
+        "openrouter" => {
+            let client = openrouter_client(api_key)?;
+            let agent = client.agent(model).preamble(preamble).build();
+            agent.chat(message, vec![]).await.map_err(|e| e.to_string())
+        }
+        "azure_openai" => {
+            let client = azure_client(api_key)?;
+            let agent = client.agent(model).preamble(preamble).build();
+            agent.chat(message, vec![]).await.map_err(|e| e.to_string())
+        }
+        "mistral" => {
+            let client = mistral_client(api_key)?;
+            let agent = client.agent(model).preamble(preamble).build();
+            agent.chat(message, vec![]).await.map_err(|e| e.to_string())
+        }
+        "openai_compatible" => {
+            let client = openai_compatible_client(api_key)?;
+            let agent = client.agent(model).preamble(preamble).build();
+            agent.chat(message, vec![]).await.map_err(|e| e.to_string())
+        }
+        "mock" => Ok("Mock conversation".to_string()),
+        _ => Err(format!("Unsupported provider: {}", provider)),
+    }
+}
+
+/// Opens the note that stands in for summarized turns.
+const HISTORY_SUMMARY_MARKER: &str = "[Summary of the earlier conversation]";
+/// Characters of one message given to the summarizer.
+const SUMMARY_INPUT_CHARS_PER_MESSAGE: usize = 4_000;
+
+const HISTORY_SUMMARY_PREAMBLE: &str = "You condense the older part of a conversation between a user and \
+an assistant so the assistant can continue it. Keep the user's goals and preferences, decisions made, \
+open questions and tasks, and facts a follow-up could need: names, IDs, dates, amounts, file paths and \
+results of tool calls. If the transcript starts with an earlier summary, fold it in. Write compact notes \
+in the conversation's language, at most 25 lines; do not invent details.";
+
+/// How many messages `summarize_history` replaced and the history's
+/// estimated size before and after.
+pub struct HistorySummary {
+    pub replaced: usize,
+    pub tokens_before: u64,
+    pub tokens_after: u64,
+}
+
+/// When the history is over its token budget (`context_usage::history_budget`),
+/// replace its oldest turns with a summary from the provider's small model:
+/// a user request for a recap followed by an assistant note, so roles still
+/// alternate. Turns are kept whole, newest first, up to half the budget, and
+/// the latest turn always stays. `Ok(None)` when nothing needed summarizing.
+pub async fn summarize_history(
+    provider: &str,
+    api_key: &str,
+    model: &str,
+    history: &mut Vec<RigMessage>,
+    redact_pii: bool,
+) -> Result<Option<HistorySummary>, String> {
+    let tokens_before = crate::context_usage::history_tokens(provider, history);
+    let budget = crate::context_usage::history_budget(provider, model);
+    if tokens_before <= budget {
+        return Ok(None);
+    }
+
+    // The earliest turn start from which the rest fits in half the budget.
+    let mut kept = 0;
+    let mut cut = None;
+    for (i, message) in history.iter().enumerate().rev() {
+        kept += crate::context_usage::message_tokens(provider, message);
+        if matches!(message, RigMessage::User { .. }) {
+            if cut.is_some() && kept > budget / 2 {
+                break;
             }
+            cut = Some(i);
         }
-    } else {
+    }
+    let Some(cut) = cut.filter(|cut| *cut >= 2) else {
+        return Ok(None);
+    };
+
+    let transcript: Vec<String> = history[..cut]
+        .iter()
+        .map(|message| {
+            let (role, text) = match message {
+                RigMessage::User { content } => (
+                    "User",
+                    content
+                        .iter()
+                        .filter_map(|c| match c {
+                            UserContent::Text(t) => Some(t.text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+                RigMessage::Assistant { content, .. } => (
+                    "Assistant",
+                    content
+                        .iter()
+                        .filter_map(|c| match c {
+                            rig::message::AssistantContent::Text(t) => Some(t.text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+            };
+            let text = if redact_pii { crate::pii::redact(&text) } else { text };
+            let truncated = text.chars().count() > SUMMARY_INPUT_CHARS_PER_MESSAGE;
+            let mut text: String = text.chars().take(SUMMARY_INPUT_CHARS_PER_MESSAGE).collect();
+            if truncated {
+                text.push_str(" …");
+            }
+            format!("{}: {}", role, text)
+        })
+        .collect();
+
+    let summary_model = crate::email_summary::summary_model(provider, model);
+    let summary = complete(provider, api_key, &summary_model, HISTORY_SUMMARY_PREAMBLE, &transcript.join("\n\n")).await?;
+    let summary = summary.trim();
+    if summary.is_empty() {
+        return Err("the summary came back empty".to_string());
+    }
+
+    let note = [
         RigMessage::User {
-            content: OneOrMany::one(UserContent::text(query)),
+            content: OneOrMany::one(UserContent::text("Summarize our conversation so far.")),
+        },
+        RigMessage::Assistant {
+            id: Default::default(),
+            content: OneOrMany::one(rig::message::AssistantContent::text(format!(
+                "{}\n{}",
+                HISTORY_SUMMARY_MARKER, summary
+            ))),
+        },
+    ];
+    history.splice(..cut, note);
+    let tokens_after = crate::context_usage::history_tokens(provider, history);
+    println!("🧾 Summarized {} older message(s): ~{} → ~{} tokens", cut, tokens_before, tokens_after);
+    Ok(Some(HistorySummary { replaced: cut, tokens_before, tokens_after }))
+}
+
+/// Run a tool-using agent whose only tools are the given MCP tool sets (plus
+/// the calculator). Shared by every sub-agent in `subagent.rs`; tool events
+/// go to `tool_tx` like the main agent's.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_mcp_agent(
+    provider: &str,
+    api_key: &str,
+    model: &str,
+    preamble: &str,
+    prompt: &str,
+    mcp_tool_sets: Vec<crate::state::McpToolSet>,
+    tool_tx: &ToolEventSender,
+    max_turns: usize,
+) -> Result<String, String> {
+    let mut _proxy_guards: Vec<crate::mcp_proxy::McpProxyGuard> = Vec::new();
+    let mut proxied_mcp_tool_sets: Vec<(Vec<rmcp::model::Tool>, rmcp::service::ServerSink)> =
+        Vec::new();
+    for tool_set in mcp_tool_sets {
+        match crate::mcp_proxy::create_notifying_proxy(tool_set, tool_tx.clone()).await {
+            Ok((sanitized_tools, proxy_peer, guard)) => {
+                proxied_mcp_tool_sets.push((sanitized_tools, proxy_peer));
+                _proxy_guards.push(guard);
+            }
+            Err(e) => {
+                println!("⚠️ MCP notification proxy failed (tool events skipped): {}", e);
+            }
         }
-    };
+    }
+
+    macro_rules! build_agent {
+        ($builder_expr:expr) => {{
+            let mut builder = $builder_expr
+                .tool(NotifyingTool { inner: Calculator, tx: tool_tx.clone() })
+                .preamble(preamble);
+            for (tools, peer) in proxied_mcp_tool_sets {
+                builder = builder.rmcp_tools(tools, peer);
+            }
+            builder.default_max_turns(max_turns).build()
+        }};
+    }
+
+    macro_rules! run_agent {
+        ($agent:expr) => {{
+            let agent = &$agent;
+            crate::retry::with_retries(provider, model, tool_tx, move || async move {
+                chat_with_agent(agent, prompt, &mut Vec::new(), &[]).await
+            })
+            .await
+        }};
+    }
+
+    match provider {
+        "gemini" => {
+            let client = gemini_client(api_key)?;
+            let agent = build_agent!(gemini_agent(client, model));
+            run_agent!(agent)
+        }
+        "openai" => {
+            let client = openai_client(api_key)?;
+            let agent = build_agent!(client.agent(model));
+            run_agent!(agent)
+        }
+        "anthropic" => {
+            let client = anthropic_client(api_key)?;
+            let agent = build_agent!(client.agent(model));
+            run_agent!(agent)
+        }
+        "ollama" => {
+            let client = ollama_client()?;
+            let agent = build_agent!(client.agent(model));
+            run_agent!(agent)
+        }
+        "openrouter" => {
+            let client = openrouter_client(api_key)?;
+            let agent = build_agent!(client.agent(model));
+            run_agent!(agent)
+        }
+        "azure_openai" => {
+            let client = azure_client(api_key)?;
+            let agent = build_agent!(client.agent(model));
+            run_agent!(agent)
+        }
+        "mistral" => {
+            let client = mistral_client(api_key)?;
+            let agent = build_agent!(client.agent(model));
+            run_agent!(agent)
+        }
+        "openai_compatible" => {
+            let client = openai_compatible_client(api_key)?;
+            let agent = build_agent!(client.agent(model));
+            run_agent!(agent)
+        }
+        "mock" => Ok(format!("Mock sub-agent result for: {}", prompt)),
+        _ => Err(format!("Unsupported provider: {}", provider)),
+    }
+}
+
+/// An image attached to a chat message, base64-encoded. Watch rules also
+/// attach PDFs this way for providers that read them (`accepts_pdf`).
+#[derive(Clone)]
+pub struct ChatImage {
+    pub data: String,
+    pub media_type: ChatMedia,
+}
+
+#[derive(Clone)]
+pub enum ChatMedia {
+    Image(ImageMediaType),
+    Pdf,
+}
+
+/// Providers that take a PDF as a document part rather than only text.
+pub fn accepts_pdf(provider: &str) -> bool {
+    matches!(provider, "anthropic" | "gemini" | "openai")
+}
 
-    match agent.chat(new_message, history).await {
+impl ChatImage {
+    pub fn pdf(data: String) -> Self {
+        Self { data, media_type: ChatMedia::Pdf }
+    }
+
+    /// `{"data": "<base64>", "media_type": "image/jpeg"}`, a `data:` URL or
+    /// bare base64. Without a media type it is read from the data's first bytes.
+    pub fn from_value(value: &serde_json::Value) -> Result<Self, String> {
+        let (data, mime) = match value {
+            serde_json::Value::String(text) => (text.as_str(), None),
+            serde_json::Value::Object(_) => (
+                value["data"].as_str().ok_or("image has no data")?,
+                value["media_type"].as_str(),
+            ),
+            _ => return Err("image must be a base64 string or an object with data".to_string()),
+        };
+        let (mime, data) = match data.strip_prefix("data:").and_then(|rest| rest.split_once(";base64,")) {
+            Some((url_mime, payload)) => (mime.or(Some(url_mime)), payload),
+            None => (mime, data),
+        };
+        if data.is_empty() {
+            return Err("image data is empty".to_string());
+        }
+        let media_type = match mime {
+            Some(mime) => media_type_for(mime).ok_or_else(|| format!("unsupported image type {}", mime))?,
+            None => sniff_media_type(data),
+        };
+        Ok(Self { data: data.to_string(), media_type: ChatMedia::Image(media_type) })
+    }
+}
+
+fn media_type_for(mime: &str) -> Option<ImageMediaType> {
+    match mime.trim().to_ascii_lowercase().trim_start_matches("image/") {
+        "png" => Some(ImageMediaType::PNG),
+        "jpeg" | "jpg" => Some(ImageMediaType::JPEG),
+        "gif" => Some(ImageMediaType::GIF),
+        "webp" => Some(ImageMediaType::WEBP),
+        "heic" => Some(ImageMediaType::HEIC),
+        "heif" => Some(ImageMediaType::HEIF),
+        _ => None,
+    }
+}
+
+/// The format from the base64 of its magic bytes; PNG when unrecognized.
+fn sniff_media_type(data: &str) -> ImageMediaType {
+    if data.starts_with("/9j/") {
+        ImageMediaType::JPEG
+    } else if data.starts_with("R0lGOD") {
+        ImageMediaType::GIF
+    } else if data.starts_with("UklGR") {
+        ImageMediaType::WEBP
+    } else {
+        ImageMediaType::PNG
+    }
+}
+
+/// Said instead of the empty answer rig-core sometimes returns after tool calls.
+const EMPTY_ANSWER_FALLBACK: &str =
+    "Done! I've completed everything you asked for. Let me know if there's anything else.";
+
+/// The user's message: the query plus any attached images and PDFs.
+fn user_message(query: &str, images: &[ChatImage]) -> Result<RigMessage, String> {
+    let mut parts = vec![UserContent::text(query)];
+    parts.extend(images.iter().map(|image| match &image.media_type {
+        ChatMedia::Image(media_type) => UserContent::Image(Image {
+            data: DocumentSourceKind::base64(&image.data),
+            media_type: Some(media_type.clone()),
+            ..Default::default()
+        }),
+        ChatMedia::Pdf => UserContent::Document(Document {
+            data: DocumentSourceKind::base64(&image.data),
+            media_type: Some(DocumentMediaType::PDF),
+            additional_params: None,
+        }),
+    }));
+    Ok(RigMessage::User {
+        content: OneOrMany::many(parts).map_err(|e| e.to_string())?,
+    })
+}
+
+async fn chat_with_agent<M>(
+    agent: &rig::agent::Agent<M>,
+    query: &str,
+    history: &mut Vec<RigMessage>,
+    images: &[ChatImage],
+) -> Result<String, String>
+where
+    M: rig::completion::CompletionModel + 'static,
+{
+    let new_message = user_message(query, images)?;
+
+    // rig appends the turn to the history it is lent; put it back as it was.
+    let len = history.len();
+    let result = agent.prompt(new_message).with_history(history).await;
+    history.truncate(len);
+    match result {
         Ok(text) => Ok(text),
         Err(e) => {
             let err_str = e.to_string();
             if err_str.contains("empty") {
                 println!("⚠️ LLM returned empty response after tool execution (rig-core bug)");
-                Ok("Done! I've completed everything you asked for. Let me know if there's anything else.".to_string())
+                Ok(EMPTY_ANSWER_FALLBACK.to_string())
             } else {
                 Err(err_str)
             }
         }
     }
 }
+
+/// Like `chat_with_agent`, but through rig's streaming API: answer text is
+/// sent as `response_chunk` events as it arrives (text written before a tool
+/// call included), then `response_done` with the final answer, which
+/// replaces whatever the client assembled from the chunks.
+async fn stream_with_agent<M>(
+    agent: &rig::agent::Agent<M>,
+    query: &str,
+    history: Vec<RigMessage>,
+    images: &[ChatImage],
+    max_turns: usize,
+    tx: &ToolEventSender,
+) -> Result<String, String>
+where
+    M: rig::completion::CompletionModel + 'static,
+    M::StreamingResponse: Send + rig::completion::GetTokenUsage,
+{
+    use futures::StreamExt;
+    use rig::agent::MultiTurnStreamItem;
+    use rig::streaming::{StreamedAssistantContent, StreamingChat};
+
+    let new_message = user_message(query, images)?;
+    let mut stream = agent.stream_chat(new_message, history).multi_turn(max_turns).await;
+
+    // Text of the current model turn; a tool call starts a new one.
+    let mut answer = String::new();
+    let mut final_answer = None;
+    let mut chunks = 0u64;
+    while let Some(item) = stream.next().await {
+        match item.map_err(|e| e.to_string())? {
+            MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(text)) => {
+                if text.text.is_empty() {
+                    continue;
+                }
+                answer.push_str(&text.text);
+                chunks += 1;
+                let _ = tx
+                    .send(serde_json::json!({"type": "response_chunk", "content": {"text": text.text}}))
+                    .await;
+            }
+            MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::ToolCall { .. }) => answer.clear(),
+            MultiTurnStreamItem::FinalResponse(response) => final_answer = Some(response.response().to_string()),
+            _ => {}
+        }
+    }
+
+    let text = match final_answer.filter(|t| !t.trim().is_empty()) {
+        Some(text) => text,
+        None if !answer.trim().is_empty() => answer,
+        None => {
+            println!("⚠️ LLM returned empty response after tool execution (rig-core bug)");
+            EMPTY_ANSWER_FALLBACK.to_string()
+        }
+    };
+    let _ = tx
+        .send(serde_json::json!({"type": "response_done", "content": {"text": text, "chunks": chunks}}))
+        .await;
+    Ok(text)
+}
+
+/// Connect and per-request timeouts for one provider's HTTP client, from
+/// `RONGE_<PROVIDER>_CONNECT_TIMEOUT_SECS` / `RONGE_<PROVIDER>_REQUEST_TIMEOUT_SECS`,
+/// then `RONGE_LLM_CONNECT_TIMEOUT_SECS` / `RONGE_LLM_REQUEST_TIMEOUT_SECS`.
+/// The request timeout covers one provider round trip, not the whole agent
+/// loop, so slow tools and confirmations don't count against it. Local
+/// models get longer by default since generation runs on the user's machine.
+#[derive(Debug, Clone, Copy)]
+pub struct ProviderTimeouts {
+    pub connect: Duration,
+    pub request: Duration,
+}
+
+impl ProviderTimeouts {
+    pub fn for_provider(provider: &str) -> Self {
+        let secs = |setting: &str, default: u64| {
+            [format!("RONGE_{}_{}", provider.to_ascii_uppercase(), setting), format!("RONGE_LLM_{}", setting)]
+                .iter()
+                .find_map(|name| std::env::var(name).ok()?.trim().parse::<u64>().ok())
+                .filter(|s| *s > 0)
+                .unwrap_or(default)
+        };
+        let request_default = if matches!(provider, "ollama" | "openai_compatible") { 600 } else { 120 };
+        Self {
+            connect: Duration::from_secs(secs("CONNECT_TIMEOUT_SECS", 10)),
+            request: Duration::from_secs(secs("REQUEST_TIMEOUT_SECS", request_default)),
+        }
+    }
+
+    fn http_client(&self) -> Result<crate::provider_http::TapClient, String> {
+        reqwest::Client::builder()
+            .connect_timeout(self.connect)
+            .timeout(self.request)
+            .build()
+            .map(crate::provider_http::TapClient::new)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Why an agent turn failed. Timeouts are kept apart from other provider
+/// errors so callers can retry or fall back on them.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum LlmError {
+    #[error("{provider} did not answer within {limit_secs}s ({phase} timeout)")]
+    Timeout {
+        provider: String,
+        /// `connect` or `request`.
+        phase: &'static str,
+        limit_secs: u64,
+    },
+    #[error("{0}")]
+    Provider(String),
+}
+
+impl From<String> for LlmError {
+    fn from(message: String) -> Self {
+        LlmError::Provider(message)
+    }
+}
+
+impl LlmError {
+    /// Recognise a timeout of `provider`'s HTTP client in a rig error message.
+    pub fn classify(provider: &str, message: String) -> Self {
+        let lower = message.to_ascii_lowercase();
+        if !["timed out", "timeout", "deadline has elapsed"].iter().any(|m| lower.contains(m)) {
+            return LlmError::Provider(message);
+        }
+        let timeouts = ProviderTimeouts::for_provider(provider);
+        let (phase, limit) = if lower.contains("connect") {
+            ("connect", timeouts.connect)
+        } else {
+            ("request", timeouts.request)
+        };
+        LlmError::Timeout { provider: provider.to_string(), phase, limit_secs: limit.as_secs() }
+    }
+
+    /// `llm_timeout` frame sent ahead of the error response.
+    pub fn timeout_event(&self, model: &str) -> Option<serde_json::Value> {
+        let LlmError::Timeout { provider, phase, limit_secs } = self else {
+            return None;
+        };
+        Some(serde_json::json!({
+            "type": "llm_timeout",
+            "content": {"provider": provider, "model": model, "phase": phase, "limit_secs": limit_secs}
+        }))
+    }
+}
+
+fn gemini_client(api_key: &str) -> Result<gemini::Client<TapClient>, String> {
+    <gemini::Client>::builder()
+        .api_key(api_key)
+        .http_client(ProviderTimeouts::for_provider("gemini").http_client()?)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// rig wires Gemini's `client.agent()` to the plain reqwest backend only, so
+/// the model is built by hand to keep our `TapClient` underneath.
+fn gemini_agent(
+    client: gemini::Client<TapClient>,
+    model: &str,
+) -> rig::agent::AgentBuilder<gemini::CompletionModel<TapClient>> {
+    rig::agent::AgentBuilder::new(gemini::CompletionModel::new(client, model))
+}
+
+fn openai_client(api_key: &str) -> Result<openai::Client<TapClient>, String> {
+    <openai::Client>::builder()
+        .api_key(api_key)
+        .http_client(ProviderTimeouts::for_provider("openai").http_client()?)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn anthropic_client(api_key: &str) -> Result<anthropic::Client<TapClient>, String> {
+    <anthropic::Client>::builder()
+        .api_key(api_key)
+        .http_client(ProviderTimeouts::for_provider("anthropic").http_client()?)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Ollama at `OLLAMA_API_BASE_URL` (defaulted in `main`).
+fn ollama_client() -> Result<ollama::Client<TapClient>, String> {
+    let base_url = std::env::var("OLLAMA_API_BASE_URL").unwrap_or_else(|_| "http://localhost:11434".to_string());
+    <ollama::Client>::builder()
+        .api_key(rig::client::Nothing)
+        .base_url(&base_url)
+        .http_client(ProviderTimeouts::for_provider("ollama").http_client()?)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn mistral_client(api_key: &str) -> Result<mistral::Client<TapClient>, String> {
+    <mistral::Client>::builder()
+        .api_key(api_key)
+        .http_client(ProviderTimeouts::for_provider("mistral").http_client()?)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// A local or self-hosted OpenAI-compatible server (LM Studio, llama.cpp,
+/// vLLM) at the `base_url` sent with `set_llm`, through the Chat Completions
+/// API these servers implement. Most take no key; any placeholder will do.
+fn openai_compatible_client(api_key: &str) -> Result<openai::CompletionsClient<TapClient>, String> {
+    let endpoint = crate::provider_settings::require("openai_compatible")?;
+    let api_key = if api_key.is_empty() { "not-needed" } else { api_key };
+    <openai::Client>::builder()
+        .api_key(api_key)
+        .base_url(&endpoint.base_url)
+        .http_client(ProviderTimeouts::for_provider("openai_compatible").http_client()?)
+        .build()
+        .map(|client| client.completions_api())
+        .map_err(|e| e.to_string())
+}
+
+/// Azure OpenAI at the endpoint and API version sent with `set_llm`
+/// (`provider_settings.rs`); the model name is the deployment name.
+fn azure_client(api_key: &str) -> Result<azure::Client<TapClient>, String> {
+    let endpoint = crate::provider_settings::require("azure_openai")?;
+    let api_version = endpoint
+        .api_version
+        .unwrap_or_else(|| crate::provider_settings::DEFAULT_AZURE_API_VERSION.to_string());
+    <azure::Client>::builder()
+        .api_key(azure::AzureOpenAIAuth::ApiKey(api_key.to_string()))
+        .azure_endpoint(endpoint.base_url)
+        .api_version(&api_version)
+        .http_client(ProviderTimeouts::for_provider("azure_openai").http_client()?)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// OpenRouter through its OpenAI-compatible API (`RONGE_OPENROUTER_BASE_URL`).
+fn openrouter_client(api_key: &str) -> Result<openai::Client<TapClient>, String> {
+    <openai::Client>::builder()
+        .api_key(api_key)
+        .base_url(crate::model_list::openrouter_base_url())
+        .http_client(ProviderTimeouts::for_provider("openrouter").http_client()?)
+        .build()
+        .map_err(|e| e.to_string())
+}
@@ -1,8 +1,7 @@
 use crate::llm;
 use crate::state::{McpConnection, SharedState};
-use axum::extract::ws::{Message, WebSocket};
-use futures::stream::SplitSink;
-use futures::SinkExt;
+use crate::session::ClientSender;
+use axum::extract::ws::Message;
 use rig::message::{AssistantContent, Message as RigMessage, UserContent};
 use rig::OneOrMany;
 use rmcp::transport::streamable_http_client::{
@@ -72,9 +71,29 @@ fn clean_llm_error(raw: &str) -> String {
     raw.to_string()
 }
 
+/// Config messages a guest session may send: the ones that only touch its
+/// own session. Server-wide settings, secrets, memory, integrations and
+/// background work stay with the owner.
+const GUEST_MESSAGES: &[&str] = &[
+    "reset_session",
+    "purge_sessions",
+    "list_archived_sessions",
+    "resume_session",
+    "set_google_access",
+    "set_pii_redaction",
+    "set_planning",
+    "set_speculative",
+    "set_streaming",
+    "set_mode",
+    "preview_system_prompt",
+    "tools_request",
+    "list_artifacts",
+    "get_artifact",
+];
+
 pub async fn process_message(
     text: &str,
-    sender: &mut SplitSink<WebSocket, Message>,
+    sender: &mut ClientSender,
     chat_history: &mut Vec<RigMessage>,
     state: &SharedState,
 ) {
@@ -87,16 +106,39 @@ pub async fn process_message(
     };
 
     if let Some(data_type) = data.get("data_type").and_then(|v| v.as_str()) {
+        if crate::session::is_guest(sender.session_id()) && !GUEST_MESSAGES.contains(&data_type) {
+            println!("🚫 Guest session {} may not send '{}'", sender.session_id(), data_type);
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "forbidden", "content": {"data_type": data_type, "message": "Guest sessions can't change this."}})
+                        .to_string(),
+                ))
+                .await;
+            return;
+        }
+        if let Err(limited) = sender.limits.check(data_type, &data) {
+            println!("🚦 Rate limited '{}': {:?}", data_type, limited.reason);
+            let _ = sender.send(Message::Text(limited.event(data_type).to_string())).await;
+            return;
+        }
         handle_config(data_type, &data, sender, chat_history, state).await;
     } else {
         handle_chat(&data, sender, chat_history, state).await;
     }
 }
 
+/// Apply the config messages a cooldown held back, now that it has run out.
+pub async fn apply_deferred(sender: &mut ClientSender, chat_history: &mut Vec<RigMessage>, state: &SharedState) {
+    for (data_type, data) in sender.limits.take_due() {
+        println!("🚦 Applying deferred '{}'", data_type);
+        handle_config(&data_type, &data, sender, chat_history, state).await;
+    }
+}
+
 async fn handle_config(
     data_type: &str,
     data: &serde_json::Value,
-    sender: &mut SplitSink<WebSocket, Message>,
+    sender: &mut ClientSender,
     chat_history: &mut Vec<RigMessage>,
     state: &SharedState,
 ) {
@@ -117,10 +159,25 @@ async fn handle_config(
         // ── Set LLM provider / model ────────────────────────────────────────
         "set_llm" => {
             let provider = data["provider"].as_str().unwrap_or("gemini");
-            let model = data["model"].as_str().unwrap_or("");
+            // Azure OpenAI routes by deployment name, which stands in for the model.
+            let model = data["model"]
+                .as_str()
+                .filter(|m| !m.is_empty())
+                .or_else(|| data["deployment"].as_str())
+                .unwrap_or("");
             let api_key = data["api_key"].as_str().unwrap_or("");
             println!("🤖 Set LLM: {} / {}", provider, model);
 
+            let reasoning = match crate::reasoning::Reasoning::from_frame(data) {
+                Ok(reasoning) => reasoning,
+                Err(e) => {
+                    let _ = sender
+                        .send(Message::Text(json!({"type": "llm_set_error", "content": e}).to_string()))
+                        .await;
+                    return;
+                }
+            };
+
             if model.is_empty() {
                 let _ = sender
                     .send(Message::Text(
@@ -145,9 +202,10 @@ async fn handle_config(
                 api_key.to_string()
             };
 
-            // Require a key for providers that aren't Ollama/OpenRouter (Ollama has
-            // no key at all; OpenRouter uses OAuth and we check the stored key below).
-            let key_exempt = provider == "ollama";
+            // Require a key for providers that aren't Ollama/OpenRouter/mock (Ollama and
+            // mock have no key at all; OpenRouter uses OAuth and we check the stored key below).
+            // Local OpenAI-compatible servers usually take no key either.
+            let key_exempt = provider == "ollama" || provider == "mock" || provider == "openai_compatible";
             if !key_exempt && provider != "openrouter" && effective_key.is_empty() {
                 let _ = sender
                     .send(Message::Text(
@@ -170,15 +228,57 @@ async fn handle_config(
                 return;
             }
 
-            match llm::verify_llm(provider, &effective_key, model).await {
-                Ok(()) => {
+            // Providers without a fixed endpoint (Azure OpenAI, OpenAI-compatible servers) take it from the
+            // frame; it is verified on this task alone and only replaces the current one once it passes.
+            let endpoint = match crate::provider_settings::from_frame(provider, data) {
+                Ok(endpoint) => endpoint,
+                Err(e) => {
+                    let _ = sender
+                        .send(Message::Text(json!({"type": "llm_set_error", "content": e}).to_string()))
+                        .await;
+                    return;
+                }
+            };
+            let refresh = data["refresh"].as_bool().unwrap_or(false);
+            let verified = crate::provider_settings::with_candidate(
+                provider,
+                endpoint.clone(),
+                crate::verify::verify(provider, &effective_key, model, refresh),
+            )
+            .await;
+            match verified {
+                Ok(cached) => {
+                    if endpoint.is_some() {
+                        crate::provider_settings::set(provider, endpoint);
+                    }
+                    if cached {
+                        println!("✅ {} / {} verified recently; skipping the check", provider, model);
+                    }
                     let mut s = state.lock().await;
-                    s.current_provider = provider.to_string();
-                    s.current_model = model.to_string();
+                    let previous = (
+                        std::mem::replace(&mut s.current_provider, provider.to_string()),
+                        std::mem::replace(&mut s.current_model, model.to_string()),
+                    );
+                    s.reasoning = reasoning;
                     if !effective_key.is_empty() {
                         s.api_keys.insert(provider.to_string(), effective_key);
                     }
                     drop(s);
+
+                    // Carry the conversation over in a form the new model accepts.
+                    if !chat_history.is_empty() && (previous.0 != provider || previous.1 != model) {
+                        let (adapted, report) =
+                            crate::transfer::adapt(chat_history, previous.0 != provider, provider, model);
+                        if !report.is_empty() {
+                            println!(
+                                "🔀 History adapted for {}: {} tool message(s) flattened, {} image(s) and {} reasoning block(s) dropped",
+                                provider, report.tool_messages, report.images, report.reasoning
+                            );
+                            *chat_history = adapted;
+                            let event = report.event((&previous.0, &previous.1), (provider, model), chat_history.len());
+                            let _ = sender.send(Message::Text(event.to_string())).await;
+                        }
+                    }
                     let _ = sender
                         .send(Message::Text(
                             json!({"type": "llm_set_success", "content": format!("Now using {} via {}. Ready to chat!", model, provider)})
@@ -199,60 +299,65 @@ async fn handle_config(
             }
         }
 
+        // OpenRouter's model catalogue for the model picker.
+        "list_models" => {
+            let provider = data["provider"].as_str().unwrap_or("openrouter");
+            let refresh = data["refresh"].as_bool().unwrap_or(false);
+            let frame = match provider {
+                "openrouter" => match crate::model_list::openrouter(refresh).await {
+                    Ok(models) => json!({"type": "models", "content": {"provider": provider, "models": models}}),
+                    Err(e) => json!({"type": "models_error", "content": format!("Could not list OpenRouter models: {}", e)}),
+                },
+                other => json!({"type": "models_error", "content": format!("Model listing isn't available for {}", other)}),
+            };
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+        }
+
+        // Check several candidate models at once without switching to any.
+        "verify_models" => {
+            let provider = data["provider"].as_str().unwrap_or("gemini");
+            let models: Vec<String> = data["models"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|m| m.as_str())
+                .filter(|m| !m.is_empty())
+                .take(crate::verify::MAX_CANDIDATES)
+                .map(|m| m.to_string())
+                .collect();
+            let api_key = match data["api_key"].as_str().filter(|k| !k.is_empty()) {
+                Some(key) => key.to_string(),
+                None => state.lock().await.api_keys.get(provider).cloned().unwrap_or_default(),
+            };
+            let refresh = data["refresh"].as_bool().unwrap_or(false);
+            println!("🔎 Verifying {} {} model(s)", models.len(), provider);
+            let results = crate::verify::verify_models(provider, &api_key, &models, refresh).await;
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "models_verified", "content": {"provider": provider, "results": results}})
+                        .to_string(),
+                ))
+                .await;
+        }
+
+        // Stop the server: sockets are closed and MCP children stopped (see `shutdown.rs`).
+        "shutdown" => {
+            crate::shutdown::trigger("shutdown message");
+        }
+
         // ── OpenRouter PKCE OAuth ───────────────────────────────────────────
         "start_openrouter_oauth" => {
-            match crate::openrouter_auth::prepare_openrouter_flow().await {
-                Ok((auth_url, verifier, state_nonce, listener)) => {
-                    println!("🌐 OpenRouter OAuth URL ready. Sending to client.");
+            // The callback is awaited in the background; the outcome arrives
+            // as a broadcast tagged with the flow ID.
+            match crate::openrouter_auth::start_openrouter_flow(state.clone()).await {
+                Ok((flow_id, auth_url)) => {
+                    println!("🌐 OpenRouter OAuth URL ready (flow {}). Sending to client.", flow_id);
                     let _ = sender
                         .send(Message::Text(
-                            json!({"type": "openrouter_oauth_url", "content": auth_url})
+                            json!({"type": "openrouter_oauth_url", "content": auth_url, "flow_id": flow_id})
                                 .to_string(),
                         ))
                         .await;
-
-                    match tokio::time::timeout(
-                        std::time::Duration::from_secs(300),
-                        crate::openrouter_auth::await_openrouter_callback(
-                            listener,
-                            &verifier,
-                            &state_nonce,
-                        ),
-                    )
-                    .await
-                    {
-                        Ok(Ok(api_key)) => {
-                            let api_key: String = api_key;
-                            state
-                                .lock()
-                                .await
-                                .api_keys
-                                .insert("openrouter".to_string(), api_key.clone());
-                            let _ = sender
-                                .send(Message::Text(
-                                    json!({"type": "openrouter_oauth_success", "content": api_key})
-                                        .to_string(),
-                                ))
-                                .await;
-                        }
-                        Ok(Err(e)) => {
-                            println!("❌ OpenRouter OAuth callback error: {}", e);
-                            let _ = sender
-                                .send(Message::Text(
-                                    json!({"type": "openrouter_oauth_error", "content": e})
-                                        .to_string(),
-                                ))
-                                .await;
-                        }
-                        Err(_) => {
-                            let _ = sender
-                                .send(Message::Text(
-                                    json!({"type": "openrouter_oauth_error", "content": "Sign-in timed out. Please try again."})
-                                        .to_string(),
-                                ))
-                                .await;
-                        }
-                    }
                 }
                 Err(e) => {
                     println!("❌ Failed to prepare OpenRouter OAuth flow: {}", e);
@@ -266,9 +371,48 @@ async fn handle_config(
             }
         }
 
+        "oauth_flow_status" => {
+            let flows = state.lock().await.oauth_flows.clone();
+            let list = flows.status(data["flow_id"].as_str());
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "oauth_flows", "content": {"flows": list}}).to_string(),
+                ))
+                .await;
+        }
+
+        "cancel_oauth" => {
+            let flow_id = data["flow_id"].as_str().unwrap_or("");
+            let flows = state.lock().await.oauth_flows.clone();
+            let frame = if flows.cancel(flow_id) {
+                json!({"type": "oauth_flow_cancelled", "content": {"flow_id": flow_id}})
+            } else {
+                json!({"type": "oauth_flow_error", "content": format!("No pending sign-in with id {}", flow_id)})
+            };
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+        }
+
         // ── Session / memory ────────────────────────────────────────────────
         "reset_session" => {
-            chat_history.clear();
+            state.lock().await.sessions.clear_scratchpad(sender.session_id());
+            crate::ollama::warm_up(state.clone());
+            if !chat_history.is_empty() {
+                let history = std::mem::take(chat_history);
+                let (entry, snapshot) = {
+                    let mut s = state.lock().await;
+                    let entry = s.sessions.archive(sender.session_id(), history);
+                    (entry, s.sessions.archive_snapshot())
+                };
+                if let Err(e) = crate::session::save_archive(&snapshot).await {
+                    println!("⚠️ Failed to save session archive: {}", e);
+                }
+                println!("🗄️ Archived {} messages of session {}", entry.history.len(), sender.session_id());
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "session_archived", "content": entry.summary()}).to_string(),
+                    ))
+                    .await;
+            }
             let _ = sender
                 .send(Message::Text(
                     json!({"type": "session_reset", "content": "Conversation cleared — starting fresh!"}).to_string(),
@@ -276,6 +420,268 @@ async fn handle_config(
                 .await;
         }
 
+        // Incremental edits of the spreadsheet registry. Every connected
+        // client gets the resulting registry as `spreadsheets_updated`.
+        "add_spreadsheet" => {
+            let id = data["spreadsheet_id"].as_str().unwrap_or("").trim().to_string();
+            if id.is_empty() {
+                send_spreadsheet_error(sender, "`spreadsheet_id` is required.").await;
+                return;
+            }
+            let mut sheet = crate::sheets::SpreadsheetConfig {
+                name: data["name"].as_str().map(str::trim).filter(|n| !n.is_empty()).unwrap_or(&id).to_string(),
+                tab: data["tab"].as_str().map(str::trim).filter(|t| !t.is_empty()).map(str::to_string),
+                spreadsheet_id: id,
+                headers: Vec::new(),
+                synced_at: None,
+            };
+            let tool_sets = state.lock().await.session_tools(sender.session_id());
+            let result = crate::sheets::sync_headers(&tool_sets, std::slice::from_mut(&mut sheet)).await;
+            let added = sheet.clone();
+            let outcome = crate::sheets::modify_spreadsheets(|sheets| {
+                if sheets.iter().any(|s| s.spreadsheet_id == added.spreadsheet_id) {
+                    return Err(format!("{} is already registered; use update_spreadsheet.", added.spreadsheet_id));
+                }
+                if sheets.iter().any(|s| s.name.eq_ignore_ascii_case(&added.name)) {
+                    return Err(format!("A spreadsheet is already called \"{}\".", added.name));
+                }
+                sheets.push(added);
+                Ok(())
+            })
+            .await;
+            match outcome {
+                Ok(sheets) => {
+                    println!("📊 Added spreadsheet {}", sheet.name);
+                    broadcast_spreadsheets(state, "added", &sheet.spreadsheet_id, &sheets, result.into_iter().next()).await;
+                }
+                Err(e) => send_spreadsheet_error(sender, &e).await,
+            }
+        }
+
+        "update_spreadsheet" => {
+            let wanted = data["spreadsheet"].as_str().or(data["spreadsheet_id"].as_str()).unwrap_or("").trim();
+            let registered = crate::sheets::load_spreadsheets();
+            let Some(mut sheet) = crate::sheets::find_registered(&registered, wanted).map(|i| registered[i].clone()) else {
+                send_spreadsheet_error(sender, &format!("\"{}\" is not a registered spreadsheet.", wanted)).await;
+                return;
+            };
+            let id = sheet.spreadsheet_id.clone();
+            if let Some(name) = data["name"].as_str().map(str::trim).filter(|n| !n.is_empty()) {
+                sheet.name = name.to_string();
+            }
+            // An empty `tab` switches back to the first tab.
+            let tab_changed = match data.get("tab") {
+                Some(tab) => {
+                    let tab = tab.as_str().map(str::trim).filter(|t| !t.is_empty()).map(str::to_string);
+                    let changed = tab != sheet.tab;
+                    sheet.tab = tab;
+                    changed
+                }
+                None => false,
+            };
+            // The header row only needs a fresh read when it points at another tab.
+            let result = if tab_changed {
+                let tool_sets = state.lock().await.session_tools(sender.session_id());
+                crate::sheets::sync_headers(&tool_sets, std::slice::from_mut(&mut sheet)).await.into_iter().next()
+            } else {
+                None
+            };
+            let updated = sheet.clone();
+            let outcome = crate::sheets::modify_spreadsheets(|sheets| {
+                if sheets.iter().any(|s| s.spreadsheet_id != id && s.name.eq_ignore_ascii_case(&updated.name)) {
+                    return Err(format!("A spreadsheet is already called \"{}\".", updated.name));
+                }
+                let entry = sheets
+                    .iter_mut()
+                    .find(|s| s.spreadsheet_id == id)
+                    .ok_or_else(|| format!("{} was removed meanwhile.", id))?;
+                *entry = updated;
+                Ok(())
+            })
+            .await;
+            match outcome {
+                Ok(sheets) => {
+                    println!("📊 Updated spreadsheet {}", sheet.name);
+                    broadcast_spreadsheets(state, "updated", &id, &sheets, result).await;
+                }
+                Err(e) => send_spreadsheet_error(sender, &e).await,
+            }
+        }
+
+        "remove_spreadsheet" => {
+            let wanted = data["spreadsheet"].as_str().or(data["spreadsheet_id"].as_str()).unwrap_or("").trim().to_string();
+            if wanted.is_empty() {
+                send_spreadsheet_error(sender, "`spreadsheet` is required.").await;
+                return;
+            }
+            let mut removed = None;
+            let outcome = crate::sheets::modify_spreadsheets(|sheets| {
+                let index = crate::sheets::find_registered(sheets, &wanted)
+                    .ok_or_else(|| format!("\"{}\" is not a registered spreadsheet.", wanted))?;
+                removed = Some(sheets.remove(index));
+                Ok(())
+            })
+            .await;
+            match (outcome, removed) {
+                (Ok(sheets), Some(sheet)) => {
+                    println!("📊 Removed spreadsheet {}", sheet.name);
+                    broadcast_spreadsheets(state, "removed", &sheet.spreadsheet_id, &sheets, None).await;
+                }
+                (Err(e), _) => send_spreadsheet_error(sender, &e).await,
+                (Ok(_), None) => {}
+            }
+        }
+
+        "list_spreadsheets" => {
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "spreadsheets", "content": {"spreadsheets": crate::sheets::load_spreadsheets()}})
+                        .to_string(),
+                ))
+                .await;
+        }
+
+        // Index (or refresh the index of) a registered spreadsheet for retrieval Q&A.
+        "index_spreadsheet" => {
+            let wanted = data["spreadsheet"].as_str().or(data["spreadsheet_id"].as_str()).unwrap_or("").trim();
+            let sheet = crate::sheets::load_spreadsheets()
+                .into_iter()
+                .find(|s| s.spreadsheet_id == wanted || s.name.eq_ignore_ascii_case(wanted));
+            let (tool_sets, embedder) = {
+                let s = state.lock().await;
+                (
+                    s.session_tools(sender.session_id()),
+                    crate::sheet_index::Embedder::choose(&s.current_provider, &s.api_keys),
+                )
+            };
+            let outcome = match (sheet, embedder) {
+                (None, _) => Err(format!("\"{}\" is not a registered spreadsheet; add it with add_spreadsheet first.", wanted)),
+                (_, None) => Err("Indexing needs a Gemini or OpenAI API key (or Ollama as the current provider).".to_string()),
+                (Some(sheet), Some(embedder)) => crate::sheet_index::build(&tool_sets, &sheet, &embedder).await,
+            };
+            let frame = match outcome {
+                Ok(index) => {
+                    println!("📇 Indexed {} ({} rows)", index.name, index.rows.len());
+                    crate::sheet_index::refresh_server(state.clone()).await;
+                    json!({"type": "sheet_indexed", "content": index.summary()})
+                }
+                Err(e) => {
+                    println!("⚠️ Spreadsheet indexing failed: {}", e);
+                    json!({"type": "sheet_index_error", "content": e})
+                }
+            };
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+        }
+
+        "list_sheet_indexes" | "remove_sheet_index" => {
+            if data_type == "remove_sheet_index" {
+                let id = data["spreadsheet_id"].as_str().unwrap_or("");
+                if crate::sheet_index::remove(id).await {
+                    crate::sheet_index::refresh_server(state.clone()).await;
+                } else {
+                    let _ = sender
+                        .send(Message::Text(
+                            json!({"type": "sheet_index_error", "content": format!("No index for {}", id)}).to_string(),
+                        ))
+                        .await;
+                    return;
+                }
+            }
+            let indexes: Vec<serde_json::Value> =
+                crate::sheet_index::load_all().iter().map(|i| i.summary()).collect();
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "sheet_indexes", "content": {"indexes": indexes}}).to_string(),
+                ))
+                .await;
+        }
+
+        // Forget every stored conversation, on disk and in memory, including
+        // this connection's current history.
+        "purge_sessions" => {
+            chat_history.clear();
+            let guest = crate::session::is_guest(sender.session_id());
+            let (removed, snapshot) = {
+                let mut s = state.lock().await;
+                s.sessions.clear_scratchpad(sender.session_id());
+                if guest {
+                    (s.sessions.purge_guest(sender.session_id()), s.sessions.archive_snapshot())
+                } else {
+                    (s.sessions.purge(), Vec::new())
+                }
+            };
+            // A guest only removes its own conversations; everyone else's stay.
+            let deleted = if guest {
+                crate::session::save_archive(&snapshot).await
+            } else {
+                match crate::session::delete_archive().await {
+                    Ok(()) => crate::history_compress::delete_outputs().await,
+                    Err(e) => Err(e),
+                }
+            };
+            let msg = match deleted {
+                Ok(()) => {
+                    println!("🗑️ Purged {} stored conversation(s)", removed);
+                    let event = json!({"type": "sessions_purged", "content": {"removed": removed, "session_id": sender.session_id()}});
+                    let _ = state.lock().await.notifier.send(event.clone());
+                    event
+                }
+                Err(e) => {
+                    println!("❌ Failed to delete stored conversations: {}", e);
+                    json!({"type": "session_archive_error", "content": format!("Could not delete stored conversations: {}", e)})
+                }
+            };
+            let _ = sender.send(Message::Text(msg.to_string())).await;
+        }
+
+        "list_archived_sessions" => {
+            let sessions: Vec<serde_json::Value> =
+                state.lock().await.sessions.archived(sender.session_id()).map(|a| a.summary()).collect();
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "archived_sessions", "content": {"sessions": sessions}}).to_string(),
+                ))
+                .await;
+        }
+
+        "resume_session" => {
+            let id = data["id"].as_str().unwrap_or("");
+            let resumed = {
+                let mut s = state.lock().await;
+                if s.sessions.archived(sender.session_id()).any(|a| a.id == id) {
+                    // The conversation being replaced is archived in turn, not lost.
+                    if !chat_history.is_empty() {
+                        s.sessions.archive(sender.session_id(), std::mem::take(chat_history));
+                    }
+                    s.sessions
+                        .unarchive(sender.session_id(), id)
+                        .map(|restored| (restored, s.sessions.archive_snapshot()))
+                } else {
+                    None
+                }
+            };
+            let Some((restored, snapshot)) = resumed else {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "session_archive_error", "content": format!("No archived conversation with id {}", id)})
+                            .to_string(),
+                    ))
+                    .await;
+                return;
+            };
+            if let Err(e) = crate::session::save_archive(&snapshot).await {
+                println!("⚠️ Failed to save session archive: {}", e);
+            }
+            println!("📂 Resumed archived conversation {} in session {}", restored.id, sender.session_id());
+            let summary = restored.summary();
+            *chat_history = restored.history;
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "session_resumed", "content": summary}).to_string(),
+                ))
+                .await;
+        }
+
         "get_memory" => {
             let memory_path = crate::tools::default_memory_path();
             let content = tokio::fs::read_to_string(&memory_path).await.unwrap_or_default();
@@ -287,15 +693,9 @@ async fn handle_config(
         }
 
         "save_memory" => {
-            let content = data["content"].as_str().unwrap_or("");
+            let content = data["content"].as_str().unwrap_or("").to_string();
             let memory_path = crate::tools::default_memory_path();
-            let result = async {
-                if let Some(parent) = memory_path.parent() {
-                    tokio::fs::create_dir_all(parent).await?;
-                }
-                tokio::fs::write(&memory_path, content).await
-            }
-            .await;
+            let result = crate::memory::write(&memory_path, crate::memory::MemoryWrite::Replace(content)).await;
             match result {
                 Ok(()) => {
                     let _ = sender
@@ -354,6 +754,8 @@ async fn handle_config(
             }
 
             let mut statuses: Vec<serde_json::Value> = Vec::new();
+            // Fetch every tool list again instead of using ~/.ronge/mcp-cache.
+            let refresh_cache = data["refresh_cache"].as_bool().unwrap_or(false);
 
             for (name, server_config) in servers {
                 // Prefix reserved built-in server names to avoid collisions
@@ -365,6 +767,7 @@ async fn handle_config(
                 };
 
                 let transport_type = server_config["transport"].as_str().unwrap_or("stdio");
+                let config_hash = crate::mcp_cache::config_hash(server_config);
 
                 if transport_type == "http" {
                     // --- HTTP/SSE transport path ---
@@ -381,14 +784,14 @@ async fn handle_config(
 
                     println!("🔗 Connecting to HTTP MCP server '{}': {}", name, url);
 
-                    match connect_http_mcp_server(&url, api_key).await {
-                        Ok(conn) => {
+                    match connect_http_mcp_server(&url, api_key, &name, &config_hash, refresh_cache).await {
+                        Ok((conn, cached)) => {
                             println!(
                                 "✅ MCP '{}' connected with {} tools",
                                 name,
                                 conn.tools.len()
                             );
-                            statuses.push(json!({"name": name, "status": "connected", "error": null}));
+                            statuses.push(json!({"name": name, "status": "connected", "error": null, "tools_cached": cached}));
                             state.lock().await.mcp_connections.insert(name.clone(), conn);
                         }
                         Err(e) => {
@@ -428,9 +831,15 @@ async fn handle_config(
                     let resolved_command = resolve_command(command, &expanded_path);
                     println!("   Resolved command: {}", resolved_command);
 
-                    // Build command
-                    let mut cmd = tokio::process::Command::new(&resolved_command);
-                    cmd.args(&args);
+                    // Build command (working directory, stdin preamble and limits)
+                    let mut cmd = match mcp_server_command(&resolved_command, &args, server_config) {
+                        Ok(cmd) => cmd,
+                        Err(e) => {
+                            println!("❌ Invalid options for '{}': {}", name, e);
+                            statuses.push(json!({"name": name, "status": "error", "error": e}));
+                            continue;
+                        }
+                    };
                     cmd.env("PATH", &expanded_path);
 
                     // Set env if provided
@@ -447,9 +856,13 @@ async fn handle_config(
                         Ok(t) => t,
                         Err(e) => {
                             println!("❌ Failed to spawn '{}': {}", name, e);
-                            statuses.push(
-                                json!({"name": name, "status": "error", "error": e.to_string()}),
-                            );
+                            statuses.push(spawn_error_status(
+                                &name,
+                                command,
+                                &resolved_command,
+                                &expanded_path,
+                                &e.to_string(),
+                            ));
                             continue;
                         }
                     };
@@ -465,30 +878,27 @@ async fn handle_config(
                         }
                     };
 
-                    let tool_list = match service.list_tools(Default::default()).await {
-                        Ok(t) => t,
-                        Err(e) => {
-                            println!("❌ Failed to list tools from '{}': {:?}", name, e);
-                            statuses.push(
-                                json!({"name": name, "status": "error", "error": format!("{:?}", e)}),
-                            );
-                            continue;
-                        }
-                    };
+                    let (tools, cached) =
+                        match crate::mcp_cache::list_tools(&name, &config_hash, &service, refresh_cache).await {
+                            Ok(t) => t,
+                            Err(e) => {
+                                println!("❌ Failed to list tools from '{}': {}", name, e);
+                                statuses.push(
+                                    json!({"name": name, "status": "error", "error": e}),
+                                );
+                                continue;
+                            }
+                        };
 
                     println!(
                         "✅ MCP '{}' connected with {} tools",
                         name,
-                        tool_list.tools.len()
+                        tools.len()
                     );
 
-                    let conn = McpConnection {
-                        tools: tool_list.tools,
-                        peer: service.peer().clone(),
-                        _service: service,
-                    };
+                    let conn = McpConnection::new(tools, service);
 
-                    statuses.push(json!({"name": name, "status": "connected", "error": null}));
+                    statuses.push(json!({"name": name, "status": "connected", "error": null, "tools_cached": cached}));
                     state.lock().await.mcp_connections.insert(name.clone(), conn);
                 }
             }
@@ -507,6 +917,15 @@ async fn handle_config(
                 .await;
         }
 
+        // Which MCP runtimes (node/npx, python/uvx, docker) are installed,
+        // so the UI can warn before a server fails to spawn.
+        "check_runtimes" => {
+            let report = crate::runtimes::check().await;
+            let _ = sender
+                .send(Message::Text(json!({"type": "runtimes", "content": report}).to_string()))
+                .await;
+        }
+
         "mcp_status_request" => {
             let s = state.lock().await;
             let servers: Vec<serde_json::Value> = s
@@ -529,15 +948,22 @@ async fn handle_config(
             let s = state.lock().await;
             let mut tools_list: Vec<serde_json::Value> = vec![
                 json!({"name": "calculator", "source": "built-in", "description": "Evaluate mathematical expressions"}),
-                json!({"name": "open_application", "source": "built-in", "description": "Launch a macOS application by name"}),
+                json!({"name": "open_application", "source": "built-in", "description": "Launch an application by name"}),
                 json!({"name": "open_chrome_tab", "source": "built-in", "description": "Open a URL in Google Chrome"}),
                 json!({"name": "read_memory", "source": "built-in", "description": "Read from the agent's persistent knowledge base"}),
                 json!({"name": "save_to_memory", "source": "built-in", "description": "Save information to the agent's persistent knowledge base"}),
                 json!({"name": "append_to_memory", "source": "built-in", "description": "Append content to an existing memory entry"}),
+                json!({"name": "render_chart", "source": "built-in", "description": "Render a bar or line chart image from series data"}),
+                json!({"name": "execute_code", "source": "built-in", "description": "Run a short Python or JavaScript snippet offline and return its output"}),
+                json!({"name": "list_archive", "source": "built-in", "description": "List the files inside a zip, tar.gz or tar archive"}),
+                json!({"name": "extract_archive", "source": "built-in", "description": "Extract an archive into ~/.ronge/extracted for the file tools"}),
+                json!({"name": "network_check", "source": "built-in", "description": "Check DNS, ping and HTTP latency to a site against a reference site"}),
+                json!({"name": "convert_time", "source": "built-in", "description": "Convert a time between time zones and cities, DST included"}),
+                json!({"name": "date_info", "source": "built-in", "description": "Weekdays, ISO weeks, public holidays and business-day arithmetic"}),
             ];
             for (server_name, conn) in &s.mcp_connections {
-                for tool in &conn.tools {
-                    let safe_name = crate::mcp_proxy::sanitize_tool_name(&tool.name);
+                for tool in conn.tools.iter() {
+                    let safe_name = tool.name.to_string();
                     let desc = tool
                         .description
                         .as_deref()
@@ -547,6 +973,21 @@ async fn handle_config(
                     tools_list.push(json!({"name": safe_name, "source": source, "description": desc}));
                 }
             }
+            if crate::docs_export::find_create_tool(&s.session_tools(sender.session_id())).is_some() {
+                tools_list.push(json!({"name": "export_to_google_doc", "source": "built-in", "description": "Create a Google Doc from a report or conversation summary"}));
+            }
+            for tool in s.custom_tools.iter().flat_map(|c| c.tools.iter()) {
+                let desc = tool.description.as_deref().unwrap_or("Custom tool");
+                tools_list.push(json!({"name": tool.name.to_string(), "source": "custom", "description": desc}));
+            }
+            for tool in s.github.iter().flat_map(|c| c.tools.iter()) {
+                let desc = tool.description.as_deref().unwrap_or("GitHub tool");
+                tools_list.push(json!({"name": tool.name.to_string(), "source": "github", "description": desc}));
+            }
+            for tool in s.plugins.iter().flat_map(|c| c.tools.iter()) {
+                let desc = tool.description.as_deref().unwrap_or("Plugin tool");
+                tools_list.push(json!({"name": tool.name.to_string(), "source": "plugin", "description": desc}));
+            }
             drop(s);
             let _ = sender
                 .send(Message::Text(
@@ -635,9 +1076,13 @@ async fn handle_config(
                 let resolved = resolve_command(def.command, &expanded_path);
                 if resolved == def.command {
                     println!("❌ {} not found on PATH for server '{}'", def.command, name);
-                    statuses.push(
-                        json!({"name": name, "status": "error", "error": "Node.js not installed"}),
-                    );
+                    statuses.push(spawn_error_status(
+                        name,
+                        def.command,
+                        &resolved,
+                        &expanded_path,
+                        "Node.js not installed",
+                    ));
                     continue;
                 }
 
@@ -693,11 +1138,7 @@ async fn handle_config(
                     tool_list.tools.len()
                 );
 
-                let conn = McpConnection {
-                    tools: tool_list.tools,
-                    peer: service.peer().clone(),
-                    _service: service,
-                };
+                let conn = McpConnection::new(tool_list.tools, service);
 
                 statuses.push(json!({"name": name, "status": "connected", "error": null}));
                 state.lock().await.builtin_servers.insert(name.clone(), conn);
@@ -756,9 +1197,10 @@ async fn handle_config(
                     Err(e) => {
                         println!("❌ Failed to spawn @composio/mcp: {}", e);
                         state.lock().await.composio_api_key = None;
+                        let status = spawn_error_status("composio", "npx", &npx, &expanded_path, &e.to_string());
                         let _ = sender
                             .send(Message::Text(
-                                json!({"type": "mcp_server_status", "content": {"servers": [{"name": "composio", "status": "error", "error": e.to_string()}]}})
+                                json!({"type": "mcp_server_status", "content": {"servers": [status]}})
                                     .to_string(),
                             ))
                             .await;
@@ -814,11 +1256,7 @@ async fn handle_config(
                 };
 
                 println!("✅ Composio MCP connected with {} tools", tool_list.tools.len());
-                let conn = McpConnection {
-                    tools: tool_list.tools,
-                    peer: service.peer().clone(),
-                    _service: service,
-                };
+                let conn = McpConnection::new(tool_list.tools, service);
                 state.lock().await.mcp_connections.insert("composio".to_string(), conn);
                 let _ = sender
                     .send(Message::Text(
@@ -829,45 +1267,895 @@ async fn handle_config(
             }
         }
 
-        _ => {
-            println!("⚠️ Unknown data_type: {}", data_type);
+        "set_debug" => {
+            let enabled = data["enabled"].as_bool().unwrap_or(false);
+            state.lock().await.debug_mode = enabled;
+            println!("🐞 Debug dumps {}", if enabled { "enabled" } else { "disabled" });
+            let dir = crate::debug_dump::default_debug_dir();
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "debug_mode", "content": {"enabled": enabled, "dir": dir.to_string_lossy()}})
+                        .to_string(),
+                ))
+                .await;
         }
-    }
-}
 
-async fn handle_chat(
-    data: &serde_json::Value,
-    sender: &mut SplitSink<WebSocket, Message>,
-    chat_history: &mut Vec<RigMessage>,
-    state: &SharedState,
-) {
-    let query = data["text"].as_str().unwrap_or("").trim().to_string();
-
-    if query.is_empty() {
-        let _ = sender
-            .send(Message::Text(
-                json!({"type": "response", "content": {"text": "Looks like your message was empty — type something and I'll help!", "images": [], "widgets": []}})
-                    .to_string(),
-            ))
-            .await;
-        return;
-    }
+        "set_confirmations" => {
+            let enabled = data["enabled"].as_bool().unwrap_or(true);
+            state.lock().await.confirm_destructive_tools = enabled;
+            println!("🛡️ Destructive tool confirmations {}", if enabled { "enabled" } else { "disabled" });
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "confirmations", "content": {"enabled": enabled}}).to_string(),
+                ))
+                .await;
+        }
 
-    let (api_key, model, provider, mcp_tool_sets) = {
-        let s = state.lock().await;
-        let key = s.api_keys.get(&s.current_provider).cloned();
-        (
-            key,
-            s.current_model.clone(),
-            s.current_provider.clone(),
-            s.all_mcp_tools(),
-        )
-    };
+        "set_generation_params" => {
+            let generation = match crate::generation::GenerationParams::from_frame(data) {
+                Ok(generation) => generation,
+                Err(e) => {
+                    let _ = sender
+                        .send(Message::Text(json!({"type": "generation_params_error", "content": e}).to_string()))
+                        .await;
+                    return;
+                }
+            };
+            state.lock().await.generation = generation;
+            println!("🌡️ Generation params: {}", generation.to_json());
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "generation_params", "content": generation.to_json()}).to_string(),
+                ))
+                .await;
+        }
+
+        "set_secret_access" => {
+            let items = match crate::secret_refs::items_from_frame(data) {
+                Ok(items) => items,
+                Err(e) => {
+                    let _ = sender
+                        .send(Message::Text(json!({"type": "secret_access_error", "content": e}).to_string()))
+                        .await;
+                    return;
+                }
+            };
+            if let Err(e) = crate::secret_refs::save_items(&items).await {
+                println!("⚠️ Failed to save secret access: {}", e);
+            }
+            let names: Vec<&str> = items.iter().map(|i| i.name.as_str()).collect();
+            println!("🔑 get_secret may read {} Keychain item(s)", names.len());
+            let content = json!({"enabled": !items.is_empty(), "items": names});
+            state.lock().await.secret_items = items;
+            let _ = sender
+                .send(Message::Text(json!({"type": "secret_access", "content": content}).to_string()))
+                .await;
+        }
+
+        "set_calendar_conflict_check" => {
+            let enabled = data["enabled"].as_bool().unwrap_or(true);
+            state.lock().await.check_calendar_conflicts = enabled;
+            println!("📅 Calendar conflict check {}", if enabled { "enabled" } else { "disabled" });
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "calendar_conflict_check", "content": {"enabled": enabled}}).to_string(),
+                ))
+                .await;
+        }
+
+        "set_planning" => {
+            let enabled = data["enabled"].as_bool().unwrap_or(false);
+            state.lock().await.sessions.set_planning(sender.session_id(), enabled);
+            println!(
+                "🗺️ Planning {} for session {}",
+                if enabled { "enabled" } else { "disabled" },
+                sender.session_id()
+            );
+            let _ = sender
+                .send(Message::Text(json!({"type": "planning", "content": {"enabled": enabled}}).to_string()))
+                .await;
+        }
+
+        "set_speculative" => {
+            let enabled = data["enabled"].as_bool().unwrap_or(false);
+            state.lock().await.sessions.set_speculative(sender.session_id(), enabled);
+            println!(
+                "📝 Draft answers {} for session {}",
+                if enabled { "enabled" } else { "disabled" },
+                sender.session_id()
+            );
+            let _ = sender
+                .send(Message::Text(json!({"type": "speculative", "content": {"enabled": enabled}}).to_string()))
+                .await;
+        }
+
+        "set_streaming" => {
+            let enabled = data["enabled"].as_bool().unwrap_or(false);
+            state.lock().await.sessions.set_streaming(sender.session_id(), enabled);
+            println!(
+                "🌊 Streaming {} for session {}",
+                if enabled { "enabled" } else { "disabled" },
+                sender.session_id()
+            );
+            let _ = sender
+                .send(Message::Text(json!({"type": "streaming", "content": {"enabled": enabled}}).to_string()))
+                .await;
+        }
+
+        "set_pii_redaction" => {
+            let enabled = data["enabled"].as_bool().unwrap_or(false);
+            state
+                .lock()
+                .await
+                .sessions
+                .set_pii_redaction(sender.session_id(), enabled);
+            println!(
+                "🕶️ PII redaction {} for session {}",
+                if enabled { "enabled" } else { "disabled" },
+                sender.session_id()
+            );
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "pii_redaction", "content": {"enabled": enabled}}).to_string(),
+                ))
+                .await;
+        }
+
+        // Restrict a session's use of the owner's Google account. A session
+        // can only grant what it has itself, and only the owner's full-access
+        // sessions can change another one, so a guest window cannot lift its
+        // own limit. Levels are kept across restarts.
+        "set_google_access" => {
+            let Some(access) = data["access"].as_str().and_then(crate::google_auth::GoogleAccess::parse) else {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "google_access_error", "content": "access must be full, calendar_only or none."})
+                            .to_string(),
+                    ))
+                    .await;
+                return;
+            };
+            let target = data["session_id"].as_str().filter(|id| !id.is_empty()).unwrap_or(sender.session_id()).to_string();
+            let mut s = state.lock().await;
+            let own = s.sessions.google_access(sender.session_id());
+            let allowed = own.covers(access)
+                && (target == sender.session_id()
+                    || (own == crate::google_auth::GoogleAccess::Full && !crate::session::is_guest(sender.session_id())));
+            if !allowed {
+                drop(s);
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "google_access_error", "content": "This session cannot grant that Google access."})
+                            .to_string(),
+                    ))
+                    .await;
+                return;
+            }
+            let levels = s.sessions.set_google_access(&target, access);
+            println!("🔒 Session {} Google access: {}", target, access.as_str());
+            let _ = s.notifier.send(json!({
+                "type": "google_access",
+                "content": {"session_id": target, "access": access.as_str()},
+            }));
+            drop(s);
+            if let Err(e) = crate::session::save_google_access(&levels).await {
+                println!("⚠️ Failed to save Google access levels: {}", e);
+            }
+        }
+
+        "set_github_token" => {
+            let token = data["token"].as_str().unwrap_or("").trim().to_string();
+            let frame = crate::github::apply_token(state, &token).await;
+            // Keep a working token (or its removal) for the next start.
+            if frame["type"] == "github_status"
+                && let Err(e) = crate::secrets::set(crate::secrets::GITHUB, Some(token.as_str()).filter(|t| !t.is_empty())).await
+            {
+                println!("⚠️ Could not store the GitHub token: {}", e);
+            }
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+        }
+
+        // Keychain-backed secrets: `list`, `set` or `delete`. Values are
+        // never sent back.
+        "secrets" => {
+            let action = data["action"].as_str().unwrap_or("list");
+            let name = data["name"].as_str().unwrap_or("").trim();
+            let result = match action {
+                "list" => Ok(()),
+                "set" => match data["value"].as_str().map(str::trim).filter(|v| !v.is_empty()) {
+                    Some(value) => crate::secrets::set(name, Some(value)).await,
+                    None => Err("A value is required to set a secret.".to_string()),
+                },
+                "delete" => crate::secrets::set(name, None).await,
+                other => Err(format!("Unknown secrets action '{}' (use list, set or delete)", other)),
+            };
+            let frame = match result {
+                Ok(()) => {
+                    if action != "list" {
+                        println!("🔐 Secret '{}' {}", name, if action == "set" { "stored" } else { "deleted" });
+                    }
+                    // Integrations pick up their new token right away.
+                    if action != "list" && name == crate::secrets::GITHUB {
+                        let token = crate::secrets::get(name).unwrap_or_default();
+                        let status = crate::github::apply_token(state, &token).await;
+                        let _ = sender.send(Message::Text(status.to_string())).await;
+                    }
+                    json!({"type": "secrets", "content": {"secrets": crate::secrets::list()}})
+                }
+                Err(e) => json!({"type": "secrets_error", "content": e}),
+            };
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+        }
+
+        "export_to_google_doc" => {
+            let (target, provider, api_key, model, session_title, redact_pii) = {
+                let s = state.lock().await;
+                (
+                    crate::docs_export::find_create_tool(&s.session_tools(sender.session_id())),
+                    s.current_provider.clone(),
+                    s.api_keys.get(&s.current_provider).cloned().unwrap_or_default(),
+                    s.current_model.clone(),
+                    s.sessions.title(sender.session_id()).map(str::to_string),
+                    s.sessions.redacts_pii(sender.session_id())
+                        && !matches!(s.current_provider.as_str(), "ollama" | "mock"),
+                )
+            };
+            let Some(target) = target else {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "google_doc_error", "content": "Connect Google Docs (e.g. through Composio) to export documents."})
+                            .to_string(),
+                    ))
+                    .await;
+                return;
+            };
+
+            // Without explicit content, export a report of this conversation.
+            let content = match data["content"].as_str().map(str::trim).filter(|c| !c.is_empty()) {
+                Some(content) => Ok(content.to_string()),
+                None if chat_history.is_empty() => Err("There's no conversation to export yet.".to_string()),
+                None => {
+                    // Masked like the turns themselves before it goes to the provider.
+                    let mut transcript = crate::session::transcript(chat_history);
+                    if redact_pii {
+                        transcript = crate::pii::redact(&transcript);
+                    }
+                    crate::docs_export::summarize_conversation(&provider, &api_key, &model, &transcript)
+                        .await
+                        .map_err(|e| clean_llm_error(&e))
+                }
+            };
+            let title = data["title"]
+                .as_str()
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .or(session_title)
+                .unwrap_or_else(|| format!("Rong-E report {}", chrono::Local::now().format("%Y-%m-%d")));
+
+            let result = match content {
+                Ok(content) => crate::docs_export::create_doc(&target, &title, &content).await,
+                Err(e) => Err(e),
+            };
+            let frame = match result {
+                Ok(url) => {
+                    println!("📄 Exported Google Doc: {}", url);
+                    json!({"type": "google_doc_exported", "content": {"url": url, "title": title}})
+                }
+                Err(e) => {
+                    println!("❌ Google Doc export failed: {}", e);
+                    json!({"type": "google_doc_error", "content": format!("Couldn't create the document: {}", e)})
+                }
+            };
+            let _ = sender.send(Message::Text(frame.to_string())).await;
+        }
+
+        "set_code_workspace" => {
+            let path = data["path"].as_str().unwrap_or("").trim().to_string();
+            if let Some(conn) = state.lock().await.workspace.take() {
+                let _ = conn._service.cancel().await;
+            }
+            if path.is_empty() {
+                println!("🛑 Code workspace cleared");
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "code_workspace", "content": {"path": null}}).to_string(),
+                    ))
+                    .await;
+                return;
+            }
+            let expanded = match path.strip_prefix("~/") {
+                Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+                None => std::path::PathBuf::from(&path),
+            };
+            match crate::workspace::connect(&expanded).await {
+                Ok(conn) => {
+                    println!("🧑‍💻 Code workspace set to {}", expanded.display());
+                    state.lock().await.workspace = Some(conn);
+                    let _ = sender
+                        .send(Message::Text(
+                            json!({"type": "code_workspace", "content": {"path": expanded.to_string_lossy()}})
+                                .to_string(),
+                        ))
+                        .await;
+                }
+                Err(e) => {
+                    println!("❌ Failed to set code workspace: {}", e);
+                    let _ = sender
+                        .send(Message::Text(
+                            json!({"type": "code_workspace_error", "content": format!("Could not use {} as the workspace: {}", path, e)})
+                                .to_string(),
+                        ))
+                        .await;
+                }
+            }
+        }
+
+        "set_mode" => {
+            let name = data["mode"].as_str().unwrap_or(crate::modes::DEFAULT_MODE);
+            let Some(mode) = crate::modes::find(name) else {
+                let available: Vec<&str> = crate::modes::MODES.iter().map(|m| m.name).collect();
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "mode_error", "content": format!("Unknown mode '{}'. Available modes: {}.", name, available.join(", "))})
+                            .to_string(),
+                    ))
+                    .await;
+                return;
+            };
+            state.lock().await.sessions.set_mode(sender.session_id(), mode.name);
+            println!("🎛️ Session {} switched to {} mode", sender.session_id(), mode.label);
+            let modes: Vec<serde_json::Value> = crate::modes::MODES
+                .iter()
+                .map(|m| json!({"name": m.name, "label": m.label, "tools": m.tools}))
+                .collect();
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "mode", "content": {"mode": mode.name, "label": mode.label, "tools": mode.tools, "available": modes}})
+                        .to_string(),
+                ))
+                .await;
+        }
+
+        // The preamble the next turn would use, built exactly as `call_llm`
+        // builds it (masked the same way when PII redaction is on), with the
+        // size of each section.
+        "preview_system_prompt" => {
+            let (mode, redact_pii, provider, model) = {
+                let s = state.lock().await;
+                let provider = s.current_provider.clone();
+                let redact_pii = s.sessions.redacts_pii(sender.session_id())
+                    && !matches!(provider.as_str(), "ollama" | "mock");
+                (s.sessions.mode(sender.session_id()), redact_pii, provider, s.current_model.clone())
+            };
+            let user_name = data["user_name"].as_str().map(|s| s.to_string());
+            let preamble = llm::compose_preamble(user_name, mode, data["system_prompt"].as_str());
+            let mut text = preamble.text();
+            if redact_pii {
+                text = crate::pii::redact(&text);
+            }
+            let sections: Vec<serde_json::Value> = preamble
+                .sections
+                .iter()
+                .map(|(name, section)| json!({"name": name, "chars": section.chars().count()}))
+                .collect();
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "system_prompt_preview", "content": {
+                        "text": text,
+                        "sections": sections,
+                        "mode": mode.name,
+                        "redacted": redact_pii,
+                        "estimated_tokens": crate::context_usage::estimate_tokens(&provider, &text),
+                        "provider": provider,
+                        "model": model,
+                    }})
+                    .to_string(),
+                ))
+                .await;
+        }
+
+        "set_language" => {
+            let outcome = match crate::profile::normalize_language(data["language"].as_str().unwrap_or("")) {
+                Ok(language) => {
+                    let mut profile = crate::profile::Profile::load();
+                    profile.language = language;
+                    profile.save().await.map(|_| profile).map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e),
+            };
+            let msg = match outcome {
+                Ok(profile) => {
+                    println!("🌐 Response language set to {}", profile.language.as_deref().unwrap_or("default"));
+                    json!({"type": "language", "content": {"language": profile.language}})
+                }
+                Err(e) => json!({"type": "language_error", "content": e}),
+            };
+            let _ = sender.send(Message::Text(msg.to_string())).await;
+        }
+
+        "set_country" => {
+            let outcome = match crate::date_info::normalize_country(data["country"].as_str().unwrap_or("")) {
+                Ok(country) => {
+                    let mut profile = crate::profile::Profile::load();
+                    profile.country = country;
+                    profile.save().await.map(|_| profile).map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e),
+            };
+            let msg = match outcome {
+                Ok(profile) => {
+                    println!("📅 Country set to {}", profile.country.as_deref().unwrap_or("none"));
+                    json!({"type": "country", "content": {"country": profile.country}})
+                }
+                Err(e) => json!({"type": "country_error", "content": e}),
+            };
+            let _ = sender.send(Message::Text(msg.to_string())).await;
+        }
+
+        "set_dry_run" => {
+            let enabled = data["enabled"].as_bool().unwrap_or(false);
+            state.lock().await.dry_run = enabled;
+            println!("🧪 Dry-run mode {}", if enabled { "enabled" } else { "disabled" });
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "dry_run", "content": {"enabled": enabled}}).to_string(),
+                ))
+                .await;
+        }
+
+        "set_replay_mode" => {
+            let mode = data["mode"].as_str().unwrap_or("off");
+            let name = data["cassette"].as_str().unwrap_or("default");
+            let outcome = match mode {
+                "record" => Ok(Some(crate::replay::ReplaySession::record(name))),
+                "replay" => crate::replay::ReplaySession::replay(name).map(Some),
+                _ => Ok(None),
+            };
+            match outcome {
+                Ok(session) => {
+                    let turns = session.as_ref().map(|r| r.turn_count()).unwrap_or(0);
+                    println!("📼 Replay mode: {} ({})", mode, name);
+                    state.lock().await.replay = session;
+                    let _ = sender
+                        .send(Message::Text(
+                            json!({"type": "replay_mode", "content": {"mode": mode, "cassette": name, "turns": turns}})
+                                .to_string(),
+                        ))
+                        .await;
+                }
+                Err(e) => {
+                    let _ = sender
+                        .send(Message::Text(
+                            json!({"type": "replay_error", "content": e}).to_string(),
+                        ))
+                        .await;
+                }
+            }
+        }
+
+        "set_llm_concurrency" => {
+            let limit = {
+                let mut s = state.lock().await;
+                if let Some(n) = data["limit"].as_u64() {
+                    s.llm_limiter.set_limit(n as usize);
+                }
+                s.llm_limiter.limit()
+            };
+            println!("🚦 LLM concurrency limit: {}", limit);
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "llm_concurrency", "content": {"limit": limit}}).to_string(),
+                ))
+                .await;
+        }
+
+        "set_tool_event_capacity" => {
+            let capacity = {
+                let mut s = state.lock().await;
+                if let Some(n) = data["capacity"].as_u64() {
+                    s.tool_event_capacity = (n as usize).max(1);
+                }
+                s.tool_event_capacity
+            };
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "tool_event_capacity", "content": {"capacity": capacity}})
+                        .to_string(),
+                ))
+                .await;
+        }
+
+        "get_provider_stats" => {
+            let summary = state.lock().await.provider_stats.summary();
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "provider_stats", "content": summary}).to_string(),
+                ))
+                .await;
+        }
+
+        // ── Watched folders ─────────────────────────────────────────────────
+        "add_watch_rule" => {
+            let folder = data["folder"].as_str().unwrap_or("").trim();
+            let prompt = data["prompt"].as_str().unwrap_or("").trim();
+            if folder.is_empty() || prompt.is_empty() {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "watch_rule_error", "content": "Both a folder and a prompt are required."})
+                            .to_string(),
+                    ))
+                    .await;
+                return;
+            }
+            if !crate::watcher::expand_home(folder).is_dir() {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "watch_rule_error", "content": format!("The folder '{}' doesn't exist.", folder)})
+                            .to_string(),
+                    ))
+                    .await;
+                return;
+            }
+
+            let extensions: Vec<String> = data["extensions"]
+                .as_array()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|v| v.as_str())
+                        .map(|s| s.trim_start_matches('.').to_ascii_lowercase())
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let rule = crate::watcher::WatchRule {
+                id: format!("watch-{}", chrono::Utc::now().timestamp_millis()),
+                folder: folder.to_string(),
+                prompt_template: prompt.to_string(),
+                extensions,
+                google_access: state.lock().await.sessions.google_access(sender.session_id()),
+            };
+            println!("👀 Adding watch rule '{}' on {}", rule.id, rule.folder);
+
+            let rules = {
+                let mut s = state.lock().await;
+                s.watch_rules.push(rule);
+                s.watch_rules.clone()
+            };
+            send_watch_rules(sender, &rules).await;
+        }
+
+        "remove_watch_rule" => {
+            let id = data["id"].as_str().unwrap_or("");
+            let rules = {
+                let mut s = state.lock().await;
+                s.watch_rules.retain(|r| r.id != id);
+                s.watch_rules.clone()
+            };
+            send_watch_rules(sender, &rules).await;
+        }
+
+        "list_watch_rules" => {
+            let rules = state.lock().await.watch_rules.clone();
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "watch_rules", "content": {"rules": rules}}).to_string(),
+                ))
+                .await;
+        }
+
+        // ── Sheet watches ───────────────────────────────────────────────────
+        "add_sheet_watch" => {
+            let spreadsheet = data["spreadsheet"].as_str().unwrap_or("").trim();
+            let range = data["range"].as_str().unwrap_or("").trim();
+            let prompt = data["prompt"].as_str().unwrap_or("").trim();
+            if spreadsheet.is_empty() || range.is_empty() || prompt.is_empty() {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "sheet_watch_error", "content": "A watch needs a spreadsheet, a range and a prompt."})
+                            .to_string(),
+                    ))
+                    .await;
+                return;
+            }
+            // Registered names resolve to their ID; anything else is taken as an ID.
+            let spreadsheet_id = crate::sheets::load_spreadsheets()
+                .into_iter()
+                .find(|s| s.spreadsheet_id == spreadsheet || s.name.eq_ignore_ascii_case(spreadsheet))
+                .map(|s| s.spreadsheet_id)
+                .unwrap_or_else(|| spreadsheet.to_string());
+            let name = data["name"].as_str().unwrap_or("").trim();
+            let watch = crate::sheet_watch::SheetWatch {
+                id: format!("sheetwatch-{}", chrono::Utc::now().timestamp_millis()),
+                name: if name.is_empty() { prompt.chars().take(40).collect() } else { name.to_string() },
+                spreadsheet_id,
+                range: range.to_string(),
+                prompt: prompt.to_string(),
+                interval_minutes: data["interval_minutes"]
+                    .as_u64()
+                    .map(|m| m.clamp(1, 24 * 60) as u32)
+                    .unwrap_or(crate::sheet_watch::DEFAULT_INTERVAL_MINUTES),
+                created_at: chrono::Utc::now().timestamp(),
+                last_checked: None,
+                last_values: None,
+                google_access: state.lock().await.sessions.google_access(sender.session_id()),
+            };
+            println!("👀 Adding sheet watch '{}' on {}", watch.name, watch.range);
+            let watches = {
+                let mut s = state.lock().await;
+                s.sheet_watches.push(watch);
+                s.sheet_watches.clone()
+            };
+            send_sheet_watches(sender, &watches).await;
+        }
+
+        "remove_sheet_watch" => {
+            let id = data["id"].as_str().unwrap_or("");
+            let watches = {
+                let mut s = state.lock().await;
+                s.sheet_watches.retain(|w| w.id != id);
+                s.sheet_watches.clone()
+            };
+            send_sheet_watches(sender, &watches).await;
+        }
+
+        "list_sheet_watches" => {
+            let watches: Vec<crate::sheet_watch::SheetWatch> =
+                state.lock().await.sheet_watches.iter().map(|w| w.summary()).collect();
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "sheet_watches", "content": {"watches": watches}}).to_string(),
+                ))
+                .await;
+        }
+
+        // ── Scheduled jobs ──────────────────────────────────────────────────
+        "add_scheduled_job" => {
+            let name = data["name"].as_str().unwrap_or("").trim();
+            let prompt = data["prompt"].as_str().unwrap_or("").trim();
+            if prompt.is_empty() {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "scheduled_job_error", "content": "Please describe what the job should do."})
+                            .to_string(),
+                    ))
+                    .await;
+                return;
+            }
+
+            let job = crate::scheduler::ScheduledJob {
+                id: format!("job-{}", chrono::Utc::now().timestamp_millis()),
+                name: if name.is_empty() { prompt.chars().take(40).collect() } else { name.to_string() },
+                prompt: prompt.to_string(),
+                weekday: data["weekday"].as_str().filter(|d| !d.is_empty()).map(|d| d.to_string()),
+                time: data["time"].as_str().unwrap_or("09:00").to_string(),
+                created_at: chrono::Utc::now().timestamp(),
+                last_run: None,
+                agent: data["agent"].as_str().filter(|a| !a.is_empty()).map(|a| a.to_string()),
+                google_access: state.lock().await.sessions.google_access(sender.session_id()),
+            };
+            if let Err(e) = job.validate() {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "scheduled_job_error", "content": e}).to_string(),
+                    ))
+                    .await;
+                return;
+            }
+            println!("⏰ Adding scheduled job '{}'", job.name);
+
+            let jobs = {
+                let mut s = state.lock().await;
+                s.scheduled_jobs.push(job);
+                s.scheduled_jobs.clone()
+            };
+            send_scheduled_jobs(sender, &jobs).await;
+        }
+
+        "remove_scheduled_job" => {
+            let id = data["id"].as_str().unwrap_or("");
+            let jobs = {
+                let mut s = state.lock().await;
+                s.scheduled_jobs.retain(|j| j.id != id);
+                s.scheduled_jobs.clone()
+            };
+            send_scheduled_jobs(sender, &jobs).await;
+        }
+
+        "list_scheduled_jobs" => {
+            let jobs = state.lock().await.scheduled_jobs.clone();
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "scheduled_jobs", "content": {"jobs": jobs}}).to_string(),
+                ))
+                .await;
+        }
+
+        "run_scheduled_job" => {
+            let id = data["id"].as_str().unwrap_or("");
+            let job = state
+                .lock()
+                .await
+                .scheduled_jobs
+                .iter()
+                .find(|j| j.id == id)
+                .cloned();
+            match job {
+                Some(job) => {
+                    // Result arrives via the notification channel like a scheduled run.
+                    let state = state.clone();
+                    tokio::spawn(async move { crate::scheduler::run_job(&state, &job).await });
+                }
+                None => {
+                    let _ = sender
+                        .send(Message::Text(
+                            json!({"type": "scheduled_job_error", "content": "That job no longer exists."})
+                                .to_string(),
+                        ))
+                        .await;
+                }
+            }
+        }
+
+        // ── Artifacts ───────────────────────────────────────────────────────
+        "list_artifacts" => {
+            let artifacts = state.lock().await.artifacts.list(sender.session_id());
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "artifacts", "content": {"artifacts": artifacts}}).to_string(),
+                ))
+                .await;
+        }
+
+        "get_artifact" => {
+            use base64::Engine;
+            let id = data["id"].as_str().unwrap_or("");
+            let artifact = state.lock().await.artifacts.get(sender.session_id(), id);
+            let response = match artifact {
+                Some(artifact) => match tokio::fs::read(&artifact.path).await {
+                    Ok(bytes) => json!({"type": "artifact", "content": {
+                        "artifact": artifact,
+                        "data": base64::engine::general_purpose::STANDARD.encode(bytes),
+                    }}),
+                    Err(e) => {
+                        println!("❌ Failed to read artifact {}: {}", artifact.path.display(), e);
+                        json!({"type": "artifact_error", "content": "That file is no longer available."})
+                    }
+                },
+                None => json!({"type": "artifact_error", "content": "That file has expired or does not exist."}),
+            };
+            let _ = sender.send(Message::Text(response.to_string())).await;
+        }
+
+        _ => {
+            println!("⚠️ Unknown data_type: {}", data_type);
+        }
+    }
+}
+
+/// Persist the watch rules and echo the updated list back to the client.
+async fn send_watch_rules(
+    sender: &mut ClientSender,
+    rules: &[crate::watcher::WatchRule],
+) {
+    if let Err(e) = crate::watcher::save_rules(rules).await {
+        println!("❌ Failed to save watch rules: {}", e);
+        let _ = sender
+            .send(Message::Text(
+                json!({"type": "watch_rule_error", "content": "Could not save watch rules. Please try again."})
+                    .to_string(),
+            ))
+            .await;
+        return;
+    }
+    let _ = sender
+        .send(Message::Text(
+            json!({"type": "watch_rules", "content": {"rules": rules}}).to_string(),
+        ))
+        .await;
+}
+
+async fn handle_chat(
+    data: &serde_json::Value,
+    sender: &mut ClientSender,
+    chat_history: &mut Vec<RigMessage>,
+    state: &SharedState,
+) {
+    let query = data["text"].as_str().unwrap_or("").trim().to_string();
+
+    if query.is_empty() {
+        let _ = sender
+            .send(Message::Text(
+                json!({"type": "response", "content": {"text": "Looks like your message was empty — type something and I'll help!", "images": [], "widgets": []}})
+                    .to_string(),
+            ))
+            .await;
+        return;
+    }
+
+    // Replay mode: the next recorded turn runs through the real pipeline,
+    // with the provider and MCP servers answered from its tape. Recordings
+    // made before tapes only have their events to send back.
+    let (replayed, recording) = {
+        let mut s = state.lock().await;
+        match s.replay.as_mut() {
+            Some(r) if r.mode == crate::replay::ReplayMode::Replay => (Some(r.next_turn(&query)), false),
+            Some(_) => (None, true),
+            None => (None, false),
+        }
+    };
+    if let Some(turn) = replayed.as_ref().filter(|turn| !turn.is_taped()) {
+        for event in &turn.events {
+            let _ = sender.send(Message::Text(event.to_string())).await;
+        }
+        let meta = json!({
+            "widgets": crate::widgets::collect_widgets(&turn.events),
+            "images": crate::widgets::collect_images(&turn.events),
+            "tool_summary": crate::timings::tool_summary(&turn.events),
+        });
+        // A replayed turn must not reach out to the web.
+        send_turn_result(sender, chat_history, &query, turn.result.clone(), meta, false).await;
+        return;
+    }
+    let tape = match &replayed {
+        Some(turn) => Some(crate::replay::Tape::replaying(turn)),
+        None => recording.then(crate::replay::Tape::recording),
+    };
+
+    let (api_key, model, provider, mcp_tool_sets) = {
+        let s = state.lock().await;
+        let key = s.api_keys.get(&s.current_provider).cloned();
+        (
+            key,
+            s.current_model.clone(),
+            s.current_provider.clone(),
+            s.session_tools(sender.session_id()),
+        )
+    };
+    // A replayed turn runs on the provider, model and tool lists it was
+    // recorded with; the servers offering those lists live for the turn.
+    let mut _replay_servers = Vec::new();
+    let (api_key, model, provider, mcp_tool_sets) = match (&replayed, &tape) {
+        (Some(turn), Some(tape)) => match tape.connect_tool_sets().await {
+            Ok(connections) => {
+                let tool_sets = connections.iter().map(|conn| conn.tool_set()).collect();
+                _replay_servers = connections;
+                (
+                    Some("replay".to_string()),
+                    turn.model.clone().unwrap_or_default(),
+                    turn.provider.clone().unwrap_or_default(),
+                    tool_sets,
+                )
+            }
+            Err(e) => {
+                let error = format!("Could not replay the recorded tools: {}", e);
+                send_turn_result(sender, chat_history, &query, Err(error), json!({}), false).await;
+                return;
+            }
+        },
+        _ => (api_key, model, provider, mcp_tool_sets),
+    };
 
     let user_name = data["user_name"].as_str().map(|s| s.to_string());
 
+    // `images` (several, any supported type) and the older single `base64_image`.
+    let images = match parse_chat_images(data) {
+        Ok(images) => images,
+        Err(e) => {
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "response", "content": {"text": format!("I couldn't read the attached image: {}.", e), "images": [], "widgets": []}})
+                        .to_string(),
+                ))
+                .await;
+            return;
+        }
+    };
+
     if provider != "ollama"
         && provider != "openrouter"
+        && provider != "mock"
+        && provider != "openai_compatible"
         && api_key.as_ref().is_none_or(|k| k.is_empty())
     {
         let _ = sender
@@ -879,44 +2167,203 @@ async fn handle_chat(
         return;
     }
 
-    let (tool_tx, mut tool_rx) = tokio::sync::mpsc::channel::<serde_json::Value>(64);
+    // Global concurrency cap: report our queue position while waiting.
+    let limiter = state.lock().await.llm_limiter.handle();
+    let _permit = match limiter.try_acquire() {
+        Ok(permit) => Some(permit),
+        Err(position) => {
+            let _ = sender
+                .send(Message::Text(
+                    json!({"type": "queue_position", "content": {"position": position}}).to_string(),
+                ))
+                .await;
+            limiter.wait().await
+        }
+    };
+
+    // Per-message reasoning overrides fall back to the session's `set_llm` setting.
+    let reasoning = match crate::reasoning::Reasoning::from_frame(data) {
+        Ok(reasoning) => reasoning,
+        Err(e) => {
+            println!("⚠️ Ignoring reasoning override: {}", e);
+            Default::default()
+        }
+    }
+    .or(state.lock().await.reasoning);
+    let generation = match crate::generation::GenerationParams::from_frame(data) {
+        Ok(generation) => generation,
+        Err(e) => {
+            println!("⚠️ Ignoring generation override: {}", e);
+            Default::default()
+        }
+    }
+    .or(state.lock().await.generation);
+
+    let (capacity, confirmations, dry_run, redact_pii, mode, check_conflicts, scratchpad, planning, drafts, streaming, secret_items) = {
+        let mut s = state.lock().await;
+        let confirmations = s.confirm_destructive_tools.then(|| s.confirmations.clone());
+        // Local providers never see the data leave the machine; leave them untouched.
+        let redact_pii = s.sessions.redacts_pii(sender.session_id())
+            && !matches!(provider.as_str(), "ollama" | "mock");
+        let mode = s.sessions.mode(sender.session_id());
+        let scratchpad = s.sessions.scratchpad(sender.session_id());
+        // Plan approval waits on the same decisions as tool confirmations.
+        let planning = s.sessions.plans(sender.session_id()).then(|| s.confirmations.clone());
+        let drafts = s.sessions.drafts(sender.session_id());
+        // A message's own `stream` flag wins over the session setting.
+        let streaming = data["stream"].as_bool().unwrap_or_else(|| s.sessions.streams(sender.session_id()));
+        (
+            s.tool_event_capacity,
+            confirmations,
+            s.dry_run,
+            redact_pii,
+            mode,
+            s.check_calendar_conflicts,
+            scratchpad,
+            planning,
+            drafts,
+            streaming,
+            // A secret only goes out once the user approves the call.
+            if s.confirm_destructive_tools { s.secret_items.clone() } else { Vec::new() },
+        )
+    };
+    let (tool_tx, mut tool_rx) = crate::tools::tool_event_channel(capacity);
+    let tool_outputs = crate::tools::ToolOutputs::default();
+    let mut tool_tx = tool_tx
+        .with_tool_outputs(tool_outputs.clone())
+        .with_dry_run(dry_run)
+        .with_pii_redaction(redact_pii)
+        .with_calendar_conflict_check(check_conflicts)
+        .with_scratchpad(scratchpad)
+        .with_streaming(streaming)
+        .with_secret_items(secret_items)
+        .with_tape(tape.clone());
+    if let Some(confirmations) = confirmations {
+        tool_tx = tool_tx.with_confirmations(confirmations);
+    }
 
     let system_prompt = data["system_prompt"].as_str().map(|s| s.to_string());
-    let base64_image = data["base64_image"].as_str().map(|s| s.to_string());
-    let history_clone = chat_history.clone();
+    // Also read the final answer aloud as binary audio frames.
+    let speak = data["speak"].as_bool().unwrap_or(false);
+    let debug = state
+        .lock()
+        .await
+        .debug_mode
+        .then(crate::debug_dump::DebugDump::new);
+
+    // With drafts on, a fast model answers short text questions right away
+    // while the main model works; its answer then supersedes the draft.
+    let draft_task = if drafts
+        && replayed.is_none()
+        && planning.is_none()
+        && images.is_empty()
+        && query.chars().count() <= crate::speculative::max_query_chars()
+        && let Some((draft_provider, draft_model)) = crate::speculative::draft_model(&provider, &model)
+    {
+        let draft_key = state.lock().await.api_keys.get(&draft_provider).cloned().unwrap_or_default();
+        Some(crate::speculative::spawn(
+            draft_provider,
+            draft_key,
+            draft_model,
+            &query,
+            chat_history,
+            redact_pii,
+            tool_tx.clone(),
+        ))
+    } else {
+        None
+    };
 
-    let mut llm_task = tokio::spawn(llm::call_llm(
-        provider,
-        api_key.unwrap_or_default(),
-        model,
-        query.clone(),
-        history_clone,
-        mcp_tool_sets,
-        system_prompt,
-        base64_image,
-        tool_tx,
-        user_name,
-    ));
-
-    let llm_result = loop {
-        tokio::select! {
-            biased;
-            Some(event) = tool_rx.recv() => {
-                let _ = sender.send(Message::Text(event.to_string())).await;
-            }
-            outcome = &mut llm_task => {
-                while let Ok(event) = tool_rx.try_recv() {
+    // With planning on, a multi-step request is planned and approved before it runs.
+    let history_len;
+    let turn = {
+        let (provider, model, query) = (provider.clone(), model.clone(), query.clone());
+        // The turn is polled here rather than spawned, so it borrows the
+        // history instead of taking a copy.
+        let history = &mut *chat_history;
+        history_len = history.len();
+        let debug = debug.clone();
+        let (tape, dump) = (tape.clone(), debug.clone());
+        let turn = crate::replay::scoped(tape, async move {
+            let api_key = api_key.unwrap_or_default();
+            let (query, tool_tx) = match planning {
+                Some(confirmations) => {
+                    match crate::planner::plan(&provider, &api_key, &model, &query, &mcp_tool_sets, &tool_tx, &confirmations)
+                        .await
+                    {
+                        crate::planner::Planned::Direct => (query, tool_tx),
+                        crate::planner::Planned::Approved { query, plan } => (query, tool_tx.with_plan(plan)),
+                        crate::planner::Planned::Rejected => return Ok(crate::planner::REJECTED_REPLY.to_string()),
+                    }
+                }
+                None => (query, tool_tx),
+            };
+            llm::call_llm(
+                provider,
+                api_key,
+                model,
+                query,
+                history,
+                mcp_tool_sets,
+                system_prompt,
+                images,
+                tool_tx,
+                user_name,
+                debug,
+                mode,
+                reasoning,
+                generation,
+            )
+            .await
+        });
+        crate::debug_dump::scoped(dump, turn)
+    };
+
+    let mut timings = crate::timings::TurnTimings::new();
+    let mut turn_events: Vec<serde_json::Value> = Vec::new();
+
+    let llm_result = {
+        let mut llm_task = std::pin::pin!(futures::FutureExt::catch_unwind(std::panic::AssertUnwindSafe(turn)));
+        loop {
+            tokio::select! {
+                biased;
+                Some(event) = tool_rx.recv() => {
+                    timings.observe(&event);
                     let _ = sender.send(Message::Text(event.to_string())).await;
+                    turn_events.push(event);
+                }
+                outcome = &mut llm_task => {
+                    while let Some(event) = tool_rx.try_recv() {
+                        timings.observe(&event);
+                        let _ = sender.send(Message::Text(event.to_string())).await;
+                        turn_events.push(event);
+                    }
+                    break outcome;
                 }
-                break outcome;
             }
         }
     };
+    if let Some(draft) = draft_task {
+        draft.abort();
+    }
+
+    let timings = timings.finish();
+    let events_dropped = tool_rx.dropped();
+    if events_dropped > 0 {
+        println!("⚠️ Dropped {} tool events (client too slow)", events_dropped);
+    }
 
     let result = match llm_result {
         Ok(r) => r,
-        Err(join_err) => {
-            println!("❌ LLM task panicked: {}", join_err);
+        Err(panic) => {
+            let reason = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            println!("❌ LLM task panicked: {}", reason);
+            // Drop whatever the interrupted turn had appended.
+            chat_history.truncate(history_len);
             let _ = sender
                 .send(Message::Text(
                     json!({"type": "response", "content": {"text": "Something went wrong on my end. Please try your request again.", "images": [], "widgets": []}})
@@ -927,21 +2374,183 @@ async fn handle_chat(
         }
     };
 
+    // A hung provider is reported as such, so the client can offer a retry
+    // or another model instead of a generic error.
+    if let Err(e) = &result
+        && let Some(event) = e.timeout_event(&model)
+    {
+        println!("⏱️ {}", e);
+        let _ = sender.send(Message::Text(event.to_string())).await;
+    }
+    let mut result = result.map_err(|e| e.to_string());
+
+    // A request that never got an answer: tell the user whether their
+    // network or the provider is at fault.
+    if let Err(e) = &result
+        && crate::netcheck::is_connection_error(e)
+        && let Some(event) = crate::netcheck::diagnose_provider(&provider).await
+    {
+        let summary = event["content"]["summary"].as_str().unwrap_or_default().to_string();
+        let _ = sender.send(Message::Text(event.to_string())).await;
+        result = Err(format!("{} ({})", summary, e));
+    }
+
+    if replayed.is_none() {
+        record_provider_stats(state, &provider, &model, &timings, &result).await;
+    }
+
+    if let Some(ref dump) = debug {
+        let lines: Vec<String> = turn_events.iter().map(|e| e.to_string()).collect();
+        dump.write("events.jsonl", &lines.join("\n")).await;
+        dump.write_json("timings.json", &timings).await;
+        match &result {
+            Ok(text) => dump.write("response.txt", text).await,
+            Err(e) => dump.write("error.txt", e).await,
+        }
+    }
+
+    let widgets = crate::widgets::collect_widgets(&turn_events);
+    let images = crate::widgets::collect_images(&turn_events);
+    let tool_summary = crate::timings::tool_summary(&turn_events);
+    let artifacts =
+        crate::artifacts::register_from_events(state, sender.session_id(), &turn_events).await;
+
+    // Record mode: append this live turn to the active cassette.
+    let recorded = {
+        let mut s = state.lock().await;
+        match s.replay.as_mut() {
+            Some(r) if r.mode == crate::replay::ReplayMode::Record => {
+                let mut turn = crate::replay::RecordedTurn {
+                    query: query.clone(),
+                    events: turn_events.clone(),
+                    result: result.clone(),
+                    provider: Some(provider.clone()),
+                    model: Some(model.clone()),
+                    http: Vec::new(),
+                    mcp: Vec::new(),
+                    tool_sets: Vec::new(),
+                };
+                if let Some(tape) = &tape {
+                    tape.fill(&mut turn);
+                }
+                Some((r.name.clone(), r.push(turn)))
+            }
+            _ => None,
+        }
+    };
+    if let Some((name, turns)) = recorded
+        && let Err(e) = crate::replay::save_cassette(&name, &turns).await
+    {
+        println!("⚠️ Failed to save recording '{}': {}", name, e);
+    }
+
+    let loop_interventions = crate::tools::loop_interventions(&turn_events);
+    let meta = json!({
+        "timings": timings,
+        "events_dropped": events_dropped,
+        "widgets": widgets,
+        "images": images,
+        "artifacts": artifacts,
+        "tool_summary": tool_summary,
+        "loop_interventions": loop_interventions,
+        "reasoning": (!reasoning.is_default()).then(|| reasoning.to_json()),
+        "generation": (!generation.is_default()).then(|| generation.to_json()),
+        "draft": crate::speculative::superseded(&turn_events),
+    });
+    let spoken = result.as_ref().ok().filter(|_| speak).cloned();
+    let succeeded = result.is_ok();
+    send_turn_result(sender, chat_history, &query, result, meta, replayed.is_none()).await;
+
+    // Keep the turn's tool outputs for follow-ups; older ones shrink to summaries.
+    if succeeded {
+        let outputs = std::mem::take(&mut *tool_outputs.lock().unwrap_or_else(|e| e.into_inner()));
+        crate::history_compress::record_tool_outputs(chat_history, &outputs);
+    }
+    // Both call the provider, which a replayed turn never does.
+    if succeeded && replayed.is_none() {
+        crate::history_compress::compress(state, sender.session_id(), chat_history, &provider, &model, redact_pii)
+            .await;
+
+        // Over the history budget: the oldest turns collapse into one note.
+        let api_key = state.lock().await.api_keys.get(&provider).cloned().unwrap_or_default();
+        match crate::llm::summarize_history(&provider, &api_key, &model, chat_history, redact_pii).await {
+            Ok(Some(summary)) => {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "history_summarized", "content": {
+                            "messages_replaced": summary.replaced,
+                            "tokens_before": summary.tokens_before,
+                            "tokens_after": summary.tokens_after,
+                        }})
+                        .to_string(),
+                    ))
+                    .await;
+            }
+            Ok(None) => {}
+            Err(e) => println!("⚠️ Could not summarize the history of session {}: {}", sender.session_id(), e),
+        }
+    }
+
+    let usage = crate::context_usage::usage_event(&provider, &model, chat_history);
+    if usage["content"]["warning"] == true {
+        println!("⚠️ Session {} history is near the context limit: {}", sender.session_id(), usage["content"]);
+    }
+    let _ = sender.send(Message::Text(usage.to_string())).await;
+
+    if chat_history.len() == crate::session::TITLE_AFTER_MESSAGES && replayed.is_none() {
+        crate::session::spawn_title_generation(
+            state.clone(),
+            sender.session_id().to_string(),
+            chat_history,
+        );
+    }
+
+    if let Some(text) = spoken {
+        crate::speech::stream_speech(sender, state, &text, data["voice"].as_str()).await;
+    }
+}
+
+/// Append a successful turn to the history and send the final response frame.
+/// Fields in `meta` (timings, counters, …) are merged into the response content.
+/// With `link_previews`, cards for the answer's links follow in a separate
+/// `link_preview` frame so fetching them never holds up the answer.
+async fn send_turn_result(
+    sender: &mut ClientSender,
+    chat_history: &mut Vec<RigMessage>,
+    query: &str,
+    result: Result<String, String>,
+    meta: serde_json::Value,
+    link_previews: bool,
+) {
     match result {
         Ok(text) => {
             chat_history.push(RigMessage::User {
-                content: OneOrMany::one(UserContent::text(query.clone())),
+                content: OneOrMany::one(UserContent::text(query)),
             });
             chat_history.push(RigMessage::Assistant {
                 id: Default::default(),
                 content: OneOrMany::one(AssistantContent::text(text.clone())),
             });
+            let mut content = json!({"text": text, "images": [], "widgets": []});
+            if let (Some(content), Some(meta)) = (content.as_object_mut(), meta.as_object()) {
+                content.extend(meta.clone());
+            }
             let _ = sender
                 .send(Message::Text(
-                    json!({"type": "response", "content": {"text": text, "images": [], "widgets": []}})
-                        .to_string(),
+                    json!({"type": "response", "content": content}).to_string(),
                 ))
                 .await;
+            if !link_previews {
+                return;
+            }
+            let previews = crate::link_preview::link_preview_widgets(&text).await;
+            if !previews.is_empty() {
+                let _ = sender
+                    .send(Message::Text(
+                        json!({"type": "link_preview", "content": {"widgets": previews}}).to_string(),
+                    ))
+                    .await;
+            }
         }
         Err(e) => {
             println!("❌ LLM error: {}", e);
@@ -955,6 +2564,192 @@ async fn handle_chat(
     }
 }
 
+/// Add one turn to the rolling provider/model statistics and persist them.
+async fn record_provider_stats(
+    state: &SharedState,
+    provider: &str,
+    model: &str,
+    timings: &serde_json::Value,
+    result: &Result<String, String>,
+) {
+    let latency_ms = timings["provider_ms"].as_u64().unwrap_or(0);
+    let stats = {
+        let mut s = state.lock().await;
+        match result {
+            Ok(text) => s.provider_stats.record(provider, model, latency_ms, true, text.len()),
+            Err(_) => s.provider_stats.record(provider, model, latency_ms, false, 0),
+        }
+        s.provider_stats.clone()
+    };
+    if let Err(e) = stats.save().await {
+        println!("⚠️ Failed to save provider stats: {}", e);
+    }
+}
+
+/// Tell every connected client about a registry change. `result` is the
+/// validation of an added or re-read sheet (as in `sheets::sync_headers`).
+async fn broadcast_spreadsheets(
+    state: &SharedState,
+    action: &str,
+    spreadsheet_id: &str,
+    sheets: &[crate::sheets::SpreadsheetConfig],
+    result: Option<serde_json::Value>,
+) {
+    let _ = state.lock().await.notifier.send(json!({
+        "type": "spreadsheets_updated",
+        "content": {"action": action, "spreadsheet_id": spreadsheet_id, "result": result, "spreadsheets": sheets},
+    }));
+}
+
+async fn send_spreadsheet_error(sender: &mut ClientSender, message: &str) {
+    println!("⚠️ Spreadsheet registry: {}", message);
+    let _ = sender
+        .send(Message::Text(json!({"type": "spreadsheet_error", "content": message}).to_string()))
+        .await;
+}
+
+/// Persist the sheet watches and echo the updated list back to the client.
+async fn send_sheet_watches(sender: &mut ClientSender, watches: &[crate::sheet_watch::SheetWatch]) {
+    if let Err(e) = crate::sheet_watch::save_watches(watches).await {
+        println!("❌ Failed to save sheet watches: {}", e);
+        let _ = sender
+            .send(Message::Text(
+                json!({"type": "sheet_watch_error", "content": "Could not save sheet watches. Please try again."})
+                    .to_string(),
+            ))
+            .await;
+        return;
+    }
+    let watches: Vec<crate::sheet_watch::SheetWatch> = watches.iter().map(|w| w.summary()).collect();
+    let _ = sender
+        .send(Message::Text(
+            json!({"type": "sheet_watches", "content": {"watches": watches}}).to_string(),
+        ))
+        .await;
+}
+
+/// Persist the scheduled jobs and echo the updated list back to the client.
+async fn send_scheduled_jobs(
+    sender: &mut ClientSender,
+    jobs: &[crate::scheduler::ScheduledJob],
+) {
+    if let Err(e) = crate::scheduler::save_jobs(jobs).await {
+        println!("❌ Failed to save scheduled jobs: {}", e);
+        let _ = sender
+            .send(Message::Text(
+                json!({"type": "scheduled_job_error", "content": "Could not save scheduled jobs. Please try again."})
+                    .to_string(),
+            ))
+            .await;
+        return;
+    }
+    let _ = sender
+        .send(Message::Text(
+            json!({"type": "scheduled_jobs", "content": {"jobs": jobs}}).to_string(),
+        ))
+        .await;
+}
+
+/// Images attached to more than this many per message are refused.
+const MAX_CHAT_IMAGES: usize = 8;
+
+/// The images of a chat message: each entry of `images` (see
+/// `ChatImage::from_value`), after a non-empty legacy `base64_image`.
+fn parse_chat_images(data: &serde_json::Value) -> Result<Vec<llm::ChatImage>, String> {
+    let mut images = Vec::new();
+    if let Some(legacy) = data["base64_image"].as_str().filter(|d| !d.is_empty()) {
+        images.push(llm::ChatImage::from_value(&json!(legacy))?);
+    }
+    for (i, image) in data["images"].as_array().into_iter().flatten().enumerate() {
+        images.push(llm::ChatImage::from_value(image).map_err(|e| format!("image {}: {}", i + 1, e))?);
+    }
+    if images.len() > MAX_CHAT_IMAGES {
+        return Err(format!("at most {} images can be sent at once", MAX_CHAT_IMAGES));
+    }
+    Ok(images)
+}
+
+/// Run a single agent turn with no client attached (watch rules, scheduled
+/// jobs). Uses the currently configured provider and all connected MCP tools;
+/// tool events are discarded.
+pub async fn run_background_turn(
+    state: &SharedState,
+    query: String,
+    images: Vec<llm::ChatImage>,
+    google_access: crate::google_auth::GoogleAccess,
+) -> Result<String, String> {
+    let (api_key, model, provider, mcp_tool_sets) = {
+        let s = state.lock().await;
+        (
+            s.api_keys.get(&s.current_provider).cloned().unwrap_or_default(),
+            s.current_model.clone(),
+            s.current_provider.clone(),
+            google_access.filter(s.all_mcp_tools()),
+        )
+    };
+
+    let (limiter, dry_run, check_conflicts, reasoning, generation) = {
+        let s = state.lock().await;
+        (s.llm_limiter.handle(), s.dry_run, s.check_calendar_conflicts, s.reasoning, s.generation)
+    };
+    let _permit = limiter.acquire().await;
+
+    // No client to forward tool events to; the receiver is dropped immediately.
+    // No secrets and no guarded calls either: nobody is there to approve them.
+    let (tool_tx, _) = crate::tools::tool_event_channel(1);
+    let tool_tx = tool_tx
+        .unattended()
+        .with_dry_run(dry_run)
+        .with_calendar_conflict_check(check_conflicts);
+
+    llm::call_llm(
+        provider,
+        api_key,
+        model,
+        query,
+        &mut Vec::new(),
+        mcp_tool_sets,
+        None,
+        images,
+        tool_tx,
+        None,
+        None,
+        crate::modes::resolve(None),
+        reasoning,
+        generation,
+    )
+    .await
+    .map_err(|e| clean_llm_error(&e.to_string()))
+}
+
+/// Run one sub-agent unattended (scheduled jobs), bypassing the main agent.
+pub async fn run_background_subagent(
+    state: &SharedState,
+    agent: &str,
+    task: &str,
+    google_access: crate::google_auth::GoogleAccess,
+) -> Result<String, String> {
+    let agent = crate::subagent::find(agent).ok_or_else(|| format!("Unknown agent '{}'", agent))?;
+    let (ctx, limiter) = {
+        let s = state.lock().await;
+        // No client to forward tool events to; the receiver is dropped immediately.
+        let (tool_tx, _) = crate::tools::tool_event_channel(1);
+        let ctx = crate::subagent::TurnContext {
+            provider: s.current_provider.clone(),
+            api_key: s.api_keys.get(&s.current_provider).cloned().unwrap_or_default(),
+            model: s.current_model.clone(),
+            mcp_tool_sets: google_access.filter(s.all_mcp_tools()),
+            tx: tool_tx
+                .unattended()
+                .with_dry_run(s.dry_run)
+                .with_calendar_conflict_check(s.check_calendar_conflicts),
+        };
+        (ctx, s.llm_limiter.handle())
+    };
+    let _permit = limiter.acquire().await;
+    agent.run(&ctx, task).await.map_err(|e| clean_llm_error(&e))
+}
+
 /// Connect to an HTTP/SSE MCP server using the streamable-http transport.
 ///
 /// The `Authorization: Bearer <api_key>` header is sent with every request when
@@ -964,7 +2759,10 @@ async fn handle_chat(
 async fn connect_http_mcp_server(
     url: &str,
     api_key: &str,
-) -> Result<McpConnection, String> {
+    name: &str,
+    config_hash: &str,
+    refresh_cache: bool,
+) -> Result<(McpConnection, bool), String> {
     let config = {
         let base = StreamableHttpClientTransportConfig::with_uri(url);
         if api_key.is_empty() {
@@ -984,24 +2782,104 @@ async fn connect_http_mcp_server(
     .map_err(|_| "Connection timed out after 30s".to_string())?
     .map_err(|e| format!("MCP handshake failed: {:?}", e))?;
 
-    let tool_list = tokio::time::timeout(
+    let (tools, cached) = tokio::time::timeout(
         std::time::Duration::from_secs(15),
-        service.list_tools(Default::default()),
+        crate::mcp_cache::list_tools(name, config_hash, &service, refresh_cache),
     )
     .await
     .map_err(|_| "list_tools timed out after 15s".to_string())?
-    .map_err(|e| format!("list_tools failed: {:?}", e))?;
+    .map_err(|e| format!("list_tools failed: {}", e))?;
+
+    let conn = McpConnection::new(tools, service);
+
+    Ok((conn, cached))
+}
 
-    let conn = McpConnection {
-        tools: tool_list.tools,
-        peer: service.peer().clone(),
-        _service: service,
+/// The child process for a stdio MCP server, with the entry's optional
+/// spawn settings applied:
+/// - `cwd`: working directory (`~` expanded; must exist), for servers that
+///   work on the files around them.
+/// - `stdin`: text fed to the server before the MCP session (a passphrase,
+///   a license prompt answer); the command then runs behind a small `sh`
+///   wrapper that writes it and forwards the session.
+/// - `nice`: scheduling priority, 0 to 19.
+/// - `memory_limit_mb`: address-space cap (`ulimit -v`); allocations past it
+///   fail instead of swapping the machine. Not enforced by macOS.
+fn mcp_server_command(
+    command: &str,
+    args: &[String],
+    server_config: &serde_json::Value,
+) -> Result<tokio::process::Command, String> {
+    let mut cmd = match server_config["stdin"].as_str().filter(|s| !s.is_empty()) {
+        Some(preamble) => {
+            let mut cmd = tokio::process::Command::new("/bin/sh");
+            cmd.arg("-c")
+                .arg(r#"{ printf '%s' "$RONGE_MCP_STDIN"; exec cat; } | exec "$0" "$@""#)
+                .arg(command)
+                .args(args)
+                .env("RONGE_MCP_STDIN", preamble);
+            cmd
+        }
+        None => {
+            let mut cmd = tokio::process::Command::new(command);
+            cmd.args(args);
+            cmd
+        }
     };
 
-    Ok(conn)
+    if let Some(cwd) = server_config["cwd"].as_str().filter(|c| !c.is_empty()) {
+        let dir = match cwd.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+            None if cwd == "~" => dirs::home_dir().unwrap_or_default(),
+            None => std::path::PathBuf::from(cwd),
+        };
+        if !dir.is_dir() {
+            return Err(format!("Working directory {} does not exist", dir.display()));
+        }
+        cmd.current_dir(dir);
+    }
+
+    let nice = match server_config.get("nice").filter(|v| !v.is_null()) {
+        Some(v) => Some(
+            v.as_i64()
+                .filter(|n| (0..=19).contains(n))
+                .ok_or("`nice` must be a number from 0 to 19")? as i32,
+        ),
+        None => None,
+    };
+    let memory_limit = match server_config.get("memory_limit_mb").filter(|v| !v.is_null()) {
+        Some(v) => Some(v.as_u64().filter(|mb| *mb > 0).ok_or("`memory_limit_mb` must be a positive number")?),
+        None => None,
+    };
+    #[cfg(unix)]
+    if nice.is_some() || memory_limit.is_some() {
+        // Runs in the child between fork and exec: only async-signal-safe calls.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(nice) = nice
+                    && libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if let Some(mb) = memory_limit {
+                    let bytes = (mb * 1024 * 1024) as libc::rlim_t;
+                    let limit = libc::rlimit { rlim_cur: bytes, rlim_max: bytes };
+                    if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    if nice.is_some() || memory_limit.is_some() {
+        println!("⚠️ MCP resource limits are not supported on this platform; ignoring them");
+    }
+    Ok(cmd)
 }
 
-fn build_expanded_path() -> String {
+pub(crate) fn build_expanded_path() -> String {
     let home = dirs::home_dir().unwrap_or_default();
     let home_str = home.to_string_lossy();
     let mut extra_paths: Vec<String> = Vec::new();
@@ -1038,7 +2916,7 @@ fn build_expanded_path() -> String {
     extra_paths.join(":")
 }
 
-fn resolve_command(command: &str, path: &str) -> String {
+pub(crate) fn resolve_command(command: &str, path: &str) -> String {
     if command.starts_with('/') {
         return command.to_string();
     }
@@ -1050,3 +2928,116 @@ fn resolve_command(command: &str, path: &str) -> String {
     }
     command.to_string()
 }
+
+/// Why a bare `command` wasn't found: the PATH directories searched and up
+/// to five executables in them with the closest names (`npm` for `npx`,
+/// `python3` for `python`), reported with a failed spawn.
+pub(crate) fn command_not_found_details(command: &str, path: &str) -> (Vec<String>, Vec<String>) {
+    let searched: Vec<String> = path.split(':').filter(|d| !d.is_empty()).map(str::to_string).collect();
+    let max_distance = (command.len() / 3).max(1);
+    let mut seen = std::collections::HashSet::new();
+    let mut similar: Vec<(usize, String)> = Vec::new();
+    for dir in &searched {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let distance = edit_distance(command, &name);
+            let related = distance <= max_distance || name.starts_with(command) || command.starts_with(&name);
+            if !related || name.len() < 2 || !is_executable(&entry.path()) || !seen.insert(name) {
+                continue;
+            }
+            similar.push((distance, entry.path().to_string_lossy().to_string()));
+        }
+    }
+    similar.sort();
+    (searched, similar.into_iter().take(5).map(|(_, p)| p).collect())
+}
+
+/// The spawn error with `command_not_found_details` appended, and the same
+/// details as status fields.
+fn spawn_error_status(name: &str, command: &str, resolved: &str, path: &str, error: &str) -> serde_json::Value {
+    if resolved.starts_with('/') {
+        return json!({"name": name, "status": "error", "error": error});
+    }
+    let (searched, similar) = command_not_found_details(command, path);
+    let mut message = format!("{} (`{}` not found in {} PATH directories", error, command, searched.len());
+    if !similar.is_empty() {
+        message.push_str(&format!("; similar: {}", similar.join(", ")));
+    }
+    message.push(')');
+    json!({"name": name, "status": "error", "error": message, "searched_path": searched, "similar": similar})
+}
+
+fn is_executable(path: &std::path::Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Levenshtein distance between two short names.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev + usize::from(ca != *cb);
+            prev = row[j + 1];
+            row[j + 1] = substitute.min(prev + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cassette turn goes through `handle_chat` like a live one: the model
+    /// and the MCP server are answered from the tape, the client gets the
+    /// tool events and the recorded answer, and the exchange joins the history.
+    #[tokio::test]
+    async fn replayed_turn_runs_through_handle_chat() {
+        let cassette = "handle-chat-replay-test";
+        crate::replay::save_cassette(cassette, &[crate::replay::tests::weather_turn()])
+            .await
+            .expect("the cassette is saved");
+        let state: SharedState = std::sync::Arc::new(tokio::sync::Mutex::new(crate::state::AppState::new()));
+        let replay = crate::replay::ReplaySession::replay(cassette).expect("the cassette is readable");
+        state.lock().await.replay = Some(replay);
+
+        let (mut sender, mut frames) = ClientSender::channel("replay-test".to_string(), state.clone());
+        let mut history = Vec::new();
+        handle_chat(&json!({"text": "Weather in Oslo?"}), &mut sender, &mut history, &state).await;
+        let _ = std::fs::remove_file(
+            dirs::home_dir()
+                .unwrap_or_default()
+                .join(".ronge")
+                .join("cassettes")
+                .join(format!("{}.json", cassette)),
+        );
+
+        let mut sent = Vec::new();
+        while let Ok(Message::Text(frame)) = frames.try_recv() {
+            sent.push(serde_json::from_str::<serde_json::Value>(&frame).unwrap());
+        }
+        let call = sent.iter().find(|f| f["type"] == "tool_call").expect("a tool_call frame");
+        assert_eq!(call["content"]["toolName"], "get_weather");
+        let result = sent.iter().find(|f| f["type"] == "tool_result").expect("a tool_result frame");
+        assert_eq!(result["content"]["success"], true);
+        let response = sent.iter().find(|f| f["type"] == "response").expect("a response frame");
+        assert_eq!(response["content"]["text"], "It is sunny in Oslo.");
+
+        assert!(matches!(history.first(), Some(RigMessage::User { .. })));
+        assert!(matches!(history.last(), Some(RigMessage::Assistant { .. })));
+    }
+}
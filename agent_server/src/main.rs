@@ -1,16 +1,80 @@
-use axum::{routing::get, Router};
+use axum::{routing::{get, post}, Router};
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
 
 // Register modules
+mod archive;
+mod artifacts;
+mod calendar;
+mod chart;
+mod code_exec;
+mod confirm;
+mod context_usage;
+mod custom_tools;
+mod date_info;
+mod debug_dump;
+mod docs_export;
+mod email_summary;
+mod generation;
+mod github;
+mod gmail;
+mod google_auth;
+mod history_compress;
+mod ics;
+mod limiter;
+mod link_preview;
 mod llm;
+mod ollama;
 mod openrouter_auth;
+mod output_budget;
 mod logic;
+mod mcp_cache;
 mod mcp_proxy;
+mod memory;
+mod mock_provider;
+mod model_list;
+mod modes;
+mod netcheck;
+mod pii;
+mod planner;
+mod plugins;
+mod profile;
+mod provider_http;
+mod provider_settings;
+mod provider_stats;
+mod quota;
+mod rate_limit;
+mod reasoning;
+mod replay;
+mod retry;
 mod routes;
+mod runtimes;
+mod sanitize;
+mod scheduler;
+mod secret_refs;
+mod secrets;
+mod session;
+mod shutdown;
+mod sheet_index;
+mod sheet_watch;
+mod sheets;
+mod speculative;
+mod speech;
 mod state;
+mod subagent;
+mod telegram;
+mod text;
+mod timings;
+mod tool_pruning;
 mod tools;
+mod transfer;
+mod vault;
+mod verify;
+mod watcher;
+mod widgets;
+mod workspace;
+mod world_clock;
 
 use state::AppState;
 
@@ -40,7 +104,7 @@ fn main() {
     #[cfg(unix)]
     fix_stdio_blocking();
 
-    // rig's ollama::Client::from_env() panics if OLLAMA_API_BASE_URL is absent.
+    // The Ollama client and warm-up read OLLAMA_API_BASE_URL.
     // Default to localhost before any threads start (safe single-threaded context).
     if std::env::var("OLLAMA_API_BASE_URL").is_err() {
         unsafe { std::env::set_var("OLLAMA_API_BASE_URL", "http://localhost:11434") };
@@ -53,23 +117,140 @@ fn main() {
         .block_on(async_main());
 }
 
+/// Ports tried after a taken `--port` in fallback mode.
+const PORT_FALLBACK_ATTEMPTS: u16 = 20;
+
+/// The value of `--<flag> <value>` / `--<flag>=<value>`, else of `env`.
+fn cli_value(flag: &str, env: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let from_args = args.iter().enumerate().find_map(|(i, arg)| match arg.strip_prefix(flag) {
+        Some("") => args.get(i + 1).cloned(),
+        Some(value) => value.strip_prefix('=').map(str::to_string),
+        None => None,
+    });
+    from_args.or_else(|| std::env::var(env).ok())
+}
+
+/// `cli_value` parsed as a number; exits on anything else.
+fn cli_number<T: std::str::FromStr>(flag: &str, env: &str) -> Option<T> {
+    let value = cli_value(flag, env)?;
+    match value.trim().parse() {
+        Ok(number) => Some(number),
+        Err(_) => {
+            eprintln!("❌ Invalid {} '{}'", flag, value);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// `--port <n>` / `--port=<n>`, else `RONGE_PORT`. `None` lets the OS pick.
+fn requested_port() -> Option<u16> {
+    cli_number("--port", "RONGE_PORT")
+}
+
+/// `--port-fallback` or `RONGE_PORT_FALLBACK=1`: when the requested port is
+/// taken, try the next ones instead of exiting.
+fn port_fallback() -> bool {
+    std::env::args().any(|a| a == "--port-fallback")
+        || std::env::var("RONGE_PORT_FALLBACK").is_ok_and(|v| v == "1" || v == "true")
+}
+
+/// Bind the requested port (with fallback to the following ports when
+/// enabled), or a free port chosen by the OS when none was requested.
+async fn bind_listener() -> TcpListener {
+    let Some(port) = requested_port() else {
+        // Port 0: the OS picks a guaranteed-free port.
+        return TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind a local port");
+    };
+    let attempts = if port_fallback() { PORT_FALLBACK_ATTEMPTS } else { 1 };
+    let mut last_error = None;
+    for candidate in (port..=u16::MAX).take(attempts as usize) {
+        match TcpListener::bind(("127.0.0.1", candidate)).await {
+            Ok(listener) => {
+                if candidate != port {
+                    println!("⚠️ Port {} is in use; using {}", port, candidate);
+                }
+                return listener;
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    let error = last_error.map(|e| e.to_string()).unwrap_or_default();
+    // Machine-parsable, like PORT=, so the parent process can report it.
+    println!("PORT_ERROR={}", error);
+    eprintln!(
+        "❌ Could not listen on 127.0.0.1:{}{}: {}",
+        port,
+        if attempts > 1 { format!(" or the next {} ports", attempts - 1) } else { String::new() },
+        error
+    );
+    std::process::exit(1);
+}
+
 async fn async_main() {
     tracing_subscriber::fmt::init();
 
+    // Connection and integration secrets (Keychain on macOS)
+    secrets::load();
+
     // Initialize State
     let state = Arc::new(Mutex::new(AppState::new()));
 
+    // Background folder watcher for user-defined watch rules
+    watcher::spawn(state.clone());
+    // Recurring unattended jobs
+    scheduler::spawn(state.clone());
+    // Sheet ranges watched for value changes
+    sheet_watch::spawn(state.clone());
+    // Expire generated files
+    artifacts::spawn_gc(state.clone());
+    // User-declared tools from ~/.ronge/tools.toml
+    custom_tools::start(state.clone()).await;
+    // Sandboxed WASM tool plugins from ~/.ronge/plugins/
+    plugins::start(state.clone()).await;
+    // Retrieval over spreadsheets indexed with index_spreadsheet
+    sheet_index::refresh_server(state.clone()).await;
+    // GitHub tools for a token stored earlier
+    tokio::spawn(github::restore(state.clone()));
+    // Optional Telegram frontend (RONGE_TELEGRAM_BOT_TOKEN or the telegram secret)
+    telegram::spawn(state.clone());
+    // SIGINT/SIGTERM, the `shutdown` message and the parent watchdog all
+    // end in the same shutdown path below.
+    shutdown::spawn_signal_handlers();
+    if let Some(parent_pid) = cli_number::<i32>("--parent-pid", "RONGE_PARENT_PID") {
+        shutdown::spawn_parent_watchdog(parent_pid);
+    }
+
     // Setup Router
     let app = Router::new()
         .route("/ws", get(routes::ws_handler))
-        .with_state(state);
+        .route("/chat", post(routes::chat_handler))
+        .route("/decision", post(routes::decision_handler))
+        .layer(axum::middleware::from_fn(routes::require_auth))
+        .with_state(state.clone());
 
-    // Bind to port 0 so the OS picks a guaranteed-free port
-    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let listener = bind_listener().await;
     let port = listener.local_addr().unwrap().port();
     // Print the actual port so the Swift parent process can read it
     println!("PORT={}", port);
     println!("🚀 Rust Server listening on 127.0.0.1:{}", port);
 
-    axum::serve(listener, app).await.unwrap();
+    // WebSocket handlers close their sockets on shutdown; connections still
+    // open after the grace period are dropped with the process.
+    let server = axum::serve(listener, app).with_graceful_shutdown(shutdown::wait());
+    tokio::select! {
+        result = server => {
+            if let Err(e) = result {
+                println!("❌ Server error: {}", e);
+            }
+        }
+        _ = async {
+            shutdown::wait().await;
+            tokio::time::sleep(shutdown::GRACE).await;
+        } => println!("⚠️ Connections still open after {}s; closing anyway", shutdown::GRACE.as_secs()),
+    }
+
+    // Stop MCP children before exiting so none outlive the server.
+    shutdown::stop_mcp_servers(&state).await;
+    println!("👋 Server stopped");
 }
\ No newline at end of file
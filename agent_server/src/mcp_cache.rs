@@ -0,0 +1,131 @@
+use rmcp::{RoleClient, model::Tool, service::RunningService};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// Cached tool lists older than this are fetched again even when the server
+/// version is unchanged (`RONGE_MCP_CACHE_MAX_AGE_HOURS`).
+const DEFAULT_MAX_AGE_HOURS: i64 = 24;
+
+/// A server's tool list as last fetched, keyed by its config and version.
+#[derive(Serialize, Deserialize)]
+struct CachedTools {
+    config_hash: String,
+    server_version: Option<String>,
+    /// RFC 3339.
+    cached_at: String,
+    tools: Vec<Tool>,
+}
+
+/// `~/.ronge/mcp-cache`.
+fn cache_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("mcp-cache")
+}
+
+fn cache_path(name: &str) -> PathBuf {
+    let safe: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    cache_dir().join(format!("{}.json", safe))
+}
+
+/// SHA-256 of a server's `mcp_config` entry; any change to its command,
+/// arguments, environment or URL invalidates the cache.
+pub fn config_hash(server_config: &Value) -> String {
+    let digest = Sha256::digest(server_config.to_string().as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn max_age() -> chrono::Duration {
+    let hours = std::env::var("RONGE_MCP_CACHE_MAX_AGE_HOURS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_AGE_HOURS);
+    chrono::Duration::hours(hours)
+}
+
+fn load(name: &str, config_hash: &str, server_version: Option<&str>) -> Option<Vec<Tool>> {
+    let body = std::fs::read_to_string(cache_path(name)).ok()?;
+    let cached: CachedTools = serde_json::from_str(&body).ok()?;
+    let fresh = chrono::DateTime::parse_from_rfc3339(&cached.cached_at)
+        .is_ok_and(|at| chrono::Utc::now().signed_duration_since(at) < max_age());
+    (cached.config_hash == config_hash && cached.server_version.as_deref() == server_version && fresh)
+        .then_some(cached.tools)
+}
+
+/// Write the tool list and update `manifest.json`, the per-server summary
+/// (config hash, version, tool count, time) read at startup and by the UI.
+async fn save(name: &str, config_hash: &str, server_version: Option<&str>, tools: &[Tool]) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(cache_dir()).await?;
+    let cached_at = chrono::Utc::now().to_rfc3339();
+    let entry = CachedTools {
+        config_hash: config_hash.to_string(),
+        server_version: server_version.map(str::to_string),
+        cached_at: cached_at.clone(),
+        tools: tools.to_vec(),
+    };
+    tokio::fs::write(cache_path(name), serde_json::to_vec(&entry).unwrap_or_default()).await?;
+
+    let mut manifest = manifest();
+    if let Some(servers) = manifest.as_object_mut() {
+        servers.insert(
+            name.to_string(),
+            json!({
+                "config_hash": config_hash,
+                "server_version": server_version,
+                "tools": tools.len(),
+                "cached_at": cached_at,
+            }),
+        );
+    }
+    tokio::fs::write(cache_dir().join("manifest.json"), manifest.to_string()).await
+}
+
+/// The cached servers: `{name: {config_hash, server_version, tools, cached_at}}`.
+pub fn manifest() -> Value {
+    std::fs::read_to_string(cache_dir().join("manifest.json"))
+        .ok()
+        .and_then(|body| serde_json::from_str::<Value>(&body).ok())
+        .filter(|v| v.is_object())
+        .unwrap_or_else(|| json!({}))
+}
+
+/// The tools of a just-connected server. A cached list for the same config
+/// and server version is used as is (`true`), so the server registers without
+/// waiting for `list_tools`; the cache is then refreshed in the background
+/// for the next connect. Otherwise the list is fetched and cached.
+pub async fn list_tools(
+    name: &str,
+    config_hash: &str,
+    service: &RunningService<RoleClient, ()>,
+    refresh: bool,
+) -> Result<(Vec<Tool>, bool), String> {
+    let version = service.peer_info().map(|info| info.server_info.version.clone());
+    if !refresh && let Some(tools) = load(name, config_hash, version.as_deref()) {
+        println!("⚡ MCP '{}' registered {} cached tools", name, tools.len());
+        let peer = service.peer().clone();
+        let (name, config_hash) = (name.to_string(), config_hash.to_string());
+        tokio::spawn(async move {
+            if let Ok(list) = peer.list_tools(Default::default()).await
+                && let Err(e) = save(&name, &config_hash, version.as_deref(), &list.tools).await
+            {
+                println!("⚠️ Failed to refresh MCP cache for '{}': {}", name, e);
+            }
+        });
+        return Ok((tools, true));
+    }
+
+    let list = service
+        .list_tools(Default::default())
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+    if let Err(e) = save(name, config_hash, version.as_deref(), &list.tools).await {
+        println!("⚠️ Failed to cache tools of MCP '{}': {}", name, e);
+    }
+    Ok((list.tools, false))
+}
@@ -1,12 +1,19 @@
-use crate::tools::ToolEventSender;
+use crate::state::McpToolSet;
+use crate::tools::{ToolEventSender, ToolPhase};
 use rmcp::{
     serve_client, serve_server, ServerHandler,
-    model::{CallToolRequestParam, CallToolResult, ErrorData, ListToolsResult, PaginatedRequestParam},
+    model::{CallToolRequestParam, CallToolResult, Content, ErrorData, ListToolsResult, PaginatedRequestParam},
     service::{Peer, RequestContext, RoleClient, RoleServer},
 };
 use serde_json::json;
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Extra attempts for a Google call that failed transiently.
+const MAX_RETRIES: u32 = 2;
+/// Delay before the first retry; doubled for each one after.
+const RETRY_BASE_MS: u64 = 1_000;
 
 /// Sanitise an MCP tool name so it is accepted by **all** LLM providers.
 ///
@@ -31,14 +38,36 @@ pub fn sanitize_tool_name(raw: &str) -> String {
     out
 }
 
+/// Sanitise every tool name once, returning the renamed tools and a
+/// sanitized → original name map (only entries that actually changed).
+pub fn sanitize_tools(
+    tools: Vec<rmcp::model::Tool>,
+) -> (Vec<rmcp::model::Tool>, HashMap<String, String>) {
+    let mut name_map: HashMap<String, String> = HashMap::new();
+    let sanitized_tools = tools
+        .into_iter()
+        .map(|mut t| {
+            let original = t.name.to_string();
+            let safe = sanitize_tool_name(&original);
+            if safe != original {
+                println!("🔧 MCP tool name sanitized: '{}' → '{}'", original, safe);
+                name_map.insert(safe.clone(), original);
+                t.name = Cow::Owned(safe);
+            }
+            t
+        })
+        .collect();
+    (sanitized_tools, name_map)
+}
+
 /// An in-process MCP server that sits between rig and a real MCP server peer.
 /// It fires `tool_call` / `tool_result` WS events whenever a tool is invoked.
 pub struct NotifyingMcpProxy {
     real_peer: Peer<RoleClient>,
     /// Tools with **sanitized** names (safe for all LLM providers).
-    tools: Vec<rmcp::model::Tool>,
+    tools: Arc<Vec<rmcp::model::Tool>>,
     /// Maps sanitized name → original MCP name for forwarding calls.
-    name_map: HashMap<String, String>,
+    name_map: Arc<HashMap<String, String>>,
     tx: ToolEventSender,
 }
 
@@ -48,7 +77,7 @@ impl ServerHandler for NotifyingMcpProxy {
         _request: Option<PaginatedRequestParam>,
         _context: RequestContext<RoleServer>,
     ) -> Result<ListToolsResult, ErrorData> {
-        Ok(ListToolsResult::with_all_items(self.tools.clone()))
+        Ok(ListToolsResult::with_all_items(self.tools.to_vec()))
     }
 
     async fn call_tool(
@@ -76,21 +105,267 @@ impl ServerHandler for NotifyingMcpProxy {
             .tx
             .send(json!({
                 "type": "tool_call",
-                "content": { "toolName": &sanitized_name, "toolArgs": args_json }
+                "content": { "toolName": &sanitized_name, "toolArgs": &args_json }
             }))
             .await;
 
+        if let Some(notice) = self.tx.check_repeat(&sanitized_name, &args_json).await {
+            let _ = self
+                .tx
+                .send(json!({
+                    "type": "tool_result",
+                    "content": { "toolName": &sanitized_name, "result": &notice, "durationMs": 0, "success": false }
+                }))
+                .await;
+            return Ok(CallToolResult::error(vec![Content::text(notice)]));
+        }
+
+        if self.tx.is_dry_run() && self.tx.is_guarded(&sanitized_name) {
+            let preview = format!(
+                "[DRY RUN] Nothing was executed. Would call {} with {}.",
+                sanitized_name,
+                crate::confirm::summarize_args(&args_json)
+            );
+            let _ = self
+                .tx
+                .send(json!({
+                    "type": "tool_result",
+                    "content": { "toolName": &sanitized_name, "result": &preview, "durationMs": 0, "success": true, "dryRun": true }
+                }))
+                .await;
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "{} Tell the user exactly what would have happened.",
+                preview
+            ))]));
+        }
+
+        // Don't double-book: report overlapping events instead of creating one.
+        let mut arguments = request.arguments;
+        if self.tx.checks_calendar_conflicts() && crate::calendar::is_create_event(&sanitized_name) {
+            let allowed = arguments
+                .as_mut()
+                .and_then(|a| a.remove(crate::calendar::ALLOW_CONFLICTS))
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let window = arguments.as_ref().and_then(crate::calendar::event_window);
+            if let (false, Some(window)) = (allowed, window) {
+                match crate::calendar::find_conflicts(&self.real_peer, &self.tools, &self.name_map, window).await {
+                    Ok(conflicts) if !conflicts.is_empty() => {
+                        println!("📅 {} would overlap {} event(s); asking first", sanitized_name, conflicts.len());
+                        let warning = crate::calendar::conflict_warning(&conflicts);
+                        let _ = self
+                            .tx
+                            .send(json!({
+                                "type": "tool_result",
+                                "content": { "toolName": &sanitized_name, "result": &warning, "durationMs": 0, "success": false, "conflicts": conflicts }
+                            }))
+                            .await;
+                        // Event titles come from whoever sent the invite.
+                        let mut result = CallToolResult::success(vec![Content::text(warning)]);
+                        crate::sanitize::sanitize_tool_result(&sanitized_name, &mut result);
+                        return Ok(result);
+                    }
+                    Ok(_) => {}
+                    Err(e) => println!("⚠️ Calendar conflict check skipped for {}: {}", sanitized_name, e),
+                }
+            }
+        }
+
+        // Attendees given by name are looked up in the user's contacts.
+        if crate::calendar::is_create_event(&sanitized_name)
+            && let Some(args) = arguments.as_mut()
+        {
+            match crate::calendar::resolve_attendees(&self.real_peer, &self.tools, &self.name_map, args).await {
+                Ok(resolved) => {
+                    for (name, email) in resolved {
+                        println!("👥 Attendee \"{}\" resolved to {}", name, email);
+                    }
+                }
+                Err(unresolved) => {
+                    println!("👥 {} has attendees that need the user's input", sanitized_name);
+                    let _ = self
+                        .tx
+                        .send(json!({
+                            "type": "tool_result",
+                            "content": { "toolName": &sanitized_name, "result": &unresolved, "durationMs": 0, "success": false }
+                        }))
+                        .await;
+                    let mut result = CallToolResult::success(vec![Content::text(unresolved)]);
+                    crate::sanitize::sanitize_tool_result(&sanitized_name, &mut result);
+                    return Ok(result);
+                }
+            }
+        }
+        // The confirmation shows the arguments as they will be sent.
+        let args_json = arguments.clone().map(serde_json::Value::Object).unwrap_or(args_json);
+
+        // Our own flag: collapse search results per thread after the call.
+        let group_threads = crate::gmail::is_search(&sanitized_name)
+            && arguments
+                .as_mut()
+                .and_then(|a| a.remove(crate::gmail::GROUP_BY_THREAD))
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+        // A secret placeholder means a credential is about to leave the machine.
+        let approved = if crate::secret_refs::SecretRefs::mentions(&args_json) {
+            self.tx.confirm_always(&sanitized_name, &args_json).await
+        } else {
+            self.tx.confirm(&sanitized_name, &args_json).await
+        };
+        if !approved {
+            let (message, shown) = if self.tx.is_unattended() {
+                (
+                    "This action needs the user's approval and this background turn has nobody to ask, so it was not performed. Do not retry it; report what you would have done instead.",
+                    "Refused in a background turn",
+                )
+            } else {
+                (
+                    "The user declined this action. Do not retry it; tell the user it was not performed.",
+                    "Declined by user",
+                )
+            };
+            let _ = self
+                .tx
+                .send(json!({
+                    "type": "tool_result",
+                    "content": { "toolName": &sanitized_name, "result": shown, "durationMs": 0, "success": false }
+                }))
+                .await;
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+
+        // Stay under per-user Google quotas: wait for budget instead of failing.
+        if let Some(api) = crate::quota::google_api_for(&sanitized_name) {
+            let waited = crate::quota::acquire(api).await;
+            if !waited.is_zero() {
+                println!("🐢 Throttled {} for {} ms ({} quota)", sanitized_name, waited.as_millis(), api);
+                let _ = self
+                    .tx
+                    .send(json!({
+                        "type": "tool_throttled",
+                        "content": { "toolName": &sanitized_name, "api": api, "waitMs": waited.as_millis() as u64 }
+                    }))
+                    .await;
+            }
+        }
+
+        // Only now, after the tool_call event and the confirmation, do the
+        // placeholders become real values.
+        if let Some(args) = arguments.as_mut()
+            && crate::secret_refs::SecretRefs::mentions(&args_json)
+        {
+            match self.tx.secret_refs().inject(&sanitized_name, args) {
+                Ok(used) => println!("🔑 Passing secret(s) {} to {}", used.join(", "), sanitized_name),
+                Err(e) => {
+                    let _ = self
+                        .tx
+                        .send(json!({
+                            "type": "tool_result",
+                            "content": { "toolName": &sanitized_name, "result": &e, "durationMs": 0, "success": false }
+                        }))
+                        .await;
+                    return Ok(CallToolResult::error(vec![Content::text(e)]));
+                }
+            }
+        }
+
         // Forward to the real MCP server using the **original** name
         let forwarded = CallToolRequestParam {
             name: Cow::Owned(original_name),
-            arguments: request.arguments,
+            arguments,
             task: request.task,
         };
-        let result = self
-            .real_peer
-            .call_tool(forwarded)
-            .await
-            .map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        // Google rate limits and brief outages are retried with backoff, but
+        // only for calls that just read: a failed write may still have acted.
+        let retryable = crate::quota::google_api_for(&sanitized_name)
+            .filter(|_| crate::confirm::is_read_only(&sanitized_name) && !self.tx.is_guarded(&sanitized_name));
+        let started = std::time::Instant::now();
+        let mut attempt: u32 = 1;
+        // A replayed turn gets the recorded result and nothing is sent.
+        let mut replayed = self.tx.tape().filter(|t| t.replays()).map(|tape| {
+            tape.next_mcp(&sanitized_name)
+                .map_err(|e| rmcp::ServiceError::McpError(ErrorData::internal_error(e, None)))
+        });
+        let called = loop {
+            if let Some(called) = replayed.take() {
+                break called;
+            }
+            self.tx
+                .phase(&sanitized_name, ToolPhase::NetworkRequest, json!({ "attempt": attempt }))
+                .await;
+            let called = self.real_peer.call_tool(forwarded.clone()).await;
+            let reason = match &called {
+                Ok(result) => crate::quota::result_transient(result).then(|| "transient error in result".to_string()),
+                Err(e) => crate::quota::is_transient(&e.to_string()).then(|| e.to_string()),
+            };
+            let (Some(api), Some(reason)) = (retryable, reason) else { break called };
+            if attempt > MAX_RETRIES {
+                break called;
+            }
+            let delay = std::time::Duration::from_millis(RETRY_BASE_MS << (attempt - 1));
+            println!("🔁 {} failed transiently, retry {} in {} ms", sanitized_name, attempt, delay.as_millis());
+            self.tx
+                .phase(
+                    &sanitized_name,
+                    ToolPhase::Retrying,
+                    json!({ "attempt": attempt, "delayMs": delay.as_millis() as u64, "reason": self.tx.secret_refs().scrub_text(&reason) }),
+                )
+                .await;
+            tokio::time::sleep(delay).await;
+            crate::quota::acquire(api).await;
+            attempt += 1;
+        };
+        let duration_ms = started.elapsed().as_millis() as u64;
+        // Nothing downstream sees a secret a server echoed back.
+        let refs = self.tx.secret_refs();
+        let called = called
+            .map(|mut result| {
+                refs.scrub(&mut result);
+                result
+            })
+            .map_err(|e| refs.scrub_text(&e.to_string()));
+        if let Some(tape) = self.tx.tape().filter(|t| !t.replays()) {
+            tape.push_mcp(crate::replay::McpExchange {
+                tool: sanitized_name.clone(),
+                arguments: args_json.clone(),
+                result: called.clone(),
+            });
+        }
+        let mut result = match called {
+            Ok(result) => result,
+            Err(e) => {
+                let _ = self
+                    .tx
+                    .send(json!({
+                        "type": "tool_result",
+                        "content": { "toolName": &sanitized_name, "result": e.to_string(), "durationMs": duration_ms, "success": false }
+                    }))
+                    .await;
+                if let Some(api) = crate::quota::google_api_for(&sanitized_name)
+                    && crate::google_auth::is_expired(&e.to_string())
+                {
+                    println!("🔒 Google authorization expired ({} via {})", api, sanitized_name);
+                    let _ = self.tx.send(crate::google_auth::expired_event(&sanitized_name, api)).await;
+                    return Ok(crate::google_auth::expired_result(api));
+                }
+                return Err(ErrorData::internal_error(e.to_string(), None));
+            }
+        };
+        // Tell the client right away instead of leaving an opaque 401 for the model.
+        let auth_expired = crate::quota::google_api_for(&sanitized_name)
+            .filter(|_| crate::google_auth::result_expired(&result));
+        if let Some(api) = auth_expired {
+            println!("🔒 Google authorization expired ({} via {})", api, sanitized_name);
+            let _ = self.tx.send(crate::google_auth::expired_event(&sanitized_name, api)).await;
+        }
+        let success = result.is_error != Some(true) && auth_expired.is_none();
+        if success && group_threads {
+            crate::gmail::group_by_thread(&mut result);
+        } else if success && crate::gmail::reads_messages(&sanitized_name) {
+            crate::gmail::decode_bodies(&mut result);
+            crate::gmail::list_attachments(&mut result);
+        }
 
         // Serialize result — matches Swift ToolResultContent { toolName, result }
         let result_str = serde_json::to_string(&result).unwrap_or_else(|_| String::from("{}"));
@@ -100,7 +375,7 @@ impl ServerHandler for NotifyingMcpProxy {
         let result_str = if result_str.len() > MAX_RESULT_BYTES {
             format!(
                 "{}... [truncated — {} bytes total]",
-                &result_str[..MAX_RESULT_BYTES],
+                crate::text::truncate_bytes(&result_str, MAX_RESULT_BYTES),
                 result_str.len()
             )
         } else {
@@ -111,10 +386,25 @@ impl ServerHandler for NotifyingMcpProxy {
             .tx
             .send(json!({
                 "type": "tool_result",
-                "content": { "toolName": &sanitized_name, "result": result_str }
+                "content": { "toolName": &sanitized_name, "result": result_str, "durationMs": duration_ms, "success": success }
             }))
             .await;
 
+        // The client sees the raw result above; the model gets delimited,
+        // sanitized text so instructions hidden in emails or pages stay data.
+        if let Some(api) = auth_expired {
+            return Ok(crate::google_auth::expired_result(api));
+        }
+        self.tx.output_budget().apply(&sanitized_name, &mut result);
+        crate::sanitize::sanitize_tool_result(&sanitized_name, &mut result);
+        if self.tx.redacts_pii() {
+            crate::pii::redact_tool_result(&mut result);
+        }
+        if success {
+            let text: Vec<&str> = result.content.iter().filter_map(|c| c.as_text()).map(|t| t.text.as_str()).collect();
+            self.tx.record_output(&sanitized_name, text.join("\n"));
+        }
+
         Ok(result)
     }
 }
@@ -134,32 +424,32 @@ pub struct McpProxyGuard {
 /// - The proxy peer to pass to `builder.rmcp_tools()`
 /// - A `McpProxyGuard` that **must stay alive** for the duration of the agent call
 pub async fn create_notifying_proxy(
-    tools: Vec<rmcp::model::Tool>,
-    real_peer: Peer<RoleClient>,
+    tool_set: McpToolSet,
     tx: ToolEventSender,
 ) -> Result<(Vec<rmcp::model::Tool>, Peer<RoleClient>, McpProxyGuard), String> {
     let (server_io, client_io) = tokio::io::duplex(4096);
 
-    // Build sanitized tools + reverse mapping
-    let mut name_map: HashMap<String, String> = HashMap::new();
-    let sanitized_tools: Vec<rmcp::model::Tool> = tools
-        .into_iter()
-        .map(|mut t| {
-            let original = t.name.to_string();
-            let safe = sanitize_tool_name(&original);
-            if safe != original {
-                println!("🔧 MCP tool name sanitized: '{}' → '{}'", original, safe);
-                name_map.insert(safe.clone(), original);
-                t.name = Cow::Owned(safe);
-            }
-            t
-        })
-        .collect();
+    // Calendar create-event tools gain `allow_conflicts` while the check is on.
+    let tools = if tx.checks_calendar_conflicts()
+        && tool_set.tools.iter().any(|t| crate::calendar::is_create_event(&t.name))
+    {
+        Arc::new(crate::calendar::with_conflict_flag(&tool_set.tools))
+    } else {
+        tool_set.tools
+    };
+    // Gmail search tools gain `group_by_thread`.
+    let tools = if tools.iter().any(|t| crate::gmail::is_search(&t.name)) {
+        Arc::new(crate::gmail::with_thread_option(&tools))
+    } else {
+        tools
+    };
+    // rig needs an owned list; the proxy itself shares the connection's copy.
+    let sanitized_tools = tools.to_vec();
 
     let proxy_handler = NotifyingMcpProxy {
-        real_peer,
-        tools: sanitized_tools.clone(),
-        name_map,
+        real_peer: tool_set.peer,
+        tools,
+        name_map: tool_set.name_map,
         tx,
     };
 
@@ -181,3 +471,25 @@ pub async fn create_notifying_proxy(
         },
     ))
 }
+
+/// Serve `handler` as an MCP server inside this process and connect to it,
+/// for tools implemented locally (e.g. `custom_tools.rs`, `plugins.rs`).
+/// The server task lives as long as the returned connection.
+pub async fn connect_in_process<H: ServerHandler>(
+    handler: H,
+) -> Result<crate::state::McpConnection, String> {
+    let (server_io, client_io) = tokio::io::duplex(4096);
+    let (server_result, client_result) =
+        tokio::join!(serve_server(handler, server_io), serve_client((), client_io));
+    let server = server_result.map_err(|e| format!("in-process MCP server: {e}"))?;
+    let client = client_result.map_err(|e| format!("in-process MCP client: {e}"))?;
+    tokio::spawn(async move {
+        let _ = server.waiting().await;
+    });
+
+    let tool_list = client
+        .list_tools(Default::default())
+        .await
+        .map_err(|e| format!("{e:?}"))?;
+    Ok(crate::state::McpConnection::new(tool_list.tools, client))
+}
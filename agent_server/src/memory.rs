@@ -0,0 +1,106 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::sync::{mpsc, oneshot};
+
+/// A change to the memory file.
+pub enum MemoryWrite {
+    /// Replace the whole file (`save_to_memory`, the `save_memory` frame).
+    Replace(String),
+    /// Add a section after a blank line (`append_to_memory`).
+    Append(String),
+}
+
+struct Request {
+    path: PathBuf,
+    write: MemoryWrite,
+    done: oneshot::Sender<std::io::Result<()>>,
+}
+
+/// The single task every memory write goes through, so the chat agent, the
+/// scheduler and the settings UI can't interleave a read-modify-write.
+fn manager() -> &'static mpsc::UnboundedSender<Request> {
+    static MANAGER: OnceLock<mpsc::UnboundedSender<Request>> = OnceLock::new();
+    MANAGER.get_or_init(|| {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Request>();
+        tokio::spawn(async move {
+            while let Some(Request { path, write, done }) = rx.recv().await {
+                let result = tokio::task::spawn_blocking(move || apply(&path, write))
+                    .await
+                    .unwrap_or_else(|e| Err(std::io::Error::other(e.to_string())));
+                let _ = done.send(result);
+            }
+        });
+        tx
+    })
+}
+
+/// Queue a write of the memory file at `path` and wait until it is on disk.
+pub async fn write(path: &Path, write: MemoryWrite) -> std::io::Result<()> {
+    let (done, result) = oneshot::channel();
+    manager()
+        .send(Request { path: path.to_path_buf(), write, done })
+        .map_err(|_| std::io::Error::other("memory manager stopped"))?;
+    result.await.map_err(|_| std::io::Error::other("memory manager stopped"))?
+}
+
+/// Holds an advisory `flock` on `<file>.lock` for other processes sharing
+/// the file (a second server, the app editing it directly).
+struct FileLock(std::fs::File);
+
+impl FileLock {
+    fn acquire(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path.with_extension("md.lock"))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(Self(file))
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            unsafe { libc::flock(self.0.as_raw_fd(), libc::LOCK_UN) };
+        }
+    }
+}
+
+/// Read, change and atomically replace the file (temp file, fsync, rename)
+/// under the lock.
+fn apply(path: &Path, write: MemoryWrite) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let _lock = FileLock::acquire(path)?;
+    let content = match write {
+        MemoryWrite::Replace(content) => content,
+        MemoryWrite::Append(content) => {
+            let existing = match std::fs::read_to_string(path) {
+                Ok(c) => c,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(e),
+            };
+            if existing.is_empty() {
+                content
+            } else {
+                format!("{}\n\n{}", existing, content)
+            }
+        }
+    };
+    let temp = path.with_extension("md.tmp");
+    let mut file = std::fs::File::create(&temp)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)
+}
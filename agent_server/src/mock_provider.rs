@@ -0,0 +1,179 @@
+use crate::tools::{
+    AppendToMemory, Calculator, NotifyingTool, OpenApplication, OpenChromeTab, ReadMemory,
+    RenderChart, SaveToMemory, ToolEventSender,
+};
+use rig::tool::Tool;
+use rmcp::model::CallToolRequestParam;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+/// A scripted conversation for the offline `mock` provider.
+///
+/// ```json
+/// {
+///   "turns": [
+///     { "match": "add", "tool_calls": [{ "name": "calculator", "args": { "x": 2, "y": 3, "operation": "add" } }],
+///       "response": "2 + 3 = {tool_results}" }
+///   ],
+///   "fallback": "Mock reply to: {query}"
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    turns: Vec<ScenarioTurn>,
+    #[serde(default)]
+    fallback: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScenarioTurn {
+    /// Case-insensitive substring of the query. Omit to match anything.
+    #[serde(rename = "match", default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ScenarioToolCall>,
+    response: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScenarioToolCall {
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+/// The scenario file is passed as the model name; `default` (or empty) means
+/// `~/.ronge/mock_scenario.json`.
+pub fn scenario_path(model: &str) -> PathBuf {
+    if model.is_empty() || model == "default" {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("/tmp"))
+            .join(".ronge")
+            .join("mock_scenario.json")
+    } else {
+        crate::watcher::expand_home(model)
+    }
+}
+
+pub async fn load_scenario(model: &str) -> Result<Scenario, String> {
+    let path = scenario_path(model);
+    let body = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| format!("Could not read mock scenario {}: {}", path.display(), e))?;
+    serde_json::from_str(&body).map_err(|e| format!("Invalid mock scenario {}: {}", path.display(), e))
+}
+
+/// Play the first scenario turn matching `query`: run its tool calls through
+/// the normal notifying wrappers (so `tool_call`/`tool_result` events fire
+/// exactly as with a real model), then return its scripted response.
+pub async fn run(
+    model: &str,
+    query: &str,
+    mcp_tool_sets: Vec<(Vec<rmcp::model::Tool>, rmcp::service::ServerSink)>,
+    tool_tx: &ToolEventSender,
+    memory_path: &Path,
+) -> Result<String, String> {
+    let scenario = load_scenario(model).await?;
+    let lowered = query.to_lowercase();
+    let turn = scenario.turns.iter().find(|t| {
+        t.pattern
+            .as_ref()
+            .is_none_or(|p| lowered.contains(&p.to_lowercase()))
+    });
+
+    let Some(turn) = turn else {
+        return Ok(scenario
+            .fallback
+            .unwrap_or_else(|| "Mock reply to: {query}".to_string())
+            .replace("{query}", query));
+    };
+
+    let mut results: Vec<String> = Vec::new();
+    for call in &turn.tool_calls {
+        let output = match call_builtin(&call.name, call.args.clone(), tool_tx, memory_path).await {
+            Some(r) => r,
+            None => call_mcp(&call.name, call.args.clone(), &mcp_tool_sets).await,
+        };
+        results.push(output.unwrap_or_else(|e| format!("error: {}", e)));
+    }
+
+    Ok(turn
+        .response
+        .replace("{query}", query)
+        .replace("{tool_results}", &results.join("\n")))
+}
+
+/// `None` when `name` is not a built-in tool.
+async fn call_builtin(
+    name: &str,
+    args: serde_json::Value,
+    tx: &ToolEventSender,
+    memory_path: &Path,
+) -> Option<Result<String, String>> {
+    let memory_path = memory_path.to_path_buf();
+    let result = match name {
+        "calculator" => invoke(Calculator, args, tx).await,
+        "open_application" => invoke(OpenApplication, args, tx).await,
+        "open_chrome_tab" => invoke(OpenChromeTab, args, tx).await,
+        "read_memory" => {
+            invoke(ReadMemory::new(memory_path).with_pii_redaction(tx.redacts_pii()), args, tx).await
+        }
+        "save_to_memory" => {
+            invoke(SaveToMemory::new(memory_path).with_dry_run(tx.is_dry_run()), args, tx).await
+        }
+        "append_to_memory" => {
+            invoke(AppendToMemory::new(memory_path).with_dry_run(tx.is_dry_run()), args, tx).await
+        }
+        "render_chart" => invoke(RenderChart, args, tx).await,
+        "execute_code" => invoke(crate::code_exec::ExecuteCode { tx: tx.clone() }, args, tx).await,
+        "network_check" => invoke(crate::netcheck::NetworkCheck, args, tx).await,
+        "convert_time" => invoke(crate::world_clock::ConvertTime, args, tx).await,
+        "date_info" => invoke(crate::date_info::DateInfo, args, tx).await,
+        "get_secret" => invoke(crate::secret_refs::GetSecret { tx: tx.clone() }, args, tx).await,
+        "list_archive" => invoke(crate::archive::ListArchive, args, tx).await,
+        "extract_archive" => invoke(crate::archive::ExtractArchive, args, tx).await,
+        _ => return None,
+    };
+    Some(result)
+}
+
+async fn invoke<T: Tool>(
+    tool: T,
+    args: serde_json::Value,
+    tx: &ToolEventSender,
+) -> Result<String, String>
+where
+    T::Args: serde::Serialize,
+    T::Output: Send,
+{
+    let args: T::Args = serde_json::from_value(args).map_err(|e| e.to_string())?;
+    let output = NotifyingTool { inner: tool, tx: tx.clone() }
+        .call(args)
+        .await
+        .map_err(|e| e.to_string())?;
+    serde_json::to_string(&output).map_err(|e| e.to_string())
+}
+
+/// Call a tool on whichever (proxied) MCP server advertises it.
+async fn call_mcp(
+    name: &str,
+    args: serde_json::Value,
+    mcp_tool_sets: &[(Vec<rmcp::model::Tool>, rmcp::service::ServerSink)],
+) -> Result<String, String> {
+    let peer = mcp_tool_sets
+        .iter()
+        .find(|(tools, _)| tools.iter().any(|t| t.name == name))
+        .map(|(_, peer)| peer)
+        .ok_or_else(|| format!("Unknown tool '{}'", name))?;
+    let result = peer
+        .call_tool(CallToolRequestParam {
+            name: Cow::Owned(name.to_string()),
+            arguments: args.as_object().cloned(),
+            task: None,
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    serde_json::to_string(&result).map_err(|e| e.to_string())
+}
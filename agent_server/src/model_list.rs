@@ -0,0 +1,76 @@
+use serde_json::{json, Value};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const DEFAULT_OPENROUTER_BASE: &str = "https://openrouter.ai/api/v1";
+/// OpenRouter's catalogue changes a few times a day at most.
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+const LIST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// OpenRouter's OpenAI-compatible endpoint: `RONGE_OPENROUTER_BASE_URL`
+/// (a self-hosted proxy or regional gateway), without a trailing slash.
+pub fn openrouter_base_url() -> String {
+    std::env::var("RONGE_OPENROUTER_BASE_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_OPENROUTER_BASE.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// The catalogue and when it was fetched.
+type Catalogue = Option<(Instant, Vec<Value>)>;
+
+fn cache() -> &'static Mutex<Catalogue> {
+    static CACHE: OnceLock<Mutex<Catalogue>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// One catalogue entry, trimmed to what a model picker shows.
+fn summarize(model: &Value) -> Value {
+    json!({
+        "id": model["id"],
+        "name": model["name"],
+        "context_length": model["context_length"],
+        "prompt_price": model["pricing"]["prompt"],
+        "completion_price": model["pricing"]["completion"],
+        "input_modalities": model["architecture"]["input_modalities"],
+        "supports_tools": model["supported_parameters"]
+            .as_array()
+            .map(|params| params.iter().any(|p| p == "tools")),
+    })
+}
+
+/// Every model OpenRouter routes to (its public `/models` list), cached for
+/// an hour unless `refresh` is set.
+pub async fn openrouter(refresh: bool) -> Result<Vec<Value>, String> {
+    if !refresh
+        && let Some((at, models)) = cache().lock().unwrap_or_else(|e| e.into_inner()).as_ref()
+        && at.elapsed() < CACHE_TTL
+    {
+        return Ok(models.clone());
+    }
+    let resp = reqwest::Client::new()
+        .get(format!("{}/models", openrouter_base_url()))
+        .timeout(LIST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("OpenRouter returned {}", resp.status()));
+    }
+    let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+    let models: Vec<Value> = body["data"].as_array().into_iter().flatten().map(summarize).collect();
+    *cache().lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), models.clone()));
+    Ok(models)
+}
+
+/// Whether OpenRouter lists `model`. Variants such as `:online` or `:nitro`
+/// aren't listed on their own, so the base ID counts too.
+pub fn lists(models: &[Value], model: &str) -> bool {
+    let base = model.split(':').next().unwrap_or(model);
+    models
+        .iter()
+        .filter_map(|m| m["id"].as_str())
+        .any(|id| id == model || id == base)
+}
@@ -0,0 +1,134 @@
+use crate::state::McpToolSet;
+use std::sync::Arc;
+
+/// A named agent configuration: extra preamble plus the tools it may use.
+/// Smaller models pick tools more reliably from a short, relevant list.
+pub struct AgentMode {
+    pub name: &'static str,
+    pub label: &'static str,
+    /// Appended to the system prompt.
+    pub preamble: &'static str,
+    /// Tool allowlist. An entry matches a built-in tool by exact name and an
+    /// MCP tool when its lowercased name contains the entry. `None` allows
+    /// every tool. The calculator is always attached, so every list names it.
+    pub tools: Option<&'static [&'static str]>,
+}
+
+pub const DEFAULT_MODE: &str = "default";
+
+pub const MODES: &[AgentMode] = &[
+    AgentMode {
+        name: DEFAULT_MODE,
+        label: "Default",
+        preamble: "",
+        tools: None,
+    },
+    AgentMode {
+        name: "research",
+        label: "Research",
+        preamble: "### Mode: Research\n\
+            Search and read sources before answering. Cite the URL of every source you rely on \
+            and say when sources disagree. Save findings worth keeping to memory.",
+        tools: Some(&[
+            "calculator",
+            "open_chrome_tab",
+            "read_memory",
+            "save_to_memory",
+            "append_to_memory",
+            "write_scratchpad",
+            "read_scratchpad",
+            "render_chart",
+            "execute_code",
+            "list_archive",
+            "extract_archive",
+            "network_check",
+            "convert_time",
+            "search",
+            "fetch",
+            "browse",
+            "web",
+        ]),
+    },
+    AgentMode {
+        name: "email_triage",
+        label: "Email Triage",
+        preamble: "### Mode: Email Triage\n\
+            Help the user work through their inbox: summarize unread mail, group it by urgency, \
+            and draft replies. Check the calendar before proposing meeting times.",
+        tools: Some(&[
+            "calculator",
+            "read_memory",
+            "save_to_memory",
+            "append_to_memory",
+            "write_scratchpad",
+            "read_scratchpad",
+            "gmail",
+            "calendar",
+            "convert_time",
+            "date_info",
+            "triage_agent",
+            // Attachments: unpack archives and read what's inside.
+            "list_archive",
+            "extract_archive",
+            "read_file",
+            "read_text_file",
+        ]),
+    },
+    AgentMode {
+        name: "coding",
+        label: "Coding",
+        preamble: "### Mode: Coding\n\
+            Act as a pair programmer. Read the relevant files before suggesting changes, keep \
+            answers focused on code, and show diffs or complete snippets.",
+        tools: Some(&["calculator", "read_memory", "write_scratchpad", "read_scratchpad", "file", "directory", "git", "code_agent", "execute_code"]),
+    },
+    AgentMode {
+        name: "minimal",
+        label: "Minimal",
+        preamble: "### Mode: Minimal\nAnswer directly and briefly.",
+        tools: Some(&["calculator", "read_memory"]),
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static AgentMode> {
+    MODES.iter().find(|m| m.name == name)
+}
+
+/// The mode for `name`, falling back to the default mode.
+pub fn resolve(name: Option<&str>) -> &'static AgentMode {
+    name.and_then(find).unwrap_or(&MODES[0])
+}
+
+impl AgentMode {
+    pub fn allows_builtin(&self, tool_name: &str) -> bool {
+        self.tools.is_none_or(|tools| tools.contains(&tool_name))
+    }
+
+    pub fn allows_mcp(&self, tool_name: &str) -> bool {
+        let lower = tool_name.to_ascii_lowercase();
+        self.tools
+            .is_none_or(|tools| tools.iter().any(|entry| lower.contains(entry)))
+    }
+
+    /// Keep only the MCP tools this mode allows, dropping servers left empty.
+    pub fn filter_mcp(&self, tool_sets: Vec<McpToolSet>) -> Vec<McpToolSet> {
+        if self.tools.is_none() {
+            return tool_sets;
+        }
+        tool_sets
+            .into_iter()
+            .filter_map(|set| {
+                let tools: Vec<rmcp::model::Tool> = set
+                    .tools
+                    .iter()
+                    .filter(|t| self.allows_mcp(&t.name))
+                    .cloned()
+                    .collect();
+                (!tools.is_empty()).then(|| McpToolSet {
+                    tools: Arc::new(tools),
+                    ..set
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,301 @@
+use crate::tools::ToolError;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+/// A well-connected site that answers HEAD requests, to tell "the site is
+/// down" from "the internet is down" (`RONGE_NETCHECK_BASELINE_URL`).
+const DEFAULT_BASELINE_URL: &str = "https://www.cloudflare.com/cdn-cgi/trace";
+/// Reached by IP, so a broken resolver doesn't look like no connection.
+const BASELINE_IP: &str = "1.1.1.1:443";
+const STEP_TIMEOUT: Duration = Duration::from_secs(8);
+const PING_COUNT: &str = "3";
+/// A HEAD request slower than this counts as a slow connection.
+const SLOW_MS: u64 = 2_000;
+
+fn baseline_url() -> String {
+    std::env::var("RONGE_NETCHECK_BASELINE_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_BASELINE_URL.to_string())
+}
+
+/// `example.com`, `example.com:8080` or a full URL, as a URL.
+fn parse_target(target: &str) -> Result<reqwest::Url, String> {
+    let target = target.trim();
+    let with_scheme = if target.contains("://") { target.to_string() } else { format!("https://{}", target) };
+    let url = reqwest::Url::parse(&with_scheme).map_err(|e| format!("'{}' is not a host or URL: {}", target, e))?;
+    if url.host_str().is_none() {
+        return Err(format!("'{}' has no host", target));
+    }
+    Ok(url)
+}
+
+fn ms(elapsed: Duration) -> u64 {
+    elapsed.as_millis() as u64
+}
+
+/// DNS lookup, TCP connect and HTTP HEAD against one URL, each timed.
+#[derive(Debug, Default, Serialize)]
+struct Probe {
+    host: String,
+    dns_ms: Option<u64>,
+    addresses: Vec<String>,
+    dns_error: Option<String>,
+    connect_ms: Option<u64>,
+    connect_error: Option<String>,
+    http_status: Option<u16>,
+    http_ms: Option<u64>,
+    http_error: Option<String>,
+}
+
+impl Probe {
+    fn resolved(&self) -> bool {
+        self.dns_error.is_none()
+    }
+
+    fn answered(&self) -> bool {
+        self.http_status.is_some()
+    }
+}
+
+async fn probe(url: &reqwest::Url) -> Probe {
+    let host = url.host_str().unwrap_or_default().to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let mut probe = Probe { host: host.clone(), ..Default::default() };
+
+    let started = Instant::now();
+    match tokio::time::timeout(STEP_TIMEOUT, tokio::net::lookup_host((host.as_str(), port))).await {
+        Ok(Ok(addrs)) => {
+            probe.dns_ms = Some(ms(started.elapsed()));
+            probe.addresses = addrs.map(|a| a.ip().to_string()).collect();
+            probe.addresses.dedup();
+        }
+        Ok(Err(e)) => probe.dns_error = Some(e.to_string()),
+        Err(_) => probe.dns_error = Some(format!("no answer within {} s", STEP_TIMEOUT.as_secs())),
+    }
+    if !probe.resolved() {
+        return probe;
+    }
+
+    let started = Instant::now();
+    match tokio::time::timeout(STEP_TIMEOUT, tokio::net::TcpStream::connect((host.as_str(), port))).await {
+        Ok(Ok(_)) => probe.connect_ms = Some(ms(started.elapsed())),
+        Ok(Err(e)) => probe.connect_error = Some(e.to_string()),
+        Err(_) => probe.connect_error = Some(format!("no connection within {} s", STEP_TIMEOUT.as_secs())),
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(STEP_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build();
+    let started = Instant::now();
+    match client {
+        Ok(client) => match client.head(url.clone()).send().await {
+            Ok(resp) => {
+                probe.http_ms = Some(ms(started.elapsed()));
+                probe.http_status = Some(resp.status().as_u16());
+            }
+            Err(e) => probe.http_error = Some(e.to_string()),
+        },
+        Err(e) => probe.http_error = Some(e.to_string()),
+    }
+    probe
+}
+
+/// Whether anything answers on port 443 of a fixed IP, bypassing DNS.
+async fn raw_connectivity() -> bool {
+    matches!(
+        tokio::time::timeout(STEP_TIMEOUT, tokio::net::TcpStream::connect(BASELINE_IP)).await,
+        Ok(Ok(_))
+    )
+}
+
+/// `ping -c 3 <host>`: packet loss and average round trip, when a `ping`
+/// binary is around (ICMP may also be filtered; the HTTP probe still counts).
+async fn ping(host: &str) -> Value {
+    let path = crate::logic::build_expanded_path();
+    let program = crate::logic::resolve_command("ping", &path);
+    if !std::path::Path::new(&program).is_absolute() {
+        return json!({"error": "ping is not installed"});
+    }
+    let mut cmd = tokio::process::Command::new(program);
+    cmd.args(["-c", PING_COUNT, host]).kill_on_drop(true);
+    let output = match tokio::time::timeout(STEP_TIMEOUT * 2, cmd.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return json!({"error": e.to_string()}),
+        Err(_) => return json!({"error": "ping did not finish"}),
+    };
+    let text = String::from_utf8_lossy(&output.stdout);
+    // "3 packets transmitted, 3 received, 0% packet loss" (Linux) or
+    // "3 packets received, 0.0% packet loss" (macOS).
+    let loss = text
+        .split(',')
+        .find(|part| part.contains("packet loss"))
+        .and_then(|part| part.trim().split('%').next()?.parse::<f64>().ok());
+    // "rtt min/avg/max/mdev = 9.1/10.2/11.0/0.4 ms" or "round-trip min/avg/max/stddev = ...".
+    let avg_ms = text
+        .lines()
+        .find(|line| line.contains("min/avg/max"))
+        .and_then(|line| line.split('=').nth(1)?.trim().split('/').nth(1)?.parse::<f64>().ok());
+    json!({"packet_loss_percent": loss, "avg_ms": avg_ms})
+}
+
+/// One word for what the probes show, plus a sentence for the user.
+fn verdict(target: Option<&Probe>, baseline: &Probe, raw_ok: bool) -> (&'static str, String) {
+    let online = baseline.answered() || raw_ok;
+    match target {
+        Some(t) if t.answered() => {
+            let status = t.http_status.unwrap_or_default();
+            let latency = t.http_ms.unwrap_or_default();
+            if status >= 500 {
+                ("site_error", format!("{} is reachable but answering with HTTP {} (a problem on their side).", t.host, status))
+            } else if latency > SLOW_MS {
+                ("slow", format!("{} answered, but slowly ({} ms).", t.host, latency))
+            } else {
+                ("ok", format!("{} is up (HTTP {} in {} ms).", t.host, status, latency))
+            }
+        }
+        Some(t) if online && !t.resolved() => {
+            ("site_not_found", format!("Your connection works, but {} could not be resolved (DNS).", t.host))
+        }
+        Some(t) if online => ("site_down", format!("Your connection works, but {} is not answering.", t.host)),
+        _ if baseline.answered() => {
+            let latency = baseline.http_ms.unwrap_or_default();
+            if latency > SLOW_MS {
+                ("slow", format!("Your connection works but is slow ({} ms to a reference site).", latency))
+            } else {
+                ("ok", format!("Your internet connection works ({} ms to a reference site).", latency))
+            }
+        }
+        _ if raw_ok => ("dns_broken", "Your network is up, but name resolution (DNS) is failing.".to_string()),
+        _ => ("offline", "Your internet connection appears to be down.".to_string()),
+    }
+}
+
+/// Probe `target` (if any) and the baseline at once.
+async fn check(target: Option<&reqwest::Url>, with_ping: bool) -> Value {
+    let baseline_url = parse_target(&baseline_url()).ok();
+    let (target_probe, baseline_probe, raw_ok, pinged) = tokio::join!(
+        async {
+            match target {
+                Some(url) => Some(probe(url).await),
+                None => None,
+            }
+        },
+        async {
+            match &baseline_url {
+                Some(url) => probe(url).await,
+                None => Probe::default(),
+            }
+        },
+        raw_connectivity(),
+        async {
+            match target.and_then(|url| url.host_str()).filter(|_| with_ping) {
+                Some(host) => Some(ping(host).await),
+                None => None,
+            }
+        },
+    );
+    let (status, summary) = verdict(target_probe.as_ref(), &baseline_probe, raw_ok);
+    json!({
+        "verdict": status,
+        "summary": summary,
+        "target": target_probe,
+        "ping": pinged,
+        "baseline": baseline_probe,
+        "raw_connectivity": raw_ok,
+    })
+}
+
+/// Where `provider`'s API lives, for diagnosing failed turns.
+fn provider_url(provider: &str) -> Option<String> {
+    match provider {
+        "gemini" => Some("https://generativelanguage.googleapis.com".to_string()),
+        "openai" => Some("https://api.openai.com".to_string()),
+        "anthropic" => Some("https://api.anthropic.com".to_string()),
+        "mistral" => Some("https://api.mistral.ai".to_string()),
+        "openrouter" => Some(crate::model_list::openrouter_base_url()),
+        "ollama" => Some(crate::ollama::base_url()),
+        "azure_openai" | "openai_compatible" => crate::provider_settings::get(provider).map(|e| e.base_url),
+        _ => None,
+    }
+}
+
+/// Whether a provider error means the request never got an answer
+/// (resolver, connection or TLS failure) rather than an API error.
+pub fn is_connection_error(message: &str) -> bool {
+    let lower = message.to_ascii_lowercase();
+    [
+        "error sending request",
+        "dns error",
+        "failed to lookup address",
+        "connection refused",
+        "connection reset",
+        "network is unreachable",
+        "tcp connect error",
+        "connect timeout",
+    ]
+    .iter()
+    .any(|m| lower.contains(m))
+}
+
+/// After a turn failed with a connection error: whether the local network
+/// or `provider` is at fault, as a `network_diagnosis` event.
+pub async fn diagnose_provider(provider: &str) -> Option<Value> {
+    let url = parse_target(&provider_url(provider)?).ok()?;
+    let mut result = check(Some(&url), false).await;
+    result["provider"] = json!(provider);
+    println!("🩺 {} unreachable: {}", provider, result["summary"].as_str().unwrap_or_default());
+    Some(json!({"type": "network_diagnosis", "content": result}))
+}
+
+/// `network_check`: DNS lookup, TCP connect, ping and HTTP HEAD with
+/// latencies for a site, next to the same checks against a reference site.
+#[derive(Deserialize, Serialize)]
+pub struct NetworkCheck;
+
+#[derive(Deserialize, Serialize)]
+pub struct NetworkCheckArgs {
+    #[serde(default)]
+    target: Option<String>,
+    #[serde(default)]
+    ping: Option<bool>,
+}
+
+impl Tool for NetworkCheck {
+    const NAME: &'static str = "network_check";
+    type Args = NetworkCheckArgs;
+    type Output = Value;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Diagnose connectivity: DNS lookup, TCP connect, ping and an HTTP HEAD request (each with \
+                latency) to a site, compared with a reference site, returning a verdict such as ok, slow, \
+                site_down, site_not_found, dns_broken or offline. Use it for questions like \"is my internet \
+                slow or is this site down?\". Without a target it only checks the user's connection."
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "target": { "type": "string", "description": "Host name or URL, e.g. github.com" },
+                    "ping": { "type": "boolean", "description": "Also ping the host (default true)" }
+                }
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let target = match args.target.as_deref().filter(|t| !t.trim().is_empty()) {
+            Some(target) => Some(parse_target(target).map_err(ToolError::CommandFailed)?),
+            None => None,
+        };
+        let result = check(target.as_ref(), args.ping.unwrap_or(true)).await;
+        println!("🩺 Network check: {}", result["summary"].as_str().unwrap_or_default());
+        Ok(result)
+    }
+}
@@ -0,0 +1,136 @@
+use crate::state::SharedState;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How long Ollama keeps a model in memory after a request.
+const DEFAULT_KEEP_ALIVE: &str = "30m";
+/// Loading a large model from disk can take a while.
+const LOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// `keep_alive` sent with every Ollama request: `RONGE_OLLAMA_KEEP_ALIVE`
+/// (an Ollama duration such as `10m`, `1h` or `-1` for forever), default 30m.
+pub fn keep_alive() -> String {
+    std::env::var("RONGE_OLLAMA_KEEP_ALIVE")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_KEEP_ALIVE.to_string())
+}
+
+/// `OLLAMA_API_BASE_URL` (defaulted in `main`), without a trailing slash.
+pub fn base_url() -> String {
+    std::env::var("OLLAMA_API_BASE_URL")
+        .unwrap_or_else(|_| "http://localhost:11434".to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Models with a warm-up in flight, so reconnecting clients don't stack loads.
+fn warming() -> &'static Mutex<HashSet<String>> {
+    static WARMING: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    WARMING.get_or_init(Default::default)
+}
+
+/// Whether Ollama already holds `model` in memory (`/api/ps`). Unreachable
+/// servers count as loaded so callers don't announce a load that can't happen.
+pub async fn is_loaded(model: &str) -> bool {
+    let resp = reqwest::Client::new()
+        .get(format!("{}/api/ps", base_url()))
+        .timeout(Duration::from_secs(3))
+        .send()
+        .await;
+    let body = match resp {
+        Ok(resp) => resp.json::<Value>().await.ok(),
+        Err(_) => None,
+    };
+    let Some(body) = body else {
+        return true;
+    };
+    // `llama3` is listed as `llama3:latest`.
+    let wanted = if model.contains(':') { model.to_string() } else { format!("{}:latest", model) };
+    body["models"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|m| [&m["name"], &m["model"]].iter().any(|n| n.as_str() == Some(wanted.as_str())))
+}
+
+/// Whether `model` has been pulled (`/api/show`), without loading it.
+pub async fn has_model(model: &str) -> Result<bool, String> {
+    let resp = reqwest::Client::new()
+        .post(format!("{}/api/show", base_url()))
+        .timeout(Duration::from_secs(10))
+        .json(&json!({"model": model}))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match resp.status() {
+        s if s.is_success() => Ok(true),
+        reqwest::StatusCode::NOT_FOUND => Ok(false),
+        status => Err(format!("Ollama returned {}", status)),
+    }
+}
+
+/// Load `model` with an empty prompt, which makes Ollama read it into memory
+/// without generating anything.
+pub async fn load(model: &str) -> Result<(), String> {
+    let resp = reqwest::Client::new()
+        .post(format!("{}/api/generate", base_url()))
+        .timeout(LOAD_TIMEOUT)
+        .json(&json!({"model": model, "prompt": "", "keep_alive": keep_alive()}))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("Ollama returned {}: {}", status, body));
+    }
+    Ok(())
+}
+
+pub fn loading_event(model: &str, status: &str, elapsed: Option<Duration>, error: Option<&str>) -> Value {
+    json!({
+        "type": "model_loading",
+        "content": {
+            "model": model,
+            "status": status,
+            "elapsed_ms": elapsed.map(|e| e.as_millis() as u64),
+            "error": error,
+        }
+    })
+}
+
+/// Load the current Ollama model in the background when a session starts,
+/// so the first reply doesn't pay for a cold load. Progress is broadcast as
+/// `model_loading` events (`loading`, then `ready` or `failed`).
+pub fn warm_up(state: SharedState) {
+    tokio::spawn(async move {
+        let (provider, model) = {
+            let s = state.lock().await;
+            (s.current_provider.clone(), s.current_model.clone())
+        };
+        if provider != "ollama" || model.is_empty() || is_loaded(&model).await {
+            return;
+        }
+        if !warming().lock().unwrap_or_else(|e| e.into_inner()).insert(model.clone()) {
+            return;
+        }
+        println!("🦙 Warming up Ollama model {}", model);
+        let started = Instant::now();
+        let _ = state.lock().await.notifier.send(loading_event(&model, "loading", None, None));
+        let event = match load(&model).await {
+            Ok(()) => {
+                println!("🦙 {} loaded in {} ms", model, started.elapsed().as_millis());
+                loading_event(&model, "ready", Some(started.elapsed()), None)
+            }
+            Err(e) => {
+                println!("⚠️ Ollama warm-up of {} failed: {}", model, e);
+                loading_event(&model, "failed", Some(started.elapsed()), Some(&e))
+            }
+        };
+        warming().lock().unwrap_or_else(|e| e.into_inner()).remove(&model);
+        let _ = state.lock().await.notifier.send(event);
+    });
+}
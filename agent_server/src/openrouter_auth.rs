@@ -1,8 +1,18 @@
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use rand::Rng;
+use crate::state::SharedState;
+use serde_json::{json, Value};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
+/// A sign-in flow gives up if the browser has not called back by then.
+const FLOW_TIMEOUT: Duration = Duration::from_secs(300);
+/// Finished flows stay pollable with `oauth_flow_status` for this long.
+const FINISHED_FLOW_TTL: Duration = Duration::from_secs(600);
+
 /// Generate a 64-character random alphanumeric PKCE code verifier (RFC 7636).
 fn random_verifier() -> String {
     const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~";
@@ -25,9 +35,167 @@ fn random_state() -> String {
     bytes.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FlowStatus {
+    Pending,
+    Succeeded,
+    Failed,
+    Cancelled,
+    TimedOut,
+}
+
+impl FlowStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            FlowStatus::Pending => "pending",
+            FlowStatus::Succeeded => "succeeded",
+            FlowStatus::Failed => "failed",
+            FlowStatus::Cancelled => "cancelled",
+            FlowStatus::TimedOut => "timed_out",
+        }
+    }
+}
+
+struct Flow {
+    provider: &'static str,
+    status: FlowStatus,
+    error: Option<String>,
+    started_at: chrono::DateTime<chrono::Local>,
+    finished: Option<Instant>,
+    task: Option<tokio::task::AbortHandle>,
+}
+
+/// Sign-in flows running in the background, keyed by flow ID.
+///
+/// Each flow owns its own loopback listener and state nonce, so several can be
+/// pending at once and none of them holds up the client's connection.
+#[derive(Clone, Default)]
+pub struct OAuthFlows(Arc<Mutex<HashMap<String, Flow>>>);
+
+impl OAuthFlows {
+    fn insert(&self, flow_id: &str, provider: &'static str) {
+        let mut flows = self.lock();
+        flows.retain(|_, f| f.finished.is_none_or(|t| t.elapsed() < FINISHED_FLOW_TTL));
+        flows.insert(
+            flow_id.to_string(),
+            Flow {
+                provider,
+                status: FlowStatus::Pending,
+                error: None,
+                started_at: chrono::Local::now(),
+                finished: None,
+                task: None,
+            },
+        );
+    }
+
+    fn attach(&self, flow_id: &str, task: tokio::task::AbortHandle) {
+        if let Some(flow) = self.lock().get_mut(flow_id) {
+            flow.task = Some(task);
+        }
+    }
+
+    /// Record the outcome; `false` if the flow was cancelled meanwhile.
+    fn finish(&self, flow_id: &str, status: FlowStatus, error: Option<String>) -> bool {
+        let mut flows = self.lock();
+        let Some(flow) = flows.get_mut(flow_id).filter(|f| f.status == FlowStatus::Pending) else {
+            return false;
+        };
+        flow.status = status;
+        flow.error = error;
+        flow.finished = Some(Instant::now());
+        flow.task = None;
+        true
+    }
+
+    /// Stop a pending flow and close its listener. `false` if it is unknown or already over.
+    pub fn cancel(&self, flow_id: &str) -> bool {
+        let mut flows = self.lock();
+        let Some(flow) = flows.get_mut(flow_id).filter(|f| f.status == FlowStatus::Pending) else {
+            return false;
+        };
+        if let Some(task) = flow.task.take() {
+            task.abort();
+        }
+        flow.status = FlowStatus::Cancelled;
+        flow.finished = Some(Instant::now());
+        println!("🛑 OAuth flow {} cancelled", flow_id);
+        true
+    }
+
+    /// `{flow_id, provider, status, error, started_at}` for one flow, or all of them.
+    pub fn status(&self, flow_id: Option<&str>) -> Vec<Value> {
+        let flows = self.lock();
+        let mut list: Vec<Value> = flows
+            .iter()
+            .filter(|(id, _)| flow_id.is_none_or(|wanted| wanted == id.as_str()))
+            .map(|(id, f)| {
+                json!({
+                    "flow_id": id,
+                    "provider": f.provider,
+                    "status": f.status.as_str(),
+                    "error": f.error,
+                    "started_at": f.started_at.to_rfc3339(),
+                })
+            })
+            .collect();
+        list.sort_by(|a, b| b["started_at"].as_str().cmp(&a["started_at"].as_str()));
+        list
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Flow>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Start an OpenRouter sign-in in the background and return `(flow_id, auth_url)`.
+///
+/// The outcome is broadcast to clients as `openrouter_oauth_success` (the API
+/// key, also stored in the state) or `openrouter_oauth_error`, tagged with the
+/// flow ID.
+pub async fn start_openrouter_flow(state: SharedState) -> Result<(String, String), String> {
+    let (auth_url, verifier, state_nonce, listener) = prepare_openrouter_flow().await?;
+    let flows = state.lock().await.oauth_flows.clone();
+    let flow_id = crate::session::new_session_id();
+    flows.insert(&flow_id, "openrouter");
+
+    let task = tokio::spawn({
+        let flows = flows.clone();
+        let flow_id = flow_id.clone();
+        async move {
+            let outcome = tokio::time::timeout(
+                FLOW_TIMEOUT,
+                await_openrouter_callback(listener, &verifier, &state_nonce),
+            )
+            .await;
+            let (status, content) = match outcome {
+                Ok(Ok(api_key)) => (FlowStatus::Succeeded, api_key),
+                Ok(Err(e)) => {
+                    println!("❌ OpenRouter OAuth callback error: {}", e);
+                    (FlowStatus::Failed, e)
+                }
+                Err(_) => (FlowStatus::TimedOut, "Sign-in timed out. Please try again.".to_string()),
+            };
+            let succeeded = status == FlowStatus::Succeeded;
+            if !flows.finish(&flow_id, status, (!succeeded).then(|| content.clone())) {
+                return;
+            }
+            let mut s = state.lock().await;
+            if succeeded {
+                s.api_keys.insert("openrouter".to_string(), content.clone());
+            }
+            let kind = if succeeded { "openrouter_oauth_success" } else { "openrouter_oauth_error" };
+            let _ = s.notifier.send(json!({"type": kind, "content": content, "flow_id": flow_id}));
+        }
+    });
+    flows.attach(&flow_id, task.abort_handle());
+
+    Ok((flow_id, auth_url))
+}
+
 /// Bind a random local listener and build the OpenRouter consent URL.
 /// Returns (auth_url, code_verifier, state_nonce, listener).
-pub async fn prepare_openrouter_flow(
+async fn prepare_openrouter_flow(
 ) -> Result<(String, String, String, tokio::net::TcpListener), String> {
     let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
         .await
@@ -54,44 +222,58 @@ pub async fn prepare_openrouter_flow(
 
 /// Accept the browser redirect, validate the state nonce, exchange the
 /// auth code for an OpenRouter API key, and return it.
-pub async fn await_openrouter_callback(
+async fn await_openrouter_callback(
     listener: tokio::net::TcpListener,
     verifier: &str,
     expected_state: &str,
 ) -> Result<String, String> {
-    let (mut stream, peer_addr) = listener
-        .accept()
-        .await
-        .map_err(|e| format!("Did not receive a response from the browser: {}", e))?;
+    // Browsers may probe the port (favicon, preconnect) before the redirect
+    // arrives: answer anything that is not the callback with a 404 and keep
+    // waiting instead of failing the flow.
+    let (mut stream, query) = loop {
+        let (mut stream, peer_addr) = listener
+            .accept()
+            .await
+            .map_err(|e| format!("Did not receive a response from the browser: {}", e))?;
 
-    // Only accept loopback connections — prevents other local processes from
-    // injecting a fake callback.
-    if !peer_addr.ip().is_loopback() {
-        return Err("Rejected non-loopback OAuth callback.".to_string());
-    }
+        // Only accept loopback connections — prevents other local processes from
+        // injecting a fake callback.
+        if !peer_addr.ip().is_loopback() {
+            println!("⚠️ Ignored non-loopback OAuth callback from {}", peer_addr);
+            continue;
+        }
 
-    let mut buf = vec![0u8; 8192];
-    let n = stream
-        .read(&mut buf)
-        .await
-        .map_err(|e| format!("Could not read the browser response: {}", e))?;
-    let request = String::from_utf8_lossy(&buf[..n]);
-
-    let path = request
-        .lines()
-        .next()
-        .unwrap_or("")
-        .split_whitespace()
-        .nth(1)
-        .unwrap_or("");
-    let query = path.split('?').nth(1).unwrap_or("");
+        let mut buf = vec![0u8; 8192];
+        let Ok(n) = stream.read(&mut buf).await else {
+            continue;
+        };
+        let request = String::from_utf8_lossy(&buf[..n]);
+
+        let path = request
+            .lines()
+            .next()
+            .unwrap_or("")
+            .split_whitespace()
+            .nth(1)
+            .unwrap_or("");
+        let query = path.split('?').nth(1).unwrap_or("").to_string();
+        if query
+            .split('&')
+            .any(|p| p.starts_with("code=") || p.starts_with("error="))
+        {
+            break (stream, query);
+        }
+        let _ = stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await;
+    };
 
     for param in query.split('&') {
         if let Some(err) = param.strip_prefix("error=") {
             let decoded = urlencoding::decode(err)
                 .map(|s| s.to_string())
                 .unwrap_or_else(|_| err.to_string());
-            let _ = stream.write_all(error_html().as_bytes()).await;
+            let _ = stream.write_all(callback_page(false).as_bytes()).await;
             return Err(format!("Sign-in was cancelled or access was denied: {}", decoded));
         }
     }
@@ -109,7 +291,7 @@ pub async fn await_openrouter_callback(
         .unwrap_or_default();
 
     if returned_state != expected_state {
-        let _ = stream.write_all(error_html().as_bytes()).await;
+        let _ = stream.write_all(callback_page(false).as_bytes()).await;
         return Err(
             "OAuth state mismatch — possible CSRF attempt. Please try signing in again."
                 .to_string(),
@@ -129,7 +311,7 @@ pub async fn await_openrouter_callback(
             "No authorization code received from OpenRouter. Please try again.".to_string()
         })?;
 
-    let _ = stream.write_all(success_html().as_bytes()).await;
+    let _ = stream.write_all(callback_page(true).as_bytes()).await;
     drop(stream);
 
     // Exchange { code, code_verifier } → API key.
@@ -179,32 +361,76 @@ pub async fn await_openrouter_callback(
     Ok(api_key)
 }
 
-fn success_html() -> &'static str {
-    "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n\
-     <html><head><meta charset=\"utf-8\">\
-     <style>body{font-family:-apple-system,sans-serif;background:#f5f5f7;\
-     display:flex;align-items:center;justify-content:center;min-height:100vh;margin:0;}\
-     .card{background:#fff;border-radius:16px;padding:48px 40px;max-width:420px;\
-     text-align:center;box-shadow:0 4px 24px rgba(0,0,0,.08);}\
-     h2{margin:0 0 12px;color:#1d1d1f;font-size:22px;font-weight:600;}\
-     p{color:#6e6e73;font-size:15px;line-height:1.5;margin:0;}\
-     </style></head><body><div class=\"card\">\
-     <h2>Connected to OpenRouter</h2>\
-     <p>You can close this tab and return to Rong-E.</p>\
-     </div></body></html>"
+/// Deep link the callback page sends the browser to, so the user lands back
+/// in the app. `RONGE_OAUTH_REDIRECT` overrides it; set it empty to disable.
+const DEFAULT_APP_REDIRECT: &str = "ronge://oauth-complete";
+
+fn app_redirect(success: bool) -> Option<String> {
+    let base = std::env::var("RONGE_OAUTH_REDIRECT").unwrap_or_else(|_| DEFAULT_APP_REDIRECT.to_string());
+    let base = base.trim();
+    if base.is_empty() {
+        return None;
+    }
+    let separator = if base.contains('?') { '&' } else { '?' };
+    Some(format!(
+        "{}{}provider=openrouter&status={}",
+        base,
+        separator,
+        if success { "success" } else { "denied" }
+    ))
+}
+
+/// Page served to the browser after the callback. A template at
+/// `~/.ronge/oauth_success.html` / `~/.ronge/oauth_denied.html` replaces the
+/// built-in one; `{{redirect_url}}` in it is replaced with the app deep link.
+fn callback_page(success: bool) -> String {
+    let status = if success { "200 OK" } else { "400 Bad Request" };
+    let redirect = app_redirect(success);
+    let template = dirs::home_dir()
+        .map(|home| {
+            home.join(".ronge")
+                .join(if success { "oauth_success.html" } else { "oauth_denied.html" })
+        })
+        .and_then(|path| std::fs::read_to_string(path).ok());
+    let body = match template {
+        Some(t) => t.replace("{{redirect_url}}", redirect.as_deref().unwrap_or("")),
+        None => {
+            let (title, message) = if success {
+                ("Connected to OpenRouter", "You can close this tab and return to Rong-E.")
+            } else {
+                ("Sign-in Cancelled", "You can close this tab and try again from the app.")
+            };
+            let (head, link) = match &redirect {
+                Some(url) => (
+                    format!(
+                        "<meta http-equiv=\"refresh\" content=\"1;url={0}\">\
+                         <script>setTimeout(function(){{window.location.href=\"{0}\";}},300);</script>",
+                        url
+                    ),
+                    format!("<p><a href=\"{}\">Return to Rong-E</a></p>", url),
+                ),
+                None => (String::new(), String::new()),
+            };
+            format!(
+                "<html><head><meta charset=\"utf-8\">{}{}</head><body><div class=\"card\">\
+                 <h2>{}</h2><p>{}</p>{}</div></body></html>",
+                PAGE_STYLE, head, title, message, link
+            )
+        }
+    };
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
 }
 
-fn error_html() -> &'static str {
-    "HTTP/1.1 400 Bad Request\r\nContent-Type: text/html\r\n\r\n\
-     <html><head><meta charset=\"utf-8\">\
-     <style>body{font-family:-apple-system,sans-serif;background:#f5f5f7;\
+const PAGE_STYLE: &str = "<style>body{font-family:-apple-system,sans-serif;background:#f5f5f7;\
      display:flex;align-items:center;justify-content:center;min-height:100vh;margin:0;}\
      .card{background:#fff;border-radius:16px;padding:48px 40px;max-width:420px;\
      text-align:center;box-shadow:0 4px 24px rgba(0,0,0,.08);}\
      h2{margin:0 0 12px;color:#1d1d1f;font-size:22px;font-weight:600;}\
      p{color:#6e6e73;font-size:15px;line-height:1.5;margin:0;}\
-     </style></head><body><div class=\"card\">\
-     <h2>Sign-in Cancelled</h2>\
-     <p>You can close this tab and try again from the app.</p>\
-     </div></body></html>"
-}
+     a{color:#0071e3;display:inline-block;margin-top:16px;}\
+     </style>";
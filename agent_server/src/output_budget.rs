@@ -0,0 +1,258 @@
+use rmcp::model::{CallToolResult, RawContent};
+use serde_json::Value;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Items an array keeps however tight the budget (a header row plus a couple
+/// of data rows, the first few messages).
+const MIN_ITEMS: usize = 3;
+/// Characters a result keeps once the turn's budget is spent, enough for
+/// the model to see what the tool returned and that it was cut.
+const MIN_RESULT_CHARS: usize = 500;
+/// Marker left in place of the dropped tail of an array.
+const OMITTED_PREFIX: &str = "[… ";
+const OMITTED_SUFFIX: &str = " more item(s) omitted]";
+/// Shrinking passes before falling back to plain text truncation.
+const MAX_PASSES: usize = 48;
+
+/// How much tool output a turn may hand to the model: a cap per MCP result
+/// (optionally per tool, by name fragment) and a cap for the whole turn.
+/// Results over budget are shrunk structurally — long arrays keep their first
+/// items (a sheet's header and first rows, the newest messages) and long
+/// strings their beginning — with a note telling the model what was cut.
+#[derive(Clone)]
+pub struct OutputBudget {
+    per_result: usize,
+    per_turn: usize,
+    per_tool: Arc<Vec<(String, usize)>>,
+    used: Arc<AtomicUsize>,
+}
+
+impl Default for OutputBudget {
+    fn default() -> Self {
+        Self::new(usize::MAX, usize::MAX)
+    }
+}
+
+/// `8000` characters, or `2000t` tokens (about four characters each).
+fn parse_size(value: &str) -> Option<usize> {
+    let value = value.trim();
+    match value.strip_suffix(['t', 'T']) {
+        Some(tokens) => tokens.trim().parse::<usize>().ok().map(|t| t.saturating_mul(4)),
+        None => value.parse().ok(),
+    }
+}
+
+fn env_size(name: &str) -> Option<usize> {
+    std::env::var(name).ok().as_deref().and_then(parse_size).filter(|n| *n > 0)
+}
+
+impl OutputBudget {
+    fn new(per_result: usize, per_turn: usize) -> Self {
+        Self {
+            per_result,
+            per_turn,
+            per_tool: Arc::new(Vec::new()),
+            used: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Budgets for a turn on `model`: an eighth of its context window per
+    /// result and half of it per turn (in characters, at four per token),
+    /// capped at 48k / 200k characters. `RONGE_TOOL_OUTPUT_CHARS`,
+    /// `RONGE_TURN_OUTPUT_CHARS` and `RONGE_TOOL_OUTPUT_BUDGETS`
+    /// (`gmail=6000,sheets=2000t`) override them.
+    pub fn for_model(provider: &str, model: &str) -> Self {
+        let context_chars = (crate::context_usage::context_limit(provider, model) as usize).saturating_mul(4);
+        let per_result = env_size("RONGE_TOOL_OUTPUT_CHARS").unwrap_or((context_chars / 8).min(48_000));
+        let per_turn = env_size("RONGE_TURN_OUTPUT_CHARS").unwrap_or((context_chars / 2).min(200_000));
+        let per_tool = std::env::var("RONGE_TOOL_OUTPUT_BUDGETS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| {
+                let (fragment, size) = entry.split_once('=')?;
+                let fragment = fragment.trim().to_ascii_lowercase();
+                (!fragment.is_empty()).then_some((fragment, parse_size(size)?))
+            })
+            .collect();
+        Self {
+            per_tool: Arc::new(per_tool),
+            ..Self::new(per_result, per_turn)
+        }
+    }
+
+    fn result_budget(&self, tool_name: &str) -> usize {
+        let lower = tool_name.to_ascii_lowercase();
+        self.per_tool
+            .iter()
+            .find(|(fragment, _)| lower.contains(fragment.as_str()))
+            .map(|(_, size)| *size)
+            .unwrap_or(self.per_result)
+    }
+
+    /// Shrink the text blocks of an MCP result to what is left of the budget
+    /// and charge what remains to the turn. Returns `(original, kept)`
+    /// character counts when something was cut.
+    pub fn apply(&self, tool_name: &str, result: &mut CallToolResult) -> Option<(usize, usize)> {
+        let remaining = self.per_turn.saturating_sub(self.used.load(Ordering::Relaxed));
+        let mut budget = self.result_budget(tool_name).min(remaining).max(MIN_RESULT_CHARS);
+        let turn_spent = remaining < self.result_budget(tool_name);
+        let (mut original, mut kept) = (0, 0);
+        for content in result.content.iter_mut() {
+            let RawContent::Text(text) = &mut content.raw else {
+                continue;
+            };
+            let length = text.text.chars().count();
+            original += length;
+            if length > budget {
+                text.text = shrink(&text.text, budget, turn_spent);
+            }
+            let length = text.text.chars().count();
+            kept += length;
+            budget = budget.saturating_sub(length).max(MIN_RESULT_CHARS);
+        }
+        self.used.fetch_add(kept, Ordering::Relaxed);
+        if kept >= original {
+            return None;
+        }
+        // The structured copy would carry the full output past the budget.
+        result.structured_content = None;
+        println!("✂️ Cut output of {} from {} to {} chars", tool_name, original, kept);
+        Some((original, kept))
+    }
+}
+
+/// `text` cut to about `budget` characters: structurally when it is JSON,
+/// line by line otherwise, followed by a note on what was left out.
+fn shrink(text: &str, budget: usize, turn_spent: bool) -> String {
+    let total = text.chars().count();
+    let reason = if turn_spent {
+        "this turn's tool output budget is nearly spent"
+    } else {
+        "the result exceeded the tool output budget"
+    };
+    let note = format!(
+        "\n[Output cut from {} characters because {}. Ask for fewer rows, a narrower date range or specific fields to see more.]",
+        total, reason
+    );
+    let budget = budget.saturating_sub(note.len()).max(MIN_RESULT_CHARS / 2);
+    if let Ok(mut value) = serde_json::from_str::<Value>(text)
+        && shrink_json(&mut value, budget)
+    {
+        return value.to_string() + &note;
+    }
+    truncate_lines(text, budget) + &note
+}
+
+/// Halve the largest array (keeping its first items) or, once no array can
+/// shrink further, the longest string, until the JSON fits.
+fn shrink_json(value: &mut Value, budget: usize) -> bool {
+    for _ in 0..MAX_PASSES {
+        if value.to_string().chars().count() <= budget {
+            return true;
+        }
+        if !halve_largest_array(value) && !halve_longest_string(value) {
+            return false;
+        }
+    }
+    false
+}
+
+/// Items dropped earlier, from the marker at the end of a shrunk array.
+fn omitted(items: &[Value]) -> Option<usize> {
+    items
+        .last()?
+        .as_str()?
+        .strip_prefix(OMITTED_PREFIX)?
+        .strip_suffix(OMITTED_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+/// The array with the largest serialized size that still has more than
+/// `MIN_ITEMS` real items.
+fn largest_array(value: &mut Value) -> Option<&mut Vec<Value>> {
+    fn visit<'a>(value: &'a mut Value, best: &mut Option<(usize, &'a mut Vec<Value>)>) {
+        match value {
+            Value::Array(items) => {
+                let real = items.len() - omitted(items).map_or(0, |_| 1);
+                if real > MIN_ITEMS {
+                    let size = serde_json::to_string(&*items).map_or(0, |s| s.len());
+                    if best.as_ref().is_none_or(|(b, _)| size > *b) {
+                        *best = Some((size, items));
+                        return;
+                    }
+                }
+                for item in items {
+                    visit(item, best);
+                }
+            }
+            Value::Object(map) => {
+                for v in map.values_mut() {
+                    visit(v, best);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut best = None;
+    visit(value, &mut best);
+    best.map(|(_, items)| items)
+}
+
+fn halve_largest_array(value: &mut Value) -> bool {
+    let Some(items) = largest_array(value) else {
+        return false;
+    };
+    let already = omitted(items);
+    if already.is_some() {
+        items.pop();
+    }
+    let keep = (items.len() / 2).max(MIN_ITEMS);
+    let dropped = items.len() - keep + already.unwrap_or(0);
+    items.truncate(keep);
+    items.push(Value::String(format!("{}{}{}", OMITTED_PREFIX, dropped, OMITTED_SUFFIX)));
+    true
+}
+
+fn halve_longest_string(value: &mut Value) -> bool {
+    fn visit<'a>(value: &'a mut Value, best: &mut Option<&'a mut String>) {
+        match value {
+            Value::String(s) if best.as_ref().is_none_or(|b| s.len() > b.len()) => *best = Some(s),
+            Value::Array(items) => items.iter_mut().for_each(|v| visit(v, best)),
+            Value::Object(map) => map.values_mut().for_each(|v| visit(v, best)),
+            _ => {}
+        }
+    }
+    let mut best = None;
+    visit(value, &mut best);
+    let Some(text) = best else {
+        return false;
+    };
+    let chars = text.chars().count();
+    if chars <= 200 {
+        return false;
+    }
+    *text = text.chars().take(chars / 2).collect::<String>() + "… [cut]";
+    true
+}
+
+/// Whole lines from the start (headers and first rows of tabular text),
+/// cutting inside a line only when the first one alone is over budget.
+fn truncate_lines(text: &str, budget: usize) -> String {
+    let mut out = String::new();
+    let mut used = 0;
+    for line in text.lines() {
+        let length = line.chars().count() + 1;
+        if used + length > budget {
+            if out.is_empty() {
+                out = line.chars().take(budget).collect();
+            }
+            break;
+        }
+        out.push_str(line);
+        out.push('\n');
+        used += length;
+    }
+    out.trim_end().to_string()
+}
@@ -0,0 +1,250 @@
+use rig::message::{AssistantContent, Message as RigMessage, UserContent};
+use rig::OneOrMany;
+use rmcp::model::{CallToolResult, RawContent};
+
+/// Mask email addresses, phone numbers and card-like numbers in `text`.
+///
+/// Card numbers are 13–19 digits passing the Luhn check; phone numbers are
+/// other runs of 10–15 digits (separators `-`, `.`, space, parentheses and a
+/// leading `+` allowed). ISO dates and IPv4 addresses are left alone.
+pub fn redact(text: &str) -> String {
+    redact_numbers(&redact_emails(text))
+}
+
+/// Redacted copy of the history (user and assistant text only).
+pub fn redact_history(history: &[RigMessage]) -> Vec<RigMessage> {
+    history
+        .iter()
+        .map(|message| {
+            if let RigMessage::User { content } = message {
+                let parts: Vec<UserContent> = content
+                    .iter()
+                    .cloned()
+                    .map(|c| match c {
+                        UserContent::Text(mut t) => {
+                            t.text = redact(&t.text);
+                            UserContent::Text(t)
+                        }
+                        other => other,
+                    })
+                    .collect();
+                RigMessage::User {
+                    content: OneOrMany::many(parts).unwrap_or_else(|_| content.clone()),
+                }
+            } else if let RigMessage::Assistant { id, content } = message {
+                let parts: Vec<AssistantContent> = content
+                    .iter()
+                    .cloned()
+                    .map(|c| match c {
+                        AssistantContent::Text(mut t) => {
+                            t.text = redact(&t.text);
+                            AssistantContent::Text(t)
+                        }
+                        other => other,
+                    })
+                    .collect();
+                RigMessage::Assistant {
+                    id: id.clone(),
+                    content: OneOrMany::many(parts).unwrap_or_else(|_| content.clone()),
+                }
+            } else {
+                message.clone()
+            }
+        })
+        .collect()
+}
+
+pub fn redact_tool_result(result: &mut CallToolResult) {
+    for content in result.content.iter_mut() {
+        if let RawContent::Text(text) = &mut content.raw {
+            text.text = redact(&text.text);
+        }
+    }
+}
+
+fn is_local_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-')
+}
+
+fn is_domain_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '-')
+}
+
+fn redact_emails(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    let mut emitted = 0;
+    while i < chars.len() {
+        if chars[i] == '@' {
+            let mut start = i;
+            while start > emitted && is_local_char(chars[start - 1]) {
+                start -= 1;
+            }
+            let mut end = i + 1;
+            while end < chars.len() && is_domain_char(chars[end]) {
+                end += 1;
+            }
+            // Trailing dots are sentence punctuation, not part of the domain.
+            while end > i + 1 && chars[end - 1] == '.' {
+                end -= 1;
+            }
+            let domain: String = chars[i + 1..end].iter().collect();
+            let tld_ok = domain
+                .rsplit_once('.')
+                .is_some_and(|(host, tld)| !host.is_empty() && tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic()));
+            if start < i && tld_ok {
+                out.extend(&chars[emitted..start]);
+                out.push_str("[email]");
+                emitted = end;
+                i = end;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    out.extend(&chars[emitted..]);
+    out
+}
+
+fn redact_numbers(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let starts_run = chars[i].is_ascii_digit()
+            || (matches!(chars[i], '+' | '(')
+                && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()));
+        // Only start at a word boundary so digits inside identifiers are kept.
+        if !starts_run || (i > 0 && chars[i - 1].is_ascii_alphanumeric()) {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let mut end = i;
+        while end < chars.len()
+            && (chars[end].is_ascii_digit() || matches!(chars[end], ' ' | '-' | '.' | '(' | ')' | '+'))
+        {
+            end += 1;
+        }
+        // The run ends at its last digit.
+        while end > i && !chars[end - 1].is_ascii_digit() {
+            end -= 1;
+        }
+        let run: String = chars[i..end].iter().collect();
+        let digits: Vec<u32> = run.chars().filter_map(|c| c.to_digit(10)).collect();
+        let followed_by_word = chars.get(end).is_some_and(|c| c.is_ascii_alphanumeric());
+
+        let replacement = if followed_by_word || run.is_empty() {
+            None
+        } else if (13..=19).contains(&digits.len()) && luhn_valid(&digits) {
+            Some("[card]")
+        } else if (10..=15).contains(&digits.len())
+            && digit_groups(&run) <= 5
+            && !looks_like_date(&run)
+            && !looks_like_ipv4(&run)
+        {
+            Some("[phone]")
+        } else {
+            None
+        };
+
+        match replacement {
+            Some(mask) => {
+                out.push_str(mask);
+                i = end;
+            }
+            None => {
+                // Not sensitive: copy the run's first char and keep scanning.
+                out.push(chars[i]);
+                i += 1;
+                while i < end && chars[i].is_ascii_digit() {
+                    out.push(chars[i]);
+                    i += 1;
+                }
+            }
+        }
+    }
+    out
+}
+
+fn luhn_valid(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                d
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Phone numbers have a handful of groups; longer runs are lists of numbers.
+fn digit_groups(run: &str) -> usize {
+    run.split(|c: char| !c.is_ascii_digit())
+        .filter(|g| !g.is_empty())
+        .count()
+}
+
+/// `YYYY-MM-DD...` (possibly followed by a time), which would otherwise
+/// count as a 10+ digit run.
+fn looks_like_date(run: &str) -> bool {
+    let b = run.as_bytes();
+    b.len() >= 10
+        && b[..4].iter().all(u8::is_ascii_digit)
+        && matches!(b[4], b'-' | b'.')
+        && b[5..7].iter().all(u8::is_ascii_digit)
+        && b[7] == b[4]
+        && b[8..10].iter().all(u8::is_ascii_digit)
+}
+
+fn looks_like_ipv4(run: &str) -> bool {
+    let parts: Vec<&str> = run.split('.').collect();
+    parts.len() == 4
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.len() <= 3 && p.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digits(number: &str) -> Vec<u32> {
+        number.chars().filter_map(|c| c.to_digit(10)).collect()
+    }
+
+    #[test]
+    fn luhn_accepts_valid_card_numbers_only() {
+        assert!(luhn_valid(&digits("4111 1111 1111 1111")));
+        assert!(luhn_valid(&digits("5500-0000-0000-0004")));
+        assert!(luhn_valid(&digits("378282246310005")));
+        assert!(!luhn_valid(&digits("4111 1111 1111 1112")));
+    }
+
+    #[test]
+    fn redacts_emails_cards_and_phones() {
+        assert_eq!(redact("Mail jane.doe+work@example.co.uk."), "Mail [email].");
+        assert_eq!(redact("Card 4111 1111 1111 1111 on file"), "Card [card] on file");
+        assert_eq!(redact("Call +1 (555) 123-4567 today"), "Call [phone] today");
+    }
+
+    #[test]
+    fn leaves_dates_addresses_and_identifiers_alone() {
+        for text in [
+            "Due 2024-03-15 10:30",
+            "Server at 192.168.100.200",
+            "Order ABC12345678901",
+            "me@localhost",
+            "Total: 42",
+        ] {
+            assert_eq!(redact(text), text);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use crate::state::McpToolSet;
+use crate::tools::{ToolError, ToolEventSender};
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Steps a plan may have; longer plans are cut.
+const MAX_STEPS: usize = 8;
+/// Tool names listed to the planner.
+const MAX_LISTED_TOOLS: usize = 80;
+/// Agent turns allowed per plan step (the unplanned default is 15).
+const TURNS_PER_STEP: usize = 5;
+
+const PLAN_PREAMBLE: &str = "You decide whether a request needs a multi-step plan before an \
+assistant with tools carries it out. A request is multi-step when it needs several dependent \
+tool calls across services or changes data in more than one place (e.g. \"find last month's \
+invoices in Gmail, total them in the Budget sheet and email the summary to Alex\"). Simple \
+questions and single lookups are not. Reply with JSON only:\n\
+{\"multi_step\": true|false, \"goal\": \"...\", \"steps\": [{\"title\": \"...\", \"tools\": [\"tool names\"], \
+\"risk\": \"what could go wrong, or null\"}], \"risks\": [\"sends email\", \"overwrites cells\", ...]}\n\
+Use at most 8 steps and only tools from the list you are given. For a request that is not \
+multi-step, reply {\"multi_step\": false}.";
+
+pub const REJECTED_REPLY: &str = "Okay, I didn't run that plan. Tell me what to change and I'll draft a new one.";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub title: String,
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(default)]
+    pub risk: Option<String>,
+}
+
+/// A structured plan for a multi-step request, shown to the user as a `plan`
+/// widget before anything runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    #[serde(default)]
+    pub multi_step: bool,
+    #[serde(default)]
+    pub goal: String,
+    #[serde(default)]
+    pub steps: Vec<PlanStep>,
+    #[serde(default)]
+    pub risks: Vec<String>,
+}
+
+/// The plan a turn is executing, carried on its `ToolEventSender`.
+#[derive(Debug, Clone)]
+pub struct ActivePlan {
+    pub id: String,
+    pub steps: usize,
+}
+
+impl ActivePlan {
+    pub fn max_turns(&self) -> usize {
+        (self.steps * TURNS_PER_STEP).max(15)
+    }
+}
+
+pub enum Planned {
+    /// Not multi-step (or no plan could be drafted): run the turn as usual.
+    Direct,
+    /// The user approved the plan; run `query`, which walks through it.
+    Approved { query: String, plan: ActivePlan },
+    /// The user rejected the plan (or did not answer); run nothing.
+    Rejected,
+}
+
+/// The JSON object in a model reply, tolerating code fences and prose around it.
+fn parse_plan(reply: &str) -> Option<Plan> {
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    serde_json::from_str(reply.get(start..=end)?).ok()
+}
+
+/// Ask the model whether `query` is multi-step and, if so, for a plan; show
+/// the plan to the user and wait for approval. Drafting failures fall back
+/// to an ordinary turn.
+pub async fn plan(
+    provider: &str,
+    api_key: &str,
+    model: &str,
+    query: &str,
+    tool_sets: &[McpToolSet],
+    tx: &ToolEventSender,
+    confirmations: &crate::confirm::Confirmations,
+) -> Planned {
+    let tools: Vec<String> = tool_sets
+        .iter()
+        .flat_map(|set| set.tools.iter().map(|t| t.name.to_string()))
+        .take(MAX_LISTED_TOOLS)
+        .collect();
+    let prompt = format!("Available tools: {}\n\nRequest: {}", tools.join(", "), query);
+    let prompt = if tx.redacts_pii() { crate::pii::redact(&prompt) } else { prompt };
+    let reply = match crate::llm::complete(provider, api_key, model, PLAN_PREAMBLE, &prompt).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            println!("⚠️ Planning skipped: {}", e);
+            return Planned::Direct;
+        }
+    };
+    let Some(mut plan) = parse_plan(&reply).filter(|p| p.multi_step && p.steps.len() > 1) else {
+        return Planned::Direct;
+    };
+    plan.steps.truncate(MAX_STEPS);
+
+    let id = crate::session::new_session_id();
+    println!("🗺️ Drafted a {}-step plan ({})", plan.steps.len(), id);
+    let plan_json = serde_json::to_value(&plan).unwrap_or_default();
+    if !confirmations.request_plan(tx, &id, &plan_json).await {
+        println!("🗺️ Plan {} rejected", id);
+        return Planned::Rejected;
+    }
+
+    let steps: Vec<String> = plan
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| format!("{}. {}", i + 1, step.title))
+        .collect();
+    let query = format!(
+        "{}\n\nThe user approved this plan for the request above. Carry it out step by step, in order. \
+         After finishing each step, call plan_checkpoint with its number, a status (done, failed or \
+         skipped) and a one-line note. If a step fails in a way that makes the rest pointless, stop \
+         and explain.\nGoal: {}\n{}",
+        query,
+        plan.goal,
+        steps.join("\n")
+    );
+    Planned::Approved {
+        query,
+        plan: ActivePlan { id, steps: plan.steps.len() },
+    }
+}
+
+/// Records progress through an approved plan and reports it to the client
+/// as `plan_step` events.
+pub struct PlanCheckpoint {
+    pub plan: ActivePlan,
+    pub tx: ToolEventSender,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PlanCheckpointArgs {
+    step: usize,
+    status: String,
+    #[serde(default)]
+    note: String,
+}
+
+impl Tool for PlanCheckpoint {
+    const NAME: &'static str = "plan_checkpoint";
+    type Args = PlanCheckpointArgs;
+    type Output = String;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Report that a step of the approved plan is finished, before moving on to the next one.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "step": { "type": "integer", "description": "Step number, starting at 1" },
+                    "status": { "type": "string", "enum": ["done", "failed", "skipped"] },
+                    "note": { "type": "string", "description": "One line on the outcome" }
+                },
+                "required": ["step", "status"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        if args.step == 0 || args.step > self.plan.steps {
+            return Err(ToolError::CommandFailed(format!(
+                "The plan has steps 1 to {}.",
+                self.plan.steps
+            )));
+        }
+        let _ = self
+            .tx
+            .send(json!({
+                "type": "plan_step",
+                "content": {
+                    "id": self.plan.id,
+                    "step": args.step,
+                    "steps": self.plan.steps,
+                    "status": args.status,
+                    "note": args.note,
+                }
+            }))
+            .await;
+        Ok(if args.step == self.plan.steps {
+            "Checkpoint recorded. That was the last step; give the user the final answer.".to_string()
+        } else {
+            format!("Checkpoint recorded. Continue with step {}.", args.step + 1)
+        })
+    }
+}
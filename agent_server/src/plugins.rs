@@ -0,0 +1,213 @@
+use crate::state::SharedState;
+use rmcp::{
+    ServerHandler,
+    model::{CallToolRequestParam, CallToolResult, Content, ErrorData, JsonObject, ListToolsResult, PaginatedRequestParam},
+    service::{RequestContext, RoleServer},
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Config, Engine, Store, StoreLimits, StoreLimitsBuilder};
+
+wasmtime::component::bindgen!({
+    world: "plugin",
+    path: "wit",
+});
+
+/// Instructions a single call may execute before it is aborted.
+const FUEL_PER_CALL: u64 = 2_000_000_000;
+/// Linear memory cap per plugin instance.
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+pub fn plugins_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("plugins")
+}
+
+struct PluginTool {
+    def: rmcp::model::Tool,
+    component: Component,
+    plugin: String,
+}
+
+/// Per-call store data: only the resource limits, plugins import nothing.
+struct Sandbox {
+    limits: StoreLimits,
+}
+
+/// Serves every tool of every loaded plugin. Each call gets a fresh instance
+/// with its own fuel and memory budget, so plugins cannot keep state between
+/// calls or affect one another.
+struct PluginServer {
+    engine: Engine,
+    tools: Arc<HashMap<String, PluginTool>>,
+}
+
+fn new_store(engine: &Engine) -> Result<Store<Sandbox>, String> {
+    let mut store = Store::new(
+        engine,
+        Sandbox {
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).instances(1).build(),
+        },
+    );
+    store.limiter(|s| &mut s.limits);
+    store.set_fuel(FUEL_PER_CALL).map_err(|e| e.to_string())?;
+    Ok(store)
+}
+
+fn instantiate(engine: &Engine, component: &Component) -> Result<(Store<Sandbox>, Plugin), String> {
+    let mut store = new_store(engine)?;
+    // No host functions are linked: a plugin that imports anything fails here.
+    let linker: Linker<Sandbox> = Linker::new(engine);
+    let plugin = Plugin::instantiate(&mut store, component, &linker).map_err(|e| e.to_string())?;
+    Ok((store, plugin))
+}
+
+/// Compile one plugin and read its tool definitions.
+fn load_plugin(engine: &Engine, path: &Path) -> Result<(Component, Vec<rmcp::model::Tool>), String> {
+    let component = Component::from_file(engine, path).map_err(|e| e.to_string())?;
+    let (mut store, plugin) = instantiate(engine, &component)?;
+    let defs = plugin.call_tools(&mut store).map_err(|e| e.to_string())?;
+    let tools = defs
+        .into_iter()
+        .map(|def| {
+            let schema: JsonObject = serde_json::from_str(&def.parameters).unwrap_or_else(|e| {
+                println!("⚠️ Plugin tool '{}' has an invalid parameters schema ({}); using an empty one", def.name, e);
+                serde_json::json!({"type": "object", "properties": {}})
+                    .as_object()
+                    .cloned()
+                    .unwrap_or_default()
+            });
+            rmcp::model::Tool::new(def.name, def.description, Arc::new(schema))
+        })
+        .collect();
+    Ok((component, tools))
+}
+
+fn load_all(engine: &Engine) -> HashMap<String, PluginTool> {
+    let mut tools: HashMap<String, PluginTool> = HashMap::new();
+    let Ok(entries) = std::fs::read_dir(plugins_dir()) else {
+        return tools;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let plugin = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match load_plugin(engine, &path) {
+            Ok((component, defs)) => {
+                println!("🧩 Loaded plugin '{}' with {} tool(s)", plugin, defs.len());
+                for def in defs {
+                    let name = def.name.to_string();
+                    if let Some(existing) = tools.get(&name) {
+                        println!(
+                            "⚠️ Plugin '{}' tool '{}' already provided by '{}'; skipped",
+                            plugin, name, existing.plugin
+                        );
+                        continue;
+                    }
+                    tools.insert(
+                        name,
+                        PluginTool {
+                            def,
+                            component: component.clone(),
+                            plugin: plugin.clone(),
+                        },
+                    );
+                }
+            }
+            Err(e) => println!("❌ Failed to load plugin {}: {}", path.display(), e),
+        }
+    }
+    tools
+}
+
+/// Load every `.wasm` component in `~/.ronge/plugins/` and expose its tools to
+/// the agent through an in-process MCP server.
+pub async fn start(state: SharedState) {
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    config.consume_fuel(true);
+    let engine = match Engine::new(&config) {
+        Ok(e) => e,
+        Err(e) => {
+            println!("❌ Failed to create the plugin engine: {}", e);
+            return;
+        }
+    };
+
+    let loader = engine.clone();
+    let tools = match tokio::task::spawn_blocking(move || load_all(&loader)).await {
+        Ok(tools) => tools,
+        Err(e) => {
+            println!("❌ Plugin loading panicked: {}", e);
+            return;
+        }
+    };
+    if tools.is_empty() {
+        return;
+    }
+
+    let handler = PluginServer {
+        engine,
+        tools: Arc::new(tools),
+    };
+    match crate::mcp_proxy::connect_in_process(handler).await {
+        Ok(conn) => state.lock().await.plugins = Some(conn),
+        Err(e) => println!("❌ Failed to start plugin tools: {}", e),
+    }
+}
+
+impl ServerHandler for PluginServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(
+            self.tools.values().map(|t| t.def.clone()).collect(),
+        ))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let name = request.name.to_string();
+        let Some(tool) = self.tools.get(&name) else {
+            return Err(ErrorData::invalid_params(format!("Unknown tool {}", name), None));
+        };
+        let args = serde_json::Value::Object(request.arguments.unwrap_or_default()).to_string();
+        let engine = self.engine.clone();
+        let component = tool.component.clone();
+        let plugin = tool.plugin.clone();
+
+        // Plugin code is synchronous and may run until its fuel is exhausted.
+        let outcome = tokio::task::spawn_blocking(move || {
+            let (mut store, instance) = instantiate(&engine, &component)?;
+            instance
+                .call_call(&mut store, &name, &args)
+                .map_err(|e| format!("plugin trapped: {}", e))?
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+        Ok(match outcome {
+            Ok(output) => CallToolResult::success(vec![Content::text(output)]),
+            Err(e) => {
+                println!("❌ Plugin '{}' tool '{}' failed: {}", plugin, request.name, e);
+                CallToolResult::error(vec![Content::text(e)])
+            }
+        })
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// User preferences that apply to every frontend and background run,
+/// persisted to `~/.ronge/profile.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Response language as a BCP 47 tag (`ko`, `de-CH`, `pt_BR`); English when unset.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// ISO 3166 country code (`US`, `KR`) for holidays and business days.
+    #[serde(default)]
+    pub country: Option<String>,
+}
+
+impl Profile {
+    pub fn load() -> Self {
+        std::fs::read_to_string(default_profile_path())
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub async fn save(&self) -> std::io::Result<()> {
+        let path = default_profile_path();
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let body = serde_json::to_string_pretty(self).unwrap_or_else(|_| "{}".to_string());
+        tokio::fs::write(&path, body).await
+    }
+
+    /// The configured country, else the region of the language tag (`de-CH` → `CH`).
+    pub fn country(&self) -> Option<String> {
+        self.country.clone().or_else(|| {
+            self.language
+                .as_deref()?
+                .split('-')
+                .skip(1)
+                .find(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_alphabetic()))
+                .map(|r| r.to_ascii_uppercase())
+        })
+    }
+
+    /// The system-prompt paragraph pinning the response language, if one is set.
+    pub fn language_instruction(&self) -> Option<String> {
+        let language = self.language.as_deref()?;
+        Some(format!(
+            "LANGUAGE: Always reply in the language with tag \"{}\", even when the user, tool \
+             results or documents use another language. Format dates, times and numbers the way \
+             speakers of that language expect, including when you present tool results such as \
+             calendar listings. Keep names, email addresses, code and quoted text unchanged.",
+            language
+        ))
+    }
+
+    /// `now` formatted for prompts, localized when the language is known to chrono.
+    pub fn format_datetime(&self, now: DateTime<Local>, hour_only: bool) -> String {
+        let english = if hour_only { "%A, %B %-d, %Y %H:00" } else { "%A, %B %-d, %Y %H:%M" };
+        let Some(locale) = self.language.as_deref().and_then(chrono_locale) else {
+            return now.format(english).to_string();
+        };
+        let localized = if hour_only { "%A %x %H:00" } else { "%A %x %H:%M" };
+        now.format_localized(localized, locale).to_string()
+    }
+}
+
+/// Validate a language tag from the client: letters, digits, `-` and `_` only.
+pub fn normalize_language(tag: &str) -> Result<Option<String>, String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Ok(None);
+    }
+    if tag.len() > 35 || !tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("\"{}\" is not a language tag (e.g. \"ko\", \"de-CH\")", tag));
+    }
+    Ok(Some(tag.replace('_', "-")))
+}
+
+/// `ko` → `ko_KR`, `de-CH` → `de_CH`: the POSIX locale chrono's tables use.
+fn chrono_locale(tag: &str) -> Option<chrono::Locale> {
+    let mut parts = tag.split('-');
+    let language = parts.next()?.to_ascii_lowercase();
+    let region = parts
+        .find(|p| p.len() == 2)
+        .map(|r| r.to_ascii_uppercase())
+        .unwrap_or_else(|| language.to_ascii_uppercase());
+    chrono::Locale::try_from(format!("{}_{}", language, region).as_str())
+        .or_else(|_| chrono::Locale::try_from(language.as_str()))
+        .ok()
+}
+
+fn default_profile_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("profile.json")
+}
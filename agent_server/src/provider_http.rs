@@ -0,0 +1,215 @@
+use bytes::Bytes;
+use futures::StreamExt;
+use rig::http_client::sse::BoxedStream;
+use rig::http_client::{self, HttpClientExt, LazyBody, MultipartForm, Request, Response, StreamingResponse};
+use rig::wasm_compat::WasmCompatSend;
+use std::sync::Arc;
+
+/// The HTTP client behind every provider client: reqwest, plus the tape of
+/// a recorded or replayed turn (`replay.rs`) and the turn's debug dump
+/// (`debug_dump.rs`). While recording, each round trip's path, request and
+/// response bodies are kept; while replaying, the recorded responses are
+/// returned in order and nothing leaves the machine. With a debug dump,
+/// every round trip's bodies are also written to its `http/` directory.
+#[derive(Debug, Clone, Default)]
+pub struct TapClient {
+    inner: reqwest::Client,
+    tape: Option<Arc<crate::replay::Tape>>,
+    dump: Option<crate::debug_dump::DebugDump>,
+}
+
+impl TapClient {
+    /// Picks up the tape and debug dump of the turn running on this task, if any.
+    pub fn new(inner: reqwest::Client) -> Self {
+        Self {
+            inner,
+            tape: crate::replay::current(),
+            dump: crate::debug_dump::current(),
+        }
+    }
+}
+
+fn tape_error(message: String) -> http_client::Error {
+    http_client::Error::Instance(message.into())
+}
+
+fn status_of(exchange: &crate::replay::HttpExchange) -> http_client::Result<http::StatusCode> {
+    http::StatusCode::from_u16(exchange.status).map_err(|e| tape_error(e.to_string()))
+}
+
+/// A recorded response as the reqwest backend would have returned it.
+fn replayed<U: From<Bytes> + WasmCompatSend + 'static>(
+    exchange: crate::replay::HttpExchange,
+) -> http_client::Result<Response<LazyBody<U>>> {
+    let status = status_of(&exchange)?;
+    if !status.is_success() {
+        return Err(http_client::Error::InvalidStatusCodeWithMessage(status, exchange.response));
+    }
+    let body: LazyBody<U> = Box::pin(async move { Ok(U::from(Bytes::from(exchange.response))) });
+    Response::builder().status(status).body(body).map_err(http_client::Error::Protocol)
+}
+
+/// Hand a finished round trip to the tape (when recording) and the dump.
+async fn keep(
+    tape: Option<&Arc<crate::replay::Tape>>,
+    dumped: Option<(&crate::debug_dump::DebugDump, usize)>,
+    exchange: &crate::replay::HttpExchange,
+) {
+    if let Some(tape) = tape.filter(|t| !t.replays()) {
+        tape.push_http(exchange.clone());
+    }
+    if let Some((dump, n)) = dumped {
+        dump.http_response(n, exchange.status, &exchange.response).await;
+    }
+}
+
+impl HttpClientExt for TapClient {
+    fn send<T, U>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = http_client::Result<Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        T: Into<Bytes>,
+        T: WasmCompatSend,
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        let (parts, body) = req.into_parts();
+        let body: Bytes = body.into();
+        let (inner, tape, dump) = (self.inner.clone(), self.tape.clone(), self.dump.clone());
+        async move {
+            if tape.is_none() && dump.is_none() {
+                return inner.send(Request::from_parts(parts, body)).await;
+            }
+            let (method, path) = (parts.method.to_string(), parts.uri.path().to_string());
+            let request = String::from_utf8_lossy(&body).into_owned();
+            let dumped = match &dump {
+                Some(dump) => Some((dump, dump.http_request(&method, &path, &request).await)),
+                None => None,
+            };
+            let exchange = match tape.as_ref().filter(|t| t.replays()) {
+                Some(tape) => tape.next_http(&method, &path).map_err(tape_error)?,
+                None => {
+                    let (status, response) =
+                        match inner.send::<Bytes, Bytes>(Request::from_parts(parts, body)).await {
+                            Ok(response) => {
+                                let status = response.status();
+                                (status, response.into_body().await?)
+                            }
+                            Err(http_client::Error::InvalidStatusCodeWithMessage(status, message)) => {
+                                (status, Bytes::from(message))
+                            }
+                            Err(e) => return Err(e),
+                        };
+                    crate::replay::HttpExchange {
+                        method,
+                        path,
+                        request,
+                        status: status.as_u16(),
+                        response: String::from_utf8_lossy(&response).into_owned(),
+                    }
+                }
+            };
+            keep(tape.as_ref(), dumped, &exchange).await;
+            replayed(exchange)
+        }
+    }
+
+    /// Not taped: chat turns never upload multipart forms.
+    fn send_multipart<U>(
+        &self,
+        req: Request<MultipartForm>,
+    ) -> impl Future<Output = http_client::Result<Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        self.inner.send_multipart(req)
+    }
+
+    /// A recorded stream is kept whole and replayed as a single chunk; the
+    /// SSE parser downstream splits it into the same events.
+    fn send_streaming<T>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = http_client::Result<StreamingResponse>> + WasmCompatSend
+    where
+        T: Into<Bytes>,
+    {
+        let (parts, body) = req.into_parts();
+        let body: Bytes = body.into();
+        let (inner, tape, dump) = (self.inner.clone(), self.tape.clone(), self.dump.clone());
+        async move {
+            if tape.is_none() && dump.is_none() {
+                return inner.send_streaming(Request::from_parts(parts, body)).await;
+            }
+            let (method, path) = (parts.method.to_string(), parts.uri.path().to_string());
+            let request = String::from_utf8_lossy(&body).into_owned();
+            let dumped = match &dump {
+                Some(dump) => Some((dump.clone(), dump.http_request(&method, &path, &request).await)),
+                None => None,
+            };
+            let dumped_ref = || dumped.as_ref().map(|(dump, n)| (dump, *n));
+            if let Some(replaying) = tape.as_ref().filter(|t| t.replays()) {
+                let exchange = replaying.next_http(&method, &path).map_err(tape_error)?;
+                keep(tape.as_ref(), dumped_ref(), &exchange).await;
+                let status = status_of(&exchange)?;
+                if !status.is_success() {
+                    return Err(http_client::Error::InvalidStatusCodeWithMessage(status, exchange.response));
+                }
+                let stream: BoxedStream =
+                    Box::pin(futures::stream::once(async move { Ok(Bytes::from(exchange.response)) }));
+                return Response::builder().status(status).body(stream).map_err(http_client::Error::Protocol);
+            }
+            let mut exchange = crate::replay::HttpExchange {
+                method,
+                path,
+                request,
+                status: 0,
+                response: String::new(),
+            };
+            let response = match inner.send_streaming(Request::from_parts(parts, body)).await {
+                Ok(response) => response,
+                Err(http_client::Error::InvalidStatusCodeWithMessage(status, message)) => {
+                    exchange.status = status.as_u16();
+                    exchange.response = message.clone();
+                    keep(tape.as_ref(), dumped_ref(), &exchange).await;
+                    return Err(http_client::Error::InvalidStatusCodeWithMessage(status, message));
+                }
+                Err(e) => return Err(e),
+            };
+            // Pass chunks through as they arrive; the tape and the dump get
+            // the whole body once the stream ends.
+            let (parts, stream) = response.into_parts();
+            exchange.status = parts.status.as_u16();
+            let mut collected: Vec<u8> = Vec::new();
+            let mut exchange = Some(exchange);
+            let stream = stream.map(Some).chain(futures::stream::once(async { None })).filter_map(move |chunk| {
+                let item = match chunk {
+                    Some(Ok(bytes)) => {
+                        collected.extend_from_slice(&bytes);
+                        Some(Ok(bytes))
+                    }
+                    Some(Err(e)) => Some(Err(e)),
+                    None => {
+                        if let Some(mut exchange) = exchange.take() {
+                            exchange.response = String::from_utf8_lossy(&collected).into_owned();
+                            if let Some(tape) = &tape {
+                                tape.push_http(exchange.clone());
+                            }
+                            if let Some((dump, n)) = dumped.clone() {
+                                tokio::spawn(async move {
+                                    dump.http_response(n, exchange.status, &exchange.response).await;
+                                });
+                            }
+                        }
+                        None
+                    }
+                };
+                futures::future::ready(item)
+            });
+            let stream: BoxedStream = Box::pin(stream);
+            Ok(Response::from_parts(parts, stream))
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// Azure OpenAI REST API version used when `set_llm` names none.
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+/// Where a provider that has no fixed endpoint is reached, from `set_llm`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Endpoint {
+    /// Azure resource endpoint (`https://<resource>.openai.azure.com`), or
+    /// an OpenAI-compatible server's API root (`http://localhost:1234/v1`).
+    pub base_url: String,
+    /// Azure REST API version.
+    pub api_version: Option<String>,
+}
+
+/// Endpoints by provider. Process-wide like the API keys, so sub-agents,
+/// summaries and background turns reach the same deployment as chat.
+fn endpoints() -> &'static Mutex<HashMap<String, Endpoint>> {
+    static ENDPOINTS: OnceLock<Mutex<HashMap<String, Endpoint>>> = OnceLock::new();
+    ENDPOINTS.get_or_init(Default::default)
+}
+
+fn http_url(raw: &str, field: &str) -> Result<String, String> {
+    let url = raw.trim().trim_end_matches('/');
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(format!("`{}` must be an http(s) URL", field));
+    }
+    Ok(url.to_string())
+}
+
+/// The endpoint a `set_llm` frame gives for `provider`: `None` for providers
+/// with a fixed endpoint, an error when a required field is missing.
+pub fn from_frame(provider: &str, data: &Value) -> Result<Option<Endpoint>, String> {
+    match provider {
+        "azure_openai" => {
+            let endpoint = data["endpoint"]
+                .as_str()
+                .filter(|e| !e.trim().is_empty())
+                .ok_or("Azure OpenAI needs the resource `endpoint` (https://<resource>.openai.azure.com).")?;
+            let api_version = data["api_version"]
+                .as_str()
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or(DEFAULT_AZURE_API_VERSION);
+            Ok(Some(Endpoint {
+                base_url: http_url(endpoint, "endpoint")?,
+                api_version: Some(api_version.trim().to_string()),
+            }))
+        }
+        // LM Studio, llama.cpp's server, vLLM and the like.
+        "openai_compatible" => {
+            let base_url = data["base_url"]
+                .as_str()
+                .filter(|u| !u.trim().is_empty())
+                .ok_or("An OpenAI-compatible provider needs its `base_url` (e.g. http://localhost:1234/v1).")?;
+            Ok(Some(Endpoint { base_url: http_url(base_url, "base_url")?, api_version: None }))
+        }
+        _ => Ok(None),
+    }
+}
+
+tokio::task_local! {
+    static CANDIDATE: (String, Endpoint);
+}
+
+/// Run `check` with `endpoint` standing in for `provider`'s published one,
+/// on this task only: `set_llm` verifies a new endpoint this way and
+/// publishes it with `set` once the check passes.
+pub async fn with_candidate<F: Future>(provider: &str, endpoint: Option<Endpoint>, check: F) -> F::Output {
+    match endpoint {
+        Some(endpoint) => CANDIDATE.scope((provider.to_string(), endpoint), check).await,
+        None => check.await,
+    }
+}
+
+/// `provider`'s endpoint: the candidate under verification on this task, if
+/// any, else the published one.
+pub fn get(provider: &str) -> Option<Endpoint> {
+    let candidate = CANDIDATE.try_with(|(p, endpoint)| (p == provider).then(|| endpoint.clone()));
+    if let Ok(Some(endpoint)) = candidate {
+        return Some(endpoint);
+    }
+    endpoints().lock().unwrap_or_else(|e| e.into_inner()).get(provider).cloned()
+}
+
+/// Set (or clear) `provider`'s endpoint and return the previous one.
+pub fn set(provider: &str, endpoint: Option<Endpoint>) -> Option<Endpoint> {
+    let mut endpoints = endpoints().lock().unwrap_or_else(|e| e.into_inner());
+    match endpoint {
+        Some(endpoint) => endpoints.insert(provider.to_string(), endpoint),
+        None => endpoints.remove(provider),
+    }
+}
+
+/// `provider`'s endpoint, or an error naming what `set_llm` must send.
+pub fn require(provider: &str) -> Result<Endpoint, String> {
+    get(provider).ok_or_else(|| format!("No endpoint configured for {}; send it with set_llm.", provider))
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+/// Number of most recent turns kept per provider/model.
+const WINDOW: usize = 100;
+
+/// Rolling window of outcomes for one provider/model pair.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelStats {
+    latencies_ms: VecDeque<u64>,
+    successes: VecDeque<bool>,
+    tokens_per_sec: VecDeque<f64>,
+}
+
+impl ModelStats {
+    fn push<T>(queue: &mut VecDeque<T>, value: T) {
+        if queue.len() == WINDOW {
+            queue.pop_front();
+        }
+        queue.push_back(value);
+    }
+
+    fn summary(&self) -> serde_json::Value {
+        let mut sorted: Vec<u64> = self.latencies_ms.iter().copied().collect();
+        sorted.sort_unstable();
+        let errors = self.successes.iter().filter(|ok| !**ok).count();
+        let error_rate = if self.successes.is_empty() {
+            0.0
+        } else {
+            errors as f64 / self.successes.len() as f64
+        };
+        let tokens_per_sec = if self.tokens_per_sec.is_empty() {
+            None
+        } else {
+            Some(self.tokens_per_sec.iter().sum::<f64>() / self.tokens_per_sec.len() as f64)
+        };
+        json!({
+            "samples": self.successes.len(),
+            "p50_ms": percentile(&sorted, 0.50),
+            "p95_ms": percentile(&sorted, 0.95),
+            "error_rate": error_rate,
+            "tokens_per_sec": tokens_per_sec,
+        })
+    }
+}
+
+fn percentile(sorted: &[u64], p: f64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted.get(idx).copied()
+}
+
+/// Per-provider/model latency and error statistics, persisted to
+/// `~/.ronge/provider_stats.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderStats {
+    models: HashMap<String, ModelStats>,
+}
+
+impl ProviderStats {
+    pub fn load() -> Self {
+        std::fs::read_to_string(default_stats_path())
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub async fn save(&self) -> std::io::Result<()> {
+        let path = default_stats_path();
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let body = serde_json::to_string(self).unwrap_or_else(|_| "{}".to_string());
+        tokio::fs::write(&path, body).await
+    }
+
+    /// Record one turn. `latency_ms` should exclude tool time; `output_chars`
+    /// is used for a rough tokens/sec estimate (~4 chars per token).
+    pub fn record(
+        &mut self,
+        provider: &str,
+        model: &str,
+        latency_ms: u64,
+        success: bool,
+        output_chars: usize,
+    ) {
+        let stats = self.models.entry(format!("{}/{}", provider, model)).or_default();
+        ModelStats::push(&mut stats.latencies_ms, latency_ms);
+        ModelStats::push(&mut stats.successes, success);
+        if success && latency_ms > 0 && output_chars > 0 {
+            let tokens = output_chars as f64 / 4.0;
+            ModelStats::push(&mut stats.tokens_per_sec, tokens / (latency_ms as f64 / 1000.0));
+        }
+    }
+
+    pub fn summary(&self) -> serde_json::Value {
+        let models: Vec<serde_json::Value> = self
+            .models
+            .iter()
+            .map(|(key, stats)| {
+                let (provider, model) = key.split_once('/').unwrap_or((key.as_str(), ""));
+                let mut entry = stats.summary();
+                entry["provider"] = json!(provider);
+                entry["model"] = json!(model);
+                entry
+            })
+            .collect();
+        json!({ "models": models })
+    }
+}
+
+fn default_stats_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("provider_stats.json")
+}
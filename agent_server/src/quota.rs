@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Google APIs reached through MCP tools (e.g. Composio's `GMAIL_*`,
+/// `GOOGLECALENDAR_*`, `GOOGLESHEETS_*`) and their default budgets:
+/// (name, name fragments, requests per minute, burst).
+///
+/// Override a budget with `RONGE_QUOTA_<API>_PER_MIN`, e.g.
+/// `RONGE_QUOTA_SHEETS_PER_MIN=30`.
+const GOOGLE_APIS: &[(&str, &[&str], f64, f64)] = &[
+    ("gmail", &["gmail"], 120.0, 10.0),
+    ("calendar", &["calendar"], 120.0, 10.0),
+    ("sheets", &["sheets", "spreadsheet"], 50.0, 5.0),
+];
+
+/// Classic token bucket: `burst` tokens, refilled at `per_sec`.
+struct TokenBucket {
+    tokens: f64,
+    burst: f64,
+    per_sec: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_min: f64, burst: f64) -> Self {
+        Self {
+            tokens: burst,
+            burst,
+            per_sec: per_min / 60.0,
+            last: Instant::now(),
+        }
+    }
+
+    /// Take a token, or report how long until one is available.
+    fn try_take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.burst);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.per_sec))
+        }
+    }
+}
+
+/// Buckets are process-wide: the quota belongs to the user's Google account,
+/// not to a session or turn.
+fn buckets() -> &'static Mutex<HashMap<&'static str, TokenBucket>> {
+    static BUCKETS: OnceLock<Mutex<HashMap<&'static str, TokenBucket>>> = OnceLock::new();
+    BUCKETS.get_or_init(|| {
+        let buckets = GOOGLE_APIS
+            .iter()
+            .map(|(api, _, per_min, burst)| {
+                let per_min = std::env::var(format!("RONGE_QUOTA_{}_PER_MIN", api.to_uppercase()))
+                    .ok()
+                    .and_then(|v| v.parse::<f64>().ok())
+                    .filter(|n| *n > 0.0)
+                    .unwrap_or(*per_min);
+                (*api, TokenBucket::new(per_min, *burst))
+            })
+            .collect();
+        Mutex::new(buckets)
+    })
+}
+
+/// The Google API a tool talks to, judged by its name.
+pub fn google_api_for(tool_name: &str) -> Option<&'static str> {
+    let lower = tool_name.to_ascii_lowercase();
+    GOOGLE_APIS
+        .iter()
+        .find(|(_, fragments, _, _)| fragments.iter().any(|f| lower.contains(f)))
+        .map(|(api, _, _, _)| *api)
+}
+
+/// Wait until `api` has budget for one more request. Returns the total time
+/// spent waiting (zero when not throttled).
+pub async fn acquire(api: &str) -> Duration {
+    let mut waited = Duration::ZERO;
+    loop {
+        let wait = {
+            let mut buckets = buckets().lock().unwrap_or_else(|e| e.into_inner());
+            match buckets.get_mut(api) {
+                Some(bucket) => bucket.try_take(),
+                None => Ok(()),
+            }
+        };
+        match wait {
+            Ok(()) => return waited,
+            Err(wait) => {
+                tokio::time::sleep(wait).await;
+                waited += wait;
+            }
+        }
+    }
+}
+
+/// Phrases of a Google failure worth retrying: rate limits and brief outages,
+/// as opposed to bad arguments or missing permissions.
+const TRANSIENT_MARKERS: &[&str] = &[
+    "ratelimitexceeded",
+    "userratelimitexceeded",
+    "rate limit exceeded",
+    "too many requests",
+    "backenderror",
+    "backend error",
+    "service unavailable",
+    "temporarily unavailable",
+];
+
+pub fn is_transient(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    TRANSIENT_MARKERS.iter().any(|m| lower.contains(m))
+}
+
+/// Whether a tool result failed for a transient reason. Like
+/// `google_auth::result_expired`, Composio's `"successful": false` counts as a failure.
+pub fn result_transient(result: &rmcp::model::CallToolResult) -> bool {
+    let text = serde_json::to_string(result).unwrap_or_default();
+    let compact: String = text.chars().filter(|c| !c.is_whitespace() && *c != '\\').collect();
+    let failed = result.is_error == Some(true) || compact.contains("\"successful\":false");
+    failed && is_transient(&text)
+}
@@ -0,0 +1,180 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// Config messages that restart servers or rebuild clients; each gets a
+/// cooldown (the latest message sent during it is applied when it ends) and
+/// identical repeats are dropped.
+const HEAVY: &[&str] = &[
+    "mcp_config",
+    "set_builtin_servers",
+    "set_composio",
+    "set_llm",
+    "set_code_workspace",
+    "set_github_token",
+    "index_spreadsheet",
+];
+/// Minimum time between two heavy messages of one type (`RONGE_CONFIG_COOLDOWN_MS`).
+const DEFAULT_COOLDOWN_MS: u64 = 2_000;
+/// An identical heavy message within this window is a duplicate.
+const DUPLICATE_WINDOW: Duration = Duration::from_secs(10);
+/// Messages of one type accepted in a burst (`RONGE_CONFIG_BURST`).
+const DEFAULT_BURST: f64 = 20.0;
+/// Tokens regained per second after a burst.
+const REFILL_PER_SEC: f64 = 5.0;
+
+fn cooldown() -> Duration {
+    Duration::from_millis(
+        std::env::var("RONGE_CONFIG_COOLDOWN_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_COOLDOWN_MS),
+    )
+}
+
+fn burst() -> f64 {
+    std::env::var("RONGE_CONFIG_BURST")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|b: &f64| *b >= 1.0)
+        .unwrap_or(DEFAULT_BURST)
+}
+
+/// Why a message was turned away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// Too many messages of this type in a short time.
+    Burst,
+    /// A heavy message arrived before its cooldown ran out; it is held and
+    /// applied when the cooldown ends unless a newer one replaces it.
+    Cooldown,
+    /// The same heavy message was sent again; it would change nothing.
+    Duplicate,
+}
+
+impl Reason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Burst => "burst",
+            Self::Cooldown => "cooldown",
+            Self::Duplicate => "duplicate",
+        }
+    }
+}
+
+/// A rejected message: why, and when the client may try again.
+#[derive(Debug, Clone, Copy)]
+pub struct Limited {
+    pub reason: Reason,
+    pub retry_after: Duration,
+}
+
+impl Limited {
+    /// The `rate_limited` frame sent back instead of handling the message.
+    pub fn event(&self, data_type: &str) -> Value {
+        let message = match self.reason {
+            Reason::Burst => format!("Too many '{}' messages; slow down.", data_type),
+            Reason::Cooldown => format!("'{}' was applied moments ago; this one follows when the cooldown ends.", data_type),
+            Reason::Duplicate => format!("'{}' is unchanged; nothing to apply.", data_type),
+        };
+        json!({
+            "type": "rate_limited",
+            "content": {
+                "data_type": data_type,
+                "reason": self.reason.as_str(),
+                "retry_after_ms": self.retry_after.as_millis() as u64,
+                "message": message,
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    /// When the last accepted heavy message arrived, and its payload hash.
+    applied: Option<(Instant, u64)>,
+    /// The latest heavy message held back by the cooldown, and when it is due.
+    deferred: Option<(Instant, Value)>,
+}
+
+/// Per-connection limits on config messages, keyed by `data_type`: a token
+/// bucket for every type, plus a trailing-edge debounce and duplicate
+/// suppression for the heavy ones, so a client resending `mcp_config` in a
+/// loop can't restart every MCP server over and over, yet its last change
+/// still lands.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    /// Record a `data_type` message, or say why it must be rejected.
+    pub fn check(&mut self, data_type: &str, data: &Value) -> Result<(), Limited> {
+        let now = Instant::now();
+        let burst = burst();
+        let bucket = self.buckets.entry(data_type.to_string()).or_insert_with(|| Bucket {
+            tokens: burst,
+            refilled: now,
+            applied: None,
+            deferred: None,
+        });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.refilled).as_secs_f64() * REFILL_PER_SEC).min(burst);
+        bucket.refilled = now;
+
+        let heavy = HEAVY.contains(&data_type).then(|| payload_hash(data));
+        if let (Some(hash), Some((at, last_hash))) = (heavy, bucket.applied) {
+            let since = now.duration_since(at);
+            if last_hash == hash && since < DUPLICATE_WINDOW {
+                // Back to what is already applied: a held change is moot.
+                bucket.deferred = None;
+                return Err(Limited { reason: Reason::Duplicate, retry_after: DUPLICATE_WINDOW - since });
+            }
+        }
+        if bucket.tokens < 1.0 {
+            let wait = (1.0 - bucket.tokens) / REFILL_PER_SEC;
+            return Err(Limited { reason: Reason::Burst, retry_after: Duration::from_secs_f64(wait) });
+        }
+        bucket.tokens -= 1.0;
+        if let Some(hash) = heavy {
+            if let Some((at, _)) = bucket.applied
+                && now.duration_since(at) < cooldown()
+            {
+                let due = at + cooldown();
+                bucket.deferred = Some((due, data.clone()));
+                return Err(Limited { reason: Reason::Cooldown, retry_after: due - now });
+            }
+            bucket.applied = Some((now, hash));
+            bucket.deferred = None;
+        }
+        Ok(())
+    }
+
+    /// Held heavy messages whose cooldown has run out, to be applied now.
+    pub fn take_due(&mut self) -> Vec<(String, Value)> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (data_type, bucket) in &mut self.buckets {
+            if bucket.deferred.as_ref().is_some_and(|(at, _)| *at <= now)
+                && let Some((_, data)) = bucket.deferred.take()
+            {
+                bucket.applied = Some((now, payload_hash(&data)));
+                due.push((data_type.clone(), data));
+            }
+        }
+        due
+    }
+
+    /// When the next held message falls due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.buckets.values().filter_map(|b| b.deferred.as_ref().map(|(at, _)| *at)).min()
+    }
+}
+
+fn payload_hash(data: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.to_string().hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,183 @@
+use serde_json::{json, Value};
+
+/// How hard a reasoning model should think before answering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effort {
+    /// Thinking off where the model allows it.
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Effort {
+    fn parse(text: &str) -> Option<Self> {
+        match text.to_ascii_lowercase().as_str() {
+            "none" | "off" => Some(Self::None),
+            "low" => Some(Self::Low),
+            "medium" => Some(Self::Medium),
+            "high" => Some(Self::High),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+
+    /// Thinking tokens for providers that take a budget rather than a level.
+    fn budget(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Low => 2_048,
+            Self::Medium => 8_192,
+            Self::High => 24_576,
+        }
+    }
+
+    /// The level closest to an explicit budget, for providers that only take levels.
+    fn from_budget(budget: u32) -> Self {
+        match budget {
+            0 => Self::None,
+            1..=4_096 => Self::Low,
+            4_097..=16_384 => Self::Medium,
+            _ => Self::High,
+        }
+    }
+}
+
+/// Anthropic rejects thinking budgets below this.
+const ANTHROPIC_MIN_BUDGET: u32 = 1_024;
+/// Room for the answer itself on top of an Anthropic thinking budget, which
+/// counts against `max_tokens`.
+const ANTHROPIC_ANSWER_TOKENS: u64 = 8_192;
+
+/// OpenAI models that accept `reasoning.effort`; others reject the parameter.
+const OPENAI_REASONING_MODELS: &[&str] = &["o1", "o3", "o4", "gpt-5"];
+
+pub fn is_openai_reasoning_model(model: &str) -> bool {
+    let model = model.to_ascii_lowercase();
+    OPENAI_REASONING_MODELS.iter().any(|m| model.starts_with(m))
+}
+
+/// Reasoning settings of a session (`set_llm`) or a single message. Unset
+/// fields leave the provider's default in place.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Reasoning {
+    pub effort: Option<Effort>,
+    pub thinking_budget: Option<u32>,
+}
+
+impl Reasoning {
+    /// `reasoning_effort` (`none`/`low`/`medium`/`high`) and `thinking_budget`
+    /// (tokens) from a `set_llm` or chat frame.
+    pub fn from_frame(data: &Value) -> Result<Self, String> {
+        let effort = match data["reasoning_effort"].as_str().filter(|e| !e.is_empty()) {
+            Some(text) => Some(Effort::parse(text).ok_or_else(|| {
+                format!("Unknown reasoning_effort '{}' (use none, low, medium or high)", text)
+            })?),
+            None => None,
+        };
+        let thinking_budget = match &data["thinking_budget"] {
+            Value::Null => None,
+            value => Some(
+                value
+                    .as_u64()
+                    .and_then(|b| u32::try_from(b).ok())
+                    .ok_or_else(|| "thinking_budget must be a non-negative number of tokens".to_string())?,
+            ),
+        };
+        Ok(Self { effort, thinking_budget })
+    }
+
+    /// Fields set on a message override the session's, one by one.
+    pub fn or(self, session: Self) -> Self {
+        Self {
+            effort: self.effort.or(session.effort),
+            thinking_budget: self.thinking_budget.or(session.thinking_budget),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.effort.is_none() && self.thinking_budget.is_none()
+    }
+
+    fn budget(&self) -> Option<u32> {
+        self.thinking_budget.or(self.effort.map(Effort::budget))
+    }
+
+    fn effort(&self) -> Option<Effort> {
+        self.effort.or(self.thinking_budget.map(Effort::from_budget))
+    }
+
+    /// The provider's request parameters for these settings, merged into the
+    /// agent's `additional_params`: OpenAI `reasoning.effort` (reasoning
+    /// models only), Anthropic `thinking.budget_tokens`, Gemini
+    /// `thinkingConfig.thinkingBudget`, OpenRouter's unified `reasoning`
+    /// object and Ollama `think`.
+    pub fn params(&self, provider: &str, model: &str) -> Option<Value> {
+        if self.is_default() {
+            return None;
+        }
+        match provider {
+            "openai" => {
+                if !is_openai_reasoning_model(model) {
+                    println!("🧠 {} takes no reasoning effort; ignoring it", model);
+                    return None;
+                }
+                let effort = match self.effort()? {
+                    Effort::None => "minimal",
+                    effort => effort.as_str(),
+                };
+                Some(json!({"reasoning": {"effort": effort}}))
+            }
+            "anthropic" => match self.budget()? {
+                0 => None,
+                budget => Some(json!({
+                    "thinking": {"type": "enabled", "budget_tokens": budget.max(ANTHROPIC_MIN_BUDGET)}
+                })),
+            },
+            "gemini" => Some(json!({
+                "generationConfig": {"thinkingConfig": {"thinkingBudget": self.budget()?}}
+            })),
+            "openrouter" => Some(match (self.thinking_budget, self.effort) {
+                (Some(0), _) | (None, Some(Effort::None)) => json!({"reasoning": {"enabled": false}}),
+                (Some(budget), _) => json!({"reasoning": {"max_tokens": budget}}),
+                (None, effort) => json!({"reasoning": {"effort": effort?.as_str()}}),
+            }),
+            "ollama" => {
+                // gpt-oss takes a level; other thinking models an on/off switch.
+                let effort = self.effort()?;
+                if model.starts_with("gpt-oss") && effort != Effort::None {
+                    Some(json!({"think": effort.as_str()}))
+                } else {
+                    Some(json!({"think": effort != Effort::None}))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// `max_tokens` to set alongside the parameters, when the provider counts
+    /// thinking against it (Anthropic).
+    pub fn max_tokens(&self, provider: &str) -> Option<u64> {
+        match (provider, self.budget()) {
+            ("anthropic", Some(budget)) if budget > 0 => {
+                Some(u64::from(budget.max(ANTHROPIC_MIN_BUDGET)) + ANTHROPIC_ANSWER_TOKENS)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({
+            "reasoning_effort": self.effort.map(Effort::as_str),
+            "thinking_budget": self.thinking_budget,
+        })
+    }
+}
@@ -0,0 +1,422 @@
+use rmcp::model::{CallToolRequestParam, CallToolResult, ErrorData, ListToolsResult, PaginatedRequestParam};
+use rmcp::service::{RequestContext, RoleServer};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// One recorded chat turn: the query, every tool event the client saw, the
+/// final outcome, and what crossed the provider and MCP boundaries: each
+/// provider HTTP round trip and each MCP call the proxy forwarded, with the
+/// tool lists offered to the model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedTurn {
+    pub query: String,
+    pub events: Vec<serde_json::Value>,
+    pub result: Result<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub http: Vec<HttpExchange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mcp: Vec<McpExchange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_sets: Vec<Vec<rmcp::model::Tool>>,
+}
+
+impl RecordedTurn {
+    fn failed(query: &str, error: String) -> Self {
+        Self {
+            query: query.to_string(),
+            events: Vec::new(),
+            result: Err(error),
+            provider: None,
+            model: None,
+            http: Vec::new(),
+            mcp: Vec::new(),
+            tool_sets: Vec::new(),
+        }
+    }
+
+    /// Recorded below the agent, so it can run through the real turn
+    /// pipeline; older recordings only have the events to send back.
+    pub fn is_taped(&self) -> bool {
+        self.provider.is_some() && !self.http.is_empty()
+    }
+}
+
+/// One provider round trip. Only the path is kept (API keys can ride in
+/// the query string) and no headers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpExchange {
+    pub method: String,
+    pub path: String,
+    pub request: String,
+    pub status: u16,
+    pub response: String,
+}
+
+/// One MCP call forwarded by the proxy, with arguments as the model sent
+/// them (secret placeholders, not values) and the scrubbed result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpExchange {
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    pub result: Result<CallToolResult, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayMode {
+    Record,
+    Replay,
+}
+
+/// A named recording ("cassette") stored at `~/.ronge/cassettes/<name>.json`.
+///
+/// In `Record` mode every live turn is appended and saved. In `Replay` mode
+/// turns are served back in order without contacting the provider or any MCP
+/// server, so `logic.rs` can be exercised end-to-end with no API keys.
+pub struct ReplaySession {
+    pub mode: ReplayMode,
+    pub name: String,
+    turns: Vec<RecordedTurn>,
+    cursor: usize,
+}
+
+impl ReplaySession {
+    pub fn record(name: &str) -> Self {
+        Self {
+            mode: ReplayMode::Record,
+            name: name.to_string(),
+            turns: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    pub fn replay(name: &str) -> Result<Self, String> {
+        let path = cassette_path(name);
+        let body = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read recording '{}': {}", name, e))?;
+        let turns: Vec<RecordedTurn> = serde_json::from_str(&body)
+            .map_err(|e| format!("Recording '{}' is not valid: {}", name, e))?;
+        Ok(Self {
+            mode: ReplayMode::Replay,
+            name: name.to_string(),
+            turns,
+            cursor: 0,
+        })
+    }
+
+    pub fn turn_count(&self) -> usize {
+        self.turns.len()
+    }
+
+    /// Next recorded turn. Turns are matched in order; a query that differs
+    /// from the recording is reported as an error rather than silently served.
+    pub fn next_turn(&mut self, query: &str) -> RecordedTurn {
+        let Some(turn) = self.turns.get(self.cursor).cloned() else {
+            return RecordedTurn::failed(query, format!("Recording '{}' has no more turns.", self.name));
+        };
+        self.cursor += 1;
+        if turn.query != query {
+            return RecordedTurn::failed(
+                query,
+                format!("Replay mismatch at turn {}: expected '{}'.", self.cursor, turn.query),
+            );
+        }
+        turn
+    }
+
+    /// Append a live turn and return the updated cassette for saving.
+    pub fn push(&mut self, turn: RecordedTurn) -> Vec<RecordedTurn> {
+        self.turns.push(turn);
+        self.turns.clone()
+    }
+}
+
+/// What one turn sends to the provider and MCP servers: collected while
+/// recording, served back in order while replaying. Provider HTTP reaches it
+/// through `provider_http::TapClient` (the turn runs inside `scoped`), MCP
+/// calls through the turn's `ToolEventSender`.
+#[derive(Debug)]
+pub struct Tape {
+    mode: ReplayMode,
+    http: Mutex<VecDeque<HttpExchange>>,
+    mcp: Mutex<VecDeque<McpExchange>>,
+    tool_sets: Mutex<Vec<Vec<rmcp::model::Tool>>>,
+}
+
+impl Tape {
+    pub fn recording() -> Arc<Self> {
+        Arc::new(Self {
+            mode: ReplayMode::Record,
+            http: Default::default(),
+            mcp: Default::default(),
+            tool_sets: Default::default(),
+        })
+    }
+
+    pub fn replaying(turn: &RecordedTurn) -> Arc<Self> {
+        Arc::new(Self {
+            mode: ReplayMode::Replay,
+            http: Mutex::new(turn.http.iter().cloned().collect()),
+            mcp: Mutex::new(turn.mcp.iter().cloned().collect()),
+            tool_sets: Mutex::new(turn.tool_sets.clone()),
+        })
+    }
+
+    pub fn replays(&self) -> bool {
+        self.mode == ReplayMode::Replay
+    }
+
+    pub fn push_http(&self, exchange: HttpExchange) {
+        lock(&self.http).push_back(exchange);
+    }
+
+    /// The recorded answer to the next provider request, which must go to
+    /// the same place as it did when recorded.
+    pub fn next_http(&self, method: &str, path: &str) -> Result<HttpExchange, String> {
+        let exchange = lock(&self.http)
+            .pop_front()
+            .ok_or_else(|| format!("Replay has no recorded provider response for {} {}", method, path))?;
+        if exchange.method != method || exchange.path != path {
+            return Err(format!(
+                "Replay mismatch: provider request {} {}, recorded {} {}",
+                method, path, exchange.method, exchange.path
+            ));
+        }
+        Ok(exchange)
+    }
+
+    pub fn push_mcp(&self, exchange: McpExchange) {
+        lock(&self.mcp).push_back(exchange);
+    }
+
+    /// The recorded result of the next MCP call, which must be to `tool`.
+    pub fn next_mcp(&self, tool: &str) -> Result<CallToolResult, String> {
+        let exchange =
+            lock(&self.mcp).pop_front().ok_or_else(|| format!("Replay has no recorded result for {}", tool))?;
+        if exchange.tool != tool {
+            return Err(format!("Replay mismatch: called {}, recorded {}", tool, exchange.tool));
+        }
+        exchange.result
+    }
+
+    pub fn push_tool_set(&self, tools: &[rmcp::model::Tool]) {
+        lock(&self.tool_sets).push(tools.to_vec());
+    }
+
+    /// The recorded tool lists, each served by an in-process `ReplayServer`
+    /// so the model is offered exactly the tools it had. The connections must
+    /// outlive the turn.
+    pub async fn connect_tool_sets(&self) -> Result<Vec<crate::state::McpConnection>, String> {
+        let tool_sets = lock(&self.tool_sets).clone();
+        let mut connections = Vec::new();
+        for tools in tool_sets {
+            connections.push(crate::mcp_proxy::connect_in_process(ReplayServer { tools }).await?);
+        }
+        Ok(connections)
+    }
+
+    /// Move what was recorded into `turn`.
+    pub fn fill(&self, turn: &mut RecordedTurn) {
+        turn.http = lock(&self.http).drain(..).collect();
+        turn.mcp = lock(&self.mcp).drain(..).collect();
+        turn.tool_sets = std::mem::take(&mut *lock(&self.tool_sets));
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+tokio::task_local! {
+    static TAPE: Arc<Tape>;
+}
+
+/// Run `turn` with `tape` (if any) visible to the provider clients it builds.
+pub async fn scoped<F: Future>(tape: Option<Arc<Tape>>, turn: F) -> F::Output {
+    match tape {
+        Some(tape) => TAPE.scope(tape, turn).await,
+        None => turn.await,
+    }
+}
+
+/// The tape of the turn running on this task.
+pub fn current() -> Option<Arc<Tape>> {
+    TAPE.try_with(Arc::clone).ok()
+}
+
+/// Offers a recorded tool list. Calls never reach it: the MCP proxy answers
+/// them from the tape.
+struct ReplayServer {
+    tools: Vec<rmcp::model::Tool>,
+}
+
+impl rmcp::ServerHandler for ReplayServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(self.tools.clone()))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        Err(ErrorData::internal_error(format!("{} was not recorded", request.name), None))
+    }
+}
+
+pub async fn save_cassette(name: &str, turns: &[RecordedTurn]) -> std::io::Result<()> {
+    let path = cassette_path(name);
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let body = serde_json::to_string_pretty(turns).unwrap_or_else(|_| "[]".to_string());
+    tokio::fs::write(&path, body).await
+}
+
+fn cassette_path(name: &str) -> PathBuf {
+    // Keep recordings inside the cassette directory whatever the client sends.
+    let safe: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("cassettes")
+        .join(format!("{}.json", safe))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn chat_completion(message: serde_json::Value, finish_reason: &str) -> HttpExchange {
+        let body = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "local-model",
+            "choices": [{"index": 0, "message": message, "finish_reason": finish_reason}],
+            "usage": {"prompt_tokens": 1, "total_tokens": 2},
+        });
+        HttpExchange {
+            method: "POST".to_string(),
+            path: "/v1/chat/completions".to_string(),
+            request: String::new(),
+            status: 200,
+            response: body.to_string(),
+        }
+    }
+
+    /// "Weather in Oslo?" answered after one `get_weather` call, as recorded
+    /// against an OpenAI-compatible server.
+    pub(crate) fn weather_turn() -> RecordedTurn {
+        crate::provider_settings::set(
+            "openai_compatible",
+            Some(crate::provider_settings::Endpoint {
+                base_url: "http://replay.invalid/v1".to_string(),
+                api_version: None,
+            }),
+        );
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"city": {"type": "string"}},
+        });
+        let tool = rmcp::model::Tool::new(
+            "get_weather",
+            "Current weather for a city",
+            Arc::new(schema.as_object().cloned().unwrap()),
+        );
+        RecordedTurn {
+            query: "Weather in Oslo?".to_string(),
+            events: Vec::new(),
+            result: Ok("It is sunny in Oslo.".to_string()),
+            provider: Some("openai_compatible".to_string()),
+            model: Some("local-model".to_string()),
+            http: vec![
+                chat_completion(
+                    serde_json::json!({
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": {"name": "get_weather", "arguments": "{\"city\":\"Oslo\"}"},
+                        }],
+                    }),
+                    "tool_calls",
+                ),
+                chat_completion(
+                    serde_json::json!({"role": "assistant", "content": "It is sunny in Oslo."}),
+                    "stop",
+                ),
+            ],
+            mcp: vec![McpExchange {
+                tool: "get_weather".to_string(),
+                arguments: serde_json::json!({"city": "Oslo"}),
+                result: Ok(CallToolResult::success(vec![rmcp::model::Content::text("Sunny, 21°C")])),
+            }],
+            tool_sets: vec![vec![tool]],
+        }
+    }
+
+    /// A recorded turn with a tool call runs through the real agent loop:
+    /// both model responses come off the tape, the MCP call is answered by
+    /// the proxy from the tape, and the client sees the same tool events.
+    #[tokio::test]
+    async fn taped_turn_replays_through_the_agent() {
+        let turn = weather_turn();
+        assert!(turn.is_taped());
+
+        let tape = Tape::replaying(&turn);
+        let servers = tape.connect_tool_sets().await.expect("recorded tools are served");
+        let (tool_tx, mut tool_rx) = crate::tools::tool_event_channel(256);
+        let tool_tx = tool_tx.with_tape(Some(tape.clone()));
+        let answer = scoped(
+            Some(tape.clone()),
+            crate::llm::call_llm(
+                "openai_compatible".to_string(),
+                "replay".to_string(),
+                "local-model".to_string(),
+                turn.query.clone(),
+                &mut Vec::new(),
+                servers.iter().map(|conn| conn.tool_set()).collect(),
+                None,
+                Vec::new(),
+                tool_tx,
+                None,
+                None,
+                crate::modes::resolve(None),
+                Default::default(),
+                Default::default(),
+            ),
+        )
+        .await
+        .expect("the replayed turn succeeds");
+        assert_eq!(answer, "It is sunny in Oslo.");
+
+        let mut events = Vec::new();
+        while let Some(event) = tool_rx.try_recv() {
+            events.push(event);
+        }
+        let call = events.iter().find(|e| e["type"] == "tool_call").expect("a tool_call event");
+        assert_eq!(call["content"]["toolName"], "get_weather");
+        assert_eq!(call["content"]["toolArgs"]["city"], "Oslo");
+        let result = events.iter().find(|e| e["type"] == "tool_result").expect("a tool_result event");
+        assert_eq!(result["content"]["success"], true);
+        assert!(result["content"]["result"].to_string().contains("Sunny"));
+
+        // Everything on the tape was used, and nothing else was asked for.
+        assert!(tape.next_http("POST", "/v1/chat/completions").is_err());
+        assert!(tape.next_mcp("get_weather").is_err());
+    }
+}
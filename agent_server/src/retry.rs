@@ -0,0 +1,116 @@
+use crate::tools::ToolEventSender;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+/// Attempts per turn, the first included (`RONGE_LLM_RETRY_ATTEMPTS`).
+const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+/// First backoff step (`RONGE_LLM_RETRY_BASE_MS`), doubled per retry.
+const DEFAULT_BASE_MS: u64 = 1_000;
+/// No single wait is longer, whatever the provider suggests.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+fn env_u64(name: &str) -> Option<u64> {
+    std::env::var(name).ok()?.trim().parse().ok()
+}
+
+fn max_attempts() -> u32 {
+    env_u64("RONGE_LLM_RETRY_ATTEMPTS").map_or(DEFAULT_MAX_ATTEMPTS, |n| n.clamp(1, 10) as u32)
+}
+
+/// Why a provider error is worth another attempt: rate limiting (429,
+/// Gemini's `RESOURCE_EXHAUSTED`) or a temporarily unavailable or overloaded
+/// service (503, Anthropic's 529 `overloaded_error`). `None` for anything
+/// else, timeouts included.
+pub fn transient_reason(message: &str) -> Option<&'static str> {
+    let lower = message.to_ascii_lowercase();
+    if lower.contains("429") || lower.contains("rate limit") || lower.contains("rate_limit")
+        || lower.contains("too many requests") || lower.contains("resource_exhausted")
+    {
+        Some("rate_limited")
+    } else if lower.contains("529") || lower.contains("overloaded") {
+        Some("overloaded")
+    } else if lower.contains("503") || lower.contains("service unavailable") || lower.contains("\"unavailable\"") {
+        Some("unavailable")
+    } else {
+        None
+    }
+}
+
+/// The wait a provider asks for in its error body: Gemini's
+/// `"retryDelay": "12s"` or "Please retry in 12.3s", OpenAI's "Please try
+/// again in 20s" (or "in 850ms").
+fn suggested_delay(message: &str) -> Option<Duration> {
+    let lower = message.to_ascii_lowercase();
+    ["retrydelay\": \"", "retry in ", "try again in "].iter().find_map(|marker| {
+        let rest = &lower[lower.find(marker)? + marker.len()..];
+        let digits: String = rest.chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+        let value: f64 = digits.parse().ok()?;
+        let unit = &rest[digits.len()..];
+        if unit.starts_with("ms") {
+            Some(Duration::from_secs_f64(value / 1000.0))
+        } else if unit.starts_with('s') {
+            Some(Duration::from_secs_f64(value))
+        } else {
+            None
+        }
+    })
+}
+
+/// Exponential backoff with jitter: half the step fixed, half random, so
+/// concurrent sessions don't retry in lockstep. A provider's own suggestion
+/// wins when it is longer.
+fn backoff(retry: u32, message: &str) -> Duration {
+    let base = env_u64("RONGE_LLM_RETRY_BASE_MS").unwrap_or(DEFAULT_BASE_MS);
+    let step = base.saturating_mul(1u64 << (retry - 1).min(16));
+    let jittered = Duration::from_millis(step / 2 + rand::thread_rng().gen_range(0..=step / 2));
+    suggested_delay(message).map_or(jittered, |s| s.max(jittered)).min(MAX_DELAY)
+}
+
+/// Run one provider call (`attempt`), retrying rate-limit and overload
+/// errors up to `RONGE_LLM_RETRY_ATTEMPTS` times in all. Each wait is
+/// announced with a `retrying` event. A failed attempt that already called
+/// tools is not repeated, since that would run them again.
+pub async fn with_retries<F, Fut>(provider: &str, model: &str, tx: &ToolEventSender, mut attempt: F) -> Result<String, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<String, String>>,
+{
+    let max_attempts = max_attempts();
+    let mut number = 1;
+    loop {
+        let calls_before = tx.calls_made();
+        let error = match attempt().await {
+            Ok(text) => return Ok(text),
+            Err(error) => error,
+        };
+        let Some(reason) = transient_reason(&error) else {
+            return Err(error);
+        };
+        if number >= max_attempts {
+            println!("❌ {} still {} after {} attempt(s)", provider, reason, number);
+            return Err(error);
+        }
+        if tx.calls_made() > calls_before {
+            println!("⚠️ {} {} after tool calls; not retrying the turn", provider, reason);
+            return Err(error);
+        }
+        let delay = backoff(number, &error);
+        println!("🔁 {} {} (attempt {}/{}), retrying in {} ms", provider, reason, number, max_attempts, delay.as_millis());
+        let _ = tx
+            .send(serde_json::json!({
+                "type": "retrying",
+                "content": {
+                    "provider": provider,
+                    "model": model,
+                    "attempt": number,
+                    "max_attempts": max_attempts,
+                    "delay_ms": delay.as_millis() as u64,
+                    "reason": reason,
+                }
+            }))
+            .await;
+        tokio::time::sleep(delay).await;
+        number += 1;
+    }
+}
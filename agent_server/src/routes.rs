@@ -1,41 +1,272 @@
 
 use crate::logic;
 
+use crate::session::ClientSender;
 use crate::state::SharedState;
 use axum::{
-    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, State},
-    response::IntoResponse,
+    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, Extension, Query, State},
+    http::StatusCode,
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
+    Json,
 };
-use futures::StreamExt; // Only need StreamExt here for receiver.next()
+use futures::StreamExt;
 use rig::message::Message as RigMessage;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// With a `ws_auth` secret set, every request must carry it (or the
+/// `ws_guest` secret), as `Authorization: Bearer <secret>` or
+/// `?token=<secret>` (for WebSocket clients that can't set headers). The
+/// request's `Role` is handed on to the handlers.
+pub async fn require_auth(mut req: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    let role = {
+        let bearer = req
+            .headers()
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|t| t.to_string());
+        let query = Query::<HashMap<String, String>>::try_from_uri(req.uri())
+            .ok()
+            .and_then(|Query(mut params)| params.remove("token"));
+        crate::secrets::role(bearer.or(query).as_deref())
+    };
+    let Some(role) = role else {
+        println!("🚫 Rejected unauthenticated request to {}", req.uri().path());
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    req.extensions_mut().insert(role);
+    next.run(req).await
+}
 
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
+    Query(params): Query<HashMap<String, String>>,
+    Extension(role): Extension<crate::secrets::Role>,
     State(state): State<SharedState>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+    // Reconnecting clients pass their previous session ID to receive any
+    // frames that were buffered while they were away.
+    let session_id = crate::session::session_for(role, params.get("session_id").map(String::as_str));
+    // A kiosk or guest window connects with `?google_access=none|calendar_only`.
+    // It can only narrow what the session already has.
+    let google_access = params
+        .get("google_access")
+        .and_then(|a| crate::google_auth::GoogleAccess::parse(a));
+    ws.on_upgrade(move |socket| handle_socket(socket, state, session_id, google_access))
 }
 
-async fn handle_socket(socket: WebSocket, state: SharedState) {
+async fn handle_socket(
+    socket: WebSocket,
+    state: SharedState,
+    session_id: String,
+    google_access: Option<crate::google_auth::GoogleAccess>,
+) {
     // Split socket into sender/receiver
-    let (mut sender, mut receiver) = socket.split();
-    println!("✅ Client connected");
+    let (sink, mut receiver) = socket.split();
+    let mut sender = ClientSender::new(sink, session_id, state.clone());
+    println!("✅ Client connected (session {})", sender.session_id());
+
+    // Only for this run of the server: a kiosk states it on every connect.
+    if let Some(access) = google_access {
+        let mut s = state.lock().await;
+        if s.sessions.google_access(sender.session_id()).covers(access) {
+            s.sessions.set_google_access(sender.session_id(), access);
+            println!("🔒 Session {} Google access: {}", sender.session_id(), access.as_str());
+        }
+    }
+
+    let title = state
+        .lock()
+        .await
+        .sessions
+        .title(sender.session_id())
+        .map(|t| t.to_string());
+    let _ = sender
+        .send(Message::Text(
+            json!({"type": "session", "content": {"session_id": sender.session_id(), "title": title}})
+                .to_string(),
+        ))
+        .await;
+    sender.flush_pending().await;
+    crate::ollama::warm_up(state.clone());
 
     // Initialize session history
     let mut chat_history: Vec<RigMessage> = Vec::new();
 
+    // Server-initiated events (watch results, etc.)
+    let mut notifications = state.lock().await.notifier.subscribe();
+
+    // Read frames on a separate task so `user_decision` replies reach a tool
+    // call that is paused inside the turn the main loop is currently running.
+    let confirmations = state.lock().await.confirmations.clone();
+    let (frame_tx, mut frames) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let reader = tokio::spawn(async move {
+        while let Some(Ok(msg)) = receiver.next().await {
+            if let Message::Text(text) = msg {
+                if confirmations.handle_frame(&text) {
+                    continue;
+                }
+                if frame_tx.send(text).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
     // The Main Loop
-    while let Some(Ok(msg)) = receiver.next().await {
-        if let Message::Text(text) = msg {
-            // Delegate all logic to the new module
-            logic::process_message(
-                &text, 
-                &mut sender, 
-                &mut chat_history, 
-                &state
-            ).await;
+    loop {
+        // A config message held back by its cooldown is applied when it ends.
+        let deferred = sender.limits.next_due();
+        let deferred_at = tokio::time::Instant::from_std(deferred.unwrap_or_else(std::time::Instant::now));
+        tokio::select! {
+            text = frames.recv() => {
+                let Some(text) = text else { break };
+                // Delegate all logic to the new module
+                logic::process_message(
+                    &text, 
+                    &mut sender, 
+                    &mut chat_history, 
+                    &state
+                ).await;
+            }
+            Ok(event) = notifications.recv() => {
+                if crate::session::receives(sender.session_id(), &event) {
+                    let _ = sender.send(Message::Text(event.to_string())).await;
+                }
+            }
+            _ = tokio::time::sleep_until(deferred_at), if deferred.is_some() => {
+                logic::apply_deferred(&mut sender, &mut chat_history, &state).await;
+            }
+            _ = crate::shutdown::wait() => {
+                let _ = sender
+                    .send(Message::Text(json!({"type": "server_shutdown", "content": {}}).to_string()))
+                    .await;
+                let _ = sender.send(Message::Close(None)).await;
+                break;
+            }
         }
     }
+    reader.abort();
 
     println!("🔌 Client disconnected");
-}
\ No newline at end of file
+}
+#[derive(Deserialize)]
+pub struct ChatRequest {
+    prompt: String,
+    /// Continue an earlier `/chat` conversation; a new session is started if absent.
+    session_id: Option<String>,
+    /// Stream every frame of the turn as server-sent events instead of
+    /// returning only the final response.
+    #[serde(default)]
+    stream: bool,
+    /// Generation overrides for this turn, as in the WebSocket chat frame.
+    temperature: Option<f64>,
+    max_tokens: Option<u64>,
+    max_turns: Option<u64>,
+}
+
+/// `POST /chat` — run one turn without the WebSocket protocol, for scripts,
+/// Raycast, Shortcuts and the like. The history of each `session_id` is kept
+/// server-side between requests.
+///
+/// Without `stream` the reply is the final `response` content plus the
+/// `session_id`; confirmations cannot be answered, so guarded tool calls are
+/// rejected. With `stream` every frame is sent as an SSE event named after its
+/// `type`, and confirmations can be answered with `POST /decision`.
+pub async fn chat_handler(
+    State(state): State<SharedState>,
+    Extension(role): Extension<crate::secrets::Role>,
+    Json(req): Json<ChatRequest>,
+) -> Response {
+    let session_id = crate::session::session_for(role, req.session_id.as_deref());
+    println!("🌐 HTTP /chat turn (session {})", session_id);
+    let (mut sender, mut frames) = ClientSender::channel(session_id.clone(), state.clone());
+    // SSE clients also get the answer's text as it is generated.
+    let frame = json!({
+        "text": req.prompt,
+        "stream": req.stream,
+        "temperature": req.temperature,
+        "max_tokens": req.max_tokens,
+        "max_turns": req.max_turns,
+    })
+    .to_string();
+
+    let turn_state = state.clone();
+    let turn = async move {
+        let mut chat_history = turn_state.lock().await.sessions.take_history(sender.session_id());
+        logic::process_message(&frame, &mut sender, &mut chat_history, &turn_state).await;
+        turn_state
+            .lock()
+            .await
+            .sessions
+            .store_history(sender.session_id(), chat_history);
+    };
+
+    if req.stream {
+        tokio::spawn(turn);
+        let events = futures::stream::unfold(frames, |mut frames| async move {
+            frames.recv().await.map(|msg| (msg, frames))
+        })
+        .filter_map(|msg| async move {
+            let Message::Text(frame) = msg else { return None };
+            let kind = serde_json::from_str::<Value>(&frame)
+                .ok()
+                .and_then(|v| v["type"].as_str().map(str::to_string))
+                .unwrap_or_else(|| "message".to_string());
+            Some(Ok::<_, std::convert::Infallible>(Event::default().event(kind).data(frame)))
+        });
+        return Sse::new(events).keep_alive(KeepAlive::default()).into_response();
+    }
+
+    let confirmations = state.lock().await.confirmations.clone();
+    let collect = async move {
+        let (mut response, mut error) = (None, None);
+        while let Some(msg) = frames.recv().await {
+            let Message::Text(frame) = msg else { continue };
+            let Ok(frame) = serde_json::from_str::<Value>(&frame) else { continue };
+            match frame["type"].as_str().unwrap_or("") {
+                "response" => response = Some(frame["content"].clone()),
+                "confirmation" => {
+                    confirmations.resolve(frame["content"]["id"].as_str().unwrap_or(""), false);
+                }
+                kind if kind.ends_with("_error") => error = frame["content"].as_str().map(str::to_string),
+                _ => {}
+            }
+        }
+        (response, error)
+    };
+    let ((), (response, error)) = tokio::join!(turn, collect);
+
+    match response {
+        Some(Value::Object(mut content)) => {
+            content.insert("session_id".to_string(), json!(session_id));
+            Json(Value::Object(content)).into_response()
+        }
+        _ => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "session_id": session_id,
+                "error": error.unwrap_or_else(|| "The turn ended without a response.".to_string()),
+            })),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DecisionRequest {
+    id: String,
+    approved: bool,
+}
+
+/// `POST /decision` — answer a `confirmation` event received over `/chat` streaming.
+pub async fn decision_handler(State(state): State<SharedState>, Json(req): Json<DecisionRequest>) -> StatusCode {
+    let confirmations = state.lock().await.confirmations.clone();
+    if confirmations.resolve(&req.id, req.approved) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
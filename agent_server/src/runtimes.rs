@@ -0,0 +1,68 @@
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Runtimes MCP servers are commonly launched with: name, executables tried
+/// in order, and what to tell the user when none is found.
+const RUNTIMES: &[(&str, &[&str], &str)] = &[
+    ("node", &["node"], "Node.js not found. Install it from nodejs.org or with `brew install node`."),
+    ("npx", &["npx"], "npx not found. It comes with Node.js (nodejs.org or `brew install node`)."),
+    ("python", &["python3", "python"], "Python not found. Install it from python.org or with `brew install python`."),
+    ("uvx", &["uvx"], "uvx not found. Install uv with `brew install uv` or from docs.astral.sh/uv."),
+    ("docker", &["docker"], "Docker not found. Install Docker Desktop from docker.com."),
+];
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Look for one runtime in the expanded PATH and ask it for its version.
+async fn probe(name: &str, executables: &[&str], hint: &str, path: &str) -> Value {
+    let Some(resolved) = executables
+        .iter()
+        .map(|exe| crate::logic::resolve_command(exe, path))
+        .find(|resolved| resolved.starts_with('/'))
+    else {
+        return json!({"name": name, "found": false, "path": null, "version": null, "hint": hint});
+    };
+    let output = tokio::time::timeout(
+        PROBE_TIMEOUT,
+        tokio::process::Command::new(&resolved).arg("--version").env("PATH", path).output(),
+    )
+    .await;
+    let (version, error) = match output {
+        Ok(Ok(out)) => {
+            // Older Pythons print the version to stderr.
+            let text = if out.stdout.is_empty() { out.stderr } else { out.stdout };
+            let line = String::from_utf8_lossy(&text).lines().next().unwrap_or("").trim().to_string();
+            if out.status.success() {
+                (Some(line), None)
+            } else {
+                (None, Some(format!("`{} --version` failed: {}", resolved, line)))
+            }
+        }
+        Ok(Err(e)) => (None, Some(e.to_string())),
+        Err(_) => (None, Some(format!("`{} --version` did not answer within {}s", resolved, PROBE_TIMEOUT.as_secs()))),
+    };
+    json!({
+        "name": name,
+        "found": true,
+        "path": resolved,
+        "version": version,
+        "error": error,
+    })
+}
+
+/// Every runtime in `RUNTIMES`, probed in parallel against the same
+/// expanded PATH MCP servers are spawned with.
+pub async fn check() -> Value {
+    let path = crate::logic::build_expanded_path();
+    let probes = RUNTIMES.iter().map(|(name, executables, hint)| probe(name, executables, hint, &path));
+    let runtimes = futures::future::join_all(probes).await;
+    let missing: Vec<&str> = runtimes
+        .iter()
+        .filter(|r| r["found"] == false)
+        .filter_map(|r| r["name"].as_str())
+        .collect();
+    if !missing.is_empty() {
+        println!("🔎 Runtimes not found: {}", missing.join(", "));
+    }
+    json!({"runtimes": runtimes, "path": path})
+}
@@ -0,0 +1,162 @@
+use crate::state::SharedState;
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::PathBuf;
+
+/// How often the scheduler checks for due jobs.
+const POLL_INTERVAL_SECS: u64 = 30;
+
+/// A recurring agent prompt, e.g. "every Friday at 17:00, append this week's
+/// totals from the Expenses sheet to the Summary tab and email me the result".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub id: String,
+    pub name: String,
+    pub prompt: String,
+    /// Day of the week (`"fri"`, `"Friday"`, …). `None` = every day.
+    #[serde(default)]
+    pub weekday: Option<String>,
+    /// Local time of day as `HH:MM`.
+    pub time: String,
+    pub created_at: i64,
+    #[serde(default)]
+    pub last_run: Option<i64>,
+    /// Sub-agent (see `subagent.rs`) to hand the prompt to directly, e.g.
+    /// `triage_agent` for a morning inbox briefing. `None` = the main agent.
+    #[serde(default)]
+    pub agent: Option<String>,
+    /// Google access of the session that created it; the runs get no more.
+    #[serde(default)]
+    pub google_access: crate::google_auth::GoogleAccess,
+}
+
+impl ScheduledJob {
+    /// Validate the weekday/time fields, returning a user-facing error.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(day) = &self.weekday
+            && day.parse::<Weekday>().is_err()
+        {
+            return Err(format!("'{}' isn't a day of the week.", day));
+        }
+        if NaiveTime::parse_from_str(&self.time, "%H:%M").is_err() {
+            return Err(format!("'{}' isn't a valid time — use HH:MM.", self.time));
+        }
+        if let Some(agent) = &self.agent
+            && crate::subagent::find(agent).is_none()
+        {
+            return Err(format!("'{}' isn't a known agent.", agent));
+        }
+        Ok(())
+    }
+
+    /// The most recent scheduled occurrence at or before `now`.
+    fn last_occurrence(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let time = NaiveTime::parse_from_str(&self.time, "%H:%M").ok()?;
+        let weekday = match &self.weekday {
+            Some(day) => Some(day.parse::<Weekday>().ok()?),
+            None => None,
+        };
+        for days_back in 0..=7 {
+            let date = now.date_naive() - chrono::Duration::days(days_back);
+            if weekday.is_some_and(|w| date.weekday() != w) {
+                continue;
+            }
+            let candidate = date.and_time(time).and_local_timezone(Local).earliest()?;
+            if candidate <= now {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    fn is_due(&self, now: DateTime<Local>) -> bool {
+        let Some(due) = self.last_occurrence(now) else {
+            return false;
+        };
+        let due = due.timestamp();
+        due >= self.created_at && self.last_run.is_none_or(|last| last < due)
+    }
+}
+
+pub fn default_jobs_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("scheduled_jobs.json")
+}
+
+pub fn load_jobs() -> Vec<ScheduledJob> {
+    std::fs::read_to_string(default_jobs_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub async fn save_jobs(jobs: &[ScheduledJob]) -> std::io::Result<()> {
+    let path = default_jobs_path();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let body = serde_json::to_string_pretty(jobs).unwrap_or_else(|_| "[]".to_string());
+    tokio::fs::write(&path, body).await
+}
+
+/// Spawn the background task that runs due jobs unattended and broadcasts a
+/// `scheduled_job_result` event for each run.
+pub fn spawn(state: SharedState) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(POLL_INTERVAL_SECS));
+
+        loop {
+            interval.tick().await;
+            let now = Local::now();
+
+            // Mark due jobs as run before starting them so a slow run is not
+            // picked up again on the next tick.
+            let (due, jobs) = {
+                let mut s = state.lock().await;
+                let mut due = Vec::new();
+                for job in s.scheduled_jobs.iter_mut() {
+                    if job.is_due(now) {
+                        job.last_run = Some(now.timestamp());
+                        due.push(job.clone());
+                    }
+                }
+                (due, s.scheduled_jobs.clone())
+            };
+            if due.is_empty() {
+                continue;
+            }
+            if let Err(e) = save_jobs(&jobs).await {
+                println!("❌ Failed to save scheduled jobs: {}", e);
+            }
+
+            for job in due {
+                println!("⏰ Running scheduled job '{}'", job.name);
+                let state = state.clone();
+                tokio::spawn(async move { run_job(&state, &job).await });
+            }
+        }
+    });
+}
+
+pub async fn run_job(state: &SharedState, job: &ScheduledJob) {
+    let result = match &job.agent {
+        Some(agent) => crate::logic::run_background_subagent(state, agent, &job.prompt, job.google_access).await,
+        None => crate::logic::run_background_turn(state, job.prompt.clone(), Vec::new(), job.google_access).await,
+    };
+    let content = match result {
+        Ok(text) => json!({"job_id": job.id, "name": job.name, "status": "success", "text": text}),
+        Err(e) => {
+            println!("❌ Scheduled job '{}' failed: {}", job.name, e);
+            json!({"job_id": job.id, "name": job.name, "status": "error", "text": e})
+        }
+    };
+    let _ = state
+        .lock()
+        .await
+        .notifier
+        .send(json!({"type": "scheduled_job_result", "content": content}));
+}
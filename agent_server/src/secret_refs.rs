@@ -0,0 +1,336 @@
+use crate::tools::{ToolError, ToolEventSender};
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use rmcp::model::{CallToolResult, JsonObject, RawContent};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const PLACEHOLDER_PREFIX: &str = "{{secret:";
+const PLACEHOLDER_SUFFIX: &str = "}}";
+
+/// A Keychain item the user allowed `get_secret` to read. Only this
+/// description is stored, never the value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretItem {
+    /// What the model asks for, e.g. `jira`.
+    pub name: String,
+    /// The generic password's service (its "Where" in Keychain Access).
+    pub service: String,
+    #[serde(default)]
+    pub account: Option<String>,
+    /// Tools the value may be passed to (case-insensitive substrings of the
+    /// tool name); any tool when empty.
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+impl SecretItem {
+    fn allows(&self, tool_name: &str) -> bool {
+        let lower = tool_name.to_ascii_lowercase();
+        self.tools.is_empty() || self.tools.iter().any(|t| lower.contains(&t.to_ascii_lowercase()))
+    }
+}
+
+pub fn default_items_path() -> std::path::PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("secret_access.json")
+}
+
+/// The allowed items; none (and so no `get_secret` tool) until the user opts in.
+pub fn load_items() -> Vec<SecretItem> {
+    std::fs::read_to_string(default_items_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub async fn save_items(items: &[SecretItem]) -> Result<(), String> {
+    let path = default_items_path();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+    }
+    let text = serde_json::to_string_pretty(items).map_err(|e| e.to_string())?;
+    tokio::fs::write(path, text).await.map_err(|e| e.to_string())
+}
+
+/// Items from a `set_secret_access` frame, checked for usable names.
+pub fn items_from_frame(data: &Value) -> Result<Vec<SecretItem>, String> {
+    let items: Vec<SecretItem> = serde_json::from_value(data["items"].clone()).map_err(|e| format!("Invalid items: {}", e))?;
+    for item in &items {
+        let valid = !item.name.is_empty() && item.name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        if !valid || item.service.trim().is_empty() {
+            return Err(format!("Item '{}' needs a name of letters, digits, '-', '_' or '.' and a service", item.name));
+        }
+    }
+    Ok(items)
+}
+
+fn placeholder(name: &str) -> String {
+    format!("{}{}{}", PLACEHOLDER_PREFIX, name, PLACEHOLDER_SUFFIX)
+}
+
+/// Read the item's password from the login Keychain.
+#[cfg(target_os = "macos")]
+async fn read_keychain(item: &SecretItem) -> Result<String, String> {
+    let mut cmd = tokio::process::Command::new("security");
+    cmd.args(["find-generic-password", "-s", &item.service]);
+    if let Some(account) = &item.account {
+        cmd.args(["-a", account]);
+    }
+    let output = cmd.arg("-w").output().await.map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("No Keychain item for service '{}'", item.service));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end_matches('\n').to_string())
+}
+
+#[cfg(not(target_os = "macos"))]
+async fn read_keychain(_item: &SecretItem) -> Result<String, String> {
+    Err("Keychain items can only be read on macOS".to_string())
+}
+
+/// Secrets fetched this turn, by name. Shared by the turn's tools so the
+/// MCP proxy can swap placeholders for values; dropped with the turn.
+#[derive(Clone, Default)]
+pub struct SecretRefs {
+    items: Arc<Vec<SecretItem>>,
+    values: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl SecretRefs {
+    pub fn new(items: Vec<SecretItem>) -> Self {
+        Self { items: Arc::new(items), values: Default::default() }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.items.is_empty()
+    }
+
+    fn item(&self, name: &str) -> Option<&SecretItem> {
+        self.items.iter().find(|i| i.name == name)
+    }
+
+    /// Whether `args` carry any placeholder.
+    pub fn mentions(args: &Value) -> bool {
+        args.to_string().contains(PLACEHOLDER_PREFIX)
+    }
+
+    /// Replace every placeholder in `args` by its value, for `tool_name`.
+    /// Fails on a secret that wasn't fetched this turn or that this tool may
+    /// not receive; `args` is then left as it was.
+    pub fn inject(&self, tool_name: &str, args: &mut JsonObject) -> Result<Vec<String>, String> {
+        let values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        let mut used = Vec::new();
+        let mut injected = args.clone();
+        for value in injected.values_mut() {
+            substitute(value, &mut |name| {
+                let item = self.item(name).ok_or_else(|| format!("Unknown secret '{}'", name))?;
+                if !item.allows(tool_name) {
+                    return Err(format!("Secret '{}' may not be passed to {}", name, tool_name));
+                }
+                let secret = values
+                    .get(name)
+                    .ok_or_else(|| format!("Secret '{}' was not fetched with get_secret", name))?;
+                if !used.iter().any(|u| u == name) {
+                    used.push(name.to_string());
+                }
+                Ok(secret.clone())
+            })?;
+        }
+        *args = injected;
+        Ok(used)
+    }
+
+    /// Put the placeholders back wherever a value shows up in a tool result
+    /// (an API echoing the token, an error quoting the request).
+    pub fn scrub(&self, result: &mut CallToolResult) {
+        for content in result.content.iter_mut() {
+            if let RawContent::Text(text) = &mut content.raw {
+                text.text = self.scrub_text(&text.text);
+            }
+        }
+    }
+
+    pub fn scrub_text(&self, text: &str) -> String {
+        let values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        values
+            .iter()
+            .filter(|(_, secret)| !secret.is_empty())
+            .fold(text.to_string(), |text, (name, secret)| text.replace(secret.as_str(), &placeholder(name)))
+    }
+}
+
+/// Rewrite the placeholders inside every string of `value` with `resolve`.
+fn substitute(value: &mut Value, resolve: &mut dyn FnMut(&str) -> Result<String, String>) -> Result<(), String> {
+    match value {
+        Value::String(s) if s.contains(PLACEHOLDER_PREFIX) => {
+            let mut out = String::with_capacity(s.len());
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find(PLACEHOLDER_PREFIX) {
+                let after = &rest[start + PLACEHOLDER_PREFIX.len()..];
+                let Some(end) = after.find(PLACEHOLDER_SUFFIX) else {
+                    break;
+                };
+                out.push_str(&rest[..start]);
+                out.push_str(&resolve(&after[..end])?);
+                rest = &after[end + PLACEHOLDER_SUFFIX.len()..];
+            }
+            out.push_str(rest);
+            *s = out;
+        }
+        Value::Array(items) => {
+            for item in items {
+                substitute(item, resolve)?;
+            }
+        }
+        Value::Object(map) => {
+            for item in map.values_mut() {
+                substitute(item, resolve)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `get_secret`: fetch an allowed Keychain item for later tool calls. The
+/// model only ever sees a placeholder; the proxy substitutes the value when
+/// the placeholder is passed to a tool.
+pub struct GetSecret {
+    pub tx: ToolEventSender,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GetSecretArgs {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GetSecretOutput {
+    pub placeholder: String,
+    pub note: String,
+}
+
+impl Tool for GetSecret {
+    const NAME: &'static str = "get_secret";
+    type Args = GetSecretArgs;
+    type Output = GetSecretOutput;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let refs = self.tx.secret_refs();
+        let names: Vec<String> = refs
+            .items
+            .iter()
+            .map(|i| {
+                if i.tools.is_empty() {
+                    i.name.clone()
+                } else {
+                    format!("{} (for {})", i.name, i.tools.join(", "))
+                }
+            })
+            .collect();
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: format!(
+                "Fetch a credential the user stored in their Keychain, to pass to another tool. You get a \
+                 placeholder such as {{{{secret:name}}}}, never the value: put the placeholder in the other \
+                 tool's arguments and it is replaced when that tool runs. Never try to reveal the value. \
+                 Available: {}.",
+                names.join(", ")
+            ),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "One of the available secret names" }
+                },
+                "required": ["name"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let refs = self.tx.secret_refs();
+        let item = refs
+            .item(&args.name)
+            .cloned()
+            .ok_or_else(|| ToolError::CommandFailed(format!("'{}' is not a secret the user made available", args.name)))?;
+        let value = read_keychain(&item).await.map_err(ToolError::CommandFailed)?;
+        refs.values.lock().unwrap_or_else(|e| e.into_inner()).insert(item.name.clone(), value);
+        println!("🔑 Fetched secret '{}' for this turn", item.name);
+        Ok(GetSecretOutput {
+            placeholder: placeholder(&item.name),
+            note: if item.tools.is_empty() {
+                "Pass the placeholder in another tool's arguments.".to_string()
+            } else {
+                format!("Pass the placeholder to one of: {}.", item.tools.join(", "))
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refs() -> SecretRefs {
+        let item = |name: &str, tools: &[&str]| SecretItem {
+            name: name.to_string(),
+            service: format!("{}.example.com", name),
+            account: None,
+            tools: tools.iter().map(|t| t.to_string()).collect(),
+        };
+        let refs = SecretRefs::new(vec![item("jira", &["jira"]), item("any", &[])]);
+        let mut values = refs.values.lock().unwrap();
+        values.insert("jira".to_string(), "tok-123".to_string());
+        values.insert("any".to_string(), "pw-456".to_string());
+        drop(values);
+        refs
+    }
+
+    fn args(value: Value) -> JsonObject {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn substitute_rewrites_nested_strings() {
+        let mut value = json!({"auth": "Bearer {{secret:a}}", "list": ["{{secret:b}}-{{secret:a}}", 7], "open": "{{secret:"});
+        substitute(&mut value, &mut |name| Ok(name.to_uppercase())).unwrap();
+        assert_eq!(value, json!({"auth": "Bearer A", "list": ["B-A", 7], "open": "{{secret:"}));
+    }
+
+    #[test]
+    fn inject_fills_allowed_tools_and_reports_used_secrets() {
+        let refs = refs();
+        let mut call = args(json!({"token": "{{secret:jira}}", "note": "{{secret:any}} {{secret:jira}}"}));
+        let mut used = refs.inject("jira_create_issue", &mut call).unwrap();
+        used.sort();
+        assert_eq!(used, vec!["any", "jira"]);
+        assert_eq!(call, args(json!({"token": "tok-123", "note": "pw-456 tok-123"})));
+    }
+
+    #[test]
+    fn inject_refuses_other_tools_and_unknown_secrets_without_touching_args() {
+        let refs = refs();
+        let original = args(json!({"token": "{{secret:jira}}", "other": "{{secret:any}}"}));
+        let mut call = original.clone();
+        assert!(refs.inject("slack_post_message", &mut call).is_err());
+        assert_eq!(call, original);
+
+        let mut call = args(json!({"token": "{{secret:github}}"}));
+        assert!(refs.inject("github_create_issue", &mut call).unwrap_err().contains("Unknown secret"));
+    }
+
+    #[test]
+    fn scrub_text_puts_placeholders_back() {
+        let refs = refs();
+        assert_eq!(
+            refs.scrub_text("401 for token tok-123 (password pw-456)"),
+            "401 for token {{secret:jira}} (password {{secret:any}})"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// Keychain service the secrets are filed under on macOS, one generic
+/// password per secret name.
+#[cfg(target_os = "macos")]
+const KEYCHAIN_SERVICE: &str = "ai.rong-e.agent-server.secrets";
+/// Longest secret name accepted.
+const MAX_NAME_LEN: usize = 64;
+
+/// The secret that, once set, every HTTP and WebSocket request must present.
+pub const WS_AUTH: &str = "ws_auth";
+/// A second connection secret for guest and kiosk windows: requests
+/// presenting it get guest sessions (`session::session_for`).
+pub const WS_GUEST: &str = "ws_guest";
+/// Secrets read by the integrations themselves.
+pub const GITHUB: &str = "github";
+pub const TELEGRAM: &str = "telegram";
+/// Names a secret may have besides `webhook:<name>`.
+const KNOWN: &[&str] = &[WS_AUTH, WS_GUEST, GITHUB, TELEGRAM, "slack", "notion"];
+
+/// Which secrets exist and when they were last set. Values are never written
+/// here, only to the Keychain (or the sealed fallback file).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Index {
+    updated: HashMap<String, String>,
+}
+
+fn ronge_dir() -> std::path::PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("/tmp"))
+        .join(".ronge")
+}
+
+fn index_path() -> std::path::PathBuf {
+    ronge_dir().join("secrets.json")
+}
+
+/// Values by name, loaded once at startup so lookups never touch the Keychain.
+fn values() -> &'static Mutex<HashMap<String, String>> {
+    static VALUES: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    VALUES.get_or_init(Default::default)
+}
+
+fn read_index() -> Index {
+    std::fs::read_to_string(index_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+async fn write_index(index: &Index) -> Result<(), String> {
+    tokio::fs::create_dir_all(ronge_dir()).await.map_err(|e| e.to_string())?;
+    let text = serde_json::to_string_pretty(index).map_err(|e| e.to_string())?;
+    tokio::fs::write(index_path(), text).await.map_err(|e| e.to_string())
+}
+
+/// `ws_auth`, `ws_guest`, `github`, `telegram`, `slack`, `notion` or `webhook:<name>`.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let webhook = name
+        .strip_prefix("webhook:")
+        .is_some_and(|hook| !hook.is_empty() && hook.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)));
+    if name.len() <= MAX_NAME_LEN && (KNOWN.contains(&name) || webhook) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unknown secret '{}' (use {} or webhook:<name>)",
+            name,
+            KNOWN.join(", ")
+        ))
+    }
+}
+
+fn kind(name: &str) -> &'static str {
+    match name {
+        WS_AUTH | WS_GUEST => "connection",
+        _ if name.starts_with("webhook:") => "webhook",
+        _ => "integration",
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn load_values(index: &Index) -> HashMap<String, String> {
+    index
+        .updated
+        .keys()
+        .filter_map(|name| {
+            let found = std::process::Command::new("security")
+                .args(["find-generic-password", "-s", KEYCHAIN_SERVICE, "-a", name, "-w"])
+                .output()
+                .ok()?;
+            found
+                .status
+                .success()
+                .then(|| (name.clone(), String::from_utf8_lossy(&found.stdout).trim_end_matches('\n').to_string()))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+async fn store_value(name: &str, value: Option<&str>) -> Result<(), String> {
+    let output = match value {
+        Some(value) => {
+            tokio::process::Command::new("security")
+                .args(["add-generic-password", "-U", "-s", KEYCHAIN_SERVICE, "-a", name, "-w", value])
+                .output()
+                .await
+        }
+        None => {
+            tokio::process::Command::new("security")
+                .args(["delete-generic-password", "-s", KEYCHAIN_SERVICE, "-a", name])
+                .output()
+                .await
+        }
+    }
+    .map_err(|e| e.to_string())?;
+    // Deleting an entry that is already gone is fine.
+    if output.status.success() || value.is_none() {
+        Ok(())
+    } else {
+        Err(format!("Keychain refused the secret: {}", String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// Without a Keychain the values live in `~/.ronge/secrets.sealed`,
+/// encrypted with the store key (`vault.rs`).
+#[cfg(not(target_os = "macos"))]
+fn sealed_path() -> std::path::PathBuf {
+    ronge_dir().join("secrets.sealed")
+}
+
+#[cfg(not(target_os = "macos"))]
+fn load_values(_index: &Index) -> HashMap<String, String> {
+    crate::vault::read_sealed(&sealed_path())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_os = "macos"))]
+async fn store_value(_name: &str, _value: Option<&str>) -> Result<(), String> {
+    // The cache already holds the change; write it out whole.
+    let all = values().lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let bytes = serde_json::to_vec(&all).map_err(|e| e.to_string())?;
+    crate::vault::write_sealed(&sealed_path(), &bytes).await.map_err(|e| e.to_string())
+}
+
+/// Read every stored secret into memory. Called once at startup.
+pub fn load() {
+    let loaded = load_values(&read_index());
+    if !loaded.is_empty() {
+        println!("🔐 Loaded {} secret(s)", loaded.len());
+    }
+    *values().lock().unwrap_or_else(|e| e.into_inner()) = loaded;
+}
+
+pub fn get(name: &str) -> Option<String> {
+    values()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(name)
+        .filter(|v| !v.is_empty())
+        .cloned()
+}
+
+/// Store (or with `None`, delete) a secret.
+pub async fn set(name: &str, value: Option<&str>) -> Result<(), String> {
+    validate_name(name)?;
+    let previous = {
+        let mut values = values().lock().unwrap_or_else(|e| e.into_inner());
+        match value {
+            Some(value) => values.insert(name.to_string(), value.to_string()),
+            None => values.remove(name),
+        }
+    };
+    if let Err(e) = store_value(name, value).await {
+        // Keep memory in step with what is actually stored.
+        let mut values = values().lock().unwrap_or_else(|e| e.into_inner());
+        match previous {
+            Some(previous) => values.insert(name.to_string(), previous),
+            None => values.remove(name),
+        };
+        return Err(e);
+    }
+    let mut index = read_index();
+    match value {
+        Some(_) => index.updated.insert(name.to_string(), chrono::Utc::now().to_rfc3339()),
+        None => index.updated.remove(name),
+    };
+    write_index(&index).await
+}
+
+/// Stored secrets without their values: `[{name, kind, updated_at}]`.
+pub fn list() -> Vec<Value> {
+    let mut index: Vec<(String, String)> = read_index().updated.into_iter().collect();
+    index.sort();
+    index
+        .into_iter()
+        .map(|(name, updated_at)| json!({"name": name, "kind": kind(&name), "updated_at": updated_at}))
+        .collect()
+}
+
+/// Who a request is from, decided by the token it presents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Owner,
+    /// Presented the `ws_guest` secret: guest sessions only.
+    Guest,
+}
+
+/// The role of a request presenting `token`, or `None` if it may not
+/// proceed. Without a `ws_auth` secret every request is the owner's, unless
+/// it presents the guest secret.
+pub fn role(token: Option<&str>) -> Option<Role> {
+    let matches = |name: &str| get(name).zip(token).is_some_and(|(expected, token)| same(&expected, token));
+    if matches(WS_AUTH) {
+        Some(Role::Owner)
+    } else if matches(WS_GUEST) {
+        Some(Role::Guest)
+    } else if get(WS_AUTH).is_none() {
+        Some(Role::Owner)
+    } else {
+        None
+    }
+}
+
+/// Compare in constant time so a secret can't be guessed byte by byte.
+fn same(expected: &str, token: &str) -> bool {
+    let (a, b) = (expected.as_bytes(), token.as_bytes());
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,538 @@
+use crate::state::SharedState;
+use axum::extract::ws::{Message, WebSocket};
+use futures::stream::SplitSink;
+use futures::SinkExt;
+use rig::message::{AssistantContent, Message as RigMessage, UserContent};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Frames kept per session while its client is away; older ones are dropped.
+const MAX_PENDING_FRAMES: usize = 256;
+
+/// Session IDs handed to connections made with the guest token (`ws_guest`).
+const GUEST_PREFIX: &str = "guest-";
+
+/// Archived conversations kept; the oldest are dropped first.
+const MAX_ARCHIVED_SESSIONS: usize = 50;
+
+/// A title is generated once the history holds this many messages
+/// (two user/assistant exchanges).
+pub const TITLE_AFTER_MESSAGES: usize = 4;
+
+const TITLE_PREAMBLE: &str = "You name chat conversations. Reply with a short title \
+(at most six words) for the conversation you are given. No quotes, no trailing punctuation.";
+
+/// Server-side data that outlives a single WebSocket connection.
+#[derive(Default)]
+pub struct SessionStore {
+    /// Frames that could not be delivered, keyed by session ID.
+    pending: HashMap<String, Vec<String>>,
+    /// Generated conversation titles, keyed by session ID.
+    titles: HashMap<String, String>,
+    /// Sessions that asked for PII to be masked before reaching cloud providers.
+    redact_pii: HashSet<String>,
+    /// Sessions whose Google access differs from their default (full for the
+    /// owner's sessions, `GoogleAccess::guest_default` for guests), persisted
+    /// to `~/.ronge/google_access.json`.
+    google_access: HashMap<String, crate::google_auth::GoogleAccess>,
+    /// Sessions that have multi-step requests planned and approved first (`planner.rs`).
+    planning: HashSet<String>,
+    /// Sessions that get a quick draft answer from a fast model while the main one works (`speculative.rs`).
+    speculative: HashSet<String>,
+    /// Sessions whose answers are streamed as `response_chunk` events.
+    streaming: HashSet<String>,
+    /// Agent mode names (see `modes.rs`) for sessions that switched away from the default.
+    modes: HashMap<String, String>,
+    /// Working notes written with `write_scratchpad`, cleared by `reset_session`.
+    scratchpads: HashMap<String, crate::tools::Scratchpad>,
+    /// Chat histories of HTTP `/chat` sessions, which have no connection to hold them.
+    histories: HashMap<String, Vec<RigMessage>>,
+    /// Conversations cleared by `reset_session`, oldest first.
+    archived: Vec<ArchivedSession>,
+}
+
+/// A conversation saved by `reset_session` so it can be resumed, persisted
+/// encrypted (see `vault.rs`) to `~/.ronge/session_archive.json`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ArchivedSession {
+    pub id: String,
+    pub title: Option<String>,
+    /// RFC 3339 local time of the reset.
+    pub archived_at: String,
+    pub history: Vec<RigMessage>,
+    /// The guest session it was archived from; only that session sees it.
+    /// `None` for the owner's conversations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guest_session: Option<String>,
+}
+
+impl ArchivedSession {
+    pub fn summary(&self) -> serde_json::Value {
+        let preview: String = transcript(&self.history[..self.history.len().min(1)])
+            .trim_start_matches("User: ")
+            .chars()
+            .take(100)
+            .collect();
+        json!({
+            "id": self.id,
+            "title": self.title,
+            "archived_at": self.archived_at,
+            "messages": self.history.len(),
+            "preview": preview,
+        })
+    }
+}
+
+impl SessionStore {
+    pub fn buffer(&mut self, session_id: &str, frame: String) {
+        let frames = self.pending.entry(session_id.to_string()).or_default();
+        if frames.len() >= MAX_PENDING_FRAMES {
+            frames.remove(0);
+        }
+        frames.push(frame);
+    }
+
+    /// Buffer `frame` unless it is already the last one waiting.
+    pub fn buffer_once(&mut self, session_id: &str, frame: String) {
+        if self.pending.get(session_id).and_then(|frames| frames.last()) != Some(&frame) {
+            self.buffer(session_id, frame);
+        }
+    }
+
+    pub fn take_pending(&mut self, session_id: &str) -> Vec<String> {
+        self.pending.remove(session_id).unwrap_or_default()
+    }
+
+    pub fn title(&self, session_id: &str) -> Option<&str> {
+        self.titles.get(session_id).map(|t| t.as_str())
+    }
+
+    /// Load archived conversations and Google access levels from disk.
+    pub fn load() -> Self {
+        let archived = crate::vault::read_sealed(&default_archive_path())
+            .and_then(|body| serde_json::from_slice(&body).ok())
+            .unwrap_or_default();
+        let google_access = std::fs::read_to_string(google_access_path())
+            .ok()
+            .and_then(|body| serde_json::from_str(&body).ok())
+            .unwrap_or_default();
+        Self {
+            archived,
+            google_access,
+            ..Self::default()
+        }
+    }
+
+    /// Move a cleared conversation and the session's title into the archive,
+    /// so the next conversation gets a title of its own.
+    pub fn archive(&mut self, session_id: &str, history: Vec<RigMessage>) -> ArchivedSession {
+        let entry = ArchivedSession {
+            id: new_session_id(),
+            title: self.titles.remove(session_id),
+            archived_at: chrono::Local::now().to_rfc3339(),
+            history,
+            guest_session: is_guest(session_id).then(|| session_id.to_string()),
+        };
+        if self.archived.len() >= MAX_ARCHIVED_SESSIONS {
+            self.archived.remove(0);
+        }
+        self.archived.push(entry.clone());
+        entry
+    }
+
+    /// The archived conversations `session_id` may see, newest first: all of
+    /// them for the owner, a guest's own for a guest.
+    pub fn archived<'a>(&'a self, session_id: &'a str) -> impl Iterator<Item = &'a ArchivedSession> {
+        self.archived.iter().rev().filter(move |a| visible_to(a, session_id))
+    }
+
+    /// Take an archived conversation out of the archive and make its title
+    /// the session's title again.
+    pub fn unarchive(&mut self, session_id: &str, id: &str) -> Option<ArchivedSession> {
+        let index = self.archived.iter().position(|a| a.id == id && visible_to(a, session_id))?;
+        let entry = self.archived.remove(index);
+        match &entry.title {
+            Some(title) => self.titles.insert(session_id.to_string(), title.clone()),
+            None => self.titles.remove(session_id),
+        };
+        Some(entry)
+    }
+
+    /// Drop every stored conversation: the archive, HTTP session histories
+    /// and titles. Returns how many conversations were removed.
+    pub fn purge(&mut self) -> usize {
+        let removed = self.archived.len() + self.histories.len();
+        self.archived.clear();
+        self.histories.clear();
+        self.titles.clear();
+        removed
+    }
+
+    /// What a guest may purge: its own archived conversations, history and title.
+    pub fn purge_guest(&mut self, session_id: &str) -> usize {
+        let before = self.archived.len();
+        self.archived.retain(|a| a.guest_session.as_deref() != Some(session_id));
+        self.titles.remove(session_id);
+        before - self.archived.len() + usize::from(self.histories.remove(session_id).is_some())
+    }
+
+    /// Snapshot of the archive for `save_archive`, taken under the state lock.
+    pub fn archive_snapshot(&self) -> Vec<ArchivedSession> {
+        self.archived.clone()
+    }
+
+    pub fn set_pii_redaction(&mut self, session_id: &str, enabled: bool) {
+        if enabled {
+            self.redact_pii.insert(session_id.to_string());
+        } else {
+            self.redact_pii.remove(session_id);
+        }
+    }
+
+    pub fn redacts_pii(&self, session_id: &str) -> bool {
+        self.redact_pii.contains(session_id)
+    }
+
+    /// Set a session's Google access; returns the levels to save with
+    /// `save_google_access`.
+    pub fn set_google_access(
+        &mut self,
+        session_id: &str,
+        access: crate::google_auth::GoogleAccess,
+    ) -> HashMap<String, crate::google_auth::GoogleAccess> {
+        if access == default_google_access(session_id) {
+            self.google_access.remove(session_id);
+        } else {
+            self.google_access.insert(session_id.to_string(), access);
+        }
+        self.google_access.clone()
+    }
+
+    /// Decided by the server: what the owner set for the session, else full
+    /// access for the owner's sessions and the guest default for guests.
+    pub fn google_access(&self, session_id: &str) -> crate::google_auth::GoogleAccess {
+        self.google_access
+            .get(session_id)
+            .copied()
+            .unwrap_or_else(|| default_google_access(session_id))
+    }
+
+    pub fn set_planning(&mut self, session_id: &str, enabled: bool) {
+        if enabled {
+            self.planning.insert(session_id.to_string());
+        } else {
+            self.planning.remove(session_id);
+        }
+    }
+
+    pub fn plans(&self, session_id: &str) -> bool {
+        self.planning.contains(session_id)
+    }
+
+    pub fn set_speculative(&mut self, session_id: &str, enabled: bool) {
+        if enabled {
+            self.speculative.insert(session_id.to_string());
+        } else {
+            self.speculative.remove(session_id);
+        }
+    }
+
+    pub fn drafts(&self, session_id: &str) -> bool {
+        self.speculative.contains(session_id)
+    }
+
+    pub fn set_streaming(&mut self, session_id: &str, enabled: bool) {
+        if enabled {
+            self.streaming.insert(session_id.to_string());
+        } else {
+            self.streaming.remove(session_id);
+        }
+    }
+
+    pub fn streams(&self, session_id: &str) -> bool {
+        self.streaming.contains(session_id)
+    }
+
+    pub fn set_mode(&mut self, session_id: &str, mode: &str) {
+        if mode == crate::modes::DEFAULT_MODE {
+            self.modes.remove(session_id);
+        } else {
+            self.modes.insert(session_id.to_string(), mode.to_string());
+        }
+    }
+
+    pub fn mode(&self, session_id: &str) -> &'static crate::modes::AgentMode {
+        crate::modes::resolve(self.modes.get(session_id).map(|m| m.as_str()))
+    }
+
+    pub fn scratchpad(&mut self, session_id: &str) -> crate::tools::Scratchpad {
+        self.scratchpads.entry(session_id.to_string()).or_default().clone()
+    }
+
+    pub fn clear_scratchpad(&mut self, session_id: &str) {
+        self.scratchpads.remove(session_id);
+    }
+
+    pub fn take_history(&mut self, session_id: &str) -> Vec<RigMessage> {
+        self.histories.remove(session_id).unwrap_or_default()
+    }
+
+    pub fn store_history(&mut self, session_id: &str, history: Vec<RigMessage>) {
+        self.histories.insert(session_id.to_string(), history);
+    }
+}
+
+/// Generate a title for the session in the background with the current
+/// provider and broadcast it as a `session_title` event. Does nothing if the
+/// session already has one.
+pub fn spawn_title_generation(state: SharedState, session_id: String, history: &[RigMessage]) {
+    let transcript = transcript(history);
+    tokio::spawn(async move {
+        let (provider, model, api_key, limiter, redact_pii) = {
+            let s = state.lock().await;
+            if s.sessions.title(&session_id).is_some() {
+                return;
+            }
+            (
+                s.current_provider.clone(),
+                s.current_model.clone(),
+                s.api_keys.get(&s.current_provider).cloned().unwrap_or_default(),
+                s.llm_limiter.handle(),
+                s.sessions.redacts_pii(&session_id) && !matches!(s.current_provider.as_str(), "ollama" | "mock"),
+            )
+        };
+        // Masked like the turns themselves before it goes to the provider.
+        let transcript = if redact_pii { crate::pii::redact(&transcript) } else { transcript };
+        let _permit = limiter.acquire().await;
+        let title = match crate::llm::complete(&provider, &api_key, &model, TITLE_PREAMBLE, &transcript).await {
+            Ok(t) => t.trim().trim_matches('"').trim_end_matches('.').to_string(),
+            Err(e) => {
+                println!("⚠️ Title generation failed for session {}: {}", session_id, e);
+                return;
+            }
+        };
+        if title.is_empty() {
+            return;
+        }
+        let mut s = state.lock().await;
+        s.sessions.titles.insert(session_id.clone(), title.clone());
+        let _ = s.notifier.send(json!({
+            "type": "session_title",
+            "content": {"session_id": session_id, "title": title}
+        }));
+    });
+}
+
+/// Plain-text rendering of the history for small background prompts.
+pub fn transcript(history: &[RigMessage]) -> String {
+    const MAX_CHARS_PER_MESSAGE: usize = 500;
+    let mut lines = Vec::new();
+    for message in history {
+        // `if let` rather than `match`: only user/assistant turns are rendered.
+        let (role, text) = if let RigMessage::User { content } = message {
+            let parts: Vec<&str> = content
+                .iter()
+                .filter_map(|c| match c {
+                    UserContent::Text(t) => Some(t.text.as_str()),
+                    _ => None,
+                })
+                .collect();
+            ("User", parts.join(" "))
+        } else if let RigMessage::Assistant { content, .. } = message {
+            let parts: Vec<&str> = content
+                .iter()
+                .filter_map(|c| match c {
+                    AssistantContent::Text(t) => Some(t.text.as_str()),
+                    _ => None,
+                })
+                .collect();
+            ("Assistant", parts.join(" "))
+        } else {
+            continue;
+        };
+        let text: String = text.chars().take(MAX_CHARS_PER_MESSAGE).collect();
+        lines.push(format!("{}: {}", role, text));
+    }
+    lines.join("\n")
+}
+
+pub async fn save_archive(archived: &[ArchivedSession]) -> std::io::Result<()> {
+    let body = serde_json::to_vec(archived).unwrap_or_else(|_| b"[]".to_vec());
+    crate::vault::write_sealed(&default_archive_path(), &body).await
+}
+
+pub async fn save_google_access(
+    levels: &HashMap<String, crate::google_auth::GoogleAccess>,
+) -> std::io::Result<()> {
+    let path = google_access_path();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let body = serde_json::to_string_pretty(levels).unwrap_or_else(|_| "{}".to_string());
+    tokio::fs::write(&path, body).await
+}
+
+fn google_access_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("google_access.json")
+}
+
+fn default_google_access(session_id: &str) -> crate::google_auth::GoogleAccess {
+    if is_guest(session_id) {
+        crate::google_auth::GoogleAccess::guest_default()
+    } else {
+        crate::google_auth::GoogleAccess::Full
+    }
+}
+
+fn visible_to(archived: &ArchivedSession, session_id: &str) -> bool {
+    !is_guest(session_id) || archived.guest_session.as_deref() == Some(session_id)
+}
+
+/// Remove the archive file (and a copy set aside as unreadable).
+pub async fn delete_archive() -> std::io::Result<()> {
+    let path = default_archive_path();
+    let _ = tokio::fs::remove_file(path.with_extension("unreadable")).await;
+    match tokio::fs::remove_file(&path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn default_archive_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("session_archive.json")
+}
+
+/// Generate a new session ID (hex, 16 random bytes).
+pub fn new_session_id() -> String {
+    use rand::RngCore;
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The session a connection gets: the one it asked for, except that a
+/// guest can only continue a guest session, so it can't take over one of
+/// the owner's.
+pub fn session_for(role: crate::secrets::Role, requested: Option<&str>) -> String {
+    let requested = requested.filter(|id| !id.is_empty());
+    match role {
+        crate::secrets::Role::Owner => requested.map(str::to_string).unwrap_or_else(new_session_id),
+        crate::secrets::Role::Guest => match requested.filter(|id| is_guest(id)) {
+            Some(id) => id.to_string(),
+            None => format!("{}{}", GUEST_PREFIX, new_session_id()),
+        },
+    }
+}
+
+/// Whether the session belongs to a guest connection.
+pub fn is_guest(session_id: &str) -> bool {
+    session_id.starts_with(GUEST_PREFIX)
+}
+
+/// Whether a broadcast event goes to `session_id`: the owner's sessions get
+/// every event, a guest only those naming its own session.
+pub fn receives(session_id: &str, event: &serde_json::Value) -> bool {
+    !is_guest(session_id) || event["content"]["session_id"].as_str() == Some(session_id)
+}
+
+/// Where a client's frames go: a WebSocket, or a channel read by a
+/// non-WebSocket frontend (Telegram, HTTP).
+enum Sink {
+    Socket(SplitSink<WebSocket, Message>),
+    Channel(tokio::sync::mpsc::UnboundedSender<Message>),
+}
+
+impl Sink {
+    async fn send(&mut self, msg: Message) -> Result<(), String> {
+        match self {
+            Sink::Socket(sink) => sink.send(msg).await.map_err(|e| e.to_string()),
+            Sink::Channel(tx) => tx.send(msg).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Outgoing half of a client connection.
+///
+/// Once a send fails (client briefly disconnected) the socket is considered
+/// gone and every later frame of the running turn — tool events and the final
+/// answer included — is buffered under the session ID instead of being lost.
+/// The buffer is flushed when a client reconnects with `?session_id=<id>`.
+/// Binary audio frames are not kept (they would push the answer out of the
+/// buffer); a single `speech_dropped` frame stands in for them.
+pub struct ClientSender {
+    sink: Option<Sink>,
+    session_id: String,
+    state: SharedState,
+    /// Limits on this connection's config messages.
+    pub limits: crate::rate_limit::RateLimiter,
+}
+
+impl ClientSender {
+    pub fn new(sink: SplitSink<WebSocket, Message>, session_id: String, state: SharedState) -> Self {
+        Self {
+            sink: Some(Sink::Socket(sink)),
+            session_id,
+            state,
+            limits: Default::default(),
+        }
+    }
+
+    /// A sender whose frames are delivered on the returned channel.
+    pub fn channel(
+        session_id: String,
+        state: SharedState,
+    ) -> (Self, tokio::sync::mpsc::UnboundedReceiver<Message>) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let sender = Self {
+            sink: Some(Sink::Channel(tx)),
+            session_id,
+            state,
+            limits: Default::default(),
+        };
+        (sender, rx)
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub async fn send(&mut self, msg: Message) -> Result<(), axum::Error> {
+        if let Some(sink) = self.sink.as_mut() {
+            match sink.send(msg.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    println!("⚠️ Send failed for session {}, buffering: {}", self.session_id, e);
+                    self.sink = None;
+                }
+            }
+        }
+        match msg {
+            Message::Text(frame) => self.state.lock().await.sessions.buffer(&self.session_id, frame),
+            Message::Binary(_) => {
+                let marker = json!({"type": "speech_dropped", "content": {}}).to_string();
+                self.state.lock().await.sessions.buffer_once(&self.session_id, marker);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Deliver frames buffered while this session was disconnected.
+    pub async fn flush_pending(&mut self) {
+        let frames = self.state.lock().await.sessions.take_pending(&self.session_id);
+        if !frames.is_empty() {
+            println!("📬 Flushing {} buffered frames to session {}", frames.len(), self.session_id);
+        }
+        for frame in frames {
+            let _ = self.send(Message::Text(frame)).await;
+        }
+    }
+}
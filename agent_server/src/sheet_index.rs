@@ -0,0 +1,401 @@
+use crate::sheets::SpreadsheetConfig;
+use crate::state::{McpToolSet, SharedState};
+use rmcp::{
+    ServerHandler,
+    model::{CallToolRequestParam, CallToolResult, Content, ErrorData, ListToolsResult, PaginatedRequestParam},
+    service::{RequestContext, RoleServer},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub const SEARCH_INDEX: &str = "sheets_search_index";
+
+/// Snapshots older than this are reported as stale; `RONGE_SHEET_INDEX_MAX_AGE_HOURS` overrides it.
+const DEFAULT_MAX_AGE_HOURS: i64 = 24;
+const DEFAULT_TOP_K: usize = 8;
+const MAX_TOP_K: usize = 30;
+/// Rows embedded per request (Gemini's batch limit).
+const EMBED_BATCH: usize = 100;
+/// Larger sheets are cut at this many data rows.
+const MAX_ROWS: usize = 20_000;
+
+/// A registered spreadsheet's rows and their embeddings, persisted to
+/// `~/.ronge/sheet_index/<spreadsheet_id>.json`. Questions are answered by
+/// retrieval over this snapshot instead of reading thousands of cells per turn.
+#[derive(Serialize, Deserialize)]
+pub struct SheetIndex {
+    pub spreadsheet_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub tab: Option<String>,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub embeddings: Vec<Vec<f32>>,
+    /// Embedding provider and model; questions are embedded with the same ones.
+    pub provider: String,
+    pub model: String,
+    pub indexed_at: String,
+}
+
+impl SheetIndex {
+    fn age_hours(&self) -> Option<i64> {
+        let indexed = chrono::DateTime::parse_from_rfc3339(&self.indexed_at).ok()?;
+        Some((chrono::Local::now().fixed_offset() - indexed).num_hours())
+    }
+
+    fn is_stale(&self) -> bool {
+        let max_age = std::env::var("RONGE_SHEET_INDEX_MAX_AGE_HOURS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_AGE_HOURS);
+        self.age_hours().is_none_or(|age| age >= max_age)
+    }
+
+    pub fn summary(&self) -> Value {
+        json!({
+            "spreadsheet_id": self.spreadsheet_id,
+            "name": self.name,
+            "tab": self.tab,
+            "rows": self.rows.len(),
+            "provider": self.provider,
+            "model": self.model,
+            "indexed_at": self.indexed_at,
+            "stale": self.is_stale(),
+        })
+    }
+
+    fn matches(&self, spreadsheet: &str) -> bool {
+        self.spreadsheet_id == spreadsheet || self.name.eq_ignore_ascii_case(spreadsheet)
+    }
+}
+
+/// `Header: value; Header: value` — what gets embedded for a row.
+fn row_text(headers: &[String], row: &[String]) -> String {
+    row.iter()
+        .enumerate()
+        .filter(|(_, v)| !v.is_empty())
+        .map(|(i, v)| match headers.get(i).filter(|h| !h.is_empty()) {
+            Some(h) => format!("{}: {}", h, v),
+            None => v.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn index_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("sheet_index")
+}
+
+fn index_path(spreadsheet_id: &str) -> PathBuf {
+    let safe: String = spreadsheet_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    index_dir().join(format!("{}.json", safe))
+}
+
+/// Every saved index.
+pub fn load_all() -> Vec<SheetIndex> {
+    let Ok(entries) = std::fs::read_dir(index_dir()) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+        .filter_map(|s| serde_json::from_str(&s).ok())
+        .collect()
+}
+
+async fn save(index: &SheetIndex) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(index_dir()).await?;
+    let body = serde_json::to_string(index).unwrap_or_else(|_| "{}".to_string());
+    tokio::fs::write(index_path(&index.spreadsheet_id), body).await
+}
+
+/// Delete the index of `spreadsheet_id`. Returns `false` if there was none.
+pub async fn remove(spreadsheet_id: &str) -> bool {
+    tokio::fs::remove_file(index_path(spreadsheet_id)).await.is_ok()
+}
+
+/// An embeddings endpoint: Gemini, OpenAI or a local Ollama.
+pub struct Embedder {
+    provider: String,
+    model: String,
+    api_key: String,
+}
+
+impl Embedder {
+    fn new(provider: &str, api_key: String) -> Option<Self> {
+        let default_model = match provider {
+            "gemini" => "text-embedding-004",
+            "openai" => "text-embedding-3-small",
+            "ollama" => "nomic-embed-text",
+            _ => return None,
+        };
+        Some(Self {
+            provider: provider.to_string(),
+            model: std::env::var("RONGE_EMBEDDING_MODEL").unwrap_or_else(|_| default_model.to_string()),
+            api_key,
+        })
+    }
+
+    /// The current provider when it offers embeddings and has a key,
+    /// otherwise Gemini or OpenAI, whichever has a key.
+    pub fn choose(current_provider: &str, api_keys: &HashMap<String, String>) -> Option<Self> {
+        if current_provider == "ollama" {
+            return Self::new("ollama", String::new());
+        }
+        [current_provider, "gemini", "openai"]
+            .into_iter()
+            .find_map(|p| Self::new(p, api_keys.get(p).filter(|k| !k.is_empty())?.clone()))
+    }
+
+    /// The embedder an index was built with, so question and rows share a vector space.
+    fn for_index(index: &SheetIndex, api_keys: &HashMap<String, String>) -> Result<Self, String> {
+        let api_key = api_keys.get(&index.provider).cloned().unwrap_or_default();
+        if api_key.is_empty() && index.provider != "ollama" {
+            return Err(format!(
+                "{} was indexed with {} embeddings, but no {} API key is set. Re-index it with index_spreadsheet.",
+                index.name, index.provider, index.provider
+            ));
+        }
+        let mut embedder = Self::new(&index.provider, api_key)
+            .ok_or_else(|| format!("Unknown embedding provider {}", index.provider))?;
+        embedder.model = index.model.clone();
+        Ok(embedder)
+    }
+
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH) {
+            vectors.extend(self.embed_batch(batch).await?);
+        }
+        Ok(vectors)
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let client = reqwest::Client::new();
+        let request = match self.provider.as_str() {
+            "gemini" => client
+                .post(format!(
+                    "https://generativelanguage.googleapis.com/v1beta/models/{}:batchEmbedContents",
+                    self.model
+                ))
+                .header("x-goog-api-key", &self.api_key)
+                .json(&json!({
+                    "requests": texts.iter().map(|t| json!({
+                        "model": format!("models/{}", self.model),
+                        "content": {"parts": [{"text": t}]},
+                    })).collect::<Vec<_>>()
+                })),
+            "openai" => client
+                .post("https://api.openai.com/v1/embeddings")
+                .bearer_auth(&self.api_key)
+                .json(&json!({"model": self.model, "input": texts})),
+            _ => {
+                let host = std::env::var("OLLAMA_HOST").unwrap_or_else(|_| "127.0.0.1:11434".to_string());
+                let host = if host.starts_with("http") { host } else { format!("http://{}", host) };
+                client
+                    .post(format!("{}/api/embed", host.trim_end_matches('/')))
+                    .json(&json!({"model": self.model, "input": texts}))
+            }
+        };
+        let resp = request.send().await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(format!("{} embeddings returned {}: {}", self.provider, status, body));
+        }
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        let vectors: Vec<&Value> = match self.provider.as_str() {
+            "gemini" => body["embeddings"].as_array().into_iter().flatten().map(|e| &e["values"]).collect(),
+            "openai" => body["data"].as_array().into_iter().flatten().map(|e| &e["embedding"]).collect(),
+            _ => body["embeddings"].as_array().into_iter().flatten().collect(),
+        };
+        if vectors.len() != texts.len() {
+            return Err(format!(
+                "{} returned {} embeddings for {} texts",
+                self.provider,
+                vectors.len(),
+                texts.len()
+            ));
+        }
+        Ok(vectors
+            .into_iter()
+            .map(|v| v.as_array().into_iter().flatten().filter_map(|x| x.as_f64()).map(|x| x as f32).collect())
+            .collect())
+    }
+}
+
+pub(crate) fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 { 0.0 } else { dot / denom }
+}
+
+/// Snapshot `sheet` with the connected Sheets tools, embed its rows and save
+/// the index (replacing an older one).
+pub async fn build(tool_sets: &[McpToolSet], sheet: &SpreadsheetConfig, embedder: &Embedder) -> Result<SheetIndex, String> {
+    let reader = crate::sheets::find_tool(tool_sets, crate::sheets::READ_FRAGMENTS, true)
+        .ok_or("No Google Sheets tools are connected to read the sheet.")?;
+    let range = match &sheet.tab {
+        Some(tab) => format!("'{}'", tab.replace('\'', "''")),
+        None => "A:ZZ".to_string(),
+    };
+    let mut rows = crate::sheets::value_rows(&reader.call(&sheet.spreadsheet_id, &range, Default::default()).await?)
+        .into_iter();
+    let headers = rows.next().ok_or_else(|| format!("{} is empty", sheet.name))?;
+    let rows: Vec<Vec<String>> = rows.filter(|r| r.iter().any(|c| !c.is_empty())).take(MAX_ROWS).collect();
+    if rows.is_empty() {
+        return Err(format!("{} has no rows below the header", sheet.name));
+    }
+
+    println!("🧮 Embedding {} rows of {} with {}", rows.len(), sheet.name, embedder.provider);
+    let texts: Vec<String> = rows.iter().map(|r| row_text(&headers, r)).collect();
+    let embeddings = embedder.embed(&texts).await?;
+    let index = SheetIndex {
+        spreadsheet_id: sheet.spreadsheet_id.clone(),
+        name: sheet.name.clone(),
+        tab: sheet.tab.clone(),
+        headers,
+        rows,
+        embeddings,
+        provider: embedder.provider.clone(),
+        model: embedder.model.clone(),
+        indexed_at: chrono::Local::now().to_rfc3339(),
+    };
+    save(&index).await.map_err(|e| format!("Could not save the index: {}", e))?;
+    Ok(index)
+}
+
+/// `sheets_search_index`, served while at least one index exists.
+struct IndexServer {
+    state: SharedState,
+}
+
+impl IndexServer {
+    fn definition() -> rmcp::model::Tool {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spreadsheet": {"type": "string", "description": "Indexed spreadsheet name (e.g. \"Expenses\") or spreadsheet ID"},
+                "question": {"type": "string", "description": "What to look for, in natural language"},
+                "top_k": {"type": "integer", "description": "Rows to return (default 8, max 30)"}
+            },
+            "required": ["spreadsheet", "question"]
+        });
+        let indexed: Vec<String> = load_all().into_iter().map(|i| i.name).collect();
+        rmcp::model::Tool::new(
+            SEARCH_INDEX,
+            format!(
+                "Find the rows of a large indexed Google Sheet most relevant to a question, from a \
+                 snapshot instead of reading the whole sheet. Prefer it over reading ranges for \
+                 lookups in these sheets: {}. The result says when the snapshot is stale; for exact \
+                 current totals over every row, read the sheet itself.",
+                indexed.join(", ")
+            ),
+            Arc::new(schema.as_object().cloned().unwrap_or_default()),
+        )
+    }
+
+    async fn search(&self, args: &serde_json::Map<String, Value>) -> Result<Value, String> {
+        let spreadsheet = args.get("spreadsheet").and_then(|v| v.as_str()).unwrap_or("").trim();
+        let question = args
+            .get("question")
+            .and_then(|v| v.as_str())
+            .filter(|q| !q.trim().is_empty())
+            .ok_or("`question` is required")?;
+        let top_k = args
+            .get("top_k")
+            .and_then(|v| v.as_u64())
+            .map(|k| (k as usize).clamp(1, MAX_TOP_K))
+            .unwrap_or(DEFAULT_TOP_K);
+        let index = load_all()
+            .into_iter()
+            .find(|i| i.matches(spreadsheet))
+            .ok_or_else(|| format!("{} is not indexed. Read the sheet instead.", spreadsheet))?;
+
+        let api_keys = self.state.lock().await.api_keys.clone();
+        let embedder = Embedder::for_index(&index, &api_keys)?;
+        let query = embedder.embed(&[question.to_string()]).await?;
+        let query = query.first().ok_or("No embedding returned for the question")?;
+
+        let mut scored: Vec<(usize, f32)> =
+            index.embeddings.iter().enumerate().map(|(i, e)| (i, cosine(query, e))).collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let matches: Vec<Value> = scored
+            .into_iter()
+            .take(top_k)
+            .map(|(i, score)| {
+                let values: serde_json::Map<String, Value> = index
+                    .headers
+                    .iter()
+                    .zip(&index.rows[i])
+                    .map(|(h, v)| (h.clone(), json!(v)))
+                    .collect();
+                // Header is row 1, so data row i is sheet row i + 2.
+                json!({"row": i + 2, "score": (score * 1000.0).round() / 1000.0, "values": values})
+            })
+            .collect();
+
+        let mut result = json!({
+            "spreadsheet": index.name,
+            "tab": index.tab,
+            "indexed_at": index.indexed_at,
+            "stale": index.is_stale(),
+            "rows_indexed": index.rows.len(),
+            "matches": matches,
+        });
+        if index.is_stale() {
+            result["note"] = json!(format!(
+                "This snapshot is {} hours old. Mention that the figures may be out of date and that the user can refresh the index.",
+                index.age_hours().unwrap_or_default()
+            ));
+        }
+        Ok(result)
+    }
+}
+
+impl ServerHandler for IndexServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(vec![Self::definition()]))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        if request.name != SEARCH_INDEX {
+            return Err(ErrorData::invalid_params(format!("Unknown tool {}", request.name), None));
+        }
+        Ok(match self.search(&request.arguments.unwrap_or_default()).await {
+            Ok(result) => CallToolResult::success(vec![Content::text(result.to_string())]),
+            Err(e) => CallToolResult::error(vec![Content::text(e)]),
+        })
+    }
+}
+
+/// (Re)connect `sheets_search_index` to match the saved indexes: served while
+/// any exist, so its description lists them, and dropped when none are left.
+pub async fn refresh_server(state: SharedState) {
+    if load_all().is_empty() {
+        state.lock().await.sheet_index = None;
+        return;
+    }
+    match crate::mcp_proxy::connect_in_process(IndexServer { state: state.clone() }).await {
+        Ok(conn) => state.lock().await.sheet_index = Some(conn),
+        Err(e) => println!("❌ Failed to start {}: {}", SEARCH_INDEX, e),
+    }
+}
@@ -0,0 +1,230 @@
+use crate::state::SharedState;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::PathBuf;
+
+/// How often the watch loop looks for watches that are due a check.
+const POLL_INTERVAL_SECS: u64 = 60;
+/// Default minutes between two reads of a watched range.
+pub const DEFAULT_INTERVAL_MINUTES: u32 = 15;
+/// The agent replies with this when a change does not meet the watch's condition.
+const NO_ALERT: &str = "NO_ALERT";
+/// Changed cells listed in the prompt; the rest are counted.
+const MAX_LISTED_CHANGES: usize = 50;
+
+/// A sheet range checked on a schedule: when its values change, the agent
+/// runs `prompt` with the changes, e.g. "when the budget total exceeds 5000,
+/// alert me".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SheetWatch {
+    pub id: String,
+    pub name: String,
+    pub spreadsheet_id: String,
+    /// A1 range, e.g. `Budget!B2:F40`.
+    pub range: String,
+    pub prompt: String,
+    #[serde(default = "default_interval")]
+    pub interval_minutes: u32,
+    pub created_at: i64,
+    #[serde(default)]
+    pub last_checked: Option<i64>,
+    /// Values at the last check; the first check only records them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_values: Option<Vec<Vec<String>>>,
+    /// Google access of the session that created it; the runs get no more.
+    #[serde(default)]
+    pub google_access: crate::google_auth::GoogleAccess,
+}
+
+fn default_interval() -> u32 {
+    DEFAULT_INTERVAL_MINUTES
+}
+
+impl SheetWatch {
+    fn is_due(&self, now: i64) -> bool {
+        self.last_checked
+            .is_none_or(|last| now - last >= i64::from(self.interval_minutes.max(1)) * 60)
+    }
+
+    /// The watch without its cached values, for listings.
+    pub fn summary(&self) -> SheetWatch {
+        SheetWatch { last_values: None, ..self.clone() }
+    }
+}
+
+pub fn default_watches_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("sheet_watches.json")
+}
+
+pub fn load_watches() -> Vec<SheetWatch> {
+    std::fs::read_to_string(default_watches_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub async fn save_watches(watches: &[SheetWatch]) -> std::io::Result<()> {
+    let path = default_watches_path();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let body = serde_json::to_string_pretty(watches).unwrap_or_else(|_| "[]".to_string());
+    tokio::fs::write(&path, body).await
+}
+
+/// Column letters → 0-based index (`A` → 0, `AA` → 26).
+fn column_index(letters: &str) -> usize {
+    letters
+        .chars()
+        .fold(0, |n, c| n * 26 + (c.to_ascii_uppercase() as usize - 'A' as usize + 1))
+        .saturating_sub(1)
+}
+
+fn column_letters(mut index: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push((b'A' + (index % 26) as u8) as char);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    letters.iter().rev().collect()
+}
+
+/// Top-left cell of an A1 range as (column, row), both 0-based.
+fn range_origin(range: &str) -> (usize, usize) {
+    let cells = range.rsplit('!').next().unwrap_or(range);
+    let start = cells.split(':').next().unwrap_or("");
+    let letters: String = start.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+    let digits: String = start.chars().skip(letters.len()).take_while(|c| c.is_ascii_digit()).collect();
+    let column = if letters.is_empty() { 0 } else { column_index(&letters) };
+    let row = digits.parse::<usize>().map(|r| r.saturating_sub(1)).unwrap_or(0);
+    (column, row)
+}
+
+/// `B3: 4200 → 5100` for every cell that differs between the two reads.
+fn changed_cells(range: &str, before: &[Vec<String>], after: &[Vec<String>]) -> Vec<String> {
+    let (column, row) = range_origin(range);
+    let empty = String::new();
+    let mut changes = Vec::new();
+    for r in 0..before.len().max(after.len()) {
+        let (old, new) = (before.get(r), after.get(r));
+        let width = old.map_or(0, Vec::len).max(new.map_or(0, Vec::len));
+        for c in 0..width {
+            let old_value = old.and_then(|o| o.get(c)).unwrap_or(&empty);
+            let new_value = new.and_then(|n| n.get(c)).unwrap_or(&empty);
+            if old_value != new_value {
+                changes.push(format!(
+                    "{}{}: {} → {}",
+                    column_letters(column + c),
+                    row + r + 1,
+                    if old_value.is_empty() { "(empty)" } else { old_value },
+                    if new_value.is_empty() { "(empty)" } else { new_value },
+                ));
+            }
+        }
+    }
+    changes
+}
+
+/// Spawn the background task that reads each watched range when it is due
+/// and runs the watch's prompt when the values changed. A `sheet_watch_result`
+/// event is broadcast unless the agent decides the change is not worth an alert.
+pub fn spawn(state: SharedState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(POLL_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            let now = chrono::Utc::now().timestamp();
+            let (due, tool_sets) = {
+                let s = state.lock().await;
+                let due: Vec<SheetWatch> = s.sheet_watches.iter().filter(|w| w.is_due(now)).cloned().collect();
+                (due, s.all_mcp_tools())
+            };
+
+            for watch in due {
+                // Read with no more access than the watch's creator had.
+                let readable = watch.google_access.filter(tool_sets.clone());
+                let Some(reader) = crate::sheets::find_tool(&readable, crate::sheets::READ_FRAGMENTS, true) else {
+                    // Sheets tools not connected (yet) or not allowed; check again next tick.
+                    continue;
+                };
+                let values = match reader.call(&watch.spreadsheet_id, &watch.range, Default::default()).await {
+                    Ok(value) => crate::sheets::value_rows(&value),
+                    Err(e) => {
+                        println!("⚠️ Sheet watch '{}' could not read {}: {}", watch.name, watch.range, e);
+                        continue;
+                    }
+                };
+                let previous = {
+                    let mut s = state.lock().await;
+                    let Some(stored) = s.sheet_watches.iter_mut().find(|w| w.id == watch.id) else {
+                        continue;
+                    };
+                    stored.last_checked = Some(now);
+                    let previous = stored.last_values.replace(values.clone());
+                    let watches = s.sheet_watches.clone();
+                    drop(s);
+                    if let Err(e) = save_watches(&watches).await {
+                        println!("❌ Failed to save sheet watches: {}", e);
+                    }
+                    previous
+                };
+                let Some(previous) = previous else {
+                    println!("👀 Sheet watch '{}' recorded its baseline", watch.name);
+                    continue;
+                };
+                let changes = changed_cells(&watch.range, &previous, &values);
+                if changes.is_empty() {
+                    continue;
+                }
+                println!("📈 Sheet watch '{}' saw {} changed cell(s)", watch.name, changes.len());
+                let state = state.clone();
+                tokio::spawn(async move { run_watch(&state, &watch, &values, &changes).await });
+            }
+        }
+    });
+}
+
+async fn run_watch(state: &SharedState, watch: &SheetWatch, values: &[Vec<String>], changes: &[String]) {
+    let mut listed = changes.iter().take(MAX_LISTED_CHANGES).cloned().collect::<Vec<_>>().join("\n");
+    if changes.len() > MAX_LISTED_CHANGES {
+        listed.push_str(&format!("\n… and {} more", changes.len() - MAX_LISTED_CHANGES));
+    }
+    let current: Vec<String> = values.iter().map(|row| row.join(" | ")).collect();
+    // Cells are whatever anyone with edit access typed.
+    let cells = crate::sanitize::sanitize_text(
+        "sheet_watch",
+        &format!("Changed cells:\n{}\n\nCurrent values:\n{}", listed, current.join("\n")),
+    );
+    let query = format!(
+        "{}\n\nThe watched range {} of spreadsheet {} changed.\n{}\n\n\
+         If the instruction above sets a condition and it is not met, reply with exactly {} and nothing else.",
+        watch.prompt,
+        watch.range,
+        watch.spreadsheet_id,
+        cells,
+        NO_ALERT
+    );
+
+    let content = match crate::logic::run_background_turn(state, query, Vec::new(), watch.google_access).await {
+        Ok(text) if text.trim() == NO_ALERT => {
+            println!("🔕 Sheet watch '{}': condition not met", watch.name);
+            return;
+        }
+        Ok(text) => json!({"watch_id": watch.id, "name": watch.name, "status": "success", "changes": changes, "text": text}),
+        Err(e) => {
+            println!("❌ Sheet watch '{}' failed: {}", watch.name, e);
+            json!({"watch_id": watch.id, "name": watch.name, "status": "error", "changes": changes, "text": e})
+        }
+    };
+    let _ = state
+        .lock()
+        .await
+        .notifier
+        .send(json!({"type": "sheet_watch_result", "content": content}));
+}
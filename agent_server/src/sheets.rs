@@ -0,0 +1,492 @@
+use crate::state::{McpConnection, McpToolSet};
+use rmcp::{
+    ServerHandler,
+    model::{CallToolRequestParam, CallToolResult, Content, ErrorData, ListToolsResult, PaginatedRequestParam},
+    service::{RequestContext, RoleServer},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+/// Name fragments of a Google Sheets MCP tool (Composio's `GOOGLESHEETS_*`, …).
+const SHEETS_FRAGMENTS: &[&str] = &["googlesheets", "google_sheets", "sheets", "spreadsheet"];
+/// Name fragments of a tool that reads a range of values.
+pub(crate) const READ_FRAGMENTS: &[&str] = &["batch_get", "values_get", "get_values", "read_range", "get_sheet_data"];
+/// Name fragments of a tool that returns a spreadsheet's metadata and tabs.
+const INFO_FRAGMENTS: &[&str] = &["spreadsheet_info", "get_spreadsheet", "spreadsheets_get", "sheet_names"];
+/// Name fragments of a tool that appends rows.
+const APPEND_FRAGMENTS: &[&str] = &["append"];
+const ID_KEYS: &[&str] = &["spreadsheet_id", "spreadsheetId", "sheet_id", "id"];
+const RANGE_KEYS: &[&str] = &["range", "ranges"];
+const VALUE_INPUT_KEYS: &[&str] = &["valueInputOption", "value_input_option"];
+
+pub const APPEND_RECORD: &str = "sheets_append_record";
+
+/// A spreadsheet the user registered with `add_spreadsheet`, persisted to
+/// `~/.ronge/spreadsheets.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpreadsheetConfig {
+    pub spreadsheet_id: String,
+    /// What the user calls it ("Expenses").
+    pub name: String,
+    /// Tab to use; the first tab when unset.
+    #[serde(default)]
+    pub tab: Option<String>,
+    /// Header row cached at the last sync.
+    #[serde(default)]
+    pub headers: Vec<String>,
+    #[serde(default)]
+    pub synced_at: Option<String>,
+}
+
+impl SpreadsheetConfig {
+    /// A1 range of the header row: `'Tab name'!1:1`, or `1:1` for the first tab.
+    fn header_range(&self) -> String {
+        match &self.tab {
+            Some(tab) => format!("'{}'!1:1", tab.replace('\'', "''")),
+            None => "1:1".to_string(),
+        }
+    }
+}
+
+pub fn default_spreadsheets_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("spreadsheets.json")
+}
+
+pub fn load_spreadsheets() -> Vec<SpreadsheetConfig> {
+    std::fs::read_to_string(default_spreadsheets_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub async fn save_spreadsheets(sheets: &[SpreadsheetConfig]) -> std::io::Result<()> {
+    let path = default_spreadsheets_path();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let body = serde_json::to_string_pretty(sheets).unwrap_or_else(|_| "[]".to_string());
+    tokio::fs::write(&path, body).await
+}
+
+/// Serializes read-modify-write of the registry file, so edits from several
+/// connections at once do not overwrite each other.
+fn registry_lock() -> &'static tokio::sync::Mutex<()> {
+    static LOCK: OnceLock<tokio::sync::Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| tokio::sync::Mutex::new(()))
+}
+
+/// The registered spreadsheet called `wanted`, by ID or (case-insensitive) name.
+pub fn find_registered(sheets: &[SpreadsheetConfig], wanted: &str) -> Option<usize> {
+    sheets
+        .iter()
+        .position(|s| s.spreadsheet_id == wanted)
+        .or_else(|| sheets.iter().position(|s| s.name.eq_ignore_ascii_case(wanted)))
+}
+
+/// Apply `change` to the registry on disk under the registry lock and save
+/// it. Returns the registry after the change.
+pub async fn modify_spreadsheets(
+    change: impl FnOnce(&mut Vec<SpreadsheetConfig>) -> Result<(), String>,
+) -> Result<Vec<SpreadsheetConfig>, String> {
+    let _guard = registry_lock().lock().await;
+    let mut sheets = load_spreadsheets();
+    change(&mut sheets)?;
+    save_spreadsheets(&sheets).await.map_err(|e| format!("Failed to save spreadsheets: {}", e))?;
+    Ok(sheets)
+}
+
+/// A connected Sheets tool with the argument names its schema uses.
+pub struct SheetsTool {
+    set: McpToolSet,
+    name: String,
+    id_key: &'static str,
+    /// `None` for tools that take only the spreadsheet ID.
+    range_key: Option<&'static str>,
+    /// How the tool takes `USER_ENTERED` (so "14.50" is stored as a number).
+    value_input_key: Option<&'static str>,
+}
+
+fn schema_key(tool: &rmcp::model::Tool, candidates: &[&'static str]) -> Option<&'static str> {
+    let properties = tool.input_schema.get("properties")?.as_object()?;
+    candidates.iter().copied().find(|k| properties.contains_key(*k))
+}
+
+/// Find a connected Sheets tool whose name contains one of `fragments` and
+/// whose schema takes a spreadsheet ID (and a range, if `with_range`).
+pub fn find_tool(tool_sets: &[McpToolSet], fragments: &[&str], with_range: bool) -> Option<SheetsTool> {
+    tool_sets.iter().find_map(|set| {
+        set.tools.iter().find_map(|tool| {
+            let lower = tool.name.to_ascii_lowercase();
+            if !SHEETS_FRAGMENTS.iter().any(|f| lower.contains(f))
+                || !fragments.iter().any(|f| lower.contains(f))
+            {
+                return None;
+            }
+            let range_key = schema_key(tool, RANGE_KEYS);
+            if with_range && range_key.is_none() {
+                return None;
+            }
+            let safe = tool.name.to_string();
+            Some(SheetsTool {
+                set: set.clone(),
+                name: set.name_map.get(&safe).cloned().unwrap_or(safe),
+                id_key: schema_key(tool, ID_KEYS)?,
+                range_key,
+                value_input_key: schema_key(tool, VALUE_INPUT_KEYS),
+            })
+        })
+    })
+}
+
+impl SheetsTool {
+    /// Call the tool for one spreadsheet and range (empty or ignored by
+    /// tools that take none), plus any extra arguments.
+    pub async fn call(
+        &self,
+        spreadsheet_id: &str,
+        range: &str,
+        extra: serde_json::Map<String, Value>,
+    ) -> Result<Value, String> {
+        let mut arguments = extra;
+        arguments.insert(self.id_key.to_string(), json!(spreadsheet_id));
+        if let Some(range_key) = self.range_key
+            && !range.is_empty()
+        {
+            let range = if range_key == "ranges" { json!([range]) } else { json!(range) };
+            arguments.insert(range_key.to_string(), range);
+        }
+        let result = self
+            .set
+            .peer
+            .call_tool(CallToolRequestParam {
+                name: Cow::Owned(self.name.clone()),
+                arguments: Some(arguments),
+                task: None,
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+        let value = serde_json::to_value(&result).unwrap_or(Value::Null);
+        if result.is_error == Some(true) {
+            return Err(format!("{} failed: {}", self.name, value));
+        }
+        Ok(value)
+    }
+}
+
+/// The first row of the first value range anywhere in a tool result.
+pub fn first_row(value: &Value) -> Option<Vec<String>> {
+    let mut row = None;
+    crate::widgets::walk(value, 0, &mut |map| {
+        if row.is_some() {
+            return;
+        }
+        if let Some(first) = map.get("values").and_then(|v| v.as_array()).and_then(|rows| rows.first()) {
+            row = first.as_array().map(|cells| {
+                cells
+                    .iter()
+                    .map(|c| c.as_str().map(str::to_string).unwrap_or_else(|| c.to_string()))
+                    .collect()
+            });
+        }
+    });
+    row
+}
+
+/// Every row of the first value range anywhere in a tool result, cells as text.
+pub fn value_rows(value: &Value) -> Vec<Vec<String>> {
+    let mut rows = None;
+    crate::widgets::walk(value, 0, &mut |map| {
+        if rows.is_some() {
+            return;
+        }
+        if let Some(values) = map.get("values").and_then(|v| v.as_array()) {
+            rows = Some(
+                values
+                    .iter()
+                    .map(|row| {
+                        row.as_array()
+                            .into_iter()
+                            .flatten()
+                            .map(|c| c.as_str().map(str::to_string).unwrap_or_else(|| c.to_string()))
+                            .collect()
+                    })
+                    .collect(),
+            );
+        }
+    });
+    rows.unwrap_or_default()
+}
+
+/// Tab titles in a spreadsheet metadata result (`sheets[].properties.title`
+/// from the Sheets API, or a plain `sheet_names` list).
+fn tab_titles(value: &Value) -> Vec<String> {
+    let mut titles = Vec::new();
+    crate::widgets::walk(value, 0, &mut |map| {
+        if let Some(props) = map.get("properties").and_then(|p| p.as_object())
+            && props.contains_key("sheetId")
+            && let Some(title) = props.get("title").and_then(|t| t.as_str())
+        {
+            titles.push(title.to_string());
+        }
+        for key in ["sheet_names", "sheetNames"] {
+            if let Some(names) = map.get(key).and_then(|n| n.as_array()) {
+                titles.extend(names.iter().filter_map(|n| n.as_str()).map(str::to_string));
+            }
+        }
+    });
+    titles.dedup();
+    titles
+}
+
+/// Check that the spreadsheet exists (and is shared with the connected
+/// account) and that its tab does, with a metadata tool.
+async fn validate(info: &SheetsTool, sheet: &SpreadsheetConfig) -> Result<(), String> {
+    let value = info
+        .call(&sheet.spreadsheet_id, "", Default::default())
+        .await
+        .map_err(|e| format!("Spreadsheet {} not found or not accessible: {}", sheet.spreadsheet_id, e))?;
+    let Some(tab) = &sheet.tab else { return Ok(()) };
+    let tabs = tab_titles(&value);
+    if tabs.is_empty() || tabs.contains(tab) {
+        Ok(())
+    } else {
+        Err(format!("Tab '{}' not found. Tabs: {}", tab, tabs.join(", ")))
+    }
+}
+
+/// Validate every spreadsheet and refresh its cached header row with the
+/// connected Sheets tools. Returns one result per spreadsheet, in order;
+/// `valid` is `null` when no Sheets tool could check it.
+pub async fn sync_headers(tool_sets: &[McpToolSet], sheets: &mut [SpreadsheetConfig]) -> Vec<Value> {
+    let reader = find_tool(tool_sets, READ_FRAGMENTS, true);
+    let info = find_tool(tool_sets, INFO_FRAGMENTS, false);
+    let mut results = Vec::new();
+    for sheet in sheets.iter_mut() {
+        let validation = match &info {
+            Some(info) => Some(validate(info, sheet).await),
+            None => None,
+        };
+        // Reading the header row of a missing sheet or tab fails too, so the
+        // read doubles as validation when there is no metadata tool.
+        let outcome = match (&validation, &reader) {
+            (Some(Err(e)), _) => Err(e.clone()),
+            (_, None) => Err("No Google Sheets tools are connected; the sheet was not checked.".to_string()),
+            (_, Some(reader)) => reader
+                .call(&sheet.spreadsheet_id, &sheet.header_range(), Default::default())
+                .await
+                .map(|value| first_row(&value).unwrap_or_default()),
+        };
+        let valid = match (&validation, &reader) {
+            (Some(checked), _) => Some(checked.is_ok()),
+            (None, Some(_)) => Some(outcome.is_ok()),
+            (None, None) => None,
+        };
+        results.push(match outcome {
+            Ok(headers) => {
+                sheet.headers = headers;
+                sheet.synced_at = Some(chrono::Local::now().to_rfc3339());
+                json!({"spreadsheet_id": sheet.spreadsheet_id, "name": sheet.name, "tab": sheet.tab, "headers": sheet.headers, "valid": valid})
+            }
+            Err(e) => {
+                println!("⚠️ Spreadsheet {} failed validation: {}", sheet.name, e);
+                json!({"spreadsheet_id": sheet.spreadsheet_id, "name": sheet.name, "tab": sheet.tab, "headers": sheet.headers, "valid": valid, "error": e})
+            }
+        });
+    }
+    results
+}
+
+/// Registered spreadsheets and their columns, for the Google sub-agent's
+/// preamble, so it does not read a sheet just to learn its layout.
+pub fn preamble_context() -> Option<String> {
+    let sheets = load_spreadsheets();
+    if sheets.is_empty() {
+        return None;
+    }
+    let lines: Vec<String> = sheets
+        .iter()
+        .map(|s| {
+            let columns = if s.headers.is_empty() {
+                "columns unknown".to_string()
+            } else {
+                format!("columns: {}", s.headers.join(" | "))
+            };
+            format!(
+                "- {} (spreadsheet_id {}, tab {}): {}",
+                s.name,
+                s.spreadsheet_id,
+                s.tab.as_deref().unwrap_or("first tab"),
+                columns
+            )
+        })
+        .collect();
+    Some(format!(
+        "REGISTERED SPREADSHEETS (header rows as of the last sync; no need to read them first):\n{}",
+        lines.join("\n")
+    ))
+}
+
+/// Header comparison that ignores case, spaces, `_` and `-`.
+fn column_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Lay `record` out along `headers`. Fields that match no column are an
+/// error rather than being dropped.
+pub fn map_record(headers: &[String], record: &serde_json::Map<String, Value>) -> Result<Vec<Value>, String> {
+    let mut row = vec![json!(""); headers.len()];
+    let mut unknown = Vec::new();
+    for (field, value) in record {
+        match headers.iter().position(|h| column_key(h) == column_key(field)) {
+            Some(i) => {
+                row[i] = match value {
+                    Value::String(_) | Value::Number(_) | Value::Bool(_) => value.clone(),
+                    Value::Null => json!(""),
+                    other => json!(other.to_string()),
+                }
+            }
+            None => unknown.push(field.as_str()),
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(format!(
+            "No column for {}. The columns are: {}",
+            unknown.join(", "),
+            headers.join(" | ")
+        ));
+    }
+    Ok(row)
+}
+
+/// `sheets_append_record`, served per turn over the connected Sheets tools.
+struct RecordServer {
+    reader: SheetsTool,
+    appender: SheetsTool,
+}
+
+impl RecordServer {
+    fn definition() -> rmcp::model::Tool {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spreadsheet": {"type": "string", "description": "Registered spreadsheet name (e.g. \"Expenses\") or spreadsheet ID"},
+                "tab": {"type": "string", "description": "Tab name; defaults to the registered tab or the first tab"},
+                "record": {"type": "object", "description": "The new row, keyed by column header, e.g. {\"Date\": \"2025-03-14\", \"Amount\": 14.5, \"Category\": \"Lunch\"}"}
+            },
+            "required": ["spreadsheet", "record"]
+        });
+        rmcp::model::Tool::new(
+            APPEND_RECORD,
+            "Append one row to a Google Sheet from an object keyed by column header. The header \
+             row is read first and each field is put in its column, so column order does not matter.",
+            Arc::new(schema.as_object().cloned().unwrap_or_default()),
+        )
+    }
+
+    async fn append(&self, args: &serde_json::Map<String, Value>) -> Result<String, String> {
+        let spreadsheet = args.get("spreadsheet").and_then(|v| v.as_str()).unwrap_or("").trim();
+        let record = args
+            .get("record")
+            .and_then(|v| v.as_object())
+            .filter(|r| !r.is_empty())
+            .ok_or("`record` must be an object keyed by column header")?;
+        let registered = load_spreadsheets().into_iter().find(|s| {
+            s.spreadsheet_id == spreadsheet || s.name.eq_ignore_ascii_case(spreadsheet)
+        });
+        let sheet = SpreadsheetConfig {
+            tab: args
+                .get("tab")
+                .and_then(|v| v.as_str())
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .or_else(|| registered.as_ref().and_then(|s| s.tab.clone())),
+            ..registered.unwrap_or(SpreadsheetConfig {
+                spreadsheet_id: spreadsheet.to_string(),
+                name: spreadsheet.to_string(),
+                tab: None,
+                headers: Vec::new(),
+                synced_at: None,
+            })
+        };
+        if sheet.spreadsheet_id.is_empty() {
+            return Err("`spreadsheet` is required".to_string());
+        }
+
+        // Always read the live header row: columns may have changed since the last sync.
+        let headers = self
+            .reader
+            .call(&sheet.spreadsheet_id, &sheet.header_range(), Default::default())
+            .await
+            .map(|v| first_row(&v).unwrap_or_default())?;
+        if headers.is_empty() {
+            return Err(format!("{} has no header row to map the record onto", sheet.name));
+        }
+        let row = map_record(&headers, record)?;
+
+        let mut extra = serde_json::Map::new();
+        extra.insert("values".to_string(), json!([row]));
+        if let Some(key) = self.appender.value_input_key {
+            extra.insert(key.to_string(), json!("USER_ENTERED"));
+        }
+        let range = match &sheet.tab {
+            Some(tab) => format!("'{}'!A1", tab.replace('\'', "''")),
+            None => "A1".to_string(),
+        };
+        self.appender.call(&sheet.spreadsheet_id, &range, extra).await?;
+        let written: Vec<String> = headers
+            .iter()
+            .zip(&row)
+            .filter(|(_, v)| v.as_str() != Some(""))
+            .map(|(h, v)| format!("{} = {}", h, v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string())))
+            .collect();
+        Ok(format!("Appended a row to {}: {}", sheet.name, written.join(", ")))
+    }
+}
+
+impl ServerHandler for RecordServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(vec![Self::definition()]))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        if request.name != APPEND_RECORD {
+            return Err(ErrorData::invalid_params(format!("Unknown tool {}", request.name), None));
+        }
+        Ok(match self.append(&request.arguments.unwrap_or_default()).await {
+            Ok(text) => CallToolResult::success(vec![Content::text(text)]),
+            Err(e) => CallToolResult::error(vec![Content::text(e)]),
+        })
+    }
+}
+
+/// Serve `sheets_append_record` for this turn when the connected Sheets tools
+/// can read and append ranges. The returned connection must stay alive for
+/// the turn.
+pub async fn connect(tool_sets: &[McpToolSet]) -> Option<McpConnection> {
+    let reader = find_tool(tool_sets, READ_FRAGMENTS, true)?;
+    let appender = find_tool(tool_sets, APPEND_FRAGMENTS, true)?;
+    match crate::mcp_proxy::connect_in_process(RecordServer { reader, appender }).await {
+        Ok(conn) => Some(conn),
+        Err(e) => {
+            println!("⚠️ {} unavailable this turn: {}", APPEND_RECORD, e);
+            None
+        }
+    }
+}
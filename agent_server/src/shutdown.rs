@@ -0,0 +1,108 @@
+use crate::state::{McpConnection, SharedState};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Time each MCP server gets to stop, and connections get to close.
+pub const GRACE: Duration = Duration::from_secs(5);
+/// How often the parent-process watchdog checks the launching app.
+const PARENT_POLL: Duration = Duration::from_secs(2);
+
+/// The shutdown reason, once one was requested.
+fn signal() -> &'static watch::Sender<Option<String>> {
+    static SIGNAL: OnceLock<watch::Sender<Option<String>>> = OnceLock::new();
+    SIGNAL.get_or_init(|| watch::channel(None).0)
+}
+
+/// Ask the server to shut down; later requests are ignored.
+pub fn trigger(reason: &str) {
+    signal().send_if_modified(|current| {
+        if current.is_some() {
+            return false;
+        }
+        println!("🛑 Shutting down: {}", reason);
+        *current = Some(reason.to_string());
+        true
+    });
+}
+
+/// Resolves once shutdown was requested.
+pub async fn wait() {
+    let mut rx = signal().subscribe();
+    let _ = rx.wait_for(|reason| reason.is_some()).await;
+}
+
+/// SIGINT (Ctrl-C) and, on unix, SIGTERM.
+pub fn spawn_signal_handlers() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            trigger("SIGINT");
+        }
+    });
+    #[cfg(unix)]
+    tokio::spawn(async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+                trigger("SIGTERM");
+            }
+            Err(e) => println!("⚠️ Could not listen for SIGTERM: {}", e),
+        }
+    });
+}
+
+#[cfg(unix)]
+fn is_alive(pid: i32) -> bool {
+    // Signal 0 checks for existence; EPERM still means the process exists.
+    let exists = unsafe { libc::kill(pid, 0) == 0 };
+    exists || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_alive(_pid: i32) -> bool {
+    true
+}
+
+/// Shut down when the launching app (`--parent-pid`) exits, so a crashed or
+/// force-quit app never leaves the server and its MCP children behind.
+pub fn spawn_parent_watchdog(parent_pid: i32) {
+    println!("👀 Watching parent process {}", parent_pid);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(PARENT_POLL).await;
+            if !is_alive(parent_pid) {
+                trigger(&format!("parent process {} exited", parent_pid));
+                break;
+            }
+        }
+    });
+}
+
+/// Stop every MCP server (spawned children and in-process servers alike),
+/// each within `GRACE`.
+pub async fn stop_mcp_servers(state: &SharedState) {
+    let connections: Vec<(String, McpConnection)> = {
+        let mut s = state.lock().await;
+        let mut connections: Vec<(String, McpConnection)> = s.mcp_connections.drain().collect();
+        connections.extend(s.builtin_servers.drain());
+        for (name, conn) in [
+            ("custom_tools", s.custom_tools.take()),
+            ("plugins", s.plugins.take()),
+            ("workspace", s.workspace.take()),
+            ("github", s.github.take()),
+            ("sheet_index", s.sheet_index.take()),
+        ] {
+            if let Some(conn) = conn {
+                connections.push((name.to_string(), conn));
+            }
+        }
+        connections
+    };
+    let stops = connections.into_iter().map(|(name, conn)| async move {
+        if tokio::time::timeout(GRACE, conn._service.cancel()).await.is_err() {
+            println!("⚠️ MCP server '{}' did not stop in time", name);
+        }
+    });
+    futures::future::join_all(stops).await;
+}
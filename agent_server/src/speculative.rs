@@ -0,0 +1,110 @@
+use crate::tools::ToolEventSender;
+use rig::message::Message as RigMessage;
+use serde_json::{json, Value};
+use std::time::Instant;
+
+/// Questions longer than this go straight to the main model; a quick draft
+/// rarely helps with a long, detailed request.
+const DEFAULT_MAX_QUERY_CHARS: usize = 500;
+/// History messages given to the draft model for context.
+const HISTORY_MESSAGES: usize = 6;
+/// What the draft model answers when the question needs tools or personal data.
+const DEFER: &str = "DEFER";
+/// Providers a `RONGE_DRAFT_MODEL` prefix may name.
+const DRAFT_PROVIDERS: &[&str] = &["ollama", "gemini", "openai", "anthropic", "openrouter", "mistral"];
+
+const DRAFT_PREAMBLE: &str = "You write a quick first answer while a more capable assistant \
+prepares the full one. Answer in two or three sentences, directly and without preamble. If the \
+question needs tools, live data, the user's email, calendar, files or spreadsheets, or anything \
+you are unsure of, reply with DEFER and nothing else.";
+
+/// The fast model drafts come from, as (provider, model): `RONGE_DRAFT_MODEL`
+/// (`model`, or `provider:model` such as `ollama:llama3.2`), else the
+/// provider's small model. `None` when that is the main model itself.
+pub fn draft_model(provider: &str, model: &str) -> Option<(String, String)> {
+    let (draft_provider, draft_model) = match std::env::var("RONGE_DRAFT_MODEL").ok().filter(|m| !m.is_empty()) {
+        // Ollama tags contain colons too (`llama3.2:3b`).
+        Some(spec) => match spec.split_once(':') {
+            Some((p, m)) if DRAFT_PROVIDERS.contains(&p) => (p.to_string(), m.to_string()),
+            _ => (provider.to_string(), spec.clone()),
+        },
+        None => {
+            let small = match provider {
+                "gemini" => "gemini-2.5-flash-lite",
+                "openai" => "gpt-4o-mini",
+                "anthropic" => "claude-3-5-haiku-latest",
+                "mistral" => "mistral-small-latest",
+                _ => return None,
+            };
+            (provider.to_string(), small.to_string())
+        }
+    };
+    (draft_provider != provider || draft_model != model).then_some((draft_provider, draft_model))
+}
+
+pub fn max_query_chars() -> usize {
+    std::env::var("RONGE_DRAFT_MAX_CHARS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_QUERY_CHARS)
+}
+
+/// Ask the draft model for a quick answer and send it as a `draft_answer`
+/// event on the turn's event queue. The caller aborts the task once the main
+/// answer is in; a draft that arrives first is superseded by it.
+pub fn spawn(
+    provider: String,
+    api_key: String,
+    model: String,
+    query: &str,
+    history: &[RigMessage],
+    redact_pii: bool,
+    tx: ToolEventSender,
+) -> tokio::task::JoinHandle<()> {
+    let recent = &history[history.len().saturating_sub(HISTORY_MESSAGES)..];
+    let mut prompt = match crate::session::transcript(recent) {
+        context if context.is_empty() => query.to_string(),
+        context => format!("Conversation so far:\n{}\n\nQuestion: {}", context, query),
+    };
+    // Local drafts never leave the machine.
+    if redact_pii && provider != "ollama" {
+        prompt = crate::pii::redact(&prompt);
+    }
+    tokio::spawn(async move {
+        let started = Instant::now();
+        match crate::llm::complete(&provider, &api_key, &model, DRAFT_PREAMBLE, &prompt).await {
+            Ok(text) if !text.trim().is_empty() && !text.trim().starts_with(DEFER) => {
+                println!("📝 Draft from {} in {} ms", model, started.elapsed().as_millis());
+                let _ = tx
+                    .send(json!({
+                        "type": "draft_answer",
+                        "content": {
+                            "text": text.trim(),
+                            "provider": provider,
+                            "model": model,
+                            "elapsed_ms": started.elapsed().as_millis() as u64,
+                        }
+                    }))
+                    .await;
+            }
+            Ok(_) => println!("📝 Draft model deferred to the main model"),
+            Err(e) => println!("⚠️ Draft from {} failed: {}", model, e),
+        }
+    })
+}
+
+/// The response's `draft` field: which draft the final answer replaces, or
+/// null when none was shown.
+pub fn superseded(events: &[Value]) -> Value {
+    events
+        .iter()
+        .find(|e| e["type"] == "draft_answer")
+        .map(|e| {
+            json!({
+                "provider": e["content"]["provider"],
+                "model": e["content"]["model"],
+                "superseded": true,
+            })
+        })
+        .unwrap_or(Value::Null)
+}
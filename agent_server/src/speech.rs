@@ -0,0 +1,110 @@
+use crate::session::ClientSender;
+use crate::state::SharedState;
+use axum::extract::ws::Message;
+use serde_json::json;
+
+/// Size of the binary frames used when audio is not already streamed.
+const CHUNK_BYTES: usize = 32 * 1024;
+const DEFAULT_VOICE: &str = "alloy";
+const OPENAI_TTS_MODEL: &str = "gpt-4o-mini-tts";
+
+/// Synthesize `text` and stream it to the client as binary WS frames:
+///
+/// ```text
+/// {"type": "speech_start", "content": {"format": "mp3"|"aiff"}}
+/// <binary> <binary> ...
+/// {"type": "speech_end", "content": {"bytes": N}}   // or speech_error
+/// ```
+///
+/// Uses OpenAI TTS (streamed chunk-by-chunk as it is synthesized) when an
+/// OpenAI key is configured, otherwise the macOS `say` command.
+pub async fn stream_speech(
+    sender: &mut ClientSender,
+    state: &SharedState,
+    text: &str,
+    voice: Option<&str>,
+) {
+    let openai_key = state.lock().await.api_keys.get("openai").cloned();
+    let result = match openai_key {
+        Some(key) => stream_openai(sender, &key, text, voice.unwrap_or(DEFAULT_VOICE)).await,
+        None => stream_say(sender, text, voice).await,
+    };
+    let frame = match result {
+        Ok(bytes) => json!({"type": "speech_end", "content": {"bytes": bytes}}),
+        Err(e) => {
+            println!("❌ Speech synthesis failed: {}", e);
+            json!({"type": "speech_error", "content": "Could not read the answer aloud."})
+        }
+    };
+    let _ = sender.send(Message::Text(frame.to_string())).await;
+}
+
+async fn send_start(sender: &mut ClientSender, format: &str) {
+    let _ = sender
+        .send(Message::Text(
+            json!({"type": "speech_start", "content": {"format": format}}).to_string(),
+        ))
+        .await;
+}
+
+/// Forward OpenAI's streamed MP3 body frame by frame so playback can start
+/// before synthesis finishes.
+async fn stream_openai(
+    sender: &mut ClientSender,
+    api_key: &str,
+    text: &str,
+    voice: &str,
+) -> Result<usize, String> {
+    let mut resp = reqwest::Client::new()
+        .post("https://api.openai.com/v1/audio/speech")
+        .bearer_auth(api_key)
+        .json(&json!({
+            "model": OPENAI_TTS_MODEL,
+            "input": text,
+            "voice": voice,
+            "response_format": "mp3",
+        }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("OpenAI TTS returned {}: {}", status, body));
+    }
+
+    send_start(sender, "mp3").await;
+    let mut total = 0;
+    while let Some(chunk) = resp.chunk().await.map_err(|e| e.to_string())? {
+        total += chunk.len();
+        let _ = sender.send(Message::Binary(chunk.to_vec())).await;
+    }
+    Ok(total)
+}
+
+/// Render with `say` to a temporary AIFF file, then send it in chunks.
+async fn stream_say(
+    sender: &mut ClientSender,
+    text: &str,
+    voice: Option<&str>,
+) -> Result<usize, String> {
+    let path = std::env::temp_dir().join(format!("ronge-speech-{}.aiff", crate::session::new_session_id()));
+    let mut cmd = tokio::process::Command::new("say");
+    cmd.arg("-o").arg(&path);
+    if let Some(voice) = voice {
+        cmd.arg("-v").arg(voice);
+    }
+    let status = cmd.arg(text).status().await.map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("say exited with {}", status));
+    }
+    let audio = tokio::fs::read(&path).await.map_err(|e| e.to_string());
+    let _ = tokio::fs::remove_file(&path).await;
+    let audio = audio?;
+
+    send_start(sender, "aiff").await;
+    for chunk in audio.chunks(CHUNK_BYTES) {
+        let _ = sender.send(Message::Binary(chunk.to_vec())).await;
+    }
+    Ok(audio.len())
+}
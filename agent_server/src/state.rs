@@ -1,22 +1,100 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, Mutex};
 
 /// A live MCP server connection.
 pub struct McpConnection {
-    pub tools: Vec<rmcp::model::Tool>,
+    /// Tools with provider-safe names, computed once at connect time.
+    pub tools: Arc<Vec<rmcp::model::Tool>>,
+    /// Maps sanitized name → original MCP name for forwarding calls.
+    pub name_map: Arc<HashMap<String, String>>,
     pub peer: rmcp::service::ServerSink,
     /// Must stay alive to keep the peer valid.
     pub _service: rmcp::service::RunningService<rmcp::RoleClient, ()>,
 }
 
+impl McpConnection {
+    pub fn new(
+        tools: Vec<rmcp::model::Tool>,
+        service: rmcp::service::RunningService<rmcp::RoleClient, ()>,
+    ) -> Self {
+        let (tools, name_map) = crate::mcp_proxy::sanitize_tools(tools);
+        Self {
+            tools: Arc::new(tools),
+            name_map: Arc::new(name_map),
+            peer: service.peer().clone(),
+            _service: service,
+        }
+    }
+
+    pub fn tool_set(&self) -> McpToolSet {
+        McpToolSet {
+            tools: self.tools.clone(),
+            name_map: self.name_map.clone(),
+            peer: self.peer.clone(),
+            subagent_only: false,
+        }
+    }
+}
+
+/// Cheap, shareable handle to one server's tools for a single agent turn.
+#[derive(Clone)]
+pub struct McpToolSet {
+    pub tools: Arc<Vec<rmcp::model::Tool>>,
+    pub name_map: Arc<HashMap<String, String>>,
+    pub peer: rmcp::service::ServerSink,
+    /// Offered to sub-agents only, never to the main agent.
+    pub subagent_only: bool,
+}
+
 pub struct AppState {
     pub current_model: String,
     pub current_provider: String,
     pub api_keys: HashMap<String, String>,
+    /// Reasoning effort / thinking budget set with `set_llm`; chat messages may override it.
+    pub reasoning: crate::reasoning::Reasoning,
+    /// Temperature, max_tokens and max_turns from `set_generation_params`.
+    pub generation: crate::generation::GenerationParams,
     pub mcp_connections: HashMap<String, McpConnection>,
     pub builtin_servers: HashMap<String, McpConnection>,
+    /// Tools declared in `~/.ronge/tools.toml`, loaded at startup.
+    pub custom_tools: Option<McpConnection>,
+    /// Tools from sandboxed WASM plugins in `~/.ronge/plugins/`.
+    pub plugins: Option<McpConnection>,
+    /// Project directory tools for `code_agent`, set with `set_code_workspace`.
+    pub workspace: Option<McpConnection>,
+    /// GitHub REST tools, connected with `set_github_token`.
+    pub github: Option<McpConnection>,
+    /// `sheets_search_index`, while any spreadsheet is indexed.
+    pub sheet_index: Option<McpConnection>,
     pub composio_api_key: Option<String>,
+    pub watch_rules: Vec<crate::watcher::WatchRule>,
+    pub scheduled_jobs: Vec<crate::scheduler::ScheduledJob>,
+    pub sheet_watches: Vec<crate::sheet_watch::SheetWatch>,
+    pub provider_stats: crate::provider_stats::ProviderStats,
+    /// When set, each chat turn is dumped to `~/.ronge/debug/<timestamp>/`.
+    pub debug_mode: bool,
+    pub llm_limiter: crate::limiter::LlmLimiter,
+    pub sessions: crate::session::SessionStore,
+    /// Buffer size of the per-turn tool-event queue.
+    pub tool_event_capacity: usize,
+    /// Active record/replay cassette, if any.
+    pub replay: Option<crate::replay::ReplaySession>,
+    /// Files generated during sessions, addressable by ID until they expire.
+    pub artifacts: crate::artifacts::ArtifactStore,
+    /// Destructive MCP tool calls pause for a `user_decision` when enabled.
+    pub confirm_destructive_tools: bool,
+    pub confirmations: crate::confirm::Confirmations,
+    /// Global dry-run: destructive tools return a preview instead of executing.
+    pub dry_run: bool,
+    /// Calendar create-event calls report overlapping events instead of double-booking.
+    pub check_calendar_conflicts: bool,
+    /// Keychain items `get_secret` may read; empty until the user opts in.
+    pub secret_items: Vec<crate::secret_refs::SecretItem>,
+    /// OAuth sign-ins waiting for their browser callback.
+    pub oauth_flows: crate::openrouter_auth::OAuthFlows,
+    /// Server-initiated events (e.g. watch results) fanned out to every connected client.
+    pub notifier: broadcast::Sender<serde_json::Value>,
 }
 
 pub type SharedState = Arc<Mutex<AppState>>;
@@ -27,18 +105,58 @@ impl AppState {
             current_model: "gemini-2.5-flash".to_string(),
             current_provider: "gemini".to_string(),
             api_keys: HashMap::new(),
+            reasoning: Default::default(),
+            generation: Default::default(),
             mcp_connections: HashMap::new(),
             builtin_servers: HashMap::new(),
+            custom_tools: None,
+            plugins: None,
+            workspace: None,
+            github: None,
+            sheet_index: None,
             composio_api_key: None,
+            watch_rules: crate::watcher::load_rules(),
+            scheduled_jobs: crate::scheduler::load_jobs(),
+            sheet_watches: crate::sheet_watch::load_watches(),
+            provider_stats: crate::provider_stats::ProviderStats::load(),
+            debug_mode: false,
+            replay: None,
+            llm_limiter: crate::limiter::LlmLimiter::from_env(),
+            sessions: crate::session::SessionStore::load(),
+            tool_event_capacity: std::env::var("RONGE_TOOL_EVENT_CAPACITY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(crate::tools::DEFAULT_TOOL_EVENT_CAPACITY),
+            artifacts: crate::artifacts::ArtifactStore::from_env(),
+            confirm_destructive_tools: true,
+            confirmations: crate::confirm::Confirmations::default(),
+            dry_run: false,
+            check_calendar_conflicts: true,
+            secret_items: crate::secret_refs::load_items(),
+            oauth_flows: crate::openrouter_auth::OAuthFlows::default(),
+            notifier: broadcast::channel(64).0,
         }
     }
 
-    /// Collect all MCP tools + peers for agent building (user-configured + built-in)
-    pub fn all_mcp_tools(&self) -> Vec<(Vec<rmcp::model::Tool>, rmcp::service::ServerSink)> {
+    /// Collect all MCP tools + peers for agent building (user-configured + built-in + custom + plugins + GitHub + sheet index)
+    pub fn all_mcp_tools(&self) -> Vec<McpToolSet> {
         self.mcp_connections
             .values()
             .chain(self.builtin_servers.values())
-            .map(|c| (c.tools.clone(), c.peer.clone()))
+            .chain(self.custom_tools.iter())
+            .chain(self.plugins.iter())
+            .chain(self.github.iter())
+            .chain(self.sheet_index.iter())
+            .map(McpConnection::tool_set)
+            .chain(self.workspace.iter().map(|c| McpToolSet {
+                subagent_only: true,
+                ..c.tool_set()
+            }))
             .collect()
     }
+
+    /// `all_mcp_tools` narrowed to what the session's Google access allows.
+    pub fn session_tools(&self, session_id: &str) -> Vec<McpToolSet> {
+        self.sessions.google_access(session_id).filter(self.all_mcp_tools())
+    }
 }
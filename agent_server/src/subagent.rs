@@ -0,0 +1,250 @@
+use crate::state::{McpConnection, McpToolSet};
+use crate::tools::ToolEventSender;
+use rmcp::{
+    ServerHandler,
+    model::{CallToolRequestParam, CallToolResult, Content, ErrorData, ListToolsResult, PaginatedRequestParam},
+    service::{RequestContext, RoleServer},
+};
+use serde_json::json;
+use std::sync::Arc;
+
+/// A delegated agent the main agent can call as a tool with a plain-language
+/// `task`. It runs its own tool loop with its own preamble and a subset of
+/// the connected MCP tools, and returns its final answer as the tool result.
+pub struct SubAgent {
+    /// Tool name shown to the main agent.
+    pub name: &'static str,
+    /// Tool description shown to the main agent.
+    pub description: &'static str,
+    /// `{current_datetime}` is filled in at call time.
+    pub preamble: &'static str,
+    /// MCP tools the sub-agent gets, matched as lowercase name fragments.
+    /// The sub-agent is only offered when at least one is connected.
+    pub mcp_tools: &'static [&'static str],
+    pub max_turns: usize,
+    /// Tools that need the user's confirmation (and are simulated in dry-run
+    /// mode) when this sub-agent calls them, beyond the destructive ones.
+    pub guarded_tools: &'static [&'static str],
+    /// Heading of a memory-file section (`## <heading>`) holding the user's
+    /// instructions for this sub-agent; appended to the preamble.
+    pub memory_section: Option<&'static str>,
+    /// Extra context computed at call time and appended to the preamble.
+    pub context: Option<fn() -> Option<String>>,
+}
+
+pub const SUBAGENTS: &[SubAgent] = &[
+    SubAgent {
+        name: "google_agent",
+        description: "Delegate a Gmail, Google Calendar or Google Sheets task to a specialized \
+            agent. Describe the whole task in `task`; the agent returns its results.",
+        preamble: include_str!("../prompts/google_agent_prompt.txt"),
+        mcp_tools: &["gmail", "calendar", "sheets", "spreadsheet", crate::email_summary::SUMMARIZE_EMAILS],
+        max_turns: 10,
+        guarded_tools: &[],
+        memory_section: None,
+        context: Some(crate::sheets::preamble_context),
+    },
+    SubAgent {
+        name: "code_agent",
+        description: "Delegate a task in the user's project workspace (read or edit files, \
+            list directories, run builds or tests) to a coding agent. Describe the whole task \
+            in `task`; the agent reports what it changed and the command results.",
+        preamble: include_str!("../prompts/code_agent_prompt.txt"),
+        mcp_tools: &["workspace_"],
+        max_turns: 20,
+        guarded_tools: &[crate::workspace::RUN_COMMAND],
+        memory_section: None,
+        context: None,
+    },
+    SubAgent {
+        name: "triage_agent",
+        description: "Triage the user's inbox against their saved rules (VIP senders, topics \
+            to archive, things to flag) and return a prioritized action list. Put any scope \
+            (e.g. \"unread from the last two days\") in `task`. The rules live in memory \
+            under a \"## Email Triage Rules\" heading; save new rules there.",
+        preamble: include_str!("../prompts/triage_agent_prompt.txt"),
+        mcp_tools: &["gmail"],
+        max_turns: 10,
+        guarded_tools: &[],
+        memory_section: Some("Email Triage Rules"),
+        context: None,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static SubAgent> {
+    SUBAGENTS.iter().find(|a| a.name == name)
+}
+
+/// The body of `## <heading>` in the memory file, up to the next heading of
+/// the same or higher level.
+fn memory_section(heading: &str) -> Option<String> {
+    let memory = std::fs::read_to_string(crate::tools::default_memory_path()).ok()?;
+    let mut lines = memory.lines();
+    lines.find(|l| {
+        l.trim_start_matches('#').trim().eq_ignore_ascii_case(heading) && l.starts_with("##")
+    })?;
+    let body: Vec<&str> = lines
+        .take_while(|l| !(l.starts_with("# ") || l.starts_with("## ")))
+        .collect();
+    let body = body.join("\n").trim().to_string();
+    (!body.is_empty()).then_some(body)
+}
+
+/// What a sub-agent inherits from the turn that calls it.
+#[derive(Clone)]
+pub struct TurnContext {
+    pub provider: String,
+    pub api_key: String,
+    pub model: String,
+    /// Every connected MCP tool set, before mode filtering.
+    pub mcp_tool_sets: Vec<McpToolSet>,
+    /// Tool events of the sub-agent are forwarded to the caller's client.
+    pub tx: ToolEventSender,
+}
+
+impl SubAgent {
+    fn tool_sets(&self, all: &[McpToolSet]) -> Vec<McpToolSet> {
+        all.iter()
+            .filter_map(|set| {
+                let tools: Vec<rmcp::model::Tool> = set
+                    .tools
+                    .iter()
+                    .filter(|t| {
+                        let lower = t.name.to_ascii_lowercase();
+                        self.mcp_tools.iter().any(|f| lower.contains(f))
+                    })
+                    .cloned()
+                    .collect();
+                (!tools.is_empty()).then(|| McpToolSet {
+                    tools: Arc::new(tools),
+                    ..set.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// `RONGE_SUBAGENT_<NAME>_MODEL` overrides the turn's model, e.g. to run
+    /// a sub-agent on a cheaper model of the same provider.
+    fn model(&self, default: &str) -> String {
+        std::env::var(format!("RONGE_SUBAGENT_{}_MODEL", self.name.to_ascii_uppercase()))
+            .ok()
+            .filter(|m| !m.is_empty())
+            .unwrap_or_else(|| default.to_string())
+    }
+
+    fn definition(&self) -> rmcp::model::Tool {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "task": {"type": "string", "description": "The task to delegate, with all the details the agent needs"}
+            },
+            "required": ["task"]
+        });
+        rmcp::model::Tool::new(
+            self.name,
+            self.description,
+            Arc::new(schema.as_object().cloned().unwrap_or_default()),
+        )
+    }
+
+    pub async fn run(&self, ctx: &TurnContext, task: &str) -> Result<String, String> {
+        let model = self.model(&ctx.model);
+        println!("🤝 Delegating to {} ({}): {}", self.name, model, task);
+        let profile = crate::profile::Profile::load();
+        let mut preamble = self
+            .preamble
+            .replace("{current_datetime}", &profile.format_datetime(chrono::Local::now(), false));
+        if let Some(instruction) = profile.language_instruction() {
+            preamble.push_str(&format!("\n\n{}", instruction));
+        }
+        if let Some(heading) = self.memory_section {
+            let rules = memory_section(heading).unwrap_or_else(|| {
+                format!("None saved. The user can add a \"## {}\" section to memory.", heading)
+            });
+            let rules = if ctx.tx.redacts_pii() { crate::pii::redact(&rules) } else { rules };
+            preamble.push_str(&format!("\n\nUSER RULES ({}):\n{}", heading, rules));
+        }
+        if let Some(context) = self.context.and_then(|f| f()) {
+            let context = if ctx.tx.redacts_pii() { crate::pii::redact(&context) } else { context };
+            preamble.push_str(&format!("\n\n{}", context));
+        }
+        // The turn's tape holds this sub-agent's answer as one call; what
+        // it does to get there is not recorded.
+        crate::llm::run_mcp_agent(
+            &ctx.provider,
+            &ctx.api_key,
+            &model,
+            &preamble,
+            task,
+            self.tool_sets(&ctx.mcp_tool_sets),
+            &ctx.tx.clone().with_guarded_tools(self.guarded_tools).with_tape(None),
+            self.max_turns,
+        )
+        .await
+    }
+}
+
+struct SubAgentServer {
+    agents: Vec<&'static SubAgent>,
+    ctx: TurnContext,
+}
+
+impl ServerHandler for SubAgentServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(
+            self.agents.iter().map(|a| a.definition()).collect(),
+        ))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let Some(agent) = self.agents.iter().find(|a| a.name == request.name) else {
+            return Err(ErrorData::invalid_params(format!("Unknown sub-agent {}", request.name), None));
+        };
+        let task = request
+            .arguments
+            .as_ref()
+            .and_then(|args| args.get("task"))
+            .and_then(|t| t.as_str())
+            .unwrap_or("")
+            .trim()
+            .to_string();
+        if task.is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text("`task` is required")]));
+        }
+        Ok(match agent.run(&self.ctx, &task).await {
+            Ok(answer) => CallToolResult::success(vec![Content::text(answer)]),
+            Err(e) => {
+                println!("❌ Sub-agent {} failed: {}", agent.name, e);
+                CallToolResult::error(vec![Content::text(format!("{} failed: {}", agent.name, e))])
+            }
+        })
+    }
+}
+
+/// Serve this turn's usable sub-agents as an in-process MCP tool set. The
+/// returned connection must stay alive for the turn. `None` when no
+/// sub-agent has any of its tools connected.
+pub async fn connect(ctx: TurnContext) -> Option<McpConnection> {
+    let agents: Vec<&'static SubAgent> = SUBAGENTS
+        .iter()
+        .filter(|a| !a.tool_sets(&ctx.mcp_tool_sets).is_empty())
+        .collect();
+    if agents.is_empty() {
+        return None;
+    }
+    match crate::mcp_proxy::connect_in_process(SubAgentServer { agents, ctx }).await {
+        Ok(conn) => Some(conn),
+        Err(e) => {
+            println!("⚠️ Sub-agents unavailable this turn: {}", e);
+            None
+        }
+    }
+}
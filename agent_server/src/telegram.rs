@@ -0,0 +1,201 @@
+use crate::session::ClientSender;
+use crate::state::SharedState;
+use axum::extract::ws::Message;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Seconds Telegram holds a `getUpdates` request open when there is nothing new.
+const POLL_TIMEOUT_SECS: u64 = 30;
+/// Telegram's limit on the text of one message.
+const MAX_MESSAGE_CHARS: usize = 4000;
+
+/// Optional Telegram frontend. Enabled by `RONGE_TELEGRAM_BOT_TOKEN`; only
+/// chats listed in `RONGE_TELEGRAM_CHAT_IDS` (comma-separated) are answered.
+///
+/// Each allowed chat is its own session (`telegram-<chat id>`) with its own
+/// history, and its messages go through `logic::process_message` exactly like
+/// WebSocket frames. Confirmations are answered with `/approve <id>` or
+/// `/reject <id>`.
+pub fn spawn(state: SharedState) {
+    let Some(token) = std::env::var("RONGE_TELEGRAM_BOT_TOKEN")
+        .ok()
+        .or_else(|| crate::secrets::get(crate::secrets::TELEGRAM))
+    else {
+        return;
+    };
+    let allowed: Vec<i64> = std::env::var("RONGE_TELEGRAM_CHAT_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect();
+    if allowed.is_empty() {
+        println!("⚠️ RONGE_TELEGRAM_BOT_TOKEN is set but RONGE_TELEGRAM_CHAT_IDS is empty; Telegram frontend disabled");
+        return;
+    }
+    println!("📱 Telegram frontend enabled for {} chat(s)", allowed.len());
+    tokio::spawn(poll(Bot::new(token), state, allowed));
+}
+
+#[derive(Clone)]
+struct Bot {
+    client: reqwest::Client,
+    base: String,
+}
+
+impl Bot {
+    fn new(token: String) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(POLL_TIMEOUT_SECS + 10))
+                .build()
+                .unwrap_or_default(),
+            base: format!("https://api.telegram.org/bot{}", token),
+        }
+    }
+
+    async fn call(&self, method: &str, body: Value) -> Result<Value, String> {
+        let resp: Value = self
+            .client
+            .post(format!("{}/{}", self.base, method))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.without_url().to_string())?
+            .json()
+            .await
+            .map_err(|e| e.without_url().to_string())?;
+        if resp["ok"].as_bool() == Some(true) {
+            Ok(resp["result"].clone())
+        } else {
+            Err(resp["description"].as_str().unwrap_or("Telegram API error").to_string())
+        }
+    }
+
+    async fn send_text(&self, chat_id: i64, text: &str) {
+        let chars: Vec<char> = text.chars().collect();
+        for chunk in chars.chunks(MAX_MESSAGE_CHARS) {
+            let chunk: String = chunk.iter().collect();
+            if let Err(e) = self.call("sendMessage", json!({"chat_id": chat_id, "text": chunk})).await {
+                println!("❌ Telegram sendMessage failed: {}", e);
+            }
+        }
+    }
+}
+
+async fn poll(bot: Bot, state: SharedState, allowed: Vec<i64>) {
+    let confirmations = state.lock().await.confirmations.clone();
+    let mut workers: HashMap<i64, mpsc::UnboundedSender<String>> = HashMap::new();
+    let mut offset: i64 = 0;
+    loop {
+        let updates = match bot
+            .call("getUpdates", json!({"offset": offset, "timeout": POLL_TIMEOUT_SECS, "allowed_updates": ["message"]}))
+            .await
+        {
+            Ok(updates) => updates,
+            Err(e) => {
+                println!("⚠️ Telegram getUpdates failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        for update in updates.as_array().into_iter().flatten() {
+            offset = offset.max(update["update_id"].as_i64().unwrap_or(0) + 1);
+            let message = &update["message"];
+            let (Some(chat_id), Some(text)) = (message["chat"]["id"].as_i64(), message["text"].as_str()) else {
+                continue;
+            };
+            if !allowed.contains(&chat_id) {
+                println!("🚫 Ignoring Telegram message from chat {}", chat_id);
+                continue;
+            }
+
+            // Decisions are handled here so they reach a turn that is waiting on them.
+            if let Some((approved, id)) = parse_decision(text) {
+                let reply = if confirmations.resolve(id, approved) {
+                    if approved { "Approved." } else { "Rejected." }
+                } else {
+                    "That confirmation has expired."
+                };
+                bot.send_text(chat_id, reply).await;
+                continue;
+            }
+
+            let frame = if text.trim() == "/reset" {
+                json!({"data_type": "reset_session"})
+            } else {
+                json!({"text": text})
+            };
+            let worker = workers
+                .entry(chat_id)
+                .or_insert_with(|| spawn_worker(bot.clone(), state.clone(), chat_id));
+            let _ = worker.send(frame.to_string());
+        }
+    }
+}
+
+fn parse_decision(text: &str) -> Option<(bool, &str)> {
+    let mut words = text.split_whitespace();
+    let approved = match words.next()? {
+        "/approve" => true,
+        "/reject" => false,
+        _ => return None,
+    };
+    Some((approved, words.next()?))
+}
+
+/// One task per chat runs its messages in order against the chat's history,
+/// while a second forwards the resulting frames to Telegram.
+fn spawn_worker(bot: Bot, state: SharedState, chat_id: i64) -> mpsc::UnboundedSender<String> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let (mut sender, mut frames) = ClientSender::channel(format!("telegram-{}", chat_id), state.clone());
+
+    let forward_bot = bot.clone();
+    tokio::spawn(async move {
+        while let Some(msg) = frames.recv().await {
+            // Binary frames (speech audio) have no Telegram equivalent here.
+            if let Message::Text(frame) = msg
+                && let Some(text) = frame_text(&frame)
+            {
+                forward_bot.send_text(chat_id, &text).await;
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut chat_history = Vec::new();
+        while let Some(text) = rx.recv().await {
+            let _ = bot.call("sendChatAction", json!({"chat_id": chat_id, "action": "typing"})).await;
+            crate::logic::process_message(&text, &mut sender, &mut chat_history, &state).await;
+        }
+    });
+    tx
+}
+
+/// The user-visible text of a server frame, if it has any.
+fn frame_text(frame: &str) -> Option<String> {
+    let frame: Value = serde_json::from_str(frame).ok()?;
+    let content = &frame["content"];
+    match frame["type"].as_str()? {
+        "response" => {
+            let mut text = content["text"].as_str().unwrap_or("").to_string();
+            for widget in content["widgets"].as_array().into_iter().flatten() {
+                if let Some(url) = widget["action"]["url"].as_str() {
+                    text.push_str(&format!("\n🔗 {}", url));
+                }
+            }
+            Some(text)
+        }
+        "confirmation" => Some(format!(
+            "{}\n{}\n\nReply /approve {} or /reject {}",
+            content["widget"]["label"].as_str().unwrap_or("Allow this action?"),
+            content["widget"]["subtitle"].as_str().unwrap_or(""),
+            content["id"].as_str().unwrap_or(""),
+            content["id"].as_str().unwrap_or("")
+        )),
+        "session_reset" => content.as_str().map(str::to_string),
+        kind if kind.ends_with("_error") => content.as_str().map(str::to_string),
+        _ => None,
+    }
+}
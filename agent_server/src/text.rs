@@ -0,0 +1,32 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// `text` cut to at most `max_chars` characters, never inside a grapheme
+/// (an emoji with modifiers, a letter with combining marks), with an
+/// ellipsis when cut.
+pub fn truncate_chars(text: &str, max_chars: usize) -> String {
+    let Some((end, _)) = text.char_indices().nth(max_chars) else {
+        return text.to_string();
+    };
+    format!("{}…", &text[..grapheme_floor(text, end)])
+}
+
+/// The longest prefix of `text` of at most `max_bytes` bytes that ends on a
+/// grapheme boundary, for byte-limited payloads such as WebSocket frames.
+pub fn truncate_bytes(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..grapheme_floor(text, end)]
+}
+
+/// The last grapheme boundary at or before byte `end` (a char boundary).
+fn grapheme_floor(text: &str, end: usize) -> usize {
+    text.grapheme_indices(true)
+        .map(|(start, g)| (start, start + g.len()))
+        .find(|&(_, stop)| stop > end)
+        .map_or(end, |(start, _)| start)
+}
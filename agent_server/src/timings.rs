@@ -0,0 +1,112 @@
+use serde_json::json;
+use std::time::Instant;
+
+/// Per-turn timing breakdown, reconstructed from the tool-event stream.
+///
+/// rig runs the agent loop internally, so provider round-trips are not
+/// directly observable. Instead, every stretch of time during which no tool is
+/// running is attributed to the provider: the turn starts with a model call,
+/// each batch of tool results is followed by another model call, and the last
+/// stretch ends with the final answer.
+pub struct TurnTimings {
+    started: Instant,
+    segment_start: Instant,
+    in_flight: usize,
+    provider_round_trips: Vec<u64>,
+    tools: Vec<(String, u64)>,
+}
+
+impl TurnTimings {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            segment_start: now,
+            in_flight: 0,
+            provider_round_trips: Vec::new(),
+            tools: Vec::new(),
+        }
+    }
+
+    /// Feed every `tool_call` / `tool_result` event forwarded to the client.
+    pub fn observe(&mut self, event: &serde_json::Value) {
+        match event["type"].as_str() {
+            Some("tool_call") => {
+                if self.in_flight == 0 {
+                    self.provider_round_trips
+                        .push(self.segment_start.elapsed().as_millis() as u64);
+                }
+                self.in_flight += 1;
+            }
+            Some("tool_result") => {
+                let name = event["content"]["toolName"].as_str().unwrap_or("").to_string();
+                let duration = event["content"]["durationMs"].as_u64().unwrap_or(0);
+                self.tools.push((name, duration));
+                self.in_flight = self.in_flight.saturating_sub(1);
+                if self.in_flight == 0 {
+                    self.segment_start = Instant::now();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Close the final provider segment and render the `timings` object.
+    pub fn finish(mut self) -> serde_json::Value {
+        if self.in_flight == 0 {
+            self.provider_round_trips
+                .push(self.segment_start.elapsed().as_millis() as u64);
+        }
+        let provider_ms: u64 = self.provider_round_trips.iter().sum();
+        let tool_ms: u64 = self.tools.iter().map(|(_, d)| d).sum();
+        let tools: Vec<serde_json::Value> = self
+            .tools
+            .iter()
+            .map(|(name, ms)| json!({"name": name, "duration_ms": ms}))
+            .collect();
+        json!({
+            "total_ms": self.started.elapsed().as_millis() as u64,
+            "provider_ms": provider_ms,
+            "provider_round_trips": self.provider_round_trips,
+            "tool_ms": tool_ms,
+            "tools": tools,
+        })
+    }
+}
+
+/// Per-tool totals for the turn's `tool_result` events, in order of first
+/// use, for the response's `tool_summary`:
+/// `[{"name", "calls", "succeeded", "failed", "duration_ms"}]`.
+pub fn tool_summary(events: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    let mut summary: Vec<(String, u64, u64, u64)> = Vec::new();
+    for event in events.iter().filter(|e| e["type"] == "tool_result") {
+        let content = &event["content"];
+        let name = content["toolName"].as_str().unwrap_or("");
+        let index = match summary.iter().position(|(n, ..)| n == name) {
+            Some(i) => i,
+            None => {
+                summary.push((name.to_string(), 0, 0, 0));
+                summary.len() - 1
+            }
+        };
+        let entry = &mut summary[index];
+        entry.1 += 1;
+        // Events recorded before `success` existed count as successes.
+        if content["success"].as_bool() != Some(false) {
+            entry.2 += 1;
+        }
+        entry.3 += content["durationMs"].as_u64().unwrap_or(0);
+    }
+    summary
+        .into_iter()
+        .map(|(name, calls, succeeded, ms)| {
+            json!({
+                "name": name,
+                "calls": calls,
+                "succeeded": succeeded,
+                "failed": calls - succeeded,
+                "duration_ms": ms,
+            })
+        })
+        .collect()
+}
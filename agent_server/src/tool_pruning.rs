@@ -0,0 +1,133 @@
+use crate::sheet_index::{cosine, Embedder};
+use crate::state::McpToolSet;
+use crate::tools::ToolEventSender;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// MCP tools kept for a small model (`RONGE_TOOL_PRUNE_LIMIT`).
+const DEFAULT_KEEP: usize = 12;
+/// Local models above this many billion parameters get every tool
+/// (`RONGE_TOOL_PRUNE_MAX_PARAMS_B`).
+const DEFAULT_MAX_PARAMS_B: f64 = 14.0;
+
+/// Whether `model` is a small local model: an Ollama model whose tag
+/// (`qwen2.5:7b`, `llama3.2:3b`) is at most `RONGE_TOOL_PRUNE_MAX_PARAMS_B`
+/// billion parameters, or has no size tag. `RONGE_TOOL_PRUNING=always|off`
+/// overrides the check.
+fn is_small_model(provider: &str, model: &str) -> bool {
+    match std::env::var("RONGE_TOOL_PRUNING").as_deref() {
+        Ok("always") => return true,
+        Ok("off") => return false,
+        _ => {}
+    }
+    if provider != "ollama" {
+        return false;
+    }
+    let max_params = std::env::var("RONGE_TOOL_PRUNE_MAX_PARAMS_B")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_PARAMS_B);
+    let params = model
+        .to_ascii_lowercase()
+        .split([':', '-', '_'])
+        .find_map(|part| part.strip_suffix('b')?.parse::<f64>().ok());
+    params.is_none_or(|p| p <= max_params)
+}
+
+fn keep_limit() -> usize {
+    std::env::var("RONGE_TOOL_PRUNE_LIMIT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_KEEP)
+}
+
+/// Tool description embeddings, keyed by the embedded text; tool lists
+/// rarely change between turns.
+fn cache() -> &'static Mutex<HashMap<String, Vec<f32>>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Vec<f32>>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+fn tool_text(tool: &rmcp::model::Tool) -> String {
+    format!("{}: {}", tool.name, tool.description.as_deref().unwrap_or(""))
+}
+
+/// Embed the tool texts not yet cached, then return every text's vector.
+async fn embed_tools(embedder: &Embedder, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    let missing: Vec<String> = {
+        let cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+        texts.iter().filter(|t| !cache.contains_key(*t)).cloned().collect()
+    };
+    if !missing.is_empty() {
+        let vectors = embedder.embed(&missing).await?;
+        let mut cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+        cache.extend(missing.into_iter().zip(vectors));
+    }
+    let cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+    Ok(texts.iter().map(|t| cache.get(t).cloned().unwrap_or_default()).collect())
+}
+
+/// For a small local model, keep only the MCP tools whose descriptions best
+/// match `query` (local Ollama embeddings), so the model isn't handed 30+
+/// definitions. The built-in tools are unaffected. Sends `tools_pruned`
+/// when anything was left out; any embedding failure leaves the list whole.
+pub async fn prune(
+    provider: &str,
+    model: &str,
+    query: &str,
+    tool_sets: Vec<McpToolSet>,
+    tx: &ToolEventSender,
+) -> Vec<McpToolSet> {
+    let keep = keep_limit();
+    let total: usize = tool_sets.iter().map(|set| set.tools.len()).sum();
+    if total <= keep || !is_small_model(provider, model) {
+        return tool_sets;
+    }
+    let Some(embedder) = Embedder::choose("ollama", &HashMap::new()) else {
+        return tool_sets;
+    };
+
+    let texts: Vec<String> = tool_sets.iter().flat_map(|set| set.tools.iter().map(tool_text)).collect();
+    let scores = match (embedder.embed(&[query.to_string()]).await, embed_tools(&embedder, &texts).await) {
+        (Ok(q), Ok(tools)) if !q.is_empty() => tools.iter().map(|t| cosine(&q[0], t)).collect::<Vec<f32>>(),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("⚠️ Tool pruning skipped, embeddings failed: {}", e);
+            return tool_sets;
+        }
+        _ => return tool_sets,
+    };
+    let mut ranked: Vec<usize> = (0..scores.len()).collect();
+    ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    let kept: std::collections::HashSet<usize> = ranked.into_iter().take(keep).collect();
+
+    let mut index = 0;
+    let mut names = Vec::new();
+    let pruned: Vec<McpToolSet> = tool_sets
+        .into_iter()
+        .filter_map(|set| {
+            let tools: Vec<rmcp::model::Tool> = set
+                .tools
+                .iter()
+                .filter(|_| {
+                    index += 1;
+                    kept.contains(&(index - 1))
+                })
+                .cloned()
+                .collect();
+            names.extend(tools.iter().map(|t| t.name.to_string()));
+            (!tools.is_empty()).then(|| McpToolSet {
+                tools: Arc::new(tools),
+                ..set
+            })
+        })
+        .collect();
+    println!("✂️ {} of {} MCP tools offered to {}", names.len(), total, model);
+    let _ = tx
+        .send(json!({
+            "type": "tools_pruned",
+            "content": {"model": model, "kept": names.len(), "total": total, "tools": names}
+        }))
+        .await;
+    pruned
+}
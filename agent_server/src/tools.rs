@@ -3,12 +3,477 @@ use rig::tool::Tool;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use thiserror::Error;
-use tokio::sync::mpsc;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
 
 // ── Tool Event Channel ──
 
+/// Default number of tool events buffered between the agent and the WS writer.
+pub const DEFAULT_TOOL_EVENT_CAPACITY: usize = 64;
+
+/// Identical calls (same tool, same arguments) allowed per turn before the
+/// loop guard stops running them.
+const DEFAULT_REPEAT_LIMIT: u32 = 3;
+
+/// Progress events a full queue may discard: each is restated by the next
+/// one for the same tool, and the `tool_call`/`tool_result` pair around them
+/// still arrives.
+const PROGRESS_EVENTS: &[&str] = &["tool_phase", "tool_throttled", "retrying", "model_loading"];
+
+/// Placeholder for a `tool_result` payload a full queue gave up.
+const OMITTED_RESULT: &str = "[result omitted: the client fell behind]";
+
+/// Bounded queue of tool events that never blocks the tool that emits them.
+///
+/// When the WS writer falls behind (chatty MCP servers, slow client) and the
+/// queue is full, a progress event superseded by a newer one for the same
+/// tool is dropped first, then the oldest progress event. Calls, results,
+/// confirmations and everything else are never dropped; with no progress
+/// event left to discard, the oldest `tool_result` still carrying its
+/// payload (up to 32 KB) is cut to a stub (`resultOmitted`). Events past
+/// the capacity are then small, so memory stays bounded. Every dropped or
+/// stubbed event is counted so the client can be told.
+struct ToolEventQueue {
+    events: Mutex<VecDeque<serde_json::Value>>,
+    capacity: usize,
+    dropped: AtomicU64,
+    closed: AtomicBool,
+    /// Set once every sender is gone, so `recv` can end.
+    senders_gone: AtomicBool,
+    notify: Notify,
+}
+
+/// Shared by all clones of a turn's `ToolEventSender`; dropping the last one
+/// tells the receiver no more events will come.
+struct SenderGuard(Arc<ToolEventQueue>);
+
+impl Drop for SenderGuard {
+    fn drop(&mut self) {
+        self.0.senders_gone.store(true, Ordering::SeqCst);
+        self.0.notify.notify_one();
+    }
+}
+
+/// Progress of one tool call, reported to the client. `tool_call` and
+/// `tool_result` events carry `started` and `finished`; the phases in between
+/// are sent as `tool_phase` events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolPhase {
+    Started,
+    /// The call was handed to the tool's server (a Google API behind an MCP server, …).
+    NetworkRequest,
+    /// The previous attempt failed transiently; another follows after a delay.
+    Retrying,
+    Finished,
+}
+
+impl ToolPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Started => "started",
+            Self::NetworkRequest => "network_request",
+            Self::Retrying => "retrying",
+            Self::Finished => "finished",
+        }
+    }
+}
+
+/// What each successful tool call of a turn handed the model (tool name,
+/// output after budgets, sanitizing and redaction), for `history_compress`.
+pub type ToolOutputs = Arc<Mutex<Vec<(String, String)>>>;
+
 /// Sender half of the tool-event channel.  Clone one per tool instance.
-pub type ToolEventSender = mpsc::Sender<serde_json::Value>;
+#[derive(Clone)]
+pub struct ToolEventSender {
+    queue: Arc<ToolEventQueue>,
+    _guard: Arc<SenderGuard>,
+    /// Set for interactive turns: destructive tool calls wait for the user.
+    confirmations: Option<crate::confirm::Confirmations>,
+    /// Nobody attends this turn (scheduled jobs, watches): guarded calls are refused.
+    unattended: bool,
+    /// Destructive tools describe what they would do instead of doing it.
+    dry_run: bool,
+    /// Mask PII in tool output before it is sent to a cloud provider.
+    redact_pii: bool,
+    /// Tools treated as destructive on top of `confirm::is_destructive`
+    /// (a sub-agent's own confirmation policy).
+    guarded_tools: &'static [&'static str],
+    /// Calendar create-event calls first look for overlapping events.
+    check_calendar_conflicts: bool,
+    /// The session's scratchpad; background turns get a fresh one.
+    scratchpad: Scratchpad,
+    /// The approved plan this turn carries out, if any.
+    plan: Option<crate::planner::ActivePlan>,
+    /// Calls made this turn, by tool name and arguments, for the loop guard.
+    calls: Arc<Mutex<HashMap<String, u32>>>,
+    /// Caps on the MCP output handed to the model, shared by the turn.
+    output_budget: crate::output_budget::OutputBudget,
+    /// Stream the answer as `response_chunk` events while it is generated.
+    streaming: bool,
+    /// Keychain items `get_secret` may read, and the values fetched this turn.
+    secret_refs: crate::secret_refs::SecretRefs,
+    /// Where MCP calls are recorded to or replayed from (`replay.rs`).
+    tape: Option<Arc<crate::replay::Tape>>,
+    /// Outputs the model was given this turn.
+    outputs: ToolOutputs,
+}
+
+/// Receiver half, owned by the chat handler forwarding events to the client.
+pub struct ToolEventReceiver(Arc<ToolEventQueue>);
+
+pub fn tool_event_channel(capacity: usize) -> (ToolEventSender, ToolEventReceiver) {
+    let queue = Arc::new(ToolEventQueue {
+        events: Mutex::new(VecDeque::new()),
+        capacity: capacity.max(1),
+        dropped: AtomicU64::new(0),
+        closed: AtomicBool::new(false),
+        senders_gone: AtomicBool::new(false),
+        notify: Notify::new(),
+    });
+    let sender = ToolEventSender {
+        queue: queue.clone(),
+        _guard: Arc::new(SenderGuard(queue.clone())),
+        confirmations: None,
+        unattended: false,
+        dry_run: false,
+        redact_pii: false,
+        guarded_tools: &[],
+        check_calendar_conflicts: false,
+        scratchpad: Scratchpad::default(),
+        plan: None,
+        calls: Default::default(),
+        output_budget: Default::default(),
+        streaming: false,
+        secret_refs: Default::default(),
+        tape: None,
+        outputs: Default::default(),
+    };
+    (sender, ToolEventReceiver(queue))
+}
+
+fn event_key(event: &serde_json::Value) -> (Option<&str>, Option<&str>) {
+    (event["type"].as_str(), event["content"]["toolName"].as_str())
+}
+
+/// Whether a full queue may discard `event`. Dropping a call or result would
+/// unpair them in the client and in `TurnTimings`; a tool waiting on a
+/// confirmation or plan frame would wait out its timeout with nothing shown.
+fn is_droppable(event: &serde_json::Value) -> bool {
+    event["type"].as_str().is_some_and(|t| PROGRESS_EVENTS.contains(&t))
+}
+
+/// A `tool_result` whose payload a full queue may still cut to a stub.
+fn is_stubbable(event: &serde_json::Value) -> bool {
+    event["type"] == "tool_result" && event["content"]["resultOmitted"] != true
+}
+
+impl ToolEventSender {
+    /// Ask the user before destructive tool calls made through this sender.
+    pub fn with_confirmations(mut self, confirmations: crate::confirm::Confirmations) -> Self {
+        self.confirmations = Some(confirmations);
+        self
+    }
+
+    /// Refuse guarded calls outright: there is nobody to ask.
+    pub fn unattended(mut self) -> Self {
+        self.unattended = true;
+        self
+    }
+
+    pub fn is_unattended(&self) -> bool {
+        self.unattended
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn with_pii_redaction(mut self, redact_pii: bool) -> Self {
+        self.redact_pii = redact_pii;
+        self
+    }
+
+    pub fn redacts_pii(&self) -> bool {
+        self.redact_pii
+    }
+
+    pub fn with_calendar_conflict_check(mut self, enabled: bool) -> Self {
+        self.check_calendar_conflicts = enabled;
+        self
+    }
+
+    pub fn checks_calendar_conflicts(&self) -> bool {
+        self.check_calendar_conflicts
+    }
+
+    pub fn with_scratchpad(mut self, scratchpad: Scratchpad) -> Self {
+        self.scratchpad = scratchpad;
+        self
+    }
+
+    pub fn scratchpad(&self) -> Scratchpad {
+        self.scratchpad.clone()
+    }
+
+    pub fn with_plan(mut self, plan: crate::planner::ActivePlan) -> Self {
+        self.plan = Some(plan);
+        self
+    }
+
+    pub fn plan(&self) -> Option<&crate::planner::ActivePlan> {
+        self.plan.as_ref()
+    }
+
+    pub fn with_output_budget(mut self, budget: crate::output_budget::OutputBudget) -> Self {
+        self.output_budget = budget;
+        self
+    }
+
+    pub fn output_budget(&self) -> &crate::output_budget::OutputBudget {
+        &self.output_budget
+    }
+
+    pub fn with_streaming(mut self, streaming: bool) -> Self {
+        self.streaming = streaming;
+        self
+    }
+
+    pub fn streams(&self) -> bool {
+        self.streaming
+    }
+
+    pub fn with_tape(mut self, tape: Option<Arc<crate::replay::Tape>>) -> Self {
+        self.tape = tape;
+        self
+    }
+
+    pub fn tape(&self) -> Option<&Arc<crate::replay::Tape>> {
+        self.tape.as_ref()
+    }
+
+    /// Whether this turn is served from a recording.
+    pub fn replays(&self) -> bool {
+        self.tape.as_ref().is_some_and(|t| t.replays())
+    }
+
+    pub fn with_tool_outputs(mut self, outputs: ToolOutputs) -> Self {
+        self.outputs = outputs;
+        self
+    }
+
+    /// Keep what a successful call handed the model.
+    pub fn record_output(&self, tool_name: &str, output: String) {
+        self.outputs.lock().unwrap_or_else(|e| e.into_inner()).push((tool_name.to_string(), output));
+    }
+
+    pub fn with_secret_items(mut self, items: Vec<crate::secret_refs::SecretItem>) -> Self {
+        self.secret_refs = crate::secret_refs::SecretRefs::new(items);
+        self
+    }
+
+    pub fn secret_refs(&self) -> &crate::secret_refs::SecretRefs {
+        &self.secret_refs
+    }
+
+    /// Also confirm (and dry-run) these tools, whatever their names.
+    pub fn with_guarded_tools(mut self, tools: &'static [&'static str]) -> Self {
+        self.guarded_tools = tools;
+        self
+    }
+
+    /// Whether a call needs confirmation and is simulated in dry-run mode.
+    pub fn is_guarded(&self, tool_name: &str) -> bool {
+        crate::confirm::is_destructive(tool_name) || self.guarded_tools.contains(&tool_name)
+    }
+
+    /// `true` if the call may proceed: it is not guarded, the user turned
+    /// confirmations off, or the user approved it. Unattended turns
+    /// (background jobs) never run a guarded call.
+    pub async fn confirm(&self, tool_name: &str, args: &serde_json::Value) -> bool {
+        if !self.is_guarded(tool_name) {
+            return true;
+        }
+        match &self.confirmations {
+            Some(c) => c.request(self, tool_name, args).await,
+            None => !self.unattended,
+        }
+    }
+
+    /// Like `confirm`, but for any tool: calls that would receive a secret
+    /// are shown to the user first whatever the tool's name. Refuses when
+    /// there is nobody to ask (background turns, confirmations turned off).
+    pub async fn confirm_always(&self, tool_name: &str, args: &serde_json::Value) -> bool {
+        match &self.confirmations {
+            Some(c) => c.request(self, tool_name, args).await,
+            None => false,
+        }
+    }
+
+    /// Tool calls made through this turn's senders so far.
+    pub fn calls_made(&self) -> u32 {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).values().sum()
+    }
+
+    /// Loop guard: `None` while a call with these exact arguments has been
+    /// made at most `RONGE_TOOL_REPEAT_LIMIT` (default 3) times this turn.
+    /// Past that the call must not run; the returned notice replaces its
+    /// result, and a `tool_loop_intervention` event is sent.
+    pub async fn check_repeat(&self, tool_name: &str, args: &serde_json::Value) -> Option<String> {
+        let count = {
+            let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+            let count = calls.entry(format!("{}\u{1f}{}", tool_name, args)).or_insert(0);
+            *count += 1;
+            *count
+        };
+        let limit = std::env::var("RONGE_TOOL_REPEAT_LIMIT")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_REPEAT_LIMIT);
+        if count <= limit {
+            return None;
+        }
+        println!("🔁 {} called {} times with identical arguments; not running it again", tool_name, count);
+        let _ = self
+            .send(serde_json::json!({
+                "type": "tool_loop_intervention",
+                "content": {"toolName": tool_name, "repeats": count, "limit": limit}
+            }))
+            .await;
+        Some(format!(
+            "[System notice] {} was already called {} times in this turn with exactly these arguments, \
+             so it was not run again. Do not repeat this call. Answer with the results you already \
+             have, or try a different tool or different arguments.",
+            tool_name,
+            count - 1
+        ))
+    }
+
+    /// Report an intermediate phase of a running tool call.
+    pub async fn phase(&self, tool_name: &str, phase: ToolPhase, detail: serde_json::Value) {
+        let _ = self
+            .send(serde_json::json!({
+                "type": "tool_phase",
+                "content": {"toolName": tool_name, "phase": phase.as_str(), "detail": detail}
+            }))
+            .await;
+    }
+
+    /// Enqueue without waiting. Returns the event back if the receiver is gone.
+    /// `tool_call` and `tool_result` events are stamped with their phase.
+    pub async fn send(&self, mut event: serde_json::Value) -> Result<(), serde_json::Value> {
+        if self.queue.closed.load(Ordering::SeqCst) {
+            return Err(event);
+        }
+        let phase = match event["type"].as_str() {
+            Some("tool_call") => Some(ToolPhase::Started),
+            Some("tool_result") => Some(ToolPhase::Finished),
+            _ => None,
+        };
+        if let (Some(phase), Some(content)) = (phase, event.get_mut("content").and_then(|c| c.as_object_mut())) {
+            content.entry("phase").or_insert_with(|| serde_json::json!(phase.as_str()));
+        }
+        {
+            let mut events = self.queue.events.lock().unwrap_or_else(|e| e.into_inner());
+            // Text chunks the client hasn't taken yet are merged, never dropped.
+            if event["type"] == "response_chunk"
+                && let Some(last) = events.back_mut().filter(|last| last["type"] == "response_chunk")
+            {
+                let text = format!(
+                    "{}{}",
+                    last["content"]["text"].as_str().unwrap_or(""),
+                    event["content"]["text"].as_str().unwrap_or("")
+                );
+                last["content"]["text"] = serde_json::json!(text);
+                return Ok(());
+            }
+            if events.len() >= self.queue.capacity {
+                let superseded = (0..events.len()).find(|&i| {
+                    let key = event_key(&events[i]);
+                    is_droppable(&events[i]) && events.iter().skip(i + 1).any(|later| event_key(later) == key)
+                });
+                if let Some(victim) = superseded.or_else(|| events.iter().position(is_droppable)) {
+                    events.remove(victim);
+                    self.queue.dropped.fetch_add(1, Ordering::SeqCst);
+                } else if let Some(result) =
+                    events.iter_mut().chain(std::iter::once(&mut event)).find(|e| is_stubbable(e))
+                {
+                    // Nothing droppable: the queue grows, but only by small events.
+                    result["content"]["result"] = serde_json::json!(OMITTED_RESULT);
+                    result["content"]["resultOmitted"] = serde_json::json!(true);
+                    self.queue.dropped.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+            events.push_back(event);
+        }
+        self.queue.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl ToolEventReceiver {
+    /// Wait for the next event; `None` once every sender is gone and the
+    /// queue is empty.
+    pub async fn recv(&mut self) -> Option<serde_json::Value> {
+        loop {
+            if let Some(event) = self.try_recv() {
+                return Some(event);
+            }
+            if self.0.senders_gone.load(Ordering::SeqCst) {
+                return self.try_recv();
+            }
+            self.0.notify.notified().await;
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Option<serde_json::Value> {
+        self.0
+            .events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front()
+    }
+
+    /// Number of events discarded because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for ToolEventReceiver {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A wrapped tool's own error, or the loop guard's refusal to run it again.
+#[derive(Debug, Error)]
+pub enum NotifyingError<E: std::error::Error + 'static> {
+    #[error(transparent)]
+    Tool(E),
+    #[error("{0}")]
+    Repeated(String),
+}
+
+/// The loop-guard interventions among a turn's events, for the response metadata.
+pub fn loop_interventions(events: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    events
+        .iter()
+        .filter(|e| e["type"] == "tool_loop_intervention")
+        .map(|e| e["content"].clone())
+        .collect()
+}
+
+/// Built-in tools whose work is a remote request, announced with a
+/// `network_request` phase once the call starts.
+const NETWORK_TOOLS: &[&str] = &[ExportToGoogleDoc::NAME];
 
 /// Wraps any `Tool` and fires `tool_call` / `tool_result` WebSocket events
 /// on `tx` whenever the tool is invoked.
@@ -25,7 +490,7 @@ where
     const NAME: &'static str = T::NAME;
     type Args = T::Args;
     type Output = T::Output;
-    type Error = T::Error;
+    type Error = NotifyingError<T::Error>;
 
     async fn definition(&self, prompt: String) -> ToolDefinition {
         self.inner.definition(prompt).await
@@ -49,16 +514,51 @@ where
             }))
             .await;
 
-        let result = self.inner.call(args).await?;
+        if let Some(notice) = self.tx.check_repeat(T::NAME, &args_json).await {
+            let _ = self
+                .tx
+                .send(serde_json::json!({
+                    "type": "tool_result",
+                    "content": {"toolName": T::NAME, "result": &notice, "durationMs": 0, "success": false}
+                }))
+                .await;
+            return Err(NotifyingError::Repeated(notice));
+        }
+
+        if NETWORK_TOOLS.contains(&T::NAME) {
+            self.tx.phase(T::NAME, ToolPhase::NetworkRequest, serde_json::json!({"attempt": 1})).await;
+        }
+        let started = std::time::Instant::now();
+        let result = self.inner.call(args).await;
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                let _ = self
+                    .tx
+                    .send(serde_json::json!({
+                        "type": "tool_result",
+                        "content": {
+                            "toolName": T::NAME,
+                            "result": e.to_string(),
+                            "durationMs": duration_ms,
+                            "success": false
+                        }
+                    }))
+                    .await;
+                return Err(NotifyingError::Tool(e));
+            }
+        };
 
         // Notify UI: tool finished
         // Schema matches Swift ToolResultContent { toolName, result }
         if let Ok(result_str) = serde_json::to_string(&result) {
+            self.tx.record_output(T::NAME, result_str.clone());
             const MAX_RESULT_BYTES: usize = 32 * 1024; // 32 KB
             let result_str = if result_str.len() > MAX_RESULT_BYTES {
                 format!(
                     "{}... [truncated — {} bytes total]",
-                    &result_str[..MAX_RESULT_BYTES],
+                    crate::text::truncate_bytes(&result_str, MAX_RESULT_BYTES),
                     result_str.len()
                 )
             } else {
@@ -70,7 +570,11 @@ where
                     "type": "tool_result",
                     "content": {
                         "toolName": T::NAME,
-                        "result": result_str
+                        "result": result_str,
+                        "durationMs": duration_ms,
+                        "success": true,
+                        // Ours, not an MCP tool that happens to share the name.
+                        "builtin": true
                     }
                 }))
                 .await;
@@ -142,6 +646,182 @@ impl Tool for Calculator {
 #[derive(Deserialize, Serialize)]
 pub struct EmptyArgs {}
 
+// ── Desktop Platform ──
+
+/// OS-specific launching of applications and browser tabs used by
+/// `OpenApplication` and `OpenChromeTab`.
+#[cfg(target_os = "macos")]
+mod desktop {
+    use super::ToolError;
+
+    /// Run an AppleScript with user-supplied values passed as `argv` of its
+    /// `on run` handler. Values are never spliced into the script source, so
+    /// quotes or AppleScript syntax in a name or URL cannot change what runs.
+    /// Every AppleScript-based tool must go through this helper.
+    async fn osascript(script: &str, args: &[&str]) -> Result<std::process::ExitStatus, ToolError> {
+        Ok(tokio::process::Command::new("osascript")
+            .arg("-e")
+            .arg(script)
+            .args(args)
+            .status()
+            .await?)
+    }
+
+    pub async fn open_application(app_name: &str) -> Result<(), ToolError> {
+        let status = tokio::process::Command::new("open")
+            .arg("-a")
+            .arg(app_name)
+            .status()
+            .await?;
+
+        if !status.success() {
+            return Err(ToolError::CommandFailed(format!("Could not open '{}'. Make sure the app is installed on this Mac.", app_name)));
+        }
+
+        let _ = osascript(
+            "on run argv\n    tell application (item 1 of argv) to activate\nend run",
+            &[app_name],
+        )
+        .await;
+        Ok(())
+    }
+
+    pub async fn open_chrome_tab(url: &str) -> Result<(), ToolError> {
+        let script = r#"on run argv
+    set targetURL to item 1 of argv
+    tell application "Google Chrome"
+        activate
+        if (count every window) = 0 then
+            make new window
+        end if
+        tell window 1
+            make new tab with properties {URL:targetURL}
+        end tell
+    end tell
+end run"#;
+
+        let status = osascript(script, &[url]).await?;
+
+        if !status.success() {
+            return Err(ToolError::CommandFailed("Could not open the URL in Chrome. Make sure Google Chrome is installed.".into()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod desktop {
+    use super::ToolError;
+
+    /// Run a PowerShell command with `target` passed through the environment,
+    /// so names and URLs are never parsed as script.
+    async fn powershell(command: &str, target: &str) -> Result<bool, ToolError> {
+        let status = tokio::process::Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", command])
+            .env("RONGE_TARGET", target)
+            .status()
+            .await?;
+        Ok(status.success())
+    }
+
+    pub async fn open_application(app_name: &str) -> Result<(), ToolError> {
+        if powershell("Start-Process -FilePath $env:RONGE_TARGET", app_name).await? {
+            return Ok(());
+        }
+        Err(ToolError::CommandFailed(format!("Could not open '{}'. Make sure the app is installed on this PC.", app_name)))
+    }
+
+    pub async fn open_chrome_tab(url: &str) -> Result<(), ToolError> {
+        if powershell("Start-Process -FilePath chrome -ArgumentList $env:RONGE_TARGET", url).await?
+            || powershell("Start-Process -FilePath $env:RONGE_TARGET", url).await?
+        {
+            return Ok(());
+        }
+        Err(ToolError::CommandFailed("Could not open the URL. Make sure Google Chrome or a default browser is installed.".into()))
+    }
+}
+
+/// Linux and other Unix desktops.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+mod desktop {
+    use super::ToolError;
+
+    const CHROME_BINARIES: &[&str] = &["google-chrome", "google-chrome-stable", "chromium", "chromium-browser"];
+
+    /// `applications/` under the XDG data directories, user entries first.
+    fn application_dirs() -> Vec<std::path::PathBuf> {
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(std::path::PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".local/share")));
+        let data_dirs = std::env::var("XDG_DATA_DIRS").unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+        data_home
+            .into_iter()
+            .chain(data_dirs.split(':').filter(|d| !d.is_empty()).map(std::path::PathBuf::from))
+            .map(|dir| dir.join("applications"))
+            .collect()
+    }
+
+    /// The id of the installed, visible desktop entry whose file name or
+    /// `Name=` matches `app_name` (case-insensitively).
+    async fn desktop_entry(app_name: &str) -> Option<String> {
+        let wanted = app_name.trim().to_lowercase();
+        for dir in application_dirs() {
+            let Ok(mut entries) = tokio::fs::read_dir(&dir).await else { continue };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                let Some(id) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".desktop"))
+                else {
+                    continue;
+                };
+                let Ok(contents) = tokio::fs::read_to_string(&path).await else { continue };
+                let field = |key: &str| {
+                    contents.lines().find_map(|line| line.strip_prefix(key)?.strip_prefix('=').map(str::trim))
+                };
+                if field("NoDisplay") == Some("true") || field("Hidden") == Some("true") {
+                    continue;
+                }
+                if id.to_lowercase() == wanted || field("Name").is_some_and(|name| name.to_lowercase() == wanted) {
+                    return Some(id.to_string());
+                }
+            }
+        }
+        None
+    }
+
+    /// Only installed desktop entries are launched (`gtk-launch <id>`); a
+    /// name is never run as a command.
+    pub async fn open_application(app_name: &str) -> Result<(), ToolError> {
+        let Some(id) = desktop_entry(app_name).await else {
+            return Err(ToolError::CommandFailed(format!(
+                "Could not find an installed application called '{}'.",
+                app_name
+            )));
+        };
+        let launched = tokio::process::Command::new("gtk-launch")
+            .arg(&id)
+            .status()
+            .await
+            .is_ok_and(|s| s.success());
+        if !launched {
+            return Err(ToolError::CommandFailed(format!("Could not open '{}'. Make sure gtk-launch is installed.", app_name)));
+        }
+        Ok(())
+    }
+
+    pub async fn open_chrome_tab(url: &str) -> Result<(), ToolError> {
+        for binary in CHROME_BINARIES {
+            if tokio::process::Command::new(binary).arg(url).spawn().is_ok() {
+                return Ok(());
+            }
+        }
+        let status = tokio::process::Command::new("xdg-open").arg(url).status().await?;
+        if !status.success() {
+            return Err(ToolError::CommandFailed("Could not open the URL. Make sure Google Chrome or a default browser is installed.".into()));
+        }
+        Ok(())
+    }
+}
+
 // ── OpenApplication ──
 
 #[derive(Deserialize, Serialize)]
@@ -161,7 +841,7 @@ impl Tool for OpenApplication {
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
             name: "open_application".to_string(),
-            description: "Opens a specified application on macOS (e.g. Safari, Spotify, Terminal).".to_string(),
+            description: "Opens a specified application on this computer (e.g. Safari, Spotify, Terminal, Firefox).".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -173,28 +853,33 @@ impl Tool for OpenApplication {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let status = tokio::process::Command::new("open")
-            .arg("-a")
-            .arg(&args.app_name)
-            .status()
-            .await?;
-
-        if !status.success() {
-            return Err(ToolError::CommandFailed(format!("Could not open '{}'. Make sure the app is installed on this Mac.", args.app_name)));
-        }
-
-        let _ = tokio::process::Command::new("osascript")
-            .arg("-e")
-            .arg(format!("activate application \"{}\"", args.app_name))
-            .status()
-            .await;
-
+        desktop::open_application(&args.app_name).await?;
         Ok(format!("Opened {}", args.app_name))
     }
 }
 
 // ── OpenChromeTab ──
 
+/// Only web pages may be opened: bare hosts get `https://`, while other
+/// schemes (`file:`, `javascript:`, app deep links) are refused.
+fn web_url(raw: &str) -> Result<String, ToolError> {
+    let url = raw.trim();
+    let lower = url.to_ascii_lowercase();
+    if lower.starts_with("https://") || lower.starts_with("http://") {
+        return Ok(url.to_string());
+    }
+    // `scheme:...` other than http(s); `host:port` is still accepted.
+    let other_scheme = lower.split_once(':').is_some_and(|(scheme, rest)| {
+        !scheme.is_empty()
+            && scheme.chars().all(|c| c.is_ascii_alphabetic() || matches!(c, '+' | '-' | '.'))
+            && !rest.starts_with(|c: char| c.is_ascii_digit())
+    });
+    if other_scheme {
+        return Err(ToolError::CommandFailed(format!("Refusing to open '{}': only http(s) links are allowed.", url)));
+    }
+    Ok(format!("https://{}", url))
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct OpenChromeTab;
 
@@ -212,7 +897,7 @@ impl Tool for OpenChromeTab {
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
             name: "open_chrome_tab".to_string(),
-            description: "Opens a URL in a new tab in Google Chrome.".to_string(),
+            description: "Opens a URL in a new tab in Google Chrome (or the default browser if Chrome is unavailable).".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -224,30 +909,9 @@ impl Tool for OpenChromeTab {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let script = format!(
-            r#"tell application "Google Chrome"
-    activate
-    if (count every window) = 0 then
-        make new window
-    end if
-    tell window 1
-        make new tab with properties {{URL:"{}"}}
-    end tell
-end tell"#,
-            args.url
-        );
-
-        let status = tokio::process::Command::new("osascript")
-            .arg("-e")
-            .arg(&script)
-            .status()
-            .await?;
-
-        if !status.success() {
-            return Err(ToolError::CommandFailed("Could not open the URL in Chrome. Make sure Google Chrome is installed.".into()));
-        }
-
-        Ok(format!("Opened {} in Chrome", args.url))
+        let url = web_url(&args.url)?;
+        desktop::open_chrome_tab(&url).await?;
+        Ok(format!("Opened {} in Chrome", url))
     }
 }
 
@@ -267,11 +931,18 @@ pub fn default_memory_path() -> PathBuf {
 pub struct ReadMemory {
     #[serde(skip)]
     pub path: PathBuf,
+    #[serde(skip)]
+    pub redact_pii: bool,
 }
 
 impl ReadMemory {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self { path, redact_pii: false }
+    }
+
+    pub fn with_pii_redaction(mut self, redact_pii: bool) -> Self {
+        self.redact_pii = redact_pii;
+        self
     }
 }
 
@@ -296,6 +967,7 @@ impl Tool for ReadMemory {
     async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
         match tokio::fs::read_to_string(&self.path).await {
             Ok(content) if content.trim().is_empty() => Ok("No memories saved yet.".to_string()),
+            Ok(content) if self.redact_pii => Ok(crate::pii::redact(&content)),
             Ok(content) => Ok(content),
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
                 Ok("No memories saved yet.".to_string())
@@ -311,11 +983,18 @@ impl Tool for ReadMemory {
 pub struct SaveToMemory {
     #[serde(skip)]
     pub path: PathBuf,
+    #[serde(skip)]
+    pub dry_run: bool,
 }
 
 impl SaveToMemory {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self { path, dry_run: false }
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
     }
 }
 
@@ -345,11 +1024,16 @@ impl Tool for SaveToMemory {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        if let Some(parent) = self.path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
+        if self.dry_run {
+            return Ok(format!(
+                "[DRY RUN] Nothing was saved. Would replace the memory file with {} characters:\n{}",
+                args.content.len(),
+                args.content
+            ));
         }
-        tokio::fs::write(&self.path, &args.content).await?;
-        Ok(format!("Saved to memory ({} characters).", args.content.len()))
+        let length = args.content.len();
+        crate::memory::write(&self.path, crate::memory::MemoryWrite::Replace(args.content)).await?;
+        Ok(format!("Saved to memory ({} characters).", length))
     }
 }
 
@@ -359,11 +1043,18 @@ impl Tool for SaveToMemory {
 pub struct AppendToMemory {
     #[serde(skip)]
     pub path: PathBuf,
+    #[serde(skip)]
+    pub dry_run: bool,
 }
 
 impl AppendToMemory {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self { path, dry_run: false }
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
     }
 }
 
@@ -393,23 +1084,318 @@ impl Tool for AppendToMemory {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        if let Some(parent) = self.path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
+        if self.dry_run {
+            return Ok(format!(
+                "[DRY RUN] Nothing was saved. Would append {} characters to memory:\n{}",
+                args.content.len(),
+                args.content
+            ));
         }
+        let length = args.content.len();
+        crate::memory::write(&self.path, crate::memory::MemoryWrite::Append(args.content)).await?;
+        Ok(format!("Added to memory ({} characters appended).", length))
+    }
+}
 
-        let existing = match tokio::fs::read_to_string(&self.path).await {
-            Ok(c) => c,
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
-            Err(e) => return Err(ToolError::Io(e)),
-        };
+// ── Scratchpad ──
+
+/// Working notes of one session, in memory only and cleared by `reset_session`.
+pub type Scratchpad = Arc<Mutex<String>>;
+
+/// Characters a scratchpad may hold.
+const MAX_SCRATCHPAD_CHARS: usize = 20_000;
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct WriteScratchpad {
+    #[serde(skip)]
+    pub pad: Scratchpad,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct WriteScratchpadArgs {
+    content: String,
+    #[serde(default)]
+    append: bool,
+}
+
+impl Tool for WriteScratchpad {
+    const NAME: &'static str = "write_scratchpad";
+    type Args = WriteScratchpadArgs;
+    type Output = String;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "write_scratchpad".to_string(),
+            description: "Write working notes for this conversation (a plan, intermediate results, what is left to do). Kept until the conversation is cleared; not saved to memory.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "content": { "type": "string", "description": "Notes to write" },
+                    "append": { "type": "boolean", "description": "Add to the existing notes instead of replacing them" }
+                },
+                "required": ["content"]
+            }),
+        }
+    }
 
-        let new_content = if existing.is_empty() {
-            args.content.clone()
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let mut pad = self.pad.lock().unwrap_or_else(|e| e.into_inner());
+        let new_content = if args.append && !pad.is_empty() {
+            format!("{}\n{}", pad, args.content)
         } else {
-            format!("{}\n\n{}", existing, args.content)
+            args.content
         };
+        let chars = new_content.chars().count();
+        if chars > MAX_SCRATCHPAD_CHARS {
+            return Err(ToolError::CommandFailed(format!(
+                "The scratchpad holds at most {} characters ({} given); condense the notes.",
+                MAX_SCRATCHPAD_CHARS, chars
+            )));
+        }
+        *pad = new_content;
+        Ok(format!("Scratchpad saved ({} characters).", chars))
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct ReadScratchpad {
+    #[serde(skip)]
+    pub pad: Scratchpad,
+}
+
+impl Tool for ReadScratchpad {
+    const NAME: &'static str = "read_scratchpad";
+    type Args = EmptyArgs;
+    type Output = String;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "read_scratchpad".to_string(),
+            description: "Read this conversation's working notes written with write_scratchpad.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        }
+    }
+
+    async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let pad = self.pad.lock().unwrap_or_else(|e| e.into_inner());
+        if pad.trim().is_empty() {
+            Ok("The scratchpad is empty.".to_string())
+        } else {
+            Ok(pad.clone())
+        }
+    }
+}
+
+// ── RenderChart ──
+
+#[derive(Deserialize, Serialize)]
+pub struct RenderChart;
+
+#[derive(Deserialize, Serialize)]
+pub struct RenderChartArgs {
+    title: String,
+    #[serde(default)]
+    chart_type: Option<String>,
+    labels: Vec<String>,
+    series: Vec<crate::chart::ChartSeries>,
+    #[serde(default)]
+    y_label: Option<String>,
+}
+
+/// Returned to the model and picked up by the response builder, which
+/// attaches the PNG at `path` to the final response's `images`.
+#[derive(Deserialize, Serialize)]
+pub struct RenderChartOutput {
+    pub path: String,
+    pub title: String,
+}
+
+impl Tool for RenderChart {
+    const NAME: &'static str = "render_chart";
+    type Args = RenderChartArgs;
+    type Output = RenderChartOutput;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "render_chart".to_string(),
+            description: "Render a bar or line chart as an image shown to the user with the answer. Use it whenever the user asks to chart, plot or graph data (e.g. values read from a spreadsheet).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string", "description": "Chart title" },
+                    "chart_type": { "type": "string", "enum": ["bar", "line"], "description": "Defaults to bar" },
+                    "labels": { "type": "array", "items": { "type": "string" }, "description": "X-axis category labels, e.g. months" },
+                    "series": {
+                        "type": "array",
+                        "description": "One entry per data series; values align with labels",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": { "type": "string" },
+                                "values": { "type": "array", "items": { "type": "number" } }
+                            },
+                            "required": ["name", "values"]
+                        }
+                    },
+                    "y_label": { "type": "string", "description": "Y-axis caption, e.g. \"USD\"" }
+                },
+                "required": ["title", "labels", "series"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let dir = crate::chart::charts_dir();
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join(format!(
+            "chart-{}.png",
+            chrono::Local::now().format("%Y%m%d-%H%M%S-%3f")
+        ));
+
+        let render_path = path.clone();
+        let title = args.title.clone();
+        tokio::task::spawn_blocking(move || {
+            crate::chart::draw_chart(
+                &render_path,
+                &args.title,
+                args.chart_type.as_deref().unwrap_or("bar"),
+                &args.labels,
+                &args.series,
+                args.y_label.as_deref(),
+            )
+        })
+        .await
+        .map_err(|e| ToolError::CommandFailed(e.to_string()))?
+        .map_err(ToolError::CommandFailed)?;
+
+        Ok(RenderChartOutput {
+            path: path.to_string_lossy().to_string(),
+            title,
+        })
+    }
+}
+
+// ── ExportToGoogleDoc ──
+
+/// Only attached when a Google Docs create-document MCP tool is connected.
+pub struct ExportToGoogleDoc {
+    pub target: Arc<crate::docs_export::DocsTarget>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExportToGoogleDocArgs {
+    title: String,
+    content: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExportToGoogleDocOutput {
+    pub url: String,
+    pub title: String,
+}
+
+impl Tool for ExportToGoogleDoc {
+    const NAME: &'static str = "export_to_google_doc";
+    type Args = ExportToGoogleDocArgs;
+    type Output = ExportToGoogleDocOutput;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "export_to_google_doc".to_string(),
+            description: "Create a Google Doc from a report or conversation summary you have written, and get its link. Give the user the link.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string", "description": "Document title" },
+                    "content": { "type": "string", "description": "Document body in Markdown" }
+                },
+                "required": ["title", "content"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let url = crate::docs_export::create_doc(&self.target, &args.title, &args.content)
+            .await
+            .map_err(ToolError::CommandFailed)?;
+        Ok(ExportToGoogleDocOutput { url, title: args.title })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result(tool: &str, payload: &str) -> serde_json::Value {
+        json!({"type": "tool_result", "content": {"toolName": tool, "success": true, "result": payload}})
+    }
+
+    fn drain(rx: &mut ToolEventReceiver) -> Vec<serde_json::Value> {
+        std::iter::from_fn(|| rx.try_recv()).collect()
+    }
+
+    #[tokio::test]
+    async fn full_queue_drops_superseded_progress_first() {
+        let (tx, mut rx) = tool_event_channel(3);
+        tx.phase("search", ToolPhase::Started, json!("first")).await;
+        tx.send(json!({"type": "tool_call", "content": {"toolName": "search"}})).await.unwrap();
+        tx.phase("search", ToolPhase::Started, json!("second")).await;
+        tx.send(result("search", "found")).await.unwrap();
+
+        let events = drain(&mut rx);
+        assert_eq!(rx.dropped(), 1);
+        let kinds: Vec<_> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["tool_call", "tool_phase", "tool_result"]);
+        assert_eq!(events[1]["content"]["detail"], "second");
+        assert_eq!(events[2]["content"]["result"], "found");
+    }
+
+    #[tokio::test]
+    async fn full_queue_stubs_results_when_nothing_is_droppable() {
+        let (tx, mut rx) = tool_event_channel(2);
+        tx.send(result("a", "first payload")).await.unwrap();
+        tx.send(result("b", "second payload")).await.unwrap();
+        tx.send(result("c", "third payload")).await.unwrap();
+        tx.send(result("d", "fourth payload")).await.unwrap();
+
+        let events = drain(&mut rx);
+        assert_eq!(rx.dropped(), 2);
+        // Every result still arrives, in order, with the oldest payloads cut.
+        let tools: Vec<_> = events.iter().map(|e| e["content"]["toolName"].as_str().unwrap()).collect();
+        assert_eq!(tools, ["a", "b", "c", "d"]);
+        for event in &events[..2] {
+            assert_eq!(event["content"]["result"], OMITTED_RESULT);
+            assert_eq!(event["content"]["resultOmitted"], true);
+        }
+        assert_eq!(events[2]["content"]["result"], "third payload");
+        assert_eq!(events[3]["content"]["result"], "fourth payload");
+    }
+
+    #[tokio::test]
+    async fn response_chunks_are_merged_not_dropped() {
+        let (tx, mut rx) = tool_event_channel(1);
+        for text in ["Hel", "lo", "!"] {
+            tx.send(json!({"type": "response_chunk", "content": {"text": text}})).await.unwrap();
+        }
+        let events = drain(&mut rx);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["content"]["text"], "Hello!");
+        assert_eq!(rx.dropped(), 0);
+    }
 
-        tokio::fs::write(&self.path, &new_content).await?;
-        Ok(format!("Added to memory ({} characters appended).", args.content.len()))
+    #[tokio::test]
+    async fn send_fails_once_the_receiver_is_gone() {
+        let (tx, rx) = tool_event_channel(4);
+        drop(rx);
+        assert!(tx.send(result("a", "late")).await.is_err());
     }
 }
@@ -0,0 +1,154 @@
+use rig::OneOrMany;
+use rig::message::{AssistantContent, Message as RigMessage, ToolResultContent, UserContent};
+use serde_json::{json, Value};
+
+/// Tool output kept when a result is flattened into text.
+const MAX_TOOL_RESULT_CHARS: usize = 2_000;
+
+/// Model-name fragments of text-only models, whose requests fail when the
+/// history still holds images.
+const TEXT_ONLY_MODELS: &[&str] = &["gpt-3.5", "o1-mini", "o3-mini", "deepseek", "mistral-small", "codestral"];
+
+/// Ollama models that accept images; other local models are text-only.
+const OLLAMA_VISION_MODELS: &[&str] = &["llava", "vision", "gemma3", "qwen2.5vl", "minicpm-v", "moondream", "mistral-small3"];
+
+fn supports_images(provider: &str, model: &str) -> bool {
+    let model = model.to_ascii_lowercase();
+    if provider == "ollama" {
+        return OLLAMA_VISION_MODELS.iter().any(|m| model.contains(m));
+    }
+    !TEXT_ONLY_MODELS.iter().any(|m| model.contains(m))
+}
+
+/// What `adapt` changed, reported to the client as `history_transferred`.
+#[derive(Default)]
+pub struct TransferReport {
+    pub tool_messages: usize,
+    pub images: usize,
+    pub reasoning: usize,
+    pub other: usize,
+}
+
+impl TransferReport {
+    pub fn is_empty(&self) -> bool {
+        self.tool_messages + self.images + self.reasoning + self.other == 0
+    }
+
+    pub fn event(&self, from: (&str, &str), to: (&str, &str), messages: usize) -> Value {
+        json!({
+            "type": "history_transferred",
+            "content": {
+                "from": {"provider": from.0, "model": from.1},
+                "to": {"provider": to.0, "model": to.1},
+                "messages": messages,
+                "tool_messages_flattened": self.tool_messages,
+                "images_dropped": self.images,
+                "reasoning_dropped": self.reasoning,
+                "other_dropped": self.other,
+            }
+        })
+    }
+}
+
+fn truncated(text: &str) -> String {
+    if text.chars().count() <= MAX_TOOL_RESULT_CHARS {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(MAX_TOOL_RESULT_CHARS).collect();
+    format!("{}… [truncated]", kept)
+}
+
+/// Rewrite `history` so the next turn on `provider`/`model` accepts it.
+/// Tool calls and results carry provider-specific IDs and pairing rules, so
+/// after a provider change they become plain text notes; reasoning blocks
+/// (signed per provider) are dropped; images are replaced by a note when the
+/// new model is text-only. Messages left empty are removed and consecutive
+/// messages from the same role merged, since Anthropic and Gemini require
+/// alternating turns.
+pub fn adapt(history: &[RigMessage], provider_changed: bool, provider: &str, model: &str) -> (Vec<RigMessage>, TransferReport) {
+    let keep_images = supports_images(provider, model);
+    let mut report = TransferReport::default();
+    let mut adapted: Vec<RigMessage> = Vec::with_capacity(history.len());
+
+    for message in history {
+        let converted = if let RigMessage::User { content } = message {
+            let parts: Vec<UserContent> = content
+                .iter()
+                .cloned()
+                .filter_map(|c| match c {
+                    UserContent::Text(t) => Some(UserContent::Text(t)),
+                    UserContent::Image(_) if keep_images => Some(c),
+                    UserContent::Image(_) => {
+                        report.images += 1;
+                        Some(UserContent::text("[An image was shared here]"))
+                    }
+                    UserContent::ToolResult(result) if provider_changed => {
+                        report.tool_messages += 1;
+                        let text: Vec<String> = result
+                            .content
+                            .iter()
+                            .map(|part| match part {
+                                ToolResultContent::Text(t) => t.text.clone(),
+                                _ => "[non-text output]".to_string(),
+                            })
+                            .collect();
+                        Some(UserContent::text(format!("[Tool result]\n{}", truncated(&text.join("\n")))))
+                    }
+                    UserContent::ToolResult(_) => Some(c),
+                    _ => {
+                        report.other += 1;
+                        None
+                    }
+                })
+                .collect();
+            OneOrMany::many(parts).ok().map(|content| RigMessage::User { content })
+        } else if let RigMessage::Assistant { id, content } = message {
+            let parts: Vec<AssistantContent> = content
+                .iter()
+                .cloned()
+                .filter_map(|c| match c {
+                    AssistantContent::Text(t) => Some(AssistantContent::Text(t)),
+                    AssistantContent::ToolCall(call) if provider_changed => {
+                        report.tool_messages += 1;
+                        Some(AssistantContent::text(format!(
+                            "[Called tool {} with {}]",
+                            call.function.name, call.function.arguments
+                        )))
+                    }
+                    AssistantContent::ToolCall(_) => Some(c),
+                    AssistantContent::Reasoning(_) if provider_changed => {
+                        report.reasoning += 1;
+                        None
+                    }
+                    AssistantContent::Reasoning(_) => Some(c),
+                    _ => {
+                        report.other += 1;
+                        None
+                    }
+                })
+                .collect();
+            OneOrMany::many(parts)
+                .ok()
+                .map(|content| RigMessage::Assistant { id: id.clone(), content })
+        } else {
+            Some(message.clone())
+        };
+        let Some(converted) = converted else {
+            continue;
+        };
+        match (adapted.last_mut(), converted) {
+            (Some(RigMessage::User { content: previous }), RigMessage::User { content }) => {
+                for part in content {
+                    previous.push(part);
+                }
+            }
+            (Some(RigMessage::Assistant { content: previous, .. }), RigMessage::Assistant { content, .. }) => {
+                for part in content {
+                    previous.push(part);
+                }
+            }
+            (_, converted) => adapted.push(converted),
+        }
+    }
+    (adapted, report)
+}
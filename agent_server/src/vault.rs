@@ -0,0 +1,186 @@
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::sync::OnceLock;
+
+/// Prefix of sealed files; anything else is read as legacy plaintext and
+/// sealed on its next save.
+const MAGIC: &[u8] = b"RONGE-SEALED-1\n";
+const NONCE_LEN: usize = 12;
+/// Keychain entry holding the store key on macOS.
+#[cfg(target_os = "macos")]
+const KEYCHAIN_SERVICE: &str = "ai.rong-e.agent-server";
+#[cfg(target_os = "macos")]
+const KEYCHAIN_ACCOUNT: &str = "session-store-key";
+/// `security`'s exit status for errSecItemNotFound.
+#[cfg(target_os = "macos")]
+const SECURITY_NOT_FOUND: i32 = 44;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<[u8; 32]> {
+    let text = text.trim();
+    if text.len() != 64 {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(key)
+}
+
+fn new_key() -> [u8; 32] {
+    ChaCha20Poly1305::generate_key(&mut OsRng).into()
+}
+
+/// The store key from the login Keychain, created there on first use. Any
+/// failure other than "no such item" (locked Keychain, denied access, a
+/// mangled value) is an error: replacing the key would orphan every sealed
+/// file.
+#[cfg(target_os = "macos")]
+fn platform_key() -> Result<[u8; 32], String> {
+    use std::io::Write;
+
+    let found = std::process::Command::new("security")
+        .args(["find-generic-password", "-s", KEYCHAIN_SERVICE, "-a", KEYCHAIN_ACCOUNT, "-w"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if found.status.success() {
+        return unhex(&String::from_utf8_lossy(&found.stdout))
+            .ok_or_else(|| "The store key in the Keychain is malformed".to_string());
+    }
+    if found.status.code() != Some(SECURITY_NOT_FOUND) {
+        return Err(format!("Can't read the store key: {}", String::from_utf8_lossy(&found.stderr).trim()));
+    }
+    let key = new_key();
+    // Fed through `security -i` on stdin so the key never shows up in argv.
+    let mut child = std::process::Command::new("security")
+        .arg("-i")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    let command = format!(
+        "add-generic-password -s {} -a {} -w {}\n",
+        KEYCHAIN_SERVICE,
+        KEYCHAIN_ACCOUNT,
+        hex(&key)
+    );
+    child
+        .stdin
+        .take()
+        .ok_or("security has no stdin")?
+        .write_all(command.as_bytes())
+        .map_err(|e| e.to_string())?;
+    let added = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !added.status.success() || !added.stderr.is_empty() {
+        return Err(format!("Keychain refused the store key: {}", String::from_utf8_lossy(&added.stderr).trim()));
+    }
+    println!("🔐 Created the session store key in the Keychain");
+    Ok(key)
+}
+
+/// Without a Keychain, the key lives in `~/.ronge/store.key`, readable by the
+/// user only. Created only when the file doesn't exist; an unreadable or
+/// malformed key is an error rather than a reason to start over.
+#[cfg(not(target_os = "macos"))]
+fn platform_key() -> Result<[u8; 32], String> {
+    use std::io::Write;
+
+    let path = dirs::home_dir().unwrap_or_else(|| std::path::PathBuf::from("/tmp")).join(".ronge").join("store.key");
+    match std::fs::read_to_string(&path) {
+        Ok(text) => return unhex(&text).ok_or_else(|| format!("{} is malformed", path.display())),
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("Can't read {}: {}", path.display(), e));
+        }
+        Err(_) => {}
+    }
+    let key = new_key();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path).map_err(|e| format!("Can't create {}: {}", path.display(), e))?;
+    file.write_all(hex(&key).as_bytes()).map_err(|e| e.to_string())?;
+    println!("🔐 Created the session store key at {}", path.display());
+    Ok(key)
+}
+
+/// `RONGE_STORE_KEY` (64 hex characters), else the platform key. Looked up
+/// once per process.
+fn key() -> Result<&'static ChaCha20Poly1305, String> {
+    static CIPHER: OnceLock<Result<ChaCha20Poly1305, String>> = OnceLock::new();
+    CIPHER
+        .get_or_init(|| {
+            let key = match std::env::var("RONGE_STORE_KEY").ok().as_deref().and_then(unhex) {
+                Some(key) => key,
+                None => platform_key()?,
+            };
+            Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+        })
+        .as_ref()
+        .map_err(|e| e.clone())
+}
+
+/// Encrypt `plaintext` with ChaCha20-Poly1305 under a fresh random nonce.
+pub fn seal(plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let cipher = key()?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, plaintext).map_err(|e| e.to_string())?;
+    let mut out = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypt what `seal` wrote; unsealed (legacy plaintext) data is returned as is.
+pub fn open(data: &[u8]) -> Result<Vec<u8>, String> {
+    let Some(sealed) = data.strip_prefix(MAGIC) else {
+        return Ok(data.to_vec());
+    };
+    if sealed.len() < NONCE_LEN {
+        return Err("Sealed file is truncated".to_string());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    key()?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Could not decrypt the file (wrong key or corrupted data)".to_string())
+}
+
+/// Seal `plaintext` into `path` through a temporary file and a rename, so a
+/// crash never leaves a half-written store behind.
+pub async fn write_sealed(path: &std::path::Path, plaintext: &[u8]) -> std::io::Result<()> {
+    let sealed = seal(plaintext).map_err(std::io::Error::other)?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let temp = path.with_extension("tmp");
+    tokio::fs::write(&temp, sealed).await?;
+    tokio::fs::rename(&temp, path).await
+}
+
+/// Read and decrypt `path`; `None` when it is missing or can't be opened.
+/// A file that can't be decrypted is moved aside (`.unreadable`) rather than
+/// overwritten by the next save.
+pub fn read_sealed(path: &std::path::Path) -> Option<Vec<u8>> {
+    let data = std::fs::read(path).ok()?;
+    match open(&data) {
+        Ok(plaintext) => Some(plaintext),
+        Err(e) => {
+            let aside = path.with_extension("unreadable");
+            println!("⚠️ Could not open {}: {} (moved to {})", path.display(), e, aside.display());
+            let _ = std::fs::rename(path, aside);
+            None
+        }
+    }
+}
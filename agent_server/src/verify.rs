@@ -0,0 +1,234 @@
+use crate::llm::ProviderTimeouts;
+use futures::StreamExt;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// A metadata lookup is a single small GET.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long a successful verification is reused (`RONGE_VERIFY_CACHE_SECS`).
+const DEFAULT_CACHE_SECS: u64 = 600;
+/// Candidate models checked at once by `verify_models`.
+const MAX_PARALLEL: usize = 4;
+/// Candidates accepted in one `verify_models` request.
+pub const MAX_CANDIDATES: usize = 20;
+
+const GEMINI_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+const OPENAI_BASE: &str = "https://api.openai.com/v1";
+const ANTHROPIC_BASE: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const MISTRAL_BASE: &str = "https://api.mistral.ai/v1";
+
+fn client(provider: &str) -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .connect_timeout(ProviderTimeouts::for_provider(provider).connect)
+        .timeout(METADATA_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// What a metadata endpoint said about the key and model.
+enum Answer {
+    Valid,
+    Invalid(String),
+    /// No clear verdict (unexpected status, network trouble); the caller
+    /// falls back to a completion.
+    Unknown,
+}
+
+fn classify(provider: &str, model: &str, status: StatusCode, body: &str) -> Answer {
+    match status {
+        s if s.is_success() => Answer::Valid,
+        // The key was accepted; the account is just busy.
+        StatusCode::TOO_MANY_REQUESTS => {
+            println!("⚠️ {} rate-limited the key check; treating the key as valid", provider);
+            Answer::Valid
+        }
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Answer::Invalid(format!("{} rejected the API key", provider))
+        }
+        // Gemini answers a bad key with 400 API_KEY_INVALID.
+        StatusCode::BAD_REQUEST if body.contains("API_KEY_INVALID") || body.contains("API key not valid") => {
+            Answer::Invalid(format!("{} rejected the API key", provider))
+        }
+        StatusCode::NOT_FOUND => Answer::Invalid(format!("{} has no model named '{}'", provider, model)),
+        other => {
+            println!("⚠️ {} key check returned {}; falling back to a test message", provider, other);
+            Answer::Unknown
+        }
+    }
+}
+
+async fn get(provider: &str, model: &str, request: reqwest::RequestBuilder) -> Answer {
+    match request.send().await {
+        Ok(resp) => {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            classify(provider, model, status, &body)
+        }
+        Err(e) => {
+            println!("⚠️ {} key check failed: {}; falling back to a test message", provider, e);
+            Answer::Unknown
+        }
+    }
+}
+
+/// Check `api_key` and `model` against the provider's model metadata
+/// endpoint, which costs nothing and doesn't count as a completion:
+/// Gemini, OpenAI and Mistral `models/<model>`, Anthropic `/v1/models/<model>`,
+/// OpenRouter's key info plus its public model list, an OpenAI-compatible
+/// server's `/models`. `None` when the
+/// provider has no such endpoint or it gave no clear answer, in which case
+/// the caller sends a test message instead.
+pub async fn check_metadata(provider: &str, api_key: &str, model: &str) -> Option<Result<(), String>> {
+    let client = match client(provider) {
+        Ok(client) => client,
+        Err(e) => return Some(Err(e)),
+    };
+    let answer = match provider {
+        "gemini" => {
+            let model = model.trim_start_matches("models/");
+            let url = format!("{}/models/{}", GEMINI_BASE, model);
+            get(provider, model, client.get(url).header("x-goog-api-key", api_key)).await
+        }
+        "openai" => {
+            let url = format!("{}/models/{}", OPENAI_BASE, model);
+            get(provider, model, client.get(url).bearer_auth(api_key)).await
+        }
+        "mistral" => {
+            let url = format!("{}/models/{}", MISTRAL_BASE, model);
+            get(provider, model, client.get(url).bearer_auth(api_key)).await
+        }
+        "anthropic" => {
+            let url = format!("{}/models/{}", ANTHROPIC_BASE, model);
+            let request = client
+                .get(url)
+                .header("x-api-key", api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION);
+            get(provider, model, request).await
+        }
+        "openai_compatible" => match crate::provider_settings::get(provider) {
+            Some(endpoint) => served_model(&client, &endpoint.base_url, api_key, model).await,
+            None => Answer::Unknown,
+        },
+        "openrouter" => {
+            let url = format!("{}/key", crate::model_list::openrouter_base_url());
+            match get(provider, model, client.get(url).bearer_auth(api_key)).await {
+                Answer::Valid => openrouter_model(model).await,
+                other => other,
+            }
+        }
+        _ => Answer::Unknown,
+    };
+    match answer {
+        Answer::Valid => Some(Ok(())),
+        Answer::Invalid(e) => Some(Err(e)),
+        Answer::Unknown => None,
+    }
+}
+
+/// Whether an OpenAI-compatible server lists `model` under `/models`.
+/// Servers without the endpoint get a test message instead.
+async fn served_model(client: &reqwest::Client, base_url: &str, api_key: &str, model: &str) -> Answer {
+    let mut request = client.get(format!("{}/models", base_url));
+    if !api_key.is_empty() {
+        request = request.bearer_auth(api_key);
+    }
+    let list = match request.send().await {
+        Ok(resp) if resp.status().is_success() => resp.json::<Value>().await.ok(),
+        Ok(resp) if matches!(resp.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+            return Answer::Invalid(format!("The server at {} rejected the API key", base_url));
+        }
+        Ok(_) => None,
+        Err(e) => return Answer::Invalid(format!("Could not reach {}: {}", base_url, e)),
+    };
+    let Some(list) = list else {
+        return Answer::Unknown;
+    };
+    let served: Vec<&str> = list["data"].as_array().into_iter().flatten().filter_map(|m| m["id"].as_str()).collect();
+    if served.is_empty() || served.contains(&model) {
+        Answer::Valid
+    } else {
+        Answer::Invalid(format!("{} serves {}, not '{}'", base_url, served.join(", "), model))
+    }
+}
+
+/// Whether OpenRouter lists `model`; an unreadable list doesn't block the key.
+async fn openrouter_model(model: &str) -> Answer {
+    match crate::model_list::openrouter(false).await {
+        Ok(models) if !crate::model_list::lists(&models, model) => {
+            Answer::Invalid(format!("openrouter has no model named '{}'", model))
+        }
+        _ => Answer::Valid,
+    }
+}
+
+fn cache_ttl() -> Duration {
+    Duration::from_secs(
+        std::env::var("RONGE_VERIFY_CACHE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_CACHE_SECS),
+    )
+}
+
+/// When each (provider, model, endpoint, key hash) last verified
+/// successfully. The same deployment name on another endpoint or API
+/// version is another model. Only the key's hash is kept; failures are
+/// never cached.
+type CacheKey = (String, String, Option<crate::provider_settings::Endpoint>, String);
+
+fn cache() -> &'static Mutex<HashMap<CacheKey, Instant>> {
+    static CACHE: OnceLock<Mutex<HashMap<CacheKey, Instant>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+fn cache_key(provider: &str, api_key: &str, model: &str) -> CacheKey {
+    let digest = Sha256::digest(api_key.as_bytes());
+    let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    (provider.to_string(), model.to_string(), crate::provider_settings::get(provider), hash)
+}
+
+/// `llm::verify_llm`, reusing a success from the last
+/// `RONGE_VERIFY_CACHE_SECS` (default 10 minutes) unless `refresh` is set,
+/// so revisiting the settings screen doesn't call the provider again.
+/// `Ok(true)` when the answer came from the cache.
+pub async fn verify(provider: &str, api_key: &str, model: &str, refresh: bool) -> Result<bool, String> {
+    let key = cache_key(provider, api_key, model);
+    if !refresh {
+        let cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+        if cache.get(&key).is_some_and(|at| at.elapsed() < cache_ttl()) {
+            return Ok(true);
+        }
+    }
+    let result = crate::llm::verify_llm(provider, api_key, model).await;
+    let mut cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+    match result {
+        Ok(()) => {
+            cache.insert(key, Instant::now());
+            Ok(false)
+        }
+        Err(e) => {
+            cache.remove(&key);
+            Err(e)
+        }
+    }
+}
+
+/// Verify several candidate models of one provider, `MAX_PARALLEL` at a
+/// time, in the order given: `[{model, valid, cached, error}]`.
+pub async fn verify_models(provider: &str, api_key: &str, models: &[String], refresh: bool) -> Vec<Value> {
+    futures::stream::iter(models.to_vec())
+        .map(|model| async move {
+            match verify(provider, api_key, &model, refresh).await {
+                Ok(cached) => json!({"model": model, "valid": true, "cached": cached, "error": null}),
+                Err(e) => json!({"model": model, "valid": false, "cached": false, "error": e}),
+            }
+        })
+        .buffered(MAX_PARALLEL)
+        .collect()
+        .await
+}
@@ -0,0 +1,253 @@
+use crate::state::SharedState;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// How often every watched folder is re-scanned.
+const POLL_INTERVAL_SECS: u64 = 5;
+
+/// Text files up to this size are inlined into the prompt.
+const MAX_INLINE_BYTES: u64 = 64 * 1024;
+
+/// PDFs above this are referenced by path rather than attached.
+const MAX_PDF_BYTES: u64 = 20 * 1024 * 1024;
+
+/// A folder + prompt pair: whenever a new file lands in `folder`, the agent
+/// runs `prompt_template` with the file attached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchRule {
+    pub id: String,
+    pub folder: String,
+    /// Supports `{file_path}` and `{file_name}` placeholders.
+    pub prompt_template: String,
+    /// Lower-case extensions without the dot (e.g. `["pdf"]`). Empty = any file.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Google access of the session that created it; the runs get no more.
+    #[serde(default)]
+    pub google_access: crate::google_auth::GoogleAccess,
+}
+
+impl WatchRule {
+    fn matches(&self, path: &Path) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| self.extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+            .unwrap_or(false)
+    }
+}
+
+pub fn default_rules_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".ronge")
+        .join("watch_rules.json")
+}
+
+/// Expand a leading `~` to the user's home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
+        dirs::home_dir().unwrap_or_default().join(rest)
+    } else if path == "~" {
+        dirs::home_dir().unwrap_or_default()
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+pub fn load_rules() -> Vec<WatchRule> {
+    std::fs::read_to_string(default_rules_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub async fn save_rules(rules: &[WatchRule]) -> std::io::Result<()> {
+    let path = default_rules_path();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let body = serde_json::to_string_pretty(rules).unwrap_or_else(|_| "[]".to_string());
+    tokio::fs::write(&path, body).await
+}
+
+/// Spawn the background task that polls every watched folder.
+///
+/// The first scan of a rule only records the files already present; a file is
+/// treated as "new" once it appears afterwards and its size is unchanged
+/// between two polls (so half-written downloads are not picked up).
+pub fn spawn(state: SharedState) {
+    tokio::spawn(async move {
+        let mut seen: HashMap<String, HashSet<PathBuf>> = HashMap::new();
+        let mut pending: HashMap<PathBuf, u64> = HashMap::new();
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(POLL_INTERVAL_SECS));
+
+        loop {
+            interval.tick().await;
+            let rules = state.lock().await.watch_rules.clone();
+
+            // Forget rules that were removed since the last poll.
+            seen.retain(|id, _| rules.iter().any(|r| &r.id == id));
+            let mut listed: HashSet<PathBuf> = HashSet::new();
+
+            for rule in rules {
+                let folder = expand_home(&rule.folder);
+                let Ok(mut entries) = tokio::fs::read_dir(&folder).await else {
+                    continue;
+                };
+
+                let mut current: Vec<(PathBuf, u64)> = Vec::new();
+                while let Ok(Some(entry)) = entries.next_entry().await {
+                    let path = entry.path();
+                    let Ok(meta) = entry.metadata().await else { continue };
+                    let hidden = path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| n.starts_with('.'));
+                    if meta.is_file() && !hidden && rule.matches(&path) {
+                        listed.insert(path.clone());
+                        current.push((path, meta.len()));
+                    }
+                }
+
+                let known = match seen.entry(rule.id.clone()) {
+                    Entry::Occupied(e) => e.into_mut(),
+                    Entry::Vacant(e) => {
+                        e.insert(current.into_iter().map(|(p, _)| p).collect());
+                        continue;
+                    }
+                };
+
+                known.retain(|p| current.iter().any(|(c, _)| c == p));
+
+                for (path, size) in current {
+                    if known.contains(&path) {
+                        continue;
+                    }
+                    // Wait for the size to settle before firing.
+                    if pending.get(&path) != Some(&size) {
+                        pending.insert(path, size);
+                        continue;
+                    }
+                    pending.remove(&path);
+                    known.insert(path.clone());
+
+                    println!("👀 Watch rule '{}' matched new file: {}", rule.id, path.display());
+                    let state = state.clone();
+                    let rule = rule.clone();
+                    tokio::spawn(async move { run_rule(&state, &rule, &path).await });
+                }
+            }
+
+            // Files deleted (or whose rule went away) before they settled.
+            pending.retain(|path, _| listed.contains(path));
+        }
+    });
+}
+
+/// Run the agent prompt for a single new file and broadcast the result.
+async fn run_rule(state: &SharedState, rule: &WatchRule, path: &Path) {
+    let file_path = path.to_string_lossy().to_string();
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut query = rule
+        .prompt_template
+        .replace("{file_path}", &file_path)
+        .replace("{file_name}", &file_name);
+    if !rule.prompt_template.contains("{file_path}") {
+        query = format!("{}\n\nFile: {}", query, file_path);
+    }
+
+    let provider = state.lock().await.current_provider.clone();
+    let (image, inline_text) = read_attachment(path, &provider).await;
+    if let Some(text) = inline_text {
+        // The file came from outside (a download, a mail attachment): data, not instructions.
+        let source = format!("watched file {}", file_name);
+        query = format!("{}\n\n{}", query, crate::sanitize::sanitize_text(&source, &text));
+    } else if image.is_none() && is_pdf(path) {
+        query = format!(
+            "{}\n\nThe PDF could not be attached for the current model; read it from its path with a tool if one can.",
+            query
+        );
+    }
+
+    let result =
+        crate::logic::run_background_turn(state, query, image.into_iter().collect(), rule.google_access).await;
+
+    let content = match result {
+        Ok(text) => json!({"rule_id": rule.id, "file": file_path, "status": "success", "text": text}),
+        Err(e) => {
+            println!("❌ Watch rule '{}' failed: {}", rule.id, e);
+            json!({"rule_id": rule.id, "file": file_path, "status": "error", "text": e})
+        }
+    };
+    let _ = state
+        .lock()
+        .await
+        .notifier
+        .send(json!({"type": "watch_result", "content": content}));
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn is_pdf(path: &Path) -> bool {
+    extension(path) == "pdf"
+}
+
+/// Images (PNG, JPEG, GIF, WebP, HEIC) are attached, and so are PDFs when
+/// the provider reads them (`llm::accepts_pdf`); small text files are
+/// inlined. Anything else is referenced by path only (the filesystem MCP
+/// server can read it).
+async fn read_attachment(path: &Path, provider: &str) -> (Option<crate::llm::ChatImage>, Option<String>) {
+    let ext = extension(path);
+
+    match ext.as_str() {
+        "png" | "jpg" | "jpeg" | "gif" | "webp" | "heic" => match tokio::fs::read(path).await {
+            Ok(bytes) => {
+                let image = json!({"data": STANDARD.encode(bytes), "media_type": format!("image/{}", ext)});
+                (crate::llm::ChatImage::from_value(&image).ok(), None)
+            }
+            Err(_) => (None, None),
+        },
+        "pdf" if crate::llm::accepts_pdf(provider) => {
+            let small = tokio::fs::metadata(path)
+                .await
+                .map(|m| m.len() <= MAX_PDF_BYTES)
+                .unwrap_or(false);
+            if !small {
+                return (None, None);
+            }
+            match tokio::fs::read(path).await {
+                Ok(bytes) => (Some(crate::llm::ChatImage::pdf(STANDARD.encode(bytes))), None),
+                Err(_) => (None, None),
+            }
+        }
+        "txt" | "md" | "csv" | "json" | "log" => {
+            let small = tokio::fs::metadata(path)
+                .await
+                .map(|m| m.len() <= MAX_INLINE_BYTES)
+                .unwrap_or(false);
+            if small {
+                (None, tokio::fs::read_to_string(path).await.ok())
+            } else {
+                (None, None)
+            }
+        }
+        _ => (None, None),
+    }
+}
@@ -0,0 +1,250 @@
+use serde_json::{json, Value};
+
+/// Max nesting followed when searching tool results for structured data.
+const MAX_DEPTH: usize = 8;
+/// Characters of an event description kept in `calendar_events` widgets;
+/// `RONGE_CALENDAR_DESCRIPTION_CHARS` overrides it. `calendar_get_event`
+/// returns the whole text.
+const DEFAULT_DESCRIPTION_CHARS: usize = 500;
+
+/// Build the `widgets` array for a final response from the turn's tool events.
+///
+/// Every widget carries the `label`/`action` envelope existing clients decode;
+/// structured payloads ride alongside it:
+///
+/// ```json
+/// {"type": "calendar_events", "label": "3 events", "action": {}, "events": [
+///   {"title": "...", "start": "RFC3339 or YYYY-MM-DD", "end": "...", "all_day": false,
+///    "link": "https://...", "location": "...", "attendees": ["a@b.com"],
+///    "description": "first 500 characters…", "conference_link": "https://meet.google.com/..."}
+/// ]}
+/// {"type": "table", "label": "Sheet1!A1:C10", "action": {}, "range": "Sheet1!A1:C10",
+///  "headers": ["..."], "rows": [["..."]]}
+/// ```
+pub fn collect_widgets(events: &[Value]) -> Vec<Value> {
+    let mut widgets = Vec::new();
+    if let Some(calendar) = calendar_widget(events) {
+        widgets.push(calendar);
+    }
+    widgets.extend(table_widgets(events));
+    widgets
+}
+
+/// Build the `images` array for a final response: every chart rendered
+/// during the turn, inlined as a PNG data URL (`{"url", "alt"}`). Only the
+/// built-in `render_chart` counts, and only for files in the charts directory,
+/// so a tool result can't get an arbitrary local file sent to the client.
+pub fn collect_images(events: &[Value]) -> Vec<Value> {
+    use base64::Engine;
+    use rig::tool::Tool;
+    let charts = crate::chart::charts_dir();
+    builtin_results(events, crate::tools::RenderChart::NAME)
+        .filter_map(|result| {
+            let path = result["path"].as_str()?;
+            let Some(path) = path_within(path, &charts) else {
+                println!("⚠️ Ignored a chart outside {}: {}", charts.display(), path);
+                return None;
+            };
+            let bytes = match std::fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("⚠️ Could not read chart {}: {}", path.display(), e);
+                    return None;
+                }
+            };
+            Some(json!({
+                "url": format!(
+                    "data:image/png;base64,{}",
+                    base64::engine::general_purpose::STANDARD.encode(bytes)
+                ),
+                "alt": result["title"].as_str().unwrap_or("Chart"),
+            }))
+        })
+        .collect()
+}
+
+/// Parsed results of every `tool_result` whose tool name contains one of `keywords`.
+pub(crate) fn tool_results_matching<'a>(
+    events: &'a [Value],
+    keywords: &'a [&'a str],
+) -> impl Iterator<Item = Value> + 'a {
+    events
+        .iter()
+        .filter(|e| e["type"] == "tool_result")
+        .filter(move |e| {
+            let name = e["content"]["toolName"].as_str().unwrap_or("").to_lowercase();
+            keywords.iter().any(|k| name.contains(k))
+        })
+        .filter_map(|e| e["content"]["result"].as_str())
+        .filter_map(|r| serde_json::from_str::<Value>(r).ok())
+}
+
+/// Parsed results of every successful `tool_result` from the built-in tool
+/// called exactly `name`.
+pub(crate) fn builtin_results<'a>(events: &'a [Value], name: &'a str) -> impl Iterator<Item = Value> + 'a {
+    events
+        .iter()
+        .filter(|e| e["type"] == "tool_result" && e["content"]["builtin"] == true)
+        .filter(move |e| e["content"]["toolName"] == name)
+        .filter_map(|e| e["content"]["result"].as_str())
+        .filter_map(|r| serde_json::from_str::<Value>(r).ok())
+}
+
+/// `path` with symlinks and `..` resolved, if the file exists inside `dir`.
+pub(crate) fn path_within(path: &str, dir: &std::path::Path) -> Option<std::path::PathBuf> {
+    let path = std::path::Path::new(path).canonicalize().ok()?;
+    path.starts_with(dir.canonicalize().ok()?).then_some(path)
+}
+
+/// Depth-first walk over `value`, descending into strings that contain JSON
+/// (MCP results wrap their payload in `content[].text`).
+pub(crate) fn walk(value: &Value, depth: usize, visit: &mut dyn FnMut(&serde_json::Map<String, Value>)) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    match value {
+        Value::Object(map) => {
+            visit(map);
+            for v in map.values() {
+                walk(v, depth + 1, visit);
+            }
+        }
+        Value::Array(items) => {
+            for v in items {
+                walk(v, depth + 1, visit);
+            }
+        }
+        Value::String(s) if s.starts_with('{') || s.starts_with('[') => {
+            if let Ok(inner) = serde_json::from_str::<Value>(s) {
+                walk(&inner, depth + 1, visit);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn calendar_widget(events: &[Value]) -> Option<Value> {
+    let mut found: Vec<Value> = Vec::new();
+    for result in tool_results_matching(events, &["calendar", "event", "agenda"]) {
+        walk(&result, 0, &mut |obj| {
+            if let Some(event) = calendar_event(obj) {
+                let duplicate = found.iter().any(|f| {
+                    f["title"] == event["title"] && f["start"] == event["start"]
+                });
+                if !duplicate {
+                    found.push(event);
+                }
+            }
+        });
+    }
+    if found.is_empty() {
+        None
+    } else {
+        let label = match found.len() {
+            1 => "1 event".to_string(),
+            n => format!("{} events", n),
+        };
+        Some(json!({"type": "calendar_events", "label": label, "action": {}, "events": found}))
+    }
+}
+
+/// Recognise a Google Calendar-style event object
+/// (`summary` + `start.dateTime`/`start.date`).
+pub(crate) fn calendar_event(obj: &serde_json::Map<String, Value>) -> Option<Value> {
+    let start = obj.get("start")?;
+    let (start_str, all_day) = match (start["dateTime"].as_str(), start["date"].as_str()) {
+        (Some(dt), _) => (dt, false),
+        (None, Some(d)) => (d, true),
+        _ => return None,
+    };
+    let end = obj
+        .get("end")
+        .and_then(|e| e["dateTime"].as_str().or_else(|| e["date"].as_str()));
+    let title = obj
+        .get("summary")
+        .or_else(|| obj.get("title"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("(No title)");
+    let attendees: Vec<&str> = obj
+        .get("attendees")
+        .and_then(|a| a.as_array())
+        .map(|a| a.iter().filter_map(|p| p["email"].as_str()).collect())
+        .unwrap_or_default();
+    let max_chars = std::env::var("RONGE_CALENDAR_DESCRIPTION_CHARS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_DESCRIPTION_CHARS);
+    let description = obj
+        .get("description")
+        .and_then(|d| d.as_str())
+        .map(|d| crate::text::truncate_chars(d, max_chars));
+
+    Some(json!({
+        "title": title,
+        "start": start_str,
+        "end": end,
+        "all_day": all_day,
+        "link": obj.get("htmlLink").and_then(|l| l.as_str()),
+        "location": obj.get("location").and_then(|l| l.as_str()),
+        "attendees": attendees,
+        "description": description,
+        "conference_link": conference_link(obj),
+    }))
+}
+
+/// The video-call link of an event (Meet `hangoutLink` or a `conferenceData` video entry point).
+pub(crate) fn conference_link(obj: &serde_json::Map<String, Value>) -> Option<String> {
+    obj.get("hangoutLink")
+        .and_then(|l| l.as_str())
+        .or_else(|| {
+            obj.get("conferenceData")?["entryPoints"]
+                .as_array()?
+                .iter()
+                .find(|e| e["entryPointType"] == "video")?["uri"]
+                .as_str()
+        })
+        .map(str::to_string)
+}
+
+/// One `table` widget per spreadsheet range read during the turn. Recognises
+/// Sheets API value ranges (`{"range": ..., "values": [[...], ...]}`); the
+/// first row is used as the header row.
+fn table_widgets(events: &[Value]) -> Vec<Value> {
+    let mut tables: Vec<Value> = Vec::new();
+    for result in tool_results_matching(events, &["sheet", "spreadsheet"]) {
+        walk(&result, 0, &mut |obj| {
+            let Some(rows) = obj.get("values").and_then(|v| v.as_array()) else {
+                return;
+            };
+            let rows: Vec<Vec<String>> = rows
+                .iter()
+                .filter_map(|r| r.as_array())
+                .map(|r| r.iter().map(cell_text).collect())
+                .collect();
+            let Some((headers, body)) = rows.split_first() else {
+                return;
+            };
+            let range = obj.get("range").and_then(|r| r.as_str());
+            if tables.iter().any(|t| range.is_some() && t["range"].as_str() == range) {
+                return;
+            }
+            tables.push(json!({
+                "type": "table",
+                "label": range.unwrap_or("Spreadsheet"),
+                "action": {},
+                "range": range,
+                "headers": headers,
+                "rows": body,
+            }));
+        });
+    }
+    tables
+}
+
+fn cell_text(cell: &Value) -> String {
+    match cell {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
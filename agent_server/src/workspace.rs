@@ -0,0 +1,246 @@
+use rmcp::{
+    ServerHandler,
+    model::{CallToolRequestParam, CallToolResult, Content, ErrorData, JsonObject, ListToolsResult, PaginatedRequestParam},
+    service::{RequestContext, RoleServer},
+};
+use serde_json::{json, Value};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Files larger than this are read truncated.
+const MAX_READ_BYTES: usize = 64 * 1024;
+/// stdout/stderr kept from a command, each.
+const MAX_OUTPUT_BYTES: usize = 16 * 1024;
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+/// Directory entries listed at most.
+const MAX_ENTRIES: usize = 500;
+
+/// Not destructive by name, but the code agent confirms every command.
+pub const RUN_COMMAND: &str = "workspace_run_command";
+
+/// File and command tools confined to one project directory, served to the
+/// `code_agent` sub-agent only.
+pub struct WorkspaceServer {
+    root: PathBuf,
+}
+
+/// Start the in-process server for `root`.
+pub async fn connect(root: &Path) -> Result<crate::state::McpConnection, String> {
+    let root = root
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", root.display(), e))?;
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", root.display()));
+    }
+    crate::mcp_proxy::connect_in_process(WorkspaceServer { root }).await
+}
+
+fn schema(value: Value) -> Arc<JsonObject> {
+    Arc::new(value.as_object().cloned().unwrap_or_default())
+}
+
+fn tool_definitions() -> Vec<rmcp::model::Tool> {
+    vec![
+        rmcp::model::Tool::new(
+            "workspace_list_dir",
+            "List a directory of the project workspace. Paths are relative to the workspace root.",
+            schema(json!({
+                "type": "object",
+                "properties": {"path": {"type": "string", "description": "Directory, default \".\""}}
+            })),
+        ),
+        rmcp::model::Tool::new(
+            "workspace_read_file",
+            "Read a text file of the project workspace.",
+            schema(json!({
+                "type": "object",
+                "properties": {"path": {"type": "string"}},
+                "required": ["path"]
+            })),
+        ),
+        rmcp::model::Tool::new(
+            "workspace_write_file",
+            "Create or overwrite a file of the project workspace with the given content.",
+            schema(json!({
+                "type": "object",
+                "properties": {"path": {"type": "string"}, "content": {"type": "string"}},
+                "required": ["path", "content"]
+            })),
+        ),
+        rmcp::model::Tool::new(
+            RUN_COMMAND,
+            "Run a program (no shell) in the workspace root, e.g. `cargo` with args [\"test\"]. \
+             Returns the exit code, stdout and stderr.",
+            schema(json!({
+                "type": "object",
+                "properties": {
+                    "program": {"type": "string"},
+                    "args": {"type": "array", "items": {"type": "string"}}
+                },
+                "required": ["program"]
+            })),
+        ),
+    ]
+}
+
+impl WorkspaceServer {
+    /// Resolve `rel` inside the root. The deepest existing ancestor is
+    /// canonicalized so `..` and symlinks cannot escape the workspace.
+    fn resolve(&self, rel: &str) -> Result<PathBuf, String> {
+        let rel = Path::new(rel);
+        if rel.is_absolute() || rel.components().any(|c| matches!(c, Component::ParentDir)) {
+            return Err(format!("{} is outside the workspace", rel.display()));
+        }
+        let full = self.root.join(rel);
+        let mut existing = full.as_path();
+        while !existing.exists() {
+            existing = existing.parent().unwrap_or(&self.root);
+        }
+        let canonical = existing.canonicalize().map_err(|e| e.to_string())?;
+        if !canonical.starts_with(&self.root) {
+            return Err(format!("{} is outside the workspace", rel.display()));
+        }
+        Ok(full)
+    }
+
+    async fn list_dir(&self, rel: &str) -> Result<String, String> {
+        let dir = self.resolve(rel)?;
+        let mut entries = tokio::fs::read_dir(&dir).await.map_err(|e| e.to_string())?;
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+            let is_dir = entry.file_type().await.map(|t| t.is_dir()).unwrap_or(false);
+            let name = entry.file_name().to_string_lossy().to_string();
+            names.push(if is_dir { format!("{}/", name) } else { name });
+        }
+        names.sort();
+        let total = names.len();
+        names.truncate(MAX_ENTRIES);
+        let mut out = names.join("\n");
+        if total > MAX_ENTRIES {
+            out.push_str(&format!("\n… {} more", total - MAX_ENTRIES));
+        }
+        Ok(out)
+    }
+
+    async fn read_file(&self, rel: &str) -> Result<String, String> {
+        let bytes = tokio::fs::read(self.resolve(rel)?).await.map_err(|e| e.to_string())?;
+        let text = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_READ_BYTES)]).to_string();
+        Ok(if bytes.len() > MAX_READ_BYTES {
+            format!("{}\n… [truncated — {} bytes total]", text, bytes.len())
+        } else {
+            text
+        })
+    }
+
+    async fn write_file(&self, rel: &str, content: &str) -> Result<String, String> {
+        let path = self.resolve(rel)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+        }
+        tokio::fs::write(&path, content).await.map_err(|e| e.to_string())?;
+        Ok(format!("Wrote {} bytes to {}", content.len(), rel))
+    }
+
+    async fn run_command(&self, program: &str, args: &[String]) -> Result<String, String> {
+        let mut cmd = sandboxed_command(&self.root, program, args);
+        cmd.current_dir(&self.root).kill_on_drop(true);
+        // Only what build tools need; no API keys or tokens from our environment.
+        cmd.env_clear();
+        for key in ["PATH", "HOME", "USER", "LANG", "TMPDIR", "CARGO_HOME", "RUSTUP_HOME"] {
+            if let Ok(value) = std::env::var(key) {
+                cmd.env(key, value);
+            }
+        }
+        cmd.env("PATH", crate::logic::build_expanded_path());
+
+        let output = tokio::time::timeout(COMMAND_TIMEOUT, cmd.output())
+            .await
+            .map_err(|_| format!("Timed out after {} s", COMMAND_TIMEOUT.as_secs()))?
+            .map_err(|e| format!("{}: {}", program, e))?;
+        Ok(format!(
+            "exit code: {}\n--- stdout ---\n{}\n--- stderr ---\n{}",
+            output.status.code().map(|c| c.to_string()).unwrap_or_else(|| "killed".to_string()),
+            tail(&output.stdout),
+            tail(&output.stderr)
+        ))
+    }
+}
+
+/// The end of a command's output, where test failures usually are.
+fn tail(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    if text.len() <= MAX_OUTPUT_BYTES {
+        return text.to_string();
+    }
+    let mut start = text.len() - MAX_OUTPUT_BYTES;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("… [truncated]\n{}", &text[start..])
+}
+
+/// On macOS, run under `sandbox-exec`: writes are limited to the workspace and
+/// temp directories and outbound network is denied.
+#[cfg(target_os = "macos")]
+fn sandboxed_command(root: &Path, program: &str, args: &[String]) -> tokio::process::Command {
+    let profile = format!(
+        "(version 1)(allow default)\
+         (deny file-write*)\
+         (allow file-write* (subpath \"{root}\") (subpath \"/private/tmp\") (subpath \"/private/var/folders\") (literal \"/dev/null\"))\
+         (deny network-outbound (remote ip \"*:*\"))",
+        root = root.display().to_string().replace('"', "")
+    );
+    let mut cmd = tokio::process::Command::new("sandbox-exec");
+    cmd.arg("-p").arg(profile).arg(program).args(args);
+    cmd
+}
+
+/// Elsewhere, commands are confined by working directory, environment and
+/// timeout only (and, like every call, need the user's approval).
+#[cfg(not(target_os = "macos"))]
+fn sandboxed_command(_root: &Path, program: &str, args: &[String]) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new(program);
+    cmd.args(args);
+    cmd
+}
+
+impl ServerHandler for WorkspaceServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(tool_definitions()))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let args = request.arguments.unwrap_or_default();
+        let str_arg = |key: &str| args.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string();
+        let outcome = match request.name.as_ref() {
+            "workspace_list_dir" => {
+                let path = str_arg("path");
+                self.list_dir(if path.is_empty() { "." } else { &path }).await
+            }
+            "workspace_read_file" => self.read_file(&str_arg("path")).await,
+            "workspace_write_file" => self.write_file(&str_arg("path"), &str_arg("content")).await,
+            RUN_COMMAND => {
+                let cmd_args: Vec<String> = args
+                    .get("args")
+                    .and_then(|v| v.as_array())
+                    .map(|a| a.iter().map(crate::custom_tools::arg_text).collect())
+                    .unwrap_or_default();
+                self.run_command(&str_arg("program"), &cmd_args).await
+            }
+            other => return Err(ErrorData::invalid_params(format!("Unknown tool {}", other), None)),
+        };
+        Ok(match outcome {
+            Ok(text) => CallToolResult::success(vec![Content::text(text)]),
+            Err(e) => CallToolResult::error(vec![Content::text(e)]),
+        })
+    }
+}
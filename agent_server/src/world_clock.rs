@@ -0,0 +1,316 @@
+use crate::tools::ToolError;
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::{OffsetComponents, Tz};
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Zones converted to at most in one call.
+const MAX_TARGETS: usize = 12;
+
+/// Abbreviations people write instead of zone names. Ambiguous ones (IST,
+/// CST) take their most common reading; the result shows the zone used.
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("pt", "America/Los_Angeles"),
+    ("pst", "America/Los_Angeles"),
+    ("pdt", "America/Los_Angeles"),
+    ("mt", "America/Denver"),
+    ("mst", "America/Denver"),
+    ("mdt", "America/Denver"),
+    ("ct", "America/Chicago"),
+    ("cst", "America/Chicago"),
+    ("cdt", "America/Chicago"),
+    ("et", "America/New_York"),
+    ("est", "America/New_York"),
+    ("edt", "America/New_York"),
+    ("akst", "America/Anchorage"),
+    ("hst", "Pacific/Honolulu"),
+    ("gmt", "Etc/UTC"),
+    ("utc", "Etc/UTC"),
+    ("z", "Etc/UTC"),
+    ("bst", "Europe/London"),
+    ("wet", "Europe/Lisbon"),
+    ("cet", "Europe/Paris"),
+    ("cest", "Europe/Paris"),
+    ("eet", "Europe/Athens"),
+    ("msk", "Europe/Moscow"),
+    ("gst", "Asia/Dubai"),
+    ("ist", "Asia/Kolkata"),
+    ("ict", "Asia/Bangkok"),
+    ("sgt", "Asia/Singapore"),
+    ("hkt", "Asia/Hong_Kong"),
+    ("kst", "Asia/Seoul"),
+    ("jst", "Asia/Tokyo"),
+    ("aest", "Australia/Sydney"),
+    ("aedt", "Australia/Sydney"),
+    ("nzst", "Pacific/Auckland"),
+    ("nzdt", "Pacific/Auckland"),
+];
+
+/// Cities that aren't the name of their zone.
+const CITIES: &[(&str, &str)] = &[
+    ("san francisco", "America/Los_Angeles"),
+    ("sf", "America/Los_Angeles"),
+    ("seattle", "America/Los_Angeles"),
+    ("san jose", "America/Los_Angeles"),
+    ("san diego", "America/Los_Angeles"),
+    ("portland", "America/Los_Angeles"),
+    ("las vegas", "America/Los_Angeles"),
+    ("salt lake city", "America/Denver"),
+    ("austin", "America/Chicago"),
+    ("dallas", "America/Chicago"),
+    ("houston", "America/Chicago"),
+    ("nyc", "America/New_York"),
+    ("boston", "America/New_York"),
+    ("washington", "America/New_York"),
+    ("atlanta", "America/New_York"),
+    ("miami", "America/New_York"),
+    ("philadelphia", "America/New_York"),
+    ("montreal", "America/Toronto"),
+    ("ottawa", "America/Toronto"),
+    ("rio de janeiro", "America/Sao_Paulo"),
+    ("manchester", "Europe/London"),
+    ("edinburgh", "Europe/London"),
+    ("munich", "Europe/Berlin"),
+    ("frankfurt", "Europe/Berlin"),
+    ("hamburg", "Europe/Berlin"),
+    ("barcelona", "Europe/Madrid"),
+    ("milan", "Europe/Rome"),
+    ("geneva", "Europe/Zurich"),
+    ("st petersburg", "Europe/Moscow"),
+    ("tel aviv", "Asia/Jerusalem"),
+    ("abu dhabi", "Asia/Dubai"),
+    ("mumbai", "Asia/Kolkata"),
+    ("delhi", "Asia/Kolkata"),
+    ("new delhi", "Asia/Kolkata"),
+    ("bangalore", "Asia/Kolkata"),
+    ("bengaluru", "Asia/Kolkata"),
+    ("hanoi", "Asia/Ho_Chi_Minh"),
+    ("beijing", "Asia/Shanghai"),
+    ("shenzhen", "Asia/Shanghai"),
+    ("busan", "Asia/Seoul"),
+    ("incheon", "Asia/Seoul"),
+    ("osaka", "Asia/Tokyo"),
+    ("kyoto", "Asia/Tokyo"),
+    ("canberra", "Australia/Sydney"),
+    ("wellington", "Pacific/Auckland"),
+];
+
+/// The system's zone, UTC when it can't be read.
+fn local_zone() -> Tz {
+    iana_time_zone::get_timezone()
+        .ok()
+        .and_then(|name| name.parse().ok())
+        .unwrap_or(chrono_tz::UTC)
+}
+
+/// An IANA name (`Asia/Seoul`), an abbreviation (`PT`, `KST`), a city
+/// (`Seoul`, `San Francisco`) or `local`.
+pub fn resolve_zone(name: &str) -> Result<Tz, String> {
+    let trimmed = name.trim();
+    let lower = trimmed.to_ascii_lowercase().replace('.', "");
+    if lower.is_empty() || lower == "local" || lower == "here" {
+        return Ok(local_zone());
+    }
+    if let Ok(tz) = Tz::from_str_insensitive(trimmed) {
+        return Ok(tz);
+    }
+    let lookup = |table: &[(&str, &'static str)]| table.iter().find(|(k, _)| *k == lower).map(|(_, zone)| *zone);
+    if let Some(zone) = lookup(ABBREVIATIONS).or_else(|| lookup(CITIES)) {
+        return zone.parse().map_err(|_| format!("Unknown zone {}", zone));
+    }
+    // A city that names its zone: "new york" → America/New_York.
+    let city = lower.replace(' ', "_");
+    chrono_tz::TZ_VARIANTS
+        .iter()
+        .find(|tz| tz.name().rsplit('/').next().is_some_and(|last| last.eq_ignore_ascii_case(&city)))
+        .copied()
+        .ok_or_else(|| format!("Unknown time zone or city '{}'; use an IANA name such as Europe/Berlin", trimmed))
+}
+
+/// `15:00`, `3pm`, `3:30 PM`, `noon` or `midnight`.
+fn parse_clock(text: &str) -> Option<NaiveTime> {
+    let lower = text.trim().to_ascii_lowercase().replace([' ', '.'], "");
+    match lower.as_str() {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+    let (digits, meridiem) = if let Some(rest) = lower.strip_suffix("am") {
+        (rest, Some(false))
+    } else if let Some(rest) = lower.strip_suffix("pm") {
+        (rest, Some(true))
+    } else {
+        (lower.as_str(), None)
+    };
+    let mut parts = digits.split(':');
+    let hour: u32 = parts.next()?.parse().ok()?;
+    let minute: u32 = parts.next().map_or(Some(0), |m| m.parse().ok())?;
+    let second: u32 = parts.next().map_or(Some(0), |s| s.parse().ok())?;
+    let hour = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, second)
+}
+
+/// `2026-10-20`, `today`, `tomorrow`, `yesterday`, a weekday (`tuesday`:
+/// today or the next one) or `next tuesday` (strictly after today).
+pub fn parse_date(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let lower = text.trim().to_ascii_lowercase();
+    match lower.as_str() {
+        "" | "today" => return Some(today),
+        "tomorrow" => return today.succ_opt(),
+        "yesterday" => return today.pred_opt(),
+        _ => {}
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(&lower, "%Y-%m-%d") {
+        return Some(date);
+    }
+    let (name, strictly_after) = match lower.strip_prefix("next ") {
+        Some(name) => (name, true),
+        None => (lower.strip_prefix("this ").unwrap_or(&lower), false),
+    };
+    let weekday: Weekday = name.parse().ok()?;
+    let ahead = (7 + weekday.num_days_from_monday() as i64 - today.weekday().num_days_from_monday() as i64) % 7;
+    let ahead = if ahead == 0 && strictly_after { 7 } else { ahead };
+    Some(today + Duration::days(ahead))
+}
+
+/// Pin a wall-clock time in `tz`. Around DST changes a time can happen twice
+/// (the earlier is used) or not at all (moved forward past the gap); either
+/// way a note says so.
+fn localize(tz: Tz, naive: NaiveDateTime) -> Result<(DateTime<Tz>, Option<String>), String> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(dt) => Ok((dt, None)),
+        LocalResult::Ambiguous(earlier, later) => Ok((
+            earlier,
+            Some(format!(
+                "{} happens twice in {} (clocks go back); used the first ({}), the second is {}",
+                naive.format("%H:%M"),
+                tz.name(),
+                earlier.format("%Z"),
+                later.format("%Z")
+            )),
+        )),
+        LocalResult::None => {
+            // Spring-forward gaps are at most an hour (two on a few islands).
+            (1..=2)
+                .find_map(|hours| tz.from_local_datetime(&(naive + Duration::hours(hours))).earliest())
+                .map(|dt| {
+                    let note = format!(
+                        "{} doesn't exist in {} (clocks go forward); used {}",
+                        naive.format("%H:%M"),
+                        tz.name(),
+                        dt.format("%H:%M %Z")
+                    );
+                    (dt, Some(note))
+                })
+                .ok_or_else(|| format!("{} doesn't exist in {}", naive, tz.name()))
+        }
+    }
+}
+
+fn describe(dt: &DateTime<Tz>, reference_date: NaiveDate) -> Value {
+    let offset = dt.offset();
+    let days = (dt.date_naive() - reference_date).num_days();
+    json!({
+        "zone": dt.timezone().name(),
+        "local": dt.to_rfc3339(),
+        "display": dt.format("%A, %B %-d, %Y %H:%M").to_string(),
+        "abbreviation": dt.format("%Z").to_string(),
+        "utc_offset": dt.format("%:z").to_string(),
+        "dst": !offset.dst_offset().is_zero(),
+        "day_shift": days,
+    })
+}
+
+/// `convert_time`: convert a wall-clock time between time zones and cities
+/// with the tz database, so DST transitions are right.
+#[derive(Deserialize, Serialize)]
+pub struct ConvertTime;
+
+#[derive(Deserialize, Serialize)]
+pub struct ConvertTimeArgs {
+    #[serde(default)]
+    time: Option<String>,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default)]
+    from: Option<String>,
+    to: Vec<String>,
+}
+
+impl Tool for ConvertTime {
+    const NAME: &'static str = "convert_time";
+    type Args = ConvertTimeArgs;
+    type Output = Value;
+    type Error = ToolError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Convert a time between time zones or cities using the time zone database, with daylight \
+                saving handled exactly (e.g. \"3pm PT next Tuesday in Seoul\"). Always use this instead of \
+                computing offsets yourself when scheduling across zones. Omit `time` for the current time."
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "time": { "type": "string", "description": "Wall-clock time in `from`: 15:00, 3pm, noon, or an RFC 3339 timestamp. Default: now" },
+                    "date": { "type": "string", "description": "YYYY-MM-DD, today, tomorrow, tuesday or next tuesday (in `from`). Default: today" },
+                    "from": { "type": "string", "description": "Zone of `time`: IANA name, abbreviation (PT, KST), city, or local. Default: local" },
+                    "to": { "type": "array", "items": { "type": "string" }, "description": "Zones or cities to convert to" }
+                },
+                "required": ["to"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let failed = ToolError::CommandFailed;
+        if args.to.is_empty() || args.to.len() > MAX_TARGETS {
+            return Err(failed(format!("Give 1 to {} zones in `to`", MAX_TARGETS)));
+        }
+        let from = resolve_zone(args.from.as_deref().unwrap_or("local")).map_err(failed)?;
+        let today = Utc::now().with_timezone(&from).date_naive();
+
+        let time = args.time.as_deref().map(str::trim).filter(|t| !t.is_empty() && !t.eq_ignore_ascii_case("now"));
+        let (instant, note) = match time {
+            None => (Utc::now().with_timezone(&from), None),
+            // A full timestamp carries its own offset.
+            Some(t) if DateTime::parse_from_rfc3339(t).is_ok() => {
+                let parsed = DateTime::parse_from_rfc3339(t).map_err(|e| failed(e.to_string()))?;
+                (parsed.with_timezone(&from), None)
+            }
+            Some(t) => {
+                let clock = parse_clock(t).ok_or_else(|| failed(format!("Can't read the time '{}'", t)))?;
+                let date_text = args.date.as_deref().unwrap_or("today");
+                let date = parse_date(date_text, today).ok_or_else(|| failed(format!("Can't read the date '{}'", date_text)))?;
+                localize(from, date.and_time(clock)).map_err(failed)?
+            }
+        };
+
+        let source_date = instant.date_naive();
+        let targets: Vec<Value> = args
+            .to
+            .iter()
+            .map(|name| match resolve_zone(name) {
+                Ok(tz) => {
+                    let mut converted = describe(&instant.with_timezone(&tz), source_date);
+                    converted["query"] = json!(name);
+                    converted
+                }
+                Err(e) => json!({"query": name, "error": e}),
+            })
+            .collect();
+        Ok(json!({
+            "from": describe(&instant, source_date),
+            "utc": instant.with_timezone(&Utc).to_rfc3339(),
+            "targets": targets,
+            "note": note,
+        }))
+    }
+}